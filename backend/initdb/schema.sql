@@ -23,7 +23,7 @@ $$ LANGUAGE plpgsql;
 
 CREATE TYPE ItemType AS ENUM ('device', 'link', 'group');
 CREATE TYPE LinkType AS ENUM ('optical', 'copper', 'wireless', 'unknown');
-CREATE TYPE DataSource AS ENUM('ssh', 'snmp', 'icmp');
+CREATE TYPE DataSource AS ENUM('ssh', 'snmp', 'icmp', 'prometheus');
 
 CREATE SEQUENCE global_item_id_seq;
 CREATE TABLE IF NOT EXISTS Analytics.items (
@@ -121,6 +121,20 @@ CREATE TABLE IF NOT EXISTS Analytics.alert_rules (
     rule_definition JSONB NOT NULL
 );
 
+-- Where alert events are delivered, on top of the websocket listeners. See `AlertRoute`
+CREATE TABLE IF NOT EXISTS Analytics.alert_routes (
+    route_id         BIGSERIAL PRIMARY KEY,
+    route_name       VARCHAR(254) NOT NULL,
+    route_definition JSONB NOT NULL
+);
+
+-- Rotations of ClientIdentity.ack_tokens paged by `oncall` route destinations. See `OnCallSchedule`
+CREATE TABLE IF NOT EXISTS Analytics.oncall_schedules (
+    schedule_id         BIGSERIAL PRIMARY KEY,
+    schedule_name       VARCHAR(254) NOT NULL UNIQUE,
+    schedule_definition JSONB NOT NULL
+);
+
 CREATE TYPE AlertSeverity AS ENUM ('emergency','alert','critical','error','warning','notice','info','debug','unknown');
 CREATE TABLE IF NOT EXISTS Analytics.alerts (
     alert_id     BIGSERIAL PRIMARY KEY,
@@ -133,15 +147,54 @@ CREATE TABLE IF NOT EXISTS Analytics.alerts (
     target_id    BIGINT,
     value        VARCHAR(254) NOT NULL,
     rule_id      BIGINT,
+    recovery     BOOLEAN NOT NULL DEFAULT FALSE, -- emitted when the clear condition of the rule was met
 
-    FOREIGN KEY (target_id) REFERENCES Analytics.devices(device_id) ON DELETE CASCADE,
+    FOREIGN KEY (target_id) REFERENCES Analytics.items(id) ON DELETE CASCADE, -- devices or links
     FOREIGN KEY (rule_id) REFERENCES Analytics.alert_rules(rule_id)
 );
 
+-- Evaluation state of alert rules, snapshotted periodically so that a restart doesn't reset it.
+-- No foreign keys, so that a snapshot never fails over a rule or item deleted meanwhile. Stale entries are pruned on restore
+CREATE TABLE IF NOT EXISTS Analytics.alert_sustained_state (
+    rule_id          BIGINT NOT NULL,
+    item_id          BIGINT NOT NULL,
+    first_raised     BIGINT NOT NULL, -- epoch seconds
+    rule_fingerprint JSONB  NOT NULL, -- evaluated parts of the rule when the timer started. Changed rules start over
+
+    PRIMARY KEY (rule_id, item_id)
+);
+
+-- Items each rule with a clear condition is raised for, so that a restart neither raises them again nor misses their recovery
+CREATE TABLE IF NOT EXISTS Analytics.alert_raised_state (
+    rule_id          BIGINT NOT NULL,
+    item_id          BIGINT NOT NULL,
+    rule_fingerprint JSONB  NOT NULL, -- evaluated parts of the rule when it raised. Changed rules start over
+
+    PRIMARY KEY (rule_id, item_id)
+);
+
+-- Devices whose alert events are not notified until `silenced_until`. They're still stored, and sent through the websocket
+CREATE TABLE IF NOT EXISTS Analytics.alert_silences (
+    target_id      BIGINT PRIMARY KEY,
+    silenced_until TIMESTAMPTZ NOT NULL,
+    silenced_by    VARCHAR(254) NOT NULL,
+
+    FOREIGN KEY (target_id) REFERENCES Analytics.items(id) ON DELETE CASCADE
+);
+
+-- Previous dataset of Delta rules. Single row
+CREATE TABLE IF NOT EXISTS Analytics.alert_delta_snapshot (
+    snapshot_id SMALLINT PRIMARY KEY DEFAULT 1 CHECK (snapshot_id = 1),
+    taken_at    TIMESTAMPTZ NOT NULL,
+    facts       JSONB NOT NULL, -- metrics, by device hostname
+    link_states JSONB NOT NULL  -- link states, by link id
+);
+
 CREATE TABLE IF NOT EXISTS Analytics.groups (
     group_id         BIGINT PRIMARY KEY DEFAULT nextval('global_item_id_seq'),
     group_name       VARCHAR(254) NOT NULL,
     is_display_group BOOLEAN NOT NULL,
+    filter           JSONB NULL, -- Dynamic groups only. Members are computed from it, group_members is left empty
 
     FOREIGN KEY (group_id) REFERENCES Analytics.items(id) ON DELETE CASCADE
 );
@@ -179,6 +232,40 @@ CREATE TABLE IF NOT EXISTS Analytics.dashboard_items(
     CONSTRAINT chk_col_span CHECK (col_span  >= 1)
 );
 
+-- Links proposed by topology discovery (LLDP/CDP neighbors). Never applied until approved via the API
+CREATE TYPE LinkProposalStatus AS ENUM ('pending', 'approved', 'rejected');
+CREATE TABLE IF NOT EXISTS Analytics.link_proposals (
+    proposal_id   BIGSERIAL PRIMARY KEY,
+    link_id       BIGINT,           -- NULL for new links, the existing link for changed ones
+    side_a        BIGINT NOT NULL,
+    side_b        BIGINT NOT NULL,
+    side_a_iface  VARCHAR(254) NOT NULL,
+    side_b_iface  VARCHAR(254) NOT NULL,
+    protocol      VARCHAR(16)  NOT NULL,
+    status        LinkProposalStatus NOT NULL DEFAULT 'pending',
+    discovered_at TIMESTAMPTZ  NOT NULL DEFAULT NOW(),
+    resolved_at   TIMESTAMPTZ,
+
+    FOREIGN KEY (link_id) REFERENCES Analytics.links(link_id) ON DELETE CASCADE,
+    FOREIGN KEY (side_a)  REFERENCES Analytics.devices(device_id) ON DELETE CASCADE,
+    FOREIGN KEY (side_b)  REFERENCES Analytics.devices(device_id) ON DELETE CASCADE,
+
+    CONSTRAINT chk_link_proposal_sides_different CHECK (side_a <> side_b)
+);
+
+-- Every configuration commit, with who made it and what changed. Snapshots allow rolling back to any revision
+CREATE TYPE AuditSource AS ENUM ('configure', 'import', 'discovery', 'rollback');
+CREATE TABLE IF NOT EXISTS Analytics.audit_log (
+    revision     BIGSERIAL PRIMARY KEY,
+    actor        VARCHAR(254) NOT NULL,
+    source       AuditSource  NOT NULL,
+    committed_at TIMESTAMPTZ  NOT NULL DEFAULT NOW(),
+    rolled_back_to BIGINT,       -- rollbacks only, the revision restored
+    summary      JSONB NOT NULL, -- changed keys, per entity kind
+    changes      JSONB NOT NULL, -- before/after of every changed entry, per entity kind
+    snapshot     JSONB NOT NULL  -- topology document after the commit
+);
+
 -- Trigger functions
 CREATE OR REPLACE FUNCTION create_item_on_device_insert()
 RETURNS TRIGGER AS $$
@@ -1,4 +0,0 @@
-#!/bin/bash
-python3 prelude/create_aggregate_tasks.py
-
-exec "$@"
\ No newline at end of file
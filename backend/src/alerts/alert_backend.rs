@@ -1,27 +1,33 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, OnceLock};
 use std::time::{SystemTime, UNIX_EPOCH};
-use chrono::Utc;
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
 use tokio::sync::{RwLock, RwLockWriteGuard};
 use tokio::sync::mpsc::{Receiver, Sender};
 use tokio::sync::Mutex;
 use tokio::sync::mpsc;
 
 use crate::alerts::telegram_backend::backend::TelegramBackend;
-use crate::types::{AlertId, AlertRuleId, DeviceId, EpochSeconds};
+use crate::AegisError;
+use crate::types::{AlertId, AlertRuleId, AlertTargetId, DeviceHostname, DeviceId, EpochSeconds, EvaluableItemId, MetricSet};
 use crate::config::Config;
-use crate::alerts::{AlertDataSource, AlertEvent, AlertRule};
+use crate::alerts::{AlertDataSource, AlertEvent, AlertRecipients, AlertRoute, AlertRule, AlertRuleKind, AlertTransition, OnCallSchedule, OnCallShift, TemplateContext, TemplateFormat, format_raising_values};
+use crate::alerts::notify_backend::NotifyBackend;
 use crate::model::cache::Cache;
-use crate::model::data::device::Device;
 use crate::model::data::device_state::DeviceStatus;
 use crate::model::db::operations::alert_operations;
+use crate::model::db::operations::alert_state_operations::{self, RaisedRecord, SilenceRecord, SustainedRecord};
 use crate::model::facts::fact_gathering_backend::{DeviceFacts, FactGatheringBackend, FactMessage};
 use crate::types::{ExposedFields, MetricValue};
 use crate::syslog::syslog_backend::SyslogBackend;
+use crate::supervisor::{ShutdownSignal, Stage, Supervisor};
+use crate::telemetry::Telemetry;
 use crate::syslog::SyslogMessage;
 
-
+/// Length of `Analytics.alerts.message`
+const MAX_MESSAGE_LEN: usize = 254;
 
 /// Singleton that stores listeners only
 pub struct AlertBackend {
@@ -41,15 +47,30 @@ pub struct AlertBackend {
     /// Local cache of alert rules that apply to data gotten via the Syslog Backend
     syslog_rules: RwLock<Vec<AlertRule>>,
 
+    /// Where alert events are delivered, in addition to the listeners
+    routes: RwLock<Vec<AlertRoute>>,
+
+    /// Rotations paged by the `oncall` destinations of routes
+    oncall_schedules: RwLock<Vec<OnCallSchedule>>,
+
     /// Mapping of RuleIDs to Rule Names, for display when an alert is issued via Telegram
     rule_names : RwLock<HashMap<AlertId, String>>,
 
     /// Mapping of Sustained rule evaluation records
     sustained_rules_records : RwLock<HashMap<AlertId, HashMap<DeviceId, EpochSeconds>>>,
 
+    /// Items each rule with a clear condition is currently raised for
+    raised_records : RwLock<HashMap<AlertRuleId, HashSet<EvaluableItemId>>>,
+
+    /// Devices whose alert events are not notified, until the silence expires
+    silences : RwLock<HashMap<AlertTargetId, SilenceRecord>>,
+
     /// Time since the last cache update for rules
     last_update: RwLock<EpochSeconds>, // epoch seconds
 
+    /// Time of the last snapshot of the evaluation state into the database
+    last_snapshot: RwLock<EpochSeconds>, // epoch seconds
+
 }
 
 static INSTANCE: OnceLock<Arc<AlertBackend>> = OnceLock::new();
@@ -65,11 +86,15 @@ impl AlertBackend {
             facts_rules: RwLock::new(Vec::new()),
             syslog_rules: RwLock::new(Vec::new()),
             rule_names: RwLock::new(HashMap::new()),
+            routes: RwLock::new(Vec::new()),
+            oncall_schedules: RwLock::new(Vec::new()),
 
             sustained_rules_records: RwLock::new(HashMap::new()),
+            raised_records: RwLock::new(HashMap::new()),
+            silences: RwLock::new(HashMap::new()),
 
             last_update: RwLock::new(0),
-
+            last_snapshot: RwLock::new(0),
         }
     }
 
@@ -78,27 +103,35 @@ impl AlertBackend {
         INSTANCE.get().expect("[FATAL]AlertBackend not initialized").clone()
     }
 
+    /// Get singleton instance, if initialized
+    pub fn try_instance() -> Option<Arc<AlertBackend>> {
+        INSTANCE.get().cloned()
+    }
+
     /// Initialize
     pub async fn init(pool: &sqlx::PgPool) {
-        println!("[INFO] Attempting to init alert backend (requires Postgres connection)");
+        log::info!("Attempting to init alert backend (requires Postgres connection)");
 
         // Add listeners to react to events that require rule eval
         let (facts_channel_tx, facts_channel_rx) = mpsc::channel::<FactMessage>(64);
         let (syslog_channel_tx, syslog_channel_rx) = mpsc::channel::<SyslogMessage>(64);
         let (internal_event_tx, internal_event_rx) = mpsc::channel::<AlertEvent>(64);
         let (telegram_event_tx, telegram_event_rx) = mpsc::channel::<AlertEvent>(64);
+        let (notify_event_tx, notify_event_rx) = mpsc::channel::<AlertEvent>(64);
 
         // Try to set instance Arc with provided value
         let backend = Arc::new(AlertBackend::new(pool));
         let innit_bruv = INSTANCE.set(backend);
 
         if innit_bruv.is_err() {
-            println!("[WARN][ALERTS] Alerts backend was init more than once!. Ignoring second init...");
+            log::warn!(subsystem = "alerts"; "Alerts backend was init more than once!. Ignoring second init...");
             return;
         }
 
-        // Force to update the ruleset before the first fact execution
+        // Force to update the ruleset before the first fact execution, and pick up the evaluation state where the last run left it
         AlertBackend::instance().update_ruleset(true).await;
+        AlertBackend::instance().restore_eval_state().await;
+        AlertBackend::instance().restore_silences().await;
 
         // Listener and task for evaluating tasks when the FactGatheringBackend provides new data
         FactGatheringBackend::instance().add_listener(facts_channel_tx).await;
@@ -117,8 +150,12 @@ impl AlertBackend {
         // Telegram notifier
         TelegramBackend::init(pool.clone(), telegram_event_rx).await;
 
+        // Webhooks and emails of alert routes
+        Self::instance().add_listener(notify_event_tx).await;
+        NotifyBackend::init(notify_event_rx);
 
-        log::info!("[INFO ][ALERTS] Init Alert Backend");
+
+        log::info!(subsystem = "alerts"; "Init Alert Backend");
     }
 
     //   ______                         __                __                            __         ______   _______   ______ 
@@ -188,6 +225,227 @@ impl AlertBackend {
 
         rules.remove(&device_id); // we don't care if it was, as long as it isn't anymore
     }
+
+    /// Whether a rule with a clear condition is currently raised for the item
+    pub async fn is_raised(rule_id: AlertRuleId, item_id: EvaluableItemId) -> bool {
+        let instance = AlertBackend::instance();
+        let raised = instance.raised_records.read().await;
+        raised.get(&rule_id).is_some_and(|items| items.contains(&item_id))
+    }
+
+    pub async fn set_raised(rule_id: AlertRuleId, item_id: EvaluableItemId, is_raised: bool) {
+        let instance = AlertBackend::instance();
+        let mut raised = instance.raised_records.write().await;
+        if is_raised {
+            raised.entry(rule_id).or_default().insert(item_id);
+        } else if let Some(items) = raised.get_mut(&rule_id) {
+            items.remove(&item_id);
+        }
+    }
+
+    /// Every item a rule with a clear condition is currently raised for, as (rule, item)
+    pub async fn get_raised_items(&self) -> Vec<(AlertRule, EvaluableItemId)> {
+        let raised = self.raised_records.read().await;
+        let facts = self.facts_rules.read().await;
+        facts.iter()
+            .filter_map(|rule| Some((rule, raised.get(&rule.rule_id)?)))
+            .flat_map(|(rule, items)| items.iter().map(|item| (rule.clone(), *item)))
+            .collect()
+    }
+
+    async fn restore_silences(&self) {
+        match alert_state_operations::get_silences(&self.pool).await {
+            Ok(silences) => {
+                log::info!(subsystem = "alerts.state"; "Restored {} silences", silences.len());
+                *self.silences.write().await = silences.into_iter().map(|s| (s.target_id, s)).collect();
+            },
+            Err(e) => log::error!(subsystem = "alerts.state"; "Failed to load the saved silences, e='{e}'. Starting without them"),
+        }
+    }
+
+    /// Silence of the target, if it hasn't expired
+    pub async fn get_silence(&self, target_id: AlertTargetId) -> Option<SilenceRecord> {
+        self.silences.read().await.get(&target_id)
+            .filter(|silence| silence.until > Utc::now())
+            .cloned()
+    }
+
+    /// Stops notifying the alert events of the target until `until`, or lifts its silence if None
+    pub async fn set_silence(&self, target_id: AlertTargetId, until: Option<DateTime<Utc>>, actor: &str) -> Result<(), AegisError> {
+        let mut silences = self.silences.write().await;
+        match until {
+            Some(until) => {
+                let silence = SilenceRecord { target_id, until, actor: actor.to_string() };
+                alert_state_operations::upsert_silence(&self.pool, &silence).await?;
+                silences.insert(target_id, silence);
+            },
+            None => {
+                alert_state_operations::delete_silence(&self.pool, target_id).await?;
+                silences.remove(&target_id);
+            },
+        }
+        log::info!(subsystem = "alerts.silence"; "Silence of target {} set until {:?} by '{}'", target_id, until, actor);
+        Ok(())
+    }
+
+    /// Fingerprint of every loaded rule that passes `keep`
+    async fn rule_fingerprints(&self, keep: impl Fn(&AlertRule) -> bool) -> HashMap<AlertRuleId, serde_json::Value> {
+        let facts = self.facts_rules.read().await;
+        let syslog = self.syslog_rules.read().await;
+        facts.iter().chain(syslog.iter())
+            .filter(|rule| keep(rule))
+            .map(|rule| (rule.rule_id, rule.fingerprint()))
+            .collect()
+    }
+
+    async fn sustained_fingerprints(&self) -> HashMap<AlertRuleId, serde_json::Value> {
+        self.rule_fingerprints(|rule| matches!(rule.rule_kind, AlertRuleKind::Sustained { .. })).await
+    }
+
+    async fn clearable_fingerprints(&self) -> HashMap<AlertRuleId, serde_json::Value> {
+        self.rule_fingerprints(|rule| rule.clear.is_some()).await
+    }
+
+    /// Drops the state of rules that were deleted, or changed how they evaluate.
+    /// Sustained timers are also dropped for rules no longer Sustained, and raised items for rules without a clear condition
+    async fn prune_eval_state(&self, previous: &HashMap<AlertRuleId, serde_json::Value>) {
+        let unchanged = |current: &HashMap<AlertRuleId, serde_json::Value>, rule_id: &AlertRuleId| {
+            current.get(rule_id).is_some_and(|f| previous.get(rule_id) == Some(f))
+        };
+
+        let sustained = self.sustained_fingerprints().await;
+        let clearable = self.clearable_fingerprints().await;
+        let dropped = {
+            let mut records = self.sustained_rules_records.write().await;
+            let mut raised = self.raised_records.write().await;
+            let before = records.len() + raised.len();
+            records.retain(|rule_id, _| unchanged(&sustained, rule_id));
+            raised.retain(|rule_id, _| unchanged(&clearable, rule_id));
+            before - records.len() - raised.len()
+        };
+
+        if dropped > 0 {
+            log::info!(subsystem = "alerts.state"; "Dropped evaluation state of {} changed or deleted rules", dropped);
+        }
+    }
+
+    /// Saves the Sustained timers, the raised items and the Delta dataset, if a snapshot is due or `forced`
+    pub async fn snapshot_eval_state(&self, forced: bool) {
+        let interval_s = Config::instance().settings().backend.model.alerts.state_snapshot_s;
+        let now: EpochSeconds = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        {
+            let mut last_snapshot = self.last_snapshot.write().await;
+            if now.saturating_sub(*last_snapshot) < interval_s && !forced {
+                return;
+            }
+            *last_snapshot = now;
+        }
+
+        let fingerprints = self.sustained_fingerprints().await;
+        let sustained: Vec<SustainedRecord> = {
+            let records = self.sustained_rules_records.read().await;
+            records.iter()
+                .filter_map(|(rule_id, items)| Some((*rule_id, items, fingerprints.get(rule_id)?)))
+                .flat_map(|(rule_id, items, fingerprint)| items.iter().map(move |(item_id, first_raised)| SustainedRecord {
+                    rule_id, item_id: *item_id, first_raised: *first_raised as i64, rule_fingerprint: fingerprint.clone(),
+                }))
+                .collect()
+        };
+
+        let fingerprints = self.clearable_fingerprints().await;
+        let raised: Vec<RaisedRecord> = {
+            let raised = self.raised_records.read().await;
+            raised.iter()
+                .filter_map(|(rule_id, items)| Some((*rule_id, items, fingerprints.get(rule_id)?)))
+                .flat_map(|(rule_id, items, fingerprint)| items.iter().map(move |item_id| RaisedRecord {
+                    rule_id, item_id: *item_id, rule_fingerprint: fingerprint.clone(),
+                }))
+                .collect()
+        };
+
+        let cache = Cache::instance();
+        let facts: HashMap<DeviceHostname, MetricSet> = cache.facts.read().await.iter()
+            .map(|(hostname, facts)| (hostname.clone(), facts.metrics.clone()))
+            .collect();
+        let link_states = cache.get_link_states().await;
+
+        let saved = alert_state_operations::replace_sustained_state(&self.pool, &sustained).await
+            .and(alert_state_operations::replace_raised_state(&self.pool, &raised).await)
+            .and(alert_state_operations::upsert_delta_snapshot(&self.pool, &facts, &link_states).await);
+        match saved {
+            Ok(()) => log::info!(subsystem = "alerts.state"; "Saved evaluation state, {} sustained timers, {} raised items and facts of {} devices", sustained.len(), raised.len(), facts.len()),
+            Err(e) => {
+                log::error!(subsystem = "alerts.state"; "Failed to save evaluation state, e='{e}'");
+                Telemetry::postgres_error();
+            },
+        }
+    }
+
+    /// Restores the evaluation state saved by the last run. State of rules that changed since is dropped.
+    /// Raised items are always restored, so that a restart neither raises them again nor misses their recovery.
+    /// Sustained timers and the Delta dataset only if they're younger than `state_max_age_s`
+    async fn restore_eval_state(&self) {
+        let max_age_s = Config::instance().settings().backend.model.alerts.state_max_age_s;
+
+        let fingerprints = self.clearable_fingerprints().await;
+        match alert_state_operations::get_raised_state(&self.pool).await {
+            Ok(saved) => {
+                let total = saved.len();
+                let mut raised = self.raised_records.write().await;
+                for record in saved.into_iter().filter(|r| fingerprints.get(&r.rule_id) == Some(&r.rule_fingerprint)) {
+                    raised.entry(record.rule_id).or_default().insert(record.item_id);
+                }
+                let restored: usize = raised.values().map(HashSet::len).sum();
+                log::info!(subsystem = "alerts.state"; "Restored {} of {} raised items", restored, total);
+            },
+            Err(e) => log::error!(subsystem = "alerts.state"; "Failed to load the saved raised items, e='{e}'. Starting without them"),
+        }
+
+        let snapshot = match alert_state_operations::get_delta_snapshot(&self.pool).await {
+            Ok(Some(snapshot)) => snapshot,
+            Ok(None) => return,
+            Err(e) => {
+                log::error!(subsystem = "alerts.state"; "Failed to load the saved Delta dataset, e='{e}'. Starting without it");
+                return;
+            },
+        };
+        let age_s = (Utc::now() - snapshot.taken_at).num_seconds().max(0) as u64;
+        if age_s > max_age_s {
+            log::info!(subsystem = "alerts.state"; "Saved evaluation state is {}s old, older than {}s. Starting over", age_s, max_age_s);
+            return;
+        }
+
+        let saved = match alert_state_operations::get_sustained_state(&self.pool).await {
+            Ok(saved) => saved,
+            Err(e) => {
+                log::error!(subsystem = "alerts.state"; "Failed to load the saved sustained timers, e='{e}'. Starting without them");
+                Vec::new()
+            },
+        };
+        let fingerprints = self.sustained_fingerprints().await;
+        let total = saved.len();
+        let mut restored = 0;
+        {
+            let mut records = self.sustained_rules_records.write().await;
+            for record in saved {
+                if fingerprints.get(&record.rule_id) != Some(&record.rule_fingerprint) {
+                    continue;
+                }
+                records.entry(record.rule_id).or_default().insert(record.item_id, record.first_raised.max(0) as EpochSeconds);
+                restored += 1;
+            }
+        }
+
+        let cache = Cache::instance();
+        let devices = snapshot.facts.len();
+        cache.restore_facts(snapshot.facts).await;
+        cache.update_link_states(snapshot.link_states).await;
+
+        log::info!(subsystem = "alerts.state"; "Restored evaluation state from {}s ago, {} of {} sustained timers and facts of {} devices", age_s, restored, total, devices);
+    }
     
     
 
@@ -203,7 +461,7 @@ impl AlertBackend {
 
     /// Add a listener channel sender; returns a listener id
     pub async fn add_listener(&self, sender: Sender<AlertEvent>) -> usize {
-        log::info!("[INFO ][ALERTS] Registered listener");
+        log::info!(subsystem = "alerts"; "Registered listener");
         let id = self.next_id.fetch_add(1, Ordering::Relaxed);
         let mut guard = self.listeners.lock().await;
         guard.insert(id, sender);
@@ -212,21 +470,64 @@ impl AlertBackend {
 
     /// Remove a listener by id; returns whether any entry was removed
     pub async fn remove_listener(&self, id: usize) -> bool {
-        log::info!("[INFO ][ALERTS] Gracefully removed listener");
+        log::info!(subsystem = "alerts"; "Gracefully removed listener");
         let mut guard = self.listeners.lock().await;
         guard.remove(&id).is_some()
     }
 
     /// Remove all listeners
     pub async fn clear_listeners(&self) {
-        log::info!("[INFO ][ALERTS] Cleared all listeners");
+        log::info!(subsystem = "alerts"; "Cleared all listeners");
         let mut guard = self.listeners.lock().await;
         guard.clear();
     }
 
-    /// Broadcast a message to all listeners; prune dead ones
+    /// Where the routes deliver the event. None if no route matches it
+    async fn route_event(&self, event: &AlertEvent) -> Option<AlertRecipients> {
+        let routes = self.routes.read().await;
+        if routes.is_empty() {
+            return None;
+        }
+
+        // Groups are only looked up if some route matches on targets
+        let mut target_groups = HashSet::new();
+        if routes.iter().any(|route| route.matcher.targets.is_some()) {
+            let cache = Cache::instance();
+            for group in cache.get_groups().await {
+                if cache.get_group_device_ids(group.group_id).await.is_some_and(|devices| devices.contains(&event.target_id)) {
+                    target_groups.insert(group.group_id);
+                }
+            }
+        }
+
+        AlertRoute::resolve(&routes, event, &target_groups, Self::routing_now())
+    }
+
+    /// Current time in `backend/model/alerts/routing_timezone`, which route schedules and on-call rotations are written in
+    fn routing_now() -> DateTime<Tz> {
+        let timezone: Tz = Config::instance().settings().backend.model.alerts.routing_timezone.parse().unwrap_or(Tz::UTC);
+        Utc::now().with_timezone(&timezone)
+    }
+
+    /// Every on-call schedule, with who is on call right now. None for the ones whose rotation hasn't started
+    pub async fn get_oncall_shifts(&self) -> Vec<(OnCallSchedule, Option<OnCallShift>)> {
+        let now = Self::routing_now().naive_local();
+        self.oncall_schedules.read().await.iter()
+            .map(|schedule| (schedule.clone(), schedule.on_call(now)))
+            .collect()
+    }
+
+    /// Broadcast a message to all listeners, with the recipients of the routes that match it; prune dead ones
     /// Best-effort: ignores per-send errors and removes disconnected senders
     pub async fn broadcast(&self, msg: &AlertEvent) {
+        let mut msg = msg.clone();
+        msg.recipients = match self.get_silence(msg.target_id).await {
+            // Silenced events are routed to nobody, the websocket and database listeners still get them
+            Some(_) => Some(AlertRecipients::default()),
+            None => self.route_event(&msg).await,
+        };
+        let msg = &msg;
+
         // Snapshot keys to avoid holding lock while awaiting send
         let keys_and_senders: Vec<(usize, Sender<AlertEvent>)> = {
             let guard = self.listeners.lock().await;
@@ -247,16 +548,9 @@ impl AlertBackend {
                             failed_ids.push(id);
                         }
                         TrySendError::Full(_full_msg) => {
-                            // channel is full; try a short, awaited send to avoid dropping the message
-                            // If that also fails because closed, mark for removal.
-                            match tx.try_reserve() {
-                                Ok(permit) => {
-                                    permit.send(msg.clone());
-                                }
-                                Err(_) => {
-                                    // treat inability to reserve as potential disconnect; don't panic
-                                }
-                            }
+                            // Listeners are not waited on, so one that's behind doesn't hold back the rest
+                            log::warn!(subsystem = "alerts"; "Listener {id} is full, dropped alert event {} for it", msg.alert_id);
+                            Telemetry::instance().alert_events_dropped.inc();
                         }
                     }
                 }
@@ -267,7 +561,7 @@ impl AlertBackend {
         if !failed_ids.is_empty() {
             let mut guard = self.listeners.lock().await;
             for id in failed_ids {
-                log::info!("[INFO ][ALERTS] Forcefully removed listener for failed receive");
+                log::info!(subsystem = "alerts"; "Forcefully removed listener for failed receive");
                 guard.remove(&id);
             }
         }
@@ -289,46 +583,63 @@ impl AlertBackend {
     // $$       | $$$/   $$       |$$ |  $$ |  $$  $$//     $$/
     // $$$$$$$$/   $/     $$$$$$$/ $$/   $$/    $$$$/ $$$$$$$/
     //
-        /// Spawns the task that will handle the evaluation of FactMessages against the rules
-    pub fn spawn_eval_facts_task(mut receiver: Receiver<FactMessage>, event_tx : Sender<AlertEvent>,) {
-        #[allow(clippy::let_underscore_future)]
-        let _ = rocket::tokio::task::spawn(async move { 
-            let instance = Self::instance();
-            println!("[INFO ][ALERTS] Spawned alert eval facts task");
-            loop {
-                let new_facts = match receiver.recv().await {
-                    Some(msg) => msg,
-                    None => break
-                };
-                #[cfg(debug_assertions)] { log::info!("[DEBUG][ALERTS] Alerts backend received facts..."); }
+    /// Spawns the task that will handle the evaluation of FactMessages against the rules
+    pub fn spawn_eval_facts_task(receiver: Receiver<FactMessage>, event_tx : Sender<AlertEvent>,) {
+        let receiver = Arc::new(Mutex::new(receiver));
+        Supervisor::spawn("alert_eval_facts", Stage::Evaluation, move |shutdown| Self::eval_facts_loop(receiver.clone(), event_tx.clone(), shutdown));
 
-                // Critical area, locks facts_rules, syslog_rules, rule_names and last_update
-                // Updates the rule set if needed, before evaluating anything
-                Self::instance().update_ruleset(false).await;
-                
-                // Critical area, requires locks
-                {
-                    let cache = Cache::instance();
-                    let facts_rules = instance.facts_rules.read().await;
-                    let old_facts = cache.facts.read().await;
-                    AlertBackend::eval_rules(&facts_rules, &old_facts, &new_facts, &event_tx).await;
-                } // Release dem locks so I can lock it again for write to update cache
+        log::info!(subsystem = "alerts"; "Spawned Eval Facts Task");
+    }
 
-                #[cfg(debug_assertions)] { log::info!("[DEBUG][ALERTS] Alerts backend finished rule eval..."); }
-        }});
+    async fn eval_facts_loop(receiver: Arc<Mutex<Receiver<FactMessage>>>, event_tx : Sender<AlertEvent>, mut shutdown: ShutdownSignal) {
+        let mut receiver = receiver.lock().await;
+        let instance = Self::instance();
+        log::info!(subsystem = "alerts"; "Spawned alert eval facts task");
+        loop {
+            let new_facts = tokio::select! {
+                msg = receiver.recv() => match msg { Some(msg) => msg, None => break },
+                _ = shutdown.requested() => break,
+            };
+            #[cfg(debug_assertions)] { log::info!(subsystem = "alerts"; "Alerts backend received facts..."); }
+
+            // Critical area, locks facts_rules, syslog_rules, rule_names and last_update
+            // Updates the rule set if needed, before evaluating anything
+            Self::instance().update_ruleset(false).await;
+
+            // Critical area, requires locks
+            {
+                let cache = Cache::instance();
+                let facts_rules = instance.facts_rules.read().await;
+                let old_facts = cache.facts.read().await;
+                AlertBackend::eval_rules(&facts_rules, &old_facts, &new_facts, &event_tx).await;
+            } // Release dem locks so I can lock it again for write to update cache
+
+            #[cfg(debug_assertions)] { log::info!(subsystem = "alerts"; "Alerts backend finished rule eval..."); }
+            instance.snapshot_eval_state(false).await;
+        }
 
-        log::info!("[INFO ][ALERTS] Spawned Eval Facts Task");
+        // Keep the latest state for the next run
+        instance.snapshot_eval_state(true).await;
     }
 
     /// Spawns the task that will handle the evaluation of SyslogMessages against the rules
-    pub fn spawn_eval_syslog_task(mut receiver: Receiver<SyslogMessage>, event_tx: Sender<AlertEvent>) {
-        #[allow(clippy::let_underscore_future)]
-        let _ = rocket::tokio::task::spawn(async move {
+    pub fn spawn_eval_syslog_task(receiver: Receiver<SyslogMessage>, event_tx: Sender<AlertEvent>) {
+        let receiver = Arc::new(Mutex::new(receiver));
+        Supervisor::spawn("alert_eval_syslog", Stage::Evaluation, move |shutdown| Self::eval_syslog_loop(receiver.clone(), event_tx.clone(), shutdown));
+
+        log::info!(subsystem = "alerts"; "Spawned Eval Syslog Task");
+    }
+
+    async fn eval_syslog_loop(receiver: Arc<Mutex<Receiver<SyslogMessage>>>, event_tx: Sender<AlertEvent>, mut shutdown: ShutdownSignal) {
+        let mut receiver = receiver.lock().await;
         let instance = Self::instance();
-        log::info!("[INFO ][ALERTS] Spawned syslog eval thread!");
+        log::info!(subsystem = "alerts"; "Spawned syslog eval thread!");
         loop {
-            let message = if let Some(msg) = receiver.recv().await { msg } else { continue; };
-            
+            let message = tokio::select! {
+                msg = receiver.recv() => match msg { Some(msg) => msg, None => break },
+                _ = shutdown.requested() => break,
+            };
+
             let syslog_rules = instance.syslog_rules.read().await;
 
             // To emulate Metrics behavior, dynamically create a "MetricSet" with the given device, into a Metrics
@@ -342,46 +653,68 @@ impl AlertBackend {
             );
 
             AlertBackend::eval_rules(&syslog_rules, &old_facts, &new_facts, &event_tx).await;
-        }});
-
-        log::info!("[INFO ][ALERTS] Spawned Eval Syslog Task");
+        }
     }
 
     /// Spawns the task that will handle the events when they are received
     /// db writes are guaranteed. If the write fails, the event is requeued
     /// If it can't requeue, the event is dropped
     /// ws writes are best effort. If it fails, it just keeps going.
-    pub fn spawn_event_handler(event_tx: Sender<AlertEvent>, mut event_rx: Receiver<AlertEvent>) {
-        #[allow(clippy::let_underscore_future)]
-        let _ = rocket::tokio::task::spawn(async move { 
-            let instance = Self::instance();
+    /// On shutdown, the events already queued are handled before stopping, without requeueing
+    pub fn spawn_event_handler(event_tx: Sender<AlertEvent>, event_rx: Receiver<AlertEvent>) {
+        let event_rx = Arc::new(Mutex::new(event_rx));
+        Supervisor::spawn("alert_events", Stage::Events, move |shutdown| Self::event_handler_loop(event_tx.clone(), event_rx.clone(), shutdown));
+
+        log::info!(subsystem = "alerts"; "Spawned handle events Task");
+    }
+
+    async fn event_handler_loop(event_tx: Sender<AlertEvent>, event_rx: Arc<Mutex<Receiver<AlertEvent>>>, mut shutdown: ShutdownSignal) {
+        let mut event_rx = event_rx.lock().await;
         loop {
-            let mut event = match event_rx.recv().await {
-                Some(e) => e, None => continue
+            let event = tokio::select! {
+                event = event_rx.recv() => match event { Some(e) => e, None => continue },
+                _ = shutdown.requested() => break,
             };
+            Self::handle_event(event, Some(&event_tx)).await;
+        }
 
-            #[cfg(debug_assertions)] {log::info!("[DEBUG][ALERTS] Received alert event!");}
+        let mut drained = 0;
+        while let Ok(event) = event_rx.try_recv() {
+            Self::handle_event(event, None).await;
+            drained += 1;
+        }
+        log::info!(subsystem = "alerts"; "Stopping alert event handler, drained {} queued events", drained);
+    }
 
-            // Write into db
-            let id = crate::model::db::operations::alert_operations::insert_alert(&event, &instance.pool).await;
-            match id {
-                Ok(id) => { event.alert_id = id; event.db_notified = true; },
-                Err(e) => {
-                    log::error!("[ERROR][ALERTS] Failed to write alert to database with e = '{e}'. Requeueing...");
-                    if let Err(e) = event_tx.send(event).await {
-                        let msg = format!("[ERROR][ALERTS] Failed to requeue failed write alert event with e = '{e}'. Event will be skipped!");
-                        log::error!("{}", msg);
-                        TelegramBackend::raw_send_message(msg.as_str()).await;
-                    };
-                    continue;
-                },
-            }
+    /// Stores an event and broadcasts it. If it can't be stored, it's put back in the queue through `requeue_tx`, if any
+    async fn handle_event(mut event: AlertEvent, requeue_tx: Option<&Sender<AlertEvent>>) {
+        let instance = Self::instance();
 
-            // Broadcast into listeners:
-            instance.broadcast(&event).await;
-        }});
+        #[cfg(debug_assertions)] {log::info!(subsystem = "alerts"; "Received alert event!");}
 
-        log::info!("[INFO ][ALERTS] Spawned handle events Task");
+        // Write into db
+        let id = crate::model::db::operations::alert_operations::insert_alert(&event, &instance.pool).await;
+        match id {
+            Ok(id) => { event.alert_id = id; event.db_notified = true; },
+            Err(e) => {
+                Telemetry::postgres_error();
+                let Some(event_tx) = requeue_tx else {
+                    log::error!(subsystem = "alerts"; "Failed to write alert to database with e = '{e}' while shutting down. Event will be skipped!");
+                    return;
+                };
+                log::error!(subsystem = "alerts"; "Failed to write alert to database with e = '{e}'. Requeueing...");
+                Telemetry::instance().alert_requeues.inc();
+                if let Err(e) = event_tx.send(event).await {
+                    let msg = format!("Failed to requeue failed write alert event with e = '{e}'. Event will be skipped!");
+                    log::error!(subsystem = "alerts"; "{}", msg);
+                    TelegramBackend::raw_send_message(msg.as_str()).await;
+                };
+                return;
+            },
+        }
+
+        // Broadcast into listeners:
+        instance.broadcast(&event).await;
     }
 
 
@@ -402,23 +735,39 @@ impl AlertBackend {
             let item = match instance.get_evaluable_item(rule.target_item).await { Some(i) => i, None=> continue };
 
             let triggered = item.eval(rule, old_facts, new_facts).await;
+            Telemetry::instance().rules_evaluated.inc();
 
             // if item trigered, raise an alert for each alerting item
             if let Some(t) = triggered {
-                log::warn!("[WARN ][ALARTS] Alert id {} raised!", rule.rule_id);
-                for (item, which) in t {
-                    match item {
+                for (item, transition, which) in t {
+                    match transition {
+                        AlertTransition::Raised => log::warn!(subsystem = "alerts", rule_id = rule.rule_id; "Alert id {} raised!", rule.rule_id),
+                        AlertTransition::Cleared => log::info!(subsystem = "alerts", rule_id = rule.rule_id; "Alert id {} cleared", rule.rule_id),
+                    }
+                    let (target_id, target, names, current, previous) = match item {
                         crate::alerts::EvaluableItem::Group(_) => {
-                            log::warn!("[WARN ][ALERTS] Alert triggered for group. This behavor is unexpected, as only devices can raise. Skipping...");
+                            log::warn!(subsystem = "alerts"; "Alert triggered for group. This behavor is unexpected, as only devices can raise. Skipping...");
                             continue;
                         },
                         crate::alerts::EvaluableItem::Device(device) => {
-                            let which = which.iter()
-                                .map(|(lmod, lhs, op, rhs, rmod)| format!("[{}{} {} {}{}]", lhs, lmod, op, rhs, rmod)).collect::<Vec<_>>().join(", ");
-
-                            AlertBackend::raise_alert(rule, &device, which, event_tx).await;
+                            let current = new_facts.get(&device.management_hostname).map(|f| f.metrics.clone());
+                            let previous = old_facts.get(&device.management_hostname).map(|f| f.metrics.clone());
+                            (device.device_id, format!("device='{}'", device.device_name), (device.device_name, device.management_hostname), current, previous)
                         },
-                    }
+                        crate::alerts::EvaluableItem::Link(link) => {
+                            let label = instance.get_link_label(link.link_id).await.unwrap_or(link.link_id.to_string());
+                            let (previous, current) = instance.get_link_metrics(link.link_id).await;
+                            (link.link_id, format!("link='{label}'"), (label, format!("link#{}", link.link_id)), current, previous)
+                        },
+                    };
+                    let which = format_raising_values(&which);
+
+                    // Recoveries keep the generic message, templates describe what raised
+                    let context = match (&rule.template, transition) {
+                        (Some(_), AlertTransition::Raised) => Some(TemplateContext::capture(rule, names, which.clone(), current.as_ref(), previous.as_ref()).await),
+                        _ => None,
+                    };
+                    AlertBackend::raise_alert(rule, transition, target_id, &target, which, context, event_tx).await;
                 }
             }
         }
@@ -429,7 +778,7 @@ impl AlertBackend {
     pub async fn update_ruleset(&self, forced: bool) {
         let instance = Self::instance();
 
-        log::info!("[INFO ][ALERTS][LOADS] Atempting to refresh ruleset");
+        log::info!(subsystem = "alerts.loads"; "Atempting to refresh ruleset");
 
         // try to acquire a lock for the last_update lock guard
         let last_update_lock = instance.try_claim_update(forced).await;
@@ -439,17 +788,16 @@ impl AlertBackend {
         let _last_update_lock = match last_update_lock {
             Some(l) => l,
             None => {
-                log::info!("[INFO ][ALERTS][LOADS] failed to acquire lock on updates, forced={forced}, Update isn't due or it's already being updated");
+                log::info!(subsystem = "alerts.loads"; "failed to acquire lock on updates, forced={forced}, Update isn't due or it's already being updated");
                 return;
             }
         };
         
-        log::info!("[INFO ][ALERTS][LOADS] Refreshing ruleset, forced={forced}");
+        log::info!(subsystem = "alerts.loads"; "Refreshing ruleset, forced={forced}");
         let rules = match alert_operations::get_alert_rules(&self.pool).await {
             Ok(r) => r,
             Err(_) => {
-                log::error!("[ERROR][ALERTS][LOADS] Failed to load alert rules!");
-                eprintln!("[ERROR][ALERTS][LOADS] Failed to load alert rules!");
+                log::error!(subsystem = "alerts.loads"; "Failed to load alert rules!");
                 return;
             }
         };
@@ -467,10 +815,27 @@ impl AlertBackend {
             }
         }
 
-        log::info!("[INFO ][ALERTS][LOADS] Loaded {} fact rules, and {} syslog rules", facts_rules.len(), syslog_rules.len());
-        println!("[INFO ][ALERTS][LOADS] Loaded {} fact rules, and {} syslog rules", facts_rules.len(), syslog_rules.len());
+        log::info!(subsystem = "alerts.loads"; "Loaded {} fact rules, and {} syslog rules", facts_rules.len(), syslog_rules.len());
+
+        // Routes are edited in the same commits as rules
+        match alert_operations::get_alert_routes(&self.pool).await {
+            Ok(routes) => {
+                log::info!(subsystem = "alerts.loads"; "Loaded {} alert routes", routes.len());
+                *self.routes.write().await = routes;
+            },
+            Err(_) => log::error!(subsystem = "alerts.loads"; "Failed to load alert routes! Keeping the previous ones"),
+        }
+        match alert_operations::get_oncall_schedules(&self.pool).await {
+            Ok(schedules) => {
+                log::info!(subsystem = "alerts.loads"; "Loaded {} on-call schedules", schedules.len());
+                *self.oncall_schedules.write().await = schedules;
+            },
+            Err(_) => log::error!(subsystem = "alerts.loads"; "Failed to load on-call schedules! Keeping the previous ones"),
+        }
 
+        let previous = self.rule_fingerprints(|_| true).await;
         Self::replace_rules(facts_rules, syslog_rules).await;
+        self.prune_eval_state(&previous).await;
     }
 
     /// Loads multiple rules into the rule set
@@ -485,6 +850,13 @@ impl AlertBackend {
         w.append(&mut syslog_rules);
     }
 
+    /// Every loaded rule, facts and syslog ones alike
+    pub async fn get_rules(&self) -> Vec<AlertRule> {
+        let facts = self.facts_rules.read().await;
+        let syslog = self.syslog_rules.read().await;
+        facts.iter().chain(syslog.iter()).cloned().collect()
+    }
+
     pub async fn get_rules_as_json() -> serde_json::Value {
         let instance = AlertBackend::instance();
         instance.update_ruleset(true).await;
@@ -508,27 +880,39 @@ impl AlertBackend {
     /// Calls to raise an alert. The alert is placed into the [sender] queue, to be written to the database
     /// db writes are guaranteed. If the write fails, the event is requeued
     /// ws writes are best effort. If it fails, it just keeps going.
-    async fn raise_alert(rule: &AlertRule, device: &Device, value: String, sender: &Sender<AlertEvent> ) {
+    /// Sends the alert event for the target. With a template context, its message is the rule's template rendered as plain text
+    async fn raise_alert(rule: &AlertRule, transition: AlertTransition, target_id: AlertTargetId, target: &str, value: String, context: Option<TemplateContext>, sender: &Sender<AlertEvent> ) {
+        let recovery = transition == AlertTransition::Cleared;
+        let template = rule.template.clone().zip(context);
+        let message = match (&template, transition) {
+            (Some((template, context)), _) => template.render(context, TemplateFormat::Plain).chars().take(MAX_MESSAGE_LEN).collect(),
+            (None, AlertTransition::Raised) => format!("'{}' Triggered for {}", rule.name, target),
+            (None, AlertTransition::Cleared) => format!("'{}' Cleared for {}", rule.name, target),
+        };
         let event = AlertEvent {
             alert_id: -1,
             alert_time: Some(Utc::now()),
             ack_time: None,
-            requires_ack: rule.requires_ack,
+            // Recoveries are informative, there is nothing to acknowledge
+            requires_ack: rule.requires_ack && !recovery,
             severity: rule.severity,
-            message: format!("'{}' Triggered for device='{}'", rule.name, device.device_name),
-            target_id: device.device_id,
+            message,
+            target_id,
             ws_notified: false,
             db_notified: false,
             acked: false,
             rule_id: Some(rule.rule_id),
             ack_actor: None,
             value,
+            recovery,
+            template,
+            recipients: None,
         };
 
         match sender.send(event).await {
-            Ok(_) => (),
+            Ok(_) => Telemetry::instance().alerts_raised.inc(),
             Err(e) => {
-                log::error!("[ERROR][ALERTS] Failed to send raised alert into alert handler! e='{e}'");
+                log::error!(subsystem = "alerts"; "Failed to send raised alert into alert handler! e='{e}'");
             }
         }
     }
@@ -551,7 +935,7 @@ impl AlertBackend {
     /// Returns None if an update is not needed (someone else updated recently).
     // TODO: Force caller to update last_update, in case the caller fails out, it doens't register as an "update"
     pub async fn try_claim_update(&self, forced: bool) -> Option<RwLockWriteGuard<'_, EpochSeconds>> {
-        let interval_secs: EpochSeconds = Config::instance().get_value_opt ("backend/model/cache/rule_set_cache_invalidation_s", "/").unwrap_or_default().as_u64().unwrap_or(3600);
+        let interval_secs: EpochSeconds = Config::instance().settings().backend.model.cache.rule_set_cache_invalidation_s;
 
         // fast read-only check
         let now: EpochSeconds = SystemTime::now()
@@ -563,7 +947,7 @@ impl AlertBackend {
         let last: EpochSeconds = *self.last_update.read().await;
         if now.saturating_sub(last) < interval_secs && !forced {
             #[cfg(debug_assertions)] {
-                log::info!("[DEBUG][ALERTS][LOADS] Failed to claim update lock, update due? = {}, forced = {}"
+                log::info!(subsystem = "alerts.loads"; "Failed to claim update lock, update due? = {}, forced = {}"
                 , now.saturating_sub(last) >= interval_secs, forced);
             }
             return None;
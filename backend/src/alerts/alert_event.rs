@@ -30,7 +30,9 @@ impl AlertEvent {
             rule_id: Some(rule_id),
             ack_actor,
             value,
-
+            recovery: false,
+            template: None,
+            recipients: None,
         }
     }
 
@@ -59,6 +61,7 @@ impl AlertEvent {
         map.insert("target-id".into(), serde_json::json!(self.target_id));
         map.insert("acked".into(), serde_json::json!(self.acked));
         map.insert("value".into(), serde_json::json!(self.value));
+        map.insert("recovery".into(), serde_json::json!(self.recovery));
 
         serde_json::Value::Object(map)
     }
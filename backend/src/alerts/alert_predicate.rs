@@ -21,7 +21,7 @@ impl AlertPredicate {
                 (lmod, accessor_left.access(dataset_left), op, accesor_right.access(dataset_right), rmod)
             },
         };
-        #[cfg(debug_assertions)] { log::info!("[DEBUG][ALERTS][EVAL] Evaluating predicate with actual = {:?}{} {:?} {:?}{}", left, lmod, op, right, rmod); }
+        #[cfg(debug_assertions)] { log::info!(subsystem = "alerts.eval"; "Evaluating predicate with actual = {:?}{} {:?} {:?}{}", left, lmod, op, right, rmod); }
 
         let (left, right) = if let Some(left) = lmod.eval(left) && let Some(right) = rmod.eval(right) {
             (left, right)
@@ -33,6 +33,15 @@ impl AlertPredicate {
         op.eval(&left, &right)
     }
 
+    /// Accessors of the predicate, one per side that reads from the dataset
+    pub fn accessors(&self) -> Vec<&Accessor> {
+        match self {
+            AlertPredicate::LeftConst (_, _, _, accessor, _) => vec![accessor],
+            AlertPredicate::RightConst(_, accessor, _, _, _) => vec![accessor],
+            AlertPredicate::Variable  (_, left, _, right, _) => vec![left, right],
+        }
+    }
+
     pub fn get_op(&self) -> AlertPredicateOperation {
         match self {
             AlertPredicate::LeftConst (_, _, op, _, _) => *op,
@@ -181,7 +190,7 @@ impl<'de> Deserialize<'de> for AlertPredicate {
                 ))
             },
             (false, false) => {
-                log::error!("[ERROR][ALERTS] Rule can't have both sides as constants");
+                log::error!(subsystem = "alerts"; "Rule can't have both sides as constants");
                 Err(serde::de::Error::custom("[ERROR][ALERTS] Rule can't have both sides as constants"))
 
             }
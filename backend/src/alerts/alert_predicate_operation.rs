@@ -54,14 +54,14 @@ impl AlertPredicateOperation {
 
                         MetricValue::Array(_) 
                         | MetricValue::Null() => {
-                            log::error!("[ERROR][RULES] Failed to evaluate contains predicate operation. Right was not 'string', 'number' 'integer' or 'boolean'");
+                            log::error!(subsystem = "rules"; "Failed to evaluate contains predicate operation. Right was not 'string', 'number' 'integer' or 'boolean'");
                             false
                         }
                     }
                 }
                 MetricValue::Array(l_arr) => l_arr.contains(right),
                 _ => {
-                    log::error!("[ERROR][RULES] Failed to evaluate contains predicate operation. Left was not 'string' or 'array'");
+                    log::error!(subsystem = "rules"; "Failed to evaluate contains predicate operation. Left was not 'string' or 'array'");
                     false
                 },
             },
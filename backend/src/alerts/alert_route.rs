@@ -0,0 +1,77 @@
+use std::collections::HashSet;
+
+use chrono::{DateTime, Datelike, Weekday};
+use chrono_tz::Tz;
+
+use crate::alerts::{AlertEvent, AlertRecipients, AlertRoute, RouteDestination, RouteMatch, RouteSchedule};
+use crate::types::GroupId;
+
+impl RouteSchedule {
+    /// Whether `now` falls within the window
+    pub fn contains(&self, now: DateTime<Tz>) -> bool {
+        let time = now.time();
+        let listed = |day: Weekday| self.days.is_empty() || self.days.contains(&day);
+
+        if self.from <= self.to {
+            listed(now.weekday()) && self.from <= time && time < self.to
+        } else {
+            // Spans midnight. Either it started today, or it started on the day before
+            (listed(now.weekday()) && time >= self.from) || (listed(now.weekday().pred()) && time < self.to)
+        }
+    }
+}
+
+impl RouteMatch {
+    /// `target_groups` are the groups the target of the event is a member of, nested groups included
+    pub fn matches(&self, event: &AlertEvent, target_groups: &HashSet<GroupId>, now: DateTime<Tz>) -> bool {
+        if self.severities.as_ref().is_some_and(|s| !s.contains(&event.severity)) {
+            return false;
+        }
+        if self.rules.as_ref().is_some_and(|r| !event.rule_id.is_some_and(|id| r.contains(&id))) {
+            return false;
+        }
+        if self.targets.as_ref().is_some_and(|t| !t.contains(&event.target_id) && t.is_disjoint(target_groups)) {
+            return false;
+        }
+        self.schedule.as_ref().is_none_or(|s| s.contains(now))
+    }
+}
+
+impl AlertRecipients {
+    fn add(&mut self, destination: &RouteDestination) {
+        match destination {
+            RouteDestination::Telegram { chats } => self.telegram_chats.extend(chats),
+            RouteDestination::Webhook { url } => {
+                if !self.webhooks.contains(url) {
+                    self.webhooks.push(url.clone());
+                }
+            },
+            RouteDestination::Email { recipients } => {
+                for recipient in recipients {
+                    if !self.emails.contains(recipient) {
+                        self.emails.push(recipient.clone());
+                    }
+                }
+            },
+            RouteDestination::OnCall { schedule } => {
+                if !self.oncall.contains(schedule) {
+                    self.oncall.push(schedule.clone());
+                }
+            },
+        }
+    }
+}
+
+impl AlertRoute {
+    /// Merges the destinations of every route that matches the event. None if no route matches
+    pub fn resolve(routes: &[AlertRoute], event: &AlertEvent, target_groups: &HashSet<GroupId>, now: DateTime<Tz>) -> Option<AlertRecipients> {
+        let mut matched = routes.iter().filter(|route| route.matcher.matches(event, target_groups, now)).peekable();
+        matched.peek()?;
+
+        let mut recipients = AlertRecipients::default();
+        for route in matched {
+            route.destinations.iter().for_each(|destination| recipients.add(destination));
+        }
+        Some(recipients)
+    }
+}
@@ -1,8 +1,35 @@
 
+use std::collections::HashSet;
+
 use crate::types::{MetricSet, MetricValue};
-use crate::alerts::{AlertPredicateOperation, AlertReduceLogic, AlertRule, AlertRuleKind, OperandModifier};
+use crate::alerts::{AlertClearCondition, AlertPredicate, AlertPredicateOperation, AlertReduceLogic, AlertRule, AlertRuleKind, EvalResult, OperandModifier, PredicatePreview};
 
 impl AlertRule {
+    /// The parts of the rule that decide when it raises. Evaluation state kept for the rule only holds while they don't change
+    pub fn fingerprint(&self) -> serde_json::Value {
+        serde_json::json!({
+            "target": self.target_item,
+            "reduce-logic": self.reduce_logic,
+            "predicates": self.predicates,
+            "data-source": self.data_source,
+            "rule-type": self.rule_kind,
+            "clear": self.clear,
+        })
+    }
+
+    /// Names of every metric the rule reads, from its predicates, its clear condition and its message template
+    pub fn metric_names(&self) -> HashSet<&str> {
+        let clear = self.clear.iter().flat_map(|c| c.predicates.iter());
+        let mut names: HashSet<&str> = self.predicates.iter().chain(clear)
+            .flat_map(AlertPredicate::accessors)
+            .map(|a| a.key.as_str())
+            .collect();
+        if let Some(template) = &self.template {
+            names.extend(template.metric_names());
+        }
+        names
+    }
+
     /// Evaluates an alert rule that compares the most recent, with the previous metric set, to trigger on value changes
     /// Typically left is previous, right is current
     pub fn eval_delta(&self, dataset_left: &MetricSet, dataset_right: &MetricSet) -> bool {
@@ -10,7 +37,7 @@ impl AlertRule {
             AlertReduceLogic::All => self.predicates.iter().all(|p| p.eval(dataset_left, dataset_right)),
             AlertReduceLogic::Any => self.predicates.iter().any(|p| p.eval(dataset_left, dataset_right)),
             AlertReduceLogic::Unknown => {
-                log::warn!("[WARN ][ALERTS] Trying to eval rule with Unknown reduce logic. Skipping...");
+                log::warn!(subsystem = "alerts"; "Trying to eval rule with Unknown reduce logic. Skipping...");
                 false
             },
         }
@@ -22,12 +49,27 @@ impl AlertRule {
             AlertReduceLogic::All => self.predicates.iter().all(|p| p.eval(dataset, dataset)),
             AlertReduceLogic::Any => self.predicates.iter().any(|p| p.eval(dataset, dataset)),
             AlertReduceLogic::Unknown => {
-                log::warn!("[WARN ][ALERTS] Trying to eval rule with Unknown reduce logic. Skipping...");
+                log::warn!(subsystem = "alerts"; "Trying to eval rule with Unknown reduce logic. Skipping...");
                 false
             },
         }
     }
 
+    /// Evaluates every predicate on its own, without any state, returning whether the rule as a whole evaluated to true.
+    /// Typically left is previous, right is current
+    pub fn preview(&self, dataset_left: &MetricSet, dataset_right: &MetricSet) -> (bool, Vec<PredicatePreview>) {
+        let predicates = self.predicates.iter()
+            .map(|p| PredicatePreview {
+                predicate: p.to_string(),
+                left: p.eval_left(dataset_left),
+                right: p.eval_right(dataset_right),
+                result: p.eval(dataset_left, dataset_right),
+            })
+            .collect();
+
+        (self.eval_delta(dataset_left, dataset_right), predicates)
+    }
+
     pub fn raising_values<'a >(&'a self, dataset_left: &'a MetricSet, dataset_right: &'a MetricSet) 
         -> Vec<(OperandModifier, MetricValue, AlertPredicateOperation, MetricValue, OperandModifier)> {
         // we know a predicate has raised. We need to know which one(s), and with which values
@@ -74,4 +116,32 @@ impl AlertRule {
         }
         result
     }
-}
\ No newline at end of file
+}
+
+impl AlertClearCondition {
+    /// Evaluates the clear condition against the most recent MetricSet
+    pub fn eval(&self, dataset: &MetricSet) -> bool {
+        match self.reduce_logic {
+            AlertReduceLogic::All => self.predicates.iter().all(|p| p.eval(dataset, dataset)),
+            AlertReduceLogic::Any => self.predicates.iter().any(|p| p.eval(dataset, dataset)),
+            AlertReduceLogic::Unknown => {
+                log::warn!(subsystem = "alerts"; "Trying to eval clear condition with Unknown reduce logic. Skipping...");
+                false
+            },
+        }
+    }
+
+    /// Values of the predicates that held when the condition cleared
+    pub fn clearing_values(&self, dataset: &MetricSet) -> Vec<EvalResult> {
+        self.predicates.iter()
+            .filter(|predicate| predicate.eval(dataset, dataset))
+            .filter_map(|predicate| Some((
+                predicate.get_lmod(),
+                predicate.eval_left(dataset)?,
+                predicate.get_op(),
+                predicate.eval_right(dataset)?,
+                predicate.get_rmod(),
+            )))
+            .collect()
+    }
+}
@@ -0,0 +1,194 @@
+use std::collections::HashSet;
+
+use crate::alerts::{AlertRule, AlertRuleKind, EvaluableItem, MessageTemplate, TemplateContext, TemplateFormat};
+use crate::model::cache::Cache;
+use crate::types::{MetricSet, MetricValue};
+
+/// Rendered in place of values that aren't available, such as the previous value of a Simple rule
+const MISSING: &str = "-";
+
+/// Characters Telegram requires to be escaped anywhere in a MarkdownV2 message
+const MARKDOWN_V2_RESERVED: &[char] = &['_', '*', '[', ']', '(', ')', '~', '`', '>', '#', '+', '-', '=', '|', '{', '}', '.', '!', '\\'];
+
+#[derive(Debug, PartialEq)]
+enum Field<'a> {
+    Device,
+    Hostname,
+    Group,
+    Rule,
+    Severity,
+    Value,
+    Duration,
+    Metric(&'a str),
+    Previous(&'a str),
+}
+
+#[derive(Debug, PartialEq)]
+enum Segment<'a> {
+    Text(String),
+    Field(Field<'a>),
+}
+
+impl<'a> Field<'a> {
+    fn parse(name: &'a str) -> Result<Self, String> {
+        let field = match name.trim() {
+            "device" => Field::Device,
+            "hostname" => Field::Hostname,
+            "group" => Field::Group,
+            "rule" => Field::Rule,
+            "severity" => Field::Severity,
+            "value" => Field::Value,
+            "duration" => Field::Duration,
+            name => match name.split_once('.') {
+                Some(("metric", metric)) if !metric.is_empty() => Field::Metric(metric),
+                Some(("previous", metric)) if !metric.is_empty() => Field::Previous(metric),
+                _ => return Err(format!("Unknown field '{{{name}}}'")),
+            },
+        };
+        Ok(field)
+    }
+}
+
+impl MessageTemplate {
+    fn parse(&self) -> Result<Vec<Segment<'_>>, String> {
+        let mut segments = Vec::new();
+        let mut text = String::new();
+        let mut chars = self.0.char_indices().peekable();
+
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '{' if chars.next_if(|(_, c)| *c == '{').is_some() => text.push('{'),
+                '}' if chars.next_if(|(_, c)| *c == '}').is_some() => text.push('}'),
+                '{' => {
+                    let end = self.0[i..].find('}').map(|end| i + end).ok_or(format!("Unclosed '{{' at position {i}"))?;
+                    let field = Field::parse(&self.0[i + 1..end])?;
+                    while chars.next_if(|(j, _)| *j <= end).is_some() {}
+
+                    if !text.is_empty() {
+                        segments.push(Segment::Text(std::mem::take(&mut text)));
+                    }
+                    segments.push(Segment::Field(field));
+                },
+                '}' => return Err(format!("Unmatched '}}' at position {i}, write '}}}}' for a literal one")),
+                c => text.push(c),
+            }
+        }
+
+        if !text.is_empty() {
+            segments.push(Segment::Text(text));
+        }
+        Ok(segments)
+    }
+
+    /// Checks the template only uses known fields, and that its braces are balanced
+    pub fn validate(&self) -> Result<(), String> {
+        self.parse().map(|_| ())
+    }
+
+    /// Names of the metrics the template references, current or previous
+    pub fn metric_names(&self) -> HashSet<&str> {
+        self.parse().unwrap_or_default().into_iter()
+            .filter_map(|segment| match segment {
+                Segment::Field(Field::Metric(name) | Field::Previous(name)) => Some(name),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Renders the template with the given values, escaped for `format`.
+    /// Templates are validated when committed, so an invalid one is only rendered as is, escaped
+    pub fn render(&self, context: &TemplateContext, format: TemplateFormat) -> String {
+        let segments = match self.parse() {
+            Ok(segments) => segments,
+            Err(e) => {
+                log::warn!(subsystem = "alerts.template"; "Rendering invalid message template as is, e='{e}'");
+                return format.escape(&self.0);
+            },
+        };
+
+        let metric = |metrics: &MetricSet, name: &str| metrics.get(name).map(MetricValue::to_string).unwrap_or(MISSING.to_string());
+        let rendered: String = segments.into_iter()
+            .map(|segment| match segment {
+                Segment::Text(text) => text,
+                Segment::Field(Field::Device) => context.device.clone(),
+                Segment::Field(Field::Hostname) => context.hostname.clone(),
+                Segment::Field(Field::Group) => context.group.clone().unwrap_or(MISSING.to_string()),
+                Segment::Field(Field::Rule) => context.rule.clone(),
+                Segment::Field(Field::Severity) => context.severity.to_string(),
+                Segment::Field(Field::Value) => context.value.clone(),
+                Segment::Field(Field::Duration) => context.duration_s.map(|s| format!("{s}s")).unwrap_or(MISSING.to_string()),
+                Segment::Field(Field::Metric(name)) => metric(&context.metrics, name),
+                Segment::Field(Field::Previous(name)) => metric(&context.previous, name),
+            })
+            .collect();
+
+        format.escape(&rendered)
+    }
+}
+
+impl TemplateFormat {
+    pub fn escape(&self, text: &str) -> String {
+        match self {
+            TemplateFormat::Plain => text.to_string(),
+            TemplateFormat::MarkdownV2 => text.chars()
+                .fold(String::with_capacity(text.len()), |mut escaped, c| {
+                    if MARKDOWN_V2_RESERVED.contains(&c) {
+                        escaped.push('\\');
+                    }
+                    escaped.push(c);
+                    escaped
+                }),
+            TemplateFormat::Html => text.chars()
+                .fold(String::with_capacity(text.len()), |mut escaped, c| {
+                    match c {
+                        '&' => escaped.push_str("&amp;"),
+                        '<' => escaped.push_str("&lt;"),
+                        '>' => escaped.push_str("&gt;"),
+                        '"' => escaped.push_str("&quot;"),
+                        c => escaped.push(c),
+                    }
+                    escaped
+                }),
+        }
+    }
+}
+
+impl TemplateContext {
+    /// Captures the values the rule's template references. `target` is the (name, hostname) of the device,
+    /// or (label, `link#id`) of the link that raised. The datasets are the ones the rule was evaluated with
+    pub async fn capture(rule: &AlertRule, target: (String, String), value: String, current: Option<&MetricSet>, previous: Option<&MetricSet>) -> TemplateContext {
+        let names = rule.template.as_ref().map(MessageTemplate::metric_names).unwrap_or_default();
+        let select = |metrics: Option<&MetricSet>| -> MetricSet {
+            metrics.map(|metrics| metrics.iter()
+                .filter(|(name, _)| names.contains(name.as_str()))
+                .map(|(name, value)| (name.clone(), value.clone()))
+                .collect())
+            .unwrap_or_default()
+        };
+
+        let group = match Cache::instance().get_evaluable_item(rule.target_item).await {
+            Some(EvaluableItem::Group(group)) => Some(group.name),
+            _ => None,
+        };
+        let duration_s = match rule.rule_kind {
+            AlertRuleKind::Sustained { seconds } => Some(seconds),
+            _ => None,
+        };
+        let previous = match rule.rule_kind {
+            AlertRuleKind::Delta => select(previous),
+            _ => MetricSet::new(),
+        };
+
+        TemplateContext {
+            device: target.0,
+            hostname: target.1,
+            group,
+            rule: rule.name.clone(),
+            severity: rule.severity,
+            value,
+            duration_s,
+            metrics: select(current),
+            previous,
+        }
+    }
+}
@@ -0,0 +1,247 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::Serialize;
+use sqlx::types::chrono::{DateTime, Utc};
+
+use crate::alerts::{AlertDataSource, AlertRule, AlertTransition, EvaluableItem, SustainedTracker, TemplateContext, TemplateFormat, format_raising_values};
+use crate::config::Config;
+use crate::model::cache::Cache;
+use crate::model::data::device_state::DeviceStatus;
+use crate::model::db::operations::{influx_operations, syslog_operations};
+use crate::model::facts::fact_gathering_backend::{DeviceFacts, FactMessage};
+use crate::types::{AlertTargetId, EpochSeconds, ExposedFields, MetricValue, Metrics};
+
+type E = (String, i16);
+
+/// An alert event a rule would have raised, had it been enabled at the time
+#[derive(Debug, Clone, Serialize)]
+pub struct BacktestEvent {
+    #[serde(rename = "alert-time", with = "chrono::serde::ts_seconds")]
+    pub alert_time: DateTime<Utc>,
+
+    #[serde(rename = "target-id")]
+    pub target_id: AlertTargetId,
+
+    pub message: String,
+
+    /// String representation of the value that raised
+    pub value: String,
+
+    /// Whether the event marks the clear condition of the rule being met
+    pub recovery: bool,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct BacktestReport {
+    pub events: Vec<BacktestEvent>,
+
+    /// Number of datasets replayed through the rule
+    pub evaluations: usize,
+
+    /// Whether the replay stopped before the end of the range, as a limit was reached
+    pub truncated: bool,
+}
+
+/// Replays the historical data between `start` and `end` through the rule, as if it had been enabled back then.
+/// Facts come from the Influx `analytics` bucket, and syslog messages from Postgres, depending on the rule data source.
+/// Nothing is written, and the state of live Sustained rules is left untouched
+pub async fn backtest(rule: &AlertRule, start: DateTime<Utc>, end: DateTime<Utc>, limit: usize, pool: &sqlx::PgPool, influx_client: &influxdb2::Client)
+    -> Result<BacktestReport, E> {
+    if start >= end {
+        return Err(("'start' must be before 'end'".to_string(), 400));
+    }
+
+    let max_range_s = Config::instance().settings().backend.model.alerts.backtest_max_range_s;
+    if (end - start).num_seconds() > max_range_s {
+        return Err((format!("Time range can't be longer than {max_range_s} seconds"), 400));
+    }
+
+    let cache = Cache::instance();
+    let item = cache.get_evaluable_item(rule.target_item).await
+        .ok_or((format!("Target with id={} does not exist", rule.target_item), 404))?;
+
+    // Devices whose data is needed to evaluate the target
+    let devices = match &item {
+        EvaluableItem::Device(device) => vec![device.device_id],
+        EvaluableItem::Group(group) => cache.get_group_device_ids(group.group_id).await.unwrap_or_default().into_iter().collect(),
+        // Link state is derived live from interface counters, and isn't stored anywhere
+        EvaluableItem::Link(_) => return Err(("Rules targeting links can't be backtested".to_string(), 400)),
+    };
+
+    let mut hostnames = HashMap::new();
+    for id in devices {
+        if let Some(hostname) = cache.get_device_hostname(id).await {
+            hostnames.insert(id, hostname);
+        }
+    }
+
+    match rule.data_source {
+        AlertDataSource::Facts => {
+            let max_rows = Config::instance().settings().backend.model.alerts.backtest_max_fact_rows;
+            let history = influx_operations::get_facts_history(influx_client, &hostnames, &rule.metric_names(), start.timestamp(), end.timestamp(), max_rows).await?;
+            Ok(replay(rule, item, history, true, limit).await)
+        },
+
+        AlertDataSource::Syslog => {
+            let max_rows = Config::instance().settings().backend.model.alerts.backtest_max_syslog_rows;
+            let hosts: Vec<String> = hostnames.into_values().collect();
+            let rows = syslog_operations::get_rows_between(start, end, &hosts, max_rows, pool).await
+                .map_err(|e| (format!("Could not read syslog messages, error = '{e}'"), 500))?;
+            let rows_truncated = rows.len() as i64 >= max_rows;
+
+            // Each message is a dataset on its own, same as when they're evaluated live
+            let history = rows.into_iter().filter_map(|message| {
+                let time = message.received_at?.timestamp();
+                let mut metrics = Metrics::new();
+                metrics.entry(message.source?).or_default().insert("syslog_message".to_string(), MetricValue::String(message.msg));
+                Some((time, metrics))
+            });
+
+            let mut report = replay(rule, item, history, false, limit).await;
+            report.truncated |= rows_truncated;
+            Ok(report)
+        },
+    }
+}
+
+/// Evaluates the rule against each dataset in order, through the same logic as live evaluation.
+/// Sustained rules use the time of each dataset as the current time.
+/// If `incremental` is set, Delta rules compare each device against its previous dataset,
+/// otherwise every dataset is evaluated on its own
+pub async fn replay(rule: &AlertRule, item: EvaluableItem, history: impl IntoIterator<Item = (i64, Metrics)>, incremental: bool, limit: usize) -> BacktestReport {
+    let mut report = BacktestReport::default();
+    let mut sustained_records = HashMap::new();
+    let mut raised = HashSet::new();
+    let mut previous = FactMessage::new();
+
+    for (time, metrics) in history {
+        let alert_time = match DateTime::from_timestamp(time, 0) { Some(t) => t, None => continue };
+        let current: FactMessage = metrics.into_iter()
+            .map(|(hostname, metrics)| (hostname, DeviceFacts { metrics, status: DeviceStatus::empty(), exposed_fields: ExposedFields::new() }))
+            .collect();
+
+        let mut tracker = SustainedTracker::Replay { now: time.max(0) as EpochSeconds, records: &mut sustained_records, raised: &mut raised };
+        let triggered = item.clone().eval_with(rule, &previous, &current, &mut tracker).await;
+        report.evaluations += 1;
+
+        for (item, transition, which) in triggered.unwrap_or_default() {
+            let device = match item {
+                EvaluableItem::Device(device) => device,
+                EvaluableItem::Group(_) | EvaluableItem::Link(_) => continue,
+            };
+            let (target_id, target) = (device.device_id, format!("device='{}'", device.device_name));
+
+            if report.events.len() >= limit {
+                report.truncated = true;
+                return report;
+            }
+            let recovery = transition == AlertTransition::Cleared;
+            let value = format_raising_values(&which);
+            let message = match (&rule.template, transition) {
+                (Some(template), AlertTransition::Raised) => {
+                    let metrics = |facts: &FactMessage| facts.get(&device.management_hostname).map(|f| f.metrics.clone());
+                    let names = (device.device_name.clone(), device.management_hostname.clone());
+                    let context = TemplateContext::capture(rule, names, value.clone(), metrics(&current).as_ref(), metrics(&previous).as_ref()).await;
+                    template.render(&context, TemplateFormat::Plain)
+                },
+                (_, AlertTransition::Raised) => format!("'{}' Triggered for {}", rule.name, target),
+                (_, AlertTransition::Cleared) => format!("'{}' Cleared for {}", rule.name, target),
+            };
+            report.events.push(BacktestEvent {
+                alert_time,
+                target_id,
+                message,
+                value,
+                recovery,
+            });
+        }
+
+        if incremental {
+            previous.extend(current);
+        }
+    }
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::data::device::Device;
+
+    #[tokio::test]
+    async fn replay_sustained_and_delta() {
+        let device = Device::new(1, "router".to_string(), 0.0, 0.0, "10.0.0.1".to_string(), Default::default());
+        let history = |values: &[(i64, i64)]| values.iter()
+            .map(|(time, rtt)| {
+                let mut metrics = Metrics::new();
+                metrics.entry("10.0.0.1".to_string()).or_default().insert("icmp_rtt".to_string(), MetricValue::Integer(*rtt));
+                (*time, metrics)
+            })
+            .collect::<Vec<_>>();
+        let rule = |kind: serde_json::Value, right: serde_json::Value| -> AlertRule {
+            serde_json::from_value(serde_json::json!({
+                "id": 1, "name": "rtt", "severity": "warning", "target": 1, "reduce-logic": "all", "data-source": "facts",
+                "rule-type": kind,
+                "predicates": [{"left": "&icmp_rtt", "op": "more_than", "right": right}]
+            })).expect("Rule should be valid")
+        };
+
+        // Raises once it's been true for 60s, then starts counting again. The drop at t=150 resets it
+        let sustained = rule(serde_json::json!({"sustained": {"seconds": 60}}), serde_json::json!(100));
+        let data = history(&[(0, 200), (30, 200), (60, 200), (90, 200), (150, 50), (180, 200), (240, 200)]);
+        let report = replay(&sustained, EvaluableItem::Device(device.clone()), data, true, 10).await;
+        let times: Vec<i64> = report.events.iter().map(|e| e.alert_time.timestamp()).collect();
+        assert_eq!(times, vec![60, 240]);
+        assert_eq!(report.evaluations, 7);
+
+        // Raises whenever the value dropped since the previous dataset
+        let delta = rule(serde_json::json!("delta"), serde_json::json!("&icmp_rtt"));
+        let data = history(&[(0, 10), (30, 5), (60, 20), (90, 5), (120, 30)]);
+        let report = replay(&delta, EvaluableItem::Device(device.clone()), data.clone(), true, 10).await;
+        let times: Vec<i64> = report.events.iter().map(|e| e.alert_time.timestamp()).collect();
+        assert_eq!(times, vec![30, 90]);
+
+        let report = replay(&delta, EvaluableItem::Device(device), data, true, 1).await;
+        assert_eq!(report.events.len(), 1);
+        assert!(report.truncated);
+    }
+
+    #[tokio::test]
+    async fn replay_clear_condition() {
+        let device = Device::new(1, "router".to_string(), 0.0, 0.0, "10.0.0.1".to_string(), Default::default());
+        let data: Vec<(i64, Metrics)> = [(0, 90), (30, 85), (60, 70), (90, 55), (120, 75), (150, 95)].iter()
+            .map(|(time, rtt)| {
+                let mut metrics = Metrics::new();
+                metrics.entry("10.0.0.1".to_string()).or_default().insert("icmp_rtt".to_string(), MetricValue::Integer(*rtt));
+                (*time, metrics)
+            })
+            .collect();
+        let rule: AlertRule = serde_json::from_value(serde_json::json!({
+            "id": 1, "name": "rtt", "severity": "warning", "target": 1, "reduce-logic": "all", "data-source": "facts",
+            "rule-type": "simple",
+            "predicates": [{"left": "&icmp_rtt", "op": "more_than", "right": 80}],
+            "clear": {"reduce-logic": "all", "predicates": [{"left": "&icmp_rtt", "op": "less_than", "right": 60}]}
+        })).expect("Rule should be valid");
+
+        // Raises once above 80, stays raised in between, clears below 60, and can raise again
+        let report = replay(&rule, EvaluableItem::Device(device), data, true, 10).await;
+        let events: Vec<(i64, bool)> = report.events.iter().map(|e| (e.alert_time.timestamp(), e.recovery)).collect();
+        assert_eq!(events, vec![(0, false), (90, true), (150, false)]);
+        assert_eq!(report.events[1].message, "'rtt' Cleared for device='router'");
+    }
+
+    #[test]
+    fn fetched_fact_names() {
+        let rule: AlertRule = serde_json::from_value(serde_json::json!({
+            "id": 1, "name": "rtt", "severity": "warning", "target": 1, "reduce-logic": "all", "data-source": "facts",
+            "rule-type": "delta",
+            "predicates": [{"left": "&icmp_rtt", "op": "more_than", "right": "&icmp_rtt_max"}],
+            "clear": {"reduce-logic": "all", "predicates": [{"left": "&icmp_status", "op": "equal", "right": "Reachable"}]},
+            "message-template": "RTT of {device} is {metric.icmp_rtt}ms, loss was {previous.icmp_loss}"
+        })).expect("Rule should be valid");
+
+        // Only the facts the rule reads are fetched
+        assert_eq!(rule.metric_names(), HashSet::from(["icmp_rtt", "icmp_rtt_max", "icmp_status", "icmp_loss"]));
+    }
+}
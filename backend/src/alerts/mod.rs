@@ -1,16 +1,15 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 
 use sqlx::types::chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::{FromRow, Type};
 
-use crate::alerts::alert_backend::AlertBackend;
 use crate::misc::{ts_to_datetime_utc, opt_ts_to_datetime_utc};
 use crate::model::facts::{fact_gathering_backend::FactMessage};
-use crate::model::data::{device::Device, group::Group};
+use crate::model::data::{device::Device, group::Group, link::Link};
 use crate::model::cache::Cache;
-use crate::types::{AlertAckActor, AlertEventId, AlertRuleId, AlertTargetId, EpochSeconds};
+use crate::types::{AlertAckActor, AlertEventId, AlertRouteId, AlertRuleId, AlertTargetId, EpochSeconds, EvaluableItemId, MetricSet, OnCallScheduleId, TelegramTypeId};
 use crate::types::MetricValue;
 
 pub mod alert_severity;
@@ -22,12 +21,18 @@ pub mod alert_reduce_logic;
 pub mod alert_backend;
 pub mod telegram_backend;
 pub mod operand_modifier;
+pub mod sustained_tracker;
+pub mod backtest;
+pub mod alert_template;
+pub mod alert_route;
+pub mod oncall_schedule;
+pub mod notify_backend;
 pub mod tests;
 
 /// Pretty self explanatory. Severity of the alert rule
 /// In case a value given is not valid, will default to Unknown
 /// Equivalent to `alertseverity` enum in PostgreSQL
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type, Hash, Default)]
 #[sqlx(type_name = "AlertSeverity", rename_all = "lowercase")]
 #[serde(rename_all = "lowercase")]
 pub enum AlertSeverity {
@@ -41,6 +46,7 @@ pub enum AlertSeverity {
     Debug,
 
     #[serde(other)]
+    #[default]
     Unknown,
 }
 
@@ -126,6 +132,21 @@ pub struct AlertEvent {
 
     /// String representation of the value that raised.
     pub value: String,
+
+    /// Whether the event is the recovery of a raised alert, emitted once the clear condition of its rule holds
+    #[serde(default)]
+    pub recovery: bool,
+
+    /// Template of the rule that raised, and the values to render it with, for notifiers that render it in their own markup.
+    /// Only present on live events raised by rules with a template
+    #[serde(skip)]
+    #[sqlx(skip)]
+    pub template: Option<(MessageTemplate, TemplateContext)>,
+
+    /// Where the routes that matched the event deliver it. None if no route matched, in which case it goes to every subscribed chat
+    #[serde(skip)]
+    #[sqlx(skip)]
+    pub recipients: Option<AlertRecipients>,
 }
 
 /// Which side (if any) is constant
@@ -403,7 +424,199 @@ pub struct AlertRule {
 
     /// Kind of rule to be evaluated. Changes which data will be used, and the behavior to raise the alert.
     #[serde(rename="rule-type")]
-    pub rule_kind: AlertRuleKind
+    pub rule_kind: AlertRuleKind,
+
+    /// Condition that clears the rule once it raised for an item. While raised, the rule won't raise again for the item,
+    /// and a recovery is emitted once the clear condition holds. Rules without one raise on every evaluation that's true
+    #[serde(rename = "clear", default, skip_serializing_if = "Option::is_none")]
+    pub clear: Option<AlertClearCondition>,
+
+    /// Message of the alert events raised by this rule. Rules without one use a generic message
+    #[serde(rename = "message-template", default, skip_serializing_if = "Option::is_none")]
+    pub template: Option<MessageTemplate>,
+}
+
+/// Predicates that clear a raised rule, evaluated on the current dataset only.
+/// Such as raising when `icmp_rtt > 80`, and clearing when `icmp_rtt < 60`, so that a value hovering around 80 doesn't flap
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AlertClearCondition {
+    #[serde(rename = "reduce-logic")]
+    pub reduce_logic: AlertReduceLogic,
+
+    #[serde(rename = "predicates")]
+    pub predicates: Vec<AlertPredicate>,
+}
+
+/// Alert message with `{placeholder}` fields, such as `RTT of {device} is {metric.icmp_rtt}ms`. Literal braces are written as `{{` and `}}`.
+/// Available fields are `device`, `hostname`, `group`, `rule`, `severity`, `value`, `duration`, `metric.<name>` and `previous.<name>`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct MessageTemplate(pub String);
+
+/// Values a message template is rendered with, captured when the rule raised
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TemplateContext {
+    /// Name of the device, or label of the link that raised
+    pub device: String,
+    /// Management hostname of the device, or `link#id` for links
+    pub hostname: String,
+    /// Name of the group the rule targets, if it targets one
+    pub group: Option<String>,
+    pub rule: String,
+    pub severity: AlertSeverity,
+    /// Predicates that raised, and their values
+    pub value: String,
+    /// How long the condition had to hold, for Sustained rules
+    pub duration_s: Option<EpochSeconds>,
+    /// Current values of the metrics referenced by the template
+    pub metrics: MetricSet,
+    /// Previous values of the metrics referenced by the template, for Delta rules
+    pub previous: MetricSet,
+}
+
+/// Markup a message template is rendered into. Templates are plain text, so everything they render is escaped for the target markup
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TemplateFormat {
+    Plain,
+    /// Telegram MarkdownV2
+    MarkdownV2,
+    Html,
+}
+
+/// Delivers the alert events it matches to its destinations. Every matching route delivers the event,
+/// and events no route matches go to every subscribed Telegram chat. Websocket clients always receive every event
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlertRoute {
+    /// Unique Database id, numeric
+    #[serde(rename = "id", default)] // Might not be present in the JSON definition, but will get overriden by the database actual values
+    pub route_id: AlertRouteId,
+
+    /// Display name. Uniqueness is not enforced
+    #[serde(rename = "name", default)] // Might not be present in the JSON definition, but will get overriden by the database actual values
+    pub name: String,
+
+    /// Events the route applies to. Matches every event if empty
+    #[serde(rename = "match", default)]
+    pub matcher: RouteMatch,
+
+    pub destinations: Vec<RouteDestination>,
+}
+
+/// Conditions an event must meet for a route to apply. Conditions that are absent match anything
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RouteMatch {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub severities: Option<HashSet<AlertSeverity>>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rules: Option<HashSet<AlertRuleId>>,
+
+    /// Devices or links that raised, or groups they're members of
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub targets: Option<HashSet<AlertTargetId>>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schedule: Option<RouteSchedule>,
+}
+
+/// Time window in which a route applies, in the timezone of `backend/model/alerts/routing_timezone`.
+/// A window that ends before it starts spans midnight, such as 22:00 to 06:00
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RouteSchedule {
+    /// Days the window starts on, such as `["Sat", "Sun"]`. Every day if empty
+    #[serde(default)]
+    pub days: HashSet<chrono::Weekday>,
+
+    pub from: chrono::NaiveTime,
+    pub to: chrono::NaiveTime,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RouteDestination {
+    /// Telegram chats, which still need to be subscribed to receive alerts
+    Telegram { chats: Vec<TelegramTypeId> },
+    /// Endpoint the event is POSTed to, as JSON
+    Webhook { url: String },
+    /// Email addresses, sent through `backend/controller/smtp`
+    Email { recipients: Vec<String> },
+    /// Whoever is on call in the schedule with this name, through Telegram
+    #[serde(rename = "oncall")]
+    OnCall { schedule: String },
+}
+
+/// Destinations of an event, merged from every route that matched it
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AlertRecipients {
+    pub telegram_chats: HashSet<TelegramTypeId>,
+    pub webhooks: Vec<String>,
+    pub emails: Vec<String>,
+    /// Names of the on-call schedules
+    pub oncall: Vec<String>,
+}
+
+/// Rotation of identities taking turns to be on call, with overrides for time off and swaps.
+/// Members are `ack_actor_name`s of `ClientIdentity.ack_tokens`, paged through the Telegram user they authenticated with.
+/// Times are in the timezone of `backend/model/alerts/routing_timezone`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OnCallSchedule {
+    /// Unique Database id, numeric
+    #[serde(rename = "id", default)] // Might not be present in the JSON definition, but will get overriden by the database actual values
+    pub schedule_id: OnCallScheduleId,
+
+    /// Referenced by the routes that page it. Unique
+    #[serde(rename = "name", default)]
+    pub name: String,
+
+    pub rotation: OnCallRotation,
+
+    #[serde(default)]
+    pub overrides: Vec<OnCallOverride>,
+
+    /// How long an alert that requires ack can go unacked before the secondary is paged as well
+    #[serde(rename = "escalate-after-s", default = "default_escalate_after_s")]
+    pub escalate_after_s: EpochSeconds,
+}
+
+fn default_escalate_after_s() -> EpochSeconds { 900 }
+
+/// Members take turns in order, each for `shift-weeks`, starting with the first one at `start`.
+/// The next member in the rotation is the secondary
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OnCallRotation {
+    pub members: Vec<String>,
+
+    /// Start of the first shift, such as `2026-10-19T09:00:00`. Shifts are handed off at the same weekday and time
+    pub start: chrono::NaiveDateTime,
+
+    #[serde(rename = "shift-weeks", default = "default_shift_weeks")]
+    pub shift_weeks: u32,
+}
+
+fn default_shift_weeks() -> u32 { 1 }
+
+/// Someone on call in place of the rotation's primary, between `from` and `to`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OnCallOverride {
+    pub from: chrono::NaiveDateTime,
+    pub to: chrono::NaiveDateTime,
+    pub member: String,
+}
+
+/// Who is on call in a schedule at a given time, and until when
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct OnCallShift {
+    pub primary: String,
+    /// None if the rotation has a single member, who is also the primary
+    pub secondary: Option<String>,
+    pub until: chrono::NaiveDateTime,
+}
+
+/// Whether an evaluation raised a rule for an item, or cleared it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlertTransition {
+    Raised,
+    Cleared,
 }
 
 pub mod alert_filters;
@@ -472,57 +685,136 @@ pub enum AlertDataSource {
 #[allow(clippy::large_enum_variant)]
 pub enum EvaluableItem {
     Device (Device),
-    Group (Group)
+    Group (Group),
+    Link (Link),
 }
 
 type EvalResult= (OperandModifier, MetricValue, AlertPredicateOperation, MetricValue, OperandModifier);
 
+/// An item for which an evaluation raised or cleared the rule, with the values of the predicates that did it
+pub type Triggered = (EvaluableItem, AlertTransition, Vec<EvalResult>);
+
+/// Outcome of a single predicate, with the values each side resolved to after modifiers.
+/// A side is None if the accessor found no such value in the dataset
+#[derive(Debug, Clone, Serialize)]
+pub struct PredicatePreview {
+    pub predicate: String,
+    pub left: Option<MetricValue>,
+    pub right: Option<MetricValue>,
+    pub result: bool,
+}
+
+/// Formats the predicates that raised, and their values, for display in the alert event
+pub fn format_raising_values(which: &[EvalResult]) -> String {
+    which.iter()
+        .map(|(lmod, lhs, op, rhs, rmod)| format!("[{}{} {} {}{}]", lhs, lmod, op, rhs, rmod)).collect::<Vec<_>>().join(", ")
+}
+
+/// Where rules keep their state between evaluations: when Sustained rules first evaluated to true for an item,
+/// which items rules with a clear condition are raised for, and what time it is now.
+/// Live evaluation uses the wall clock and the records in the AlertBackend.
+/// Replays of historical data bring their own clock and records, so they never interfere with live alerts
+pub enum SustainedTracker<'a> {
+    Live,
+    Replay {
+        now: EpochSeconds,
+        records: &'a mut HashMap<(AlertRuleId, EvaluableItemId), EpochSeconds>,
+        raised: &'a mut HashSet<(AlertRuleId, EvaluableItemId)>,
+    },
+}
+
 impl EvaluableItem {
-    async fn eval_device<'a>(device: Device, rule: &'a AlertRule, dataset_left: &'a FactMessage, dataset_right: &'a FactMessage)
-        -> Option<(EvaluableItem, Vec<EvalResult>)> {
+    async fn eval_device<'a>(device: Device, rule: &'a AlertRule, dataset_left: &'a FactMessage, dataset_right: &'a FactMessage, tracker: &mut SustainedTracker<'_>)
+        -> Option<Triggered> {
         let dataset_right = &dataset_right.get(&device.management_hostname)?.metrics;
+        let dataset_left = dataset_left.get(&device.management_hostname).map(|f| &f.metrics);
+
+        #[cfg(debug_assertions)] { log::info!(subsystem = "alerts.eval"; "Evaluating rule for device={} kind is {}", device.management_hostname, rule.rule_kind); }
+        let device_id = device.device_id;
+        EvaluableItem::eval_metrics(EvaluableItem::Device(device), device_id, rule, dataset_left, dataset_right, tracker).await
+    }
 
-        #[cfg(debug_assertions)] { log::info!("[DEBUG][ALERTS][EVAL] Evaluating rule for device={} kind is {}", device.management_hostname, rule.rule_kind); }
+    /// Links are evaluated against the state derived from their endpoints' interfaces, not against the given datasets
+    async fn eval_link(link: Link, rule: &AlertRule, tracker: &mut SustainedTracker<'_>) -> Option<Triggered> {
+        if !matches!(rule.data_source, AlertDataSource::Facts) {
+            return None;
+        }
+
+        let (dataset_left, dataset_right) = Cache::instance().get_link_metrics(link.link_id).await;
+        let dataset_right = dataset_right?;
+
+        #[cfg(debug_assertions)] { log::info!(subsystem = "alerts.eval"; "Evaluating rule for link={} kind is {}", link.link_id, rule.rule_kind); }
+        let link_id = link.link_id;
+        EvaluableItem::eval_metrics(EvaluableItem::Link(link), link_id, rule, dataset_left.as_ref(), &dataset_right, tracker).await
+    }
+
+    async fn eval_metrics<'a>(item: EvaluableItem, item_id: EvaluableItemId, rule: &'a AlertRule, dataset_left: Option<&'a MetricSet>, dataset_right: &'a MetricSet, tracker: &mut SustainedTracker<'_>)
+        -> Option<Triggered> {
+        // Evaluated even while raised, so that Sustained timers keep running
+        let raised = EvaluableItem::eval_kind(item_id, rule, dataset_left, dataset_right, tracker).await;
+
+        let clear = match &rule.clear {
+            Some(clear) => clear,
+            None => return raised.map(|which| (item, AlertTransition::Raised, which)),
+        };
+
+        // With a clear condition, the rule only raises once, and stays raised until it clears
+        if tracker.is_raised(rule.rule_id, item_id).await {
+            if !clear.eval(dataset_right) {
+                return None;
+            }
+            tracker.set_raised(rule.rule_id, item_id, false).await;
+            Some((item, AlertTransition::Cleared, clear.clearing_values(dataset_right)))
+        } else {
+            let which = raised?;
+            tracker.set_raised(rule.rule_id, item_id, true).await;
+            Some((item, AlertTransition::Raised, which))
+        }
+    }
+
+    /// Evaluates the rule according to its kind. Returns the values of the predicates that raised, if it did
+    async fn eval_kind<'a>(item_id: EvaluableItemId, rule: &'a AlertRule, dataset_left: Option<&'a MetricSet>, dataset_right: &'a MetricSet, tracker: &mut SustainedTracker<'_>)
+        -> Option<Vec<EvalResult>> {
         match rule.rule_kind {
             AlertRuleKind::Simple => {
                 if rule.eval_single(dataset_right) {
                     let which = rule.raising_values(dataset_right, dataset_right);
-                    Some((EvaluableItem::Device(device), which))
+                    Some(which)
                 } else { None }
             },
 
             AlertRuleKind::Delta => {
-                let dataset_left = &dataset_left.get(&device.management_hostname)?.metrics;
+                let dataset_left = dataset_left?;
                 if rule.eval_delta(dataset_left, dataset_right) {
                     let which = rule.raising_values(dataset_left, dataset_right);
-                    Some((EvaluableItem::Device(device), which))
+                    Some(which)
                 } else { None }
             },
 
             AlertRuleKind::Sustained { seconds } => {
                 if !rule.eval_single(dataset_right) {
                     // returned false, we let the backend know that it should reset the counter (if any)
-                    AlertBackend::sustained_reset(rule.rule_id, device.device_id).await;
+                    tracker.reset(rule.rule_id, item_id).await;
                     return None
                 }
 
                 // Rule returned true
                 // has it returned true before?
-                let t = match AlertBackend::sustained_check_first_raised(rule.rule_id, device.device_id).await {
+                let t = match tracker.first_raised(rule.rule_id, item_id).await {
                     Some(t) => t ,
                     None => {
                         // Hasn't raised before, we insert it
-                        AlertBackend::sustained_set_first_raised(rule.rule_id, device.device_id).await;
+                        tracker.set_first_raised(rule.rule_id, item_id).await;
                         return None // return None, we can't raise the alert yet
                     },
                 };
                 // It has, we check if it's time to raise
-                if AlertBackend::sustained_should_raise(t, seconds).await {
+                if tracker.should_raise(t, seconds).await {
                     // Dayum, we need to raise. Also reset the alert so it doesn't trigger immediately again
                     let which = rule.raising_values(dataset_right, dataset_right);
-                    AlertBackend::sustained_reset(rule.rule_id, device.device_id).await;
+                    tracker.reset(rule.rule_id, item_id).await;
 
-                    Some((EvaluableItem::Device(device), which))
+                    Some(which)
                 } else {
                     // Not yet, Ferb
                     None
@@ -532,9 +824,15 @@ impl EvaluableItem {
         }
     }
 
-    /// Evaluates the rule with the given datasets. Returns the items for which the rule is raised
+    /// Evaluates the rule with the given datasets. Returns the items for which the rule is raised, or cleared
     pub async fn eval<'a>(self, rule: &'a AlertRule, dataset_left: &'a FactMessage, dataset_right: &'a FactMessage)
-        -> Option<Vec<(EvaluableItem, Vec<EvalResult>)>> {
+        -> Option<Vec<Triggered>> {
+        self.eval_with(rule, dataset_left, dataset_right, &mut SustainedTracker::Live).await
+    }
+
+    /// Same as `eval`, keeping the rule state in the given tracker
+    pub async fn eval_with<'a>(self, rule: &'a AlertRule, dataset_left: &'a FactMessage, dataset_right: &'a FactMessage, tracker: &mut SustainedTracker<'_>)
+        -> Option<Vec<Triggered>> {
         let cache = Cache::instance();
         match self {
             EvaluableItem::Group(group) => {
@@ -544,12 +842,13 @@ impl EvaluableItem {
                     let device = match cache.get_evaluable_item(member).await { Some(d) => d, None => continue };
 
                     match device {
-                        EvaluableItem::Group(_) => {
-                            log::error!("[ERROR][ALERTS][EVAL] Rule called for group item. Skipping evaluation");
+                        // Members are already flattened into devices, nested groups included
+                        EvaluableItem::Group(_) | EvaluableItem::Link(_) => {
+                            log::error!(subsystem = "alerts.eval"; "Group expansion yielded non-device item with id = {member}. Skipping evaluation");
                             continue
                         },
                         EvaluableItem::Device(device) => {
-                            let rule_result = EvaluableItem::eval_device(device, rule, dataset_left, dataset_right).await;
+                            let rule_result = EvaluableItem::eval_device(device, rule, dataset_left, dataset_right, tracker).await;
 
                             if let Some(item) = rule_result {
                                 triggered.push(item);
@@ -563,7 +862,10 @@ impl EvaluableItem {
                 Some(triggered)
             },
             EvaluableItem::Device(device) => {
-                Some(vec![EvaluableItem::eval_device(device, rule, dataset_left, dataset_right).await?])
+                Some(vec![EvaluableItem::eval_device(device, rule, dataset_left, dataset_right, tracker).await?])
+            },
+            EvaluableItem::Link(link) => {
+                Some(vec![EvaluableItem::eval_link(link, rule, tracker).await?])
             },
         }
     }
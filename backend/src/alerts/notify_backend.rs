@@ -0,0 +1,114 @@
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use lettre::message::Mailbox;
+use lettre::message::header::ContentType;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use rocket::futures::future::join_all;
+use tokio::sync::Mutex;
+use tokio::sync::mpsc::Receiver;
+use tokio::task::JoinSet;
+
+use crate::alerts::AlertEvent;
+use crate::config::Config;
+use crate::supervisor::{ShutdownSignal, Stage, Supervisor};
+use crate::telemetry::Telemetry;
+
+// Delivers alert events to the webhooks and email recipients of the routes that matched them.
+// Telegram chats are handled by the TelegramBackend, which receives the same events
+
+static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+
+fn client() -> &'static reqwest::Client {
+    CLIENT.get_or_init(reqwest::Client::new)
+}
+
+pub struct NotifyBackend;
+
+impl NotifyBackend {
+    /// Spawns the task that delivers routed events as they arrive. On shutdown, the ones already queued are delivered before stopping
+    pub fn init(receiver: Receiver<AlertEvent>) {
+        let receiver = Arc::new(Mutex::new(receiver));
+        Supervisor::spawn("alert_notifications", Stage::Notifications, move |shutdown| Self::notify_loop(receiver.clone(), shutdown));
+    }
+
+    /// Each event is delivered on its own task, so a slow webhook or SMTP server doesn't hold back the queue
+    async fn notify_loop(receiver: Arc<Mutex<Receiver<AlertEvent>>>, mut shutdown: ShutdownSignal) {
+        let mut receiver = receiver.lock().await;
+        let mut deliveries = JoinSet::new();
+        loop {
+            let event = tokio::select! {
+                event = receiver.recv() => match event { Some(event) => event, None => break },
+                _ = shutdown.requested() => break,
+            };
+            deliveries.spawn(async move { Self::notify(&event).await });
+            while deliveries.try_join_next().is_some() {}
+        }
+
+        while let Ok(event) = receiver.try_recv() {
+            deliveries.spawn(async move { Self::notify(&event).await });
+        }
+        while deliveries.join_next().await.is_some() {}
+        log::info!(subsystem = "alerts.notify"; "Stopping alert notifications");
+    }
+
+    async fn notify(event: &AlertEvent) {
+        let Some(recipients) = &event.recipients else { return };
+
+        let webhooks = join_all(recipients.webhooks.iter().map(|url| async move {
+            if let Err(e) = Self::send_webhook(url, event).await {
+                log::error!(subsystem = "alerts.notify"; "Failed to deliver alert {} to webhook '{}', e='{e}'", event.alert_id, url);
+                Telemetry::instance().notifications_failed.inc("webhook");
+            }
+        }));
+        let email = async {
+            if !recipients.emails.is_empty() && let Err(e) = Self::send_email(&recipients.emails, event).await {
+                log::error!(subsystem = "alerts.notify"; "Failed to email alert {} to {} recipients, e='{e}'", event.alert_id, recipients.emails.len());
+                Telemetry::instance().notifications_failed.inc("email");
+            }
+        };
+        tokio::join!(webhooks, email);
+    }
+
+    /// POSTs the event as JSON. Fails on any non 2xx response
+    async fn send_webhook(url: &str, event: &AlertEvent) -> Result<(), reqwest::Error> {
+        let timeout_s = Config::instance().settings().backend.controller.webhooks.timeout_s;
+        client().post(url)
+            .timeout(Duration::from_secs(timeout_s))
+            .json(&event.to_dict(false))
+            .send().await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    async fn send_email(recipients: &[String], event: &AlertEvent) -> anyhow::Result<()> {
+        let smtp = Config::instance().settings().backend.controller.smtp.clone();
+        if !smtp.enabled {
+            anyhow::bail!("SMTP is disabled");
+        }
+
+        let mut message = Message::builder()
+            .from(smtp.from.parse::<Mailbox>()?)
+            .subject(format!("[{}] {}", event.severity, event.message));
+        for recipient in recipients {
+            message = message.to(recipient.parse::<Mailbox>()?);
+        }
+        let time = event.alert_time.map(|t| t.to_rfc3339()).unwrap_or_default();
+        let message = message
+            .header(ContentType::TEXT_PLAIN)
+            .body(format!("{}\n\nSeverity: {}\nTime: {}\nEvaluated: {}\n", event.message, event.severity, time, event.value))?;
+
+        let builder = match smtp.starttls {
+            true => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&smtp.hostname)?,
+            false => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&smtp.hostname),
+        };
+        let mut builder = builder.port(smtp.port);
+        if !smtp.username.is_empty() {
+            builder = builder.credentials(Credentials::new(smtp.username, smtp.password));
+        }
+
+        builder.build().send(message).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,50 @@
+use chrono::{NaiveDateTime, TimeDelta};
+
+use crate::alerts::{OnCallSchedule, OnCallShift};
+
+/// Longest shift of a rotation, in weeks
+pub const MAX_SHIFT_WEEKS: u32 = 52;
+
+impl OnCallSchedule {
+    /// Who is on call at `now`, local to the routing timezone. None before the rotation starts, or if it has no members.
+    /// An override replaces the primary while it lasts, the secondary is still the rotation's
+    pub fn on_call(&self, now: NaiveDateTime) -> Option<OnCallShift> {
+        let rotation = &self.rotation;
+        if rotation.members.is_empty() || now < rotation.start {
+            return None;
+        }
+
+        let shift = TimeDelta::try_weeks(rotation.shift_weeks.clamp(1, MAX_SHIFT_WEEKS) as i64)?;
+        let shifts = (now - rotation.start).num_seconds() / shift.num_seconds();
+        let index = (shifts as usize) % rotation.members.len();
+        let handoff = i32::try_from(shifts + 1).ok()
+            .and_then(|next| shift.checked_mul(next))
+            .and_then(|elapsed| rotation.start.checked_add_signed(elapsed));
+        let Some(handoff) = handoff else {
+            log::warn!(subsystem = "alerts.oncall"; "Next handoff of schedule '{}' is out of range, nobody is on call", self.name);
+            return None;
+        };
+
+        let primary = rotation.members[index].clone();
+        let secondary = Some(rotation.members[(index + 1) % rotation.members.len()].clone())
+            .filter(|secondary| *secondary != primary);
+
+        let shift = match self.overrides.iter().find(|o| o.from <= now && now < o.to) {
+            Some(o) => OnCallShift {
+                secondary: secondary.filter(|secondary| *secondary != o.member),
+                primary: o.member.clone(),
+                until: o.to.min(handoff),
+            },
+            None => OnCallShift {
+                primary,
+                secondary,
+                // The next override that starts before the handoff ends this shift early
+                until: self.overrides.iter()
+                    .filter(|o| o.from > now)
+                    .map(|o| o.from)
+                    .fold(handoff, NaiveDateTime::min),
+            },
+        };
+        Some(shift)
+    }
+}
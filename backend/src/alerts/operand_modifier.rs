@@ -62,8 +62,7 @@ impl OperandModifier {
                     | OperandModifier::BitwiseComplement
                     | OperandModifier::Truncate
                     | OperandModifier::Mul(_) => {
-                        log::error!("[ERROR][ALERTS][RULES] Tried to apply arithmetic operand modifier to String Metric.");
-                        log::warn!("^ This item will be skipped from modification");
+                        log::error!(subsystem = "alerts.rules"; "Tried to apply arithmetic operand modifier to String Metric. This item will be skipped from modification");
                         Some(value.clone())
                     },
                 }
@@ -100,8 +99,7 @@ impl OperandModifier {
                     | OperandModifier::BitwiseLShift(_)
                     | OperandModifier::BitwiseRShift(_)
                     | OperandModifier::BitwiseComplement => {
-                        log::error!("[ERROR][ALERTS][RULES] Tried to apply bitwise operand modifier to Numeric Metric.");
-                        log::warn!("^ This item will be skipped from modification");
+                        log::error!(subsystem = "alerts.rules"; "Tried to apply bitwise operand modifier to Numeric Metric. This item will be skipped from modification");
                         Some(value.clone())
                     }
 
@@ -113,8 +111,7 @@ impl OperandModifier {
                     | OperandModifier::Replace{..}
                     | OperandModifier::ReplaceN{..}
                     | OperandModifier::Append(_) => {
-                        log::error!("[ERROR][ALERTS][RULES] Tried to apply String operand modifier to Numeric Metric.");
-                        log::warn!("^ This item will be skipped from modification");
+                        log::error!(subsystem = "alerts.rules"; "Tried to apply String operand modifier to Numeric Metric. This item will be skipped from modification");
                         Some(value.clone())
                     }
                     
@@ -157,8 +154,7 @@ impl OperandModifier {
                     | OperandModifier::ReplaceN{..}
                     | OperandModifier::Trim
                     | OperandModifier::Append(_) => {
-                        log::error!("[ERROR][ALERTS][RULES] Tried to apply String operand modifier to Numeric Metric.");
-                        log::warn!("^ This item will be skipped from modification");
+                        log::error!(subsystem = "alerts.rules"; "Tried to apply String operand modifier to Numeric Metric. This item will be skipped from modification");
                         Some(value.clone())
                     }
                 },
@@ -166,8 +162,7 @@ impl OperandModifier {
             MetricValue::Boolean(_)
             | MetricValue::Null()
             | MetricValue::Array(_) => {
-                log::error!("[ERROR][ALERTS][RULES] Tried to apply operand modifier to non-modifiable value. Actual metric: {}", value);
-                log::warn!("^ This item will be skipped from modification");
+                log::error!(subsystem = "alerts.rules"; "Tried to apply operand modifier to non-modifiable value. Actual metric: {}. This item will be skipped from modification", value);
                 Some(value.clone())
             },
         }
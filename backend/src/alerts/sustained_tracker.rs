@@ -0,0 +1,53 @@
+use crate::alerts::SustainedTracker;
+use crate::alerts::alert_backend::AlertBackend;
+use crate::types::{AlertRuleId, EpochSeconds, EvaluableItemId};
+
+impl SustainedTracker<'_> {
+    /// When the rule first evaluated to true for the item, without being interrupted
+    pub async fn first_raised(&self, rule_id: AlertRuleId, item_id: EvaluableItemId) -> Option<EpochSeconds> {
+        match self {
+            SustainedTracker::Live => AlertBackend::sustained_check_first_raised(rule_id, item_id).await,
+            SustainedTracker::Replay { records, .. } => records.get(&(rule_id, item_id)).copied(),
+        }
+    }
+
+    /// Records that the rule evaluated to true for the item, now
+    pub async fn set_first_raised(&mut self, rule_id: AlertRuleId, item_id: EvaluableItemId) {
+        match self {
+            SustainedTracker::Live => AlertBackend::sustained_set_first_raised(rule_id, item_id).await,
+            SustainedTracker::Replay { now, records, .. } => { records.insert((rule_id, item_id), *now); },
+        }
+    }
+
+    pub async fn should_raise(&self, first_raised: EpochSeconds, sustained_duration_s: EpochSeconds) -> bool {
+        match self {
+            SustainedTracker::Live => AlertBackend::sustained_should_raise(first_raised, sustained_duration_s).await,
+            SustainedTracker::Replay { now, .. } => now.saturating_sub(first_raised) >= sustained_duration_s,
+        }
+    }
+
+    pub async fn reset(&mut self, rule_id: AlertRuleId, item_id: EvaluableItemId) {
+        match self {
+            SustainedTracker::Live => AlertBackend::sustained_reset(rule_id, item_id).await,
+            SustainedTracker::Replay { records, .. } => { records.remove(&(rule_id, item_id)); },
+        }
+    }
+
+    /// Whether a rule with a clear condition is currently raised for the item
+    pub async fn is_raised(&self, rule_id: AlertRuleId, item_id: EvaluableItemId) -> bool {
+        match self {
+            SustainedTracker::Live => AlertBackend::is_raised(rule_id, item_id).await,
+            SustainedTracker::Replay { raised, .. } => raised.contains(&(rule_id, item_id)),
+        }
+    }
+
+    pub async fn set_raised(&mut self, rule_id: AlertRuleId, item_id: EvaluableItemId, is_raised: bool) {
+        match self {
+            SustainedTracker::Live => AlertBackend::set_raised(rule_id, item_id, is_raised).await,
+            SustainedTracker::Replay { raised, .. } => match is_raised {
+                true => { raised.insert((rule_id, item_id)); },
+                false => { raised.remove(&(rule_id, item_id)); },
+            },
+        }
+    }
+}
@@ -1,15 +1,18 @@
-use std::{collections::HashSet, sync::{Arc, OnceLock}};
+use std::{collections::HashSet, sync::{Arc, OnceLock}, time::Duration};
 
 use chrono_tz::Tz;
 use rocket::futures::future::join_all;
 use tgbot::{api::Client, handler::LongPoll, types::ChatPeerId};
-use tokio::sync::{RwLock, mpsc::Receiver};
+use tokio::sync::{Mutex, RwLock, mpsc::Receiver};
 
-use crate::{alerts::telegram_backend::Handler, model::db::operations::telegram_operations};
-use crate::{alerts::{AlertEvent, AlertSeverity, alert_backend::AlertBackend}, config::Config, model::{cache::Cache, data::device::Device}, types::TelegramTypeId};
+use crate::{alerts::telegram_backend::Handler, model::db::operations::{alert_operations, telegram_operations}};
+use crate::alerts::telegram_backend::telegram_handler::escape_message;
+use crate::supervisor::{ShutdownSignal, Stage, Supervisor};
+use crate::{alerts::{AlertEvent, AlertSeverity, OnCallSchedule, OnCallShift, TemplateFormat, alert_backend::AlertBackend}, config::Config, model::cache::Cache, types::{AlertTargetId, EpochSeconds, TelegramTypeId}};
+use crate::model::db::pools::init_influx_client;
 
 // Emoji map as a function returning &'static str
-fn emoji_map(severity: &AlertSeverity) -> &'static str {
+pub(super) fn emoji_map(severity: &AlertSeverity) -> &'static str {
     match severity {
         AlertSeverity::Emergency => "🆘🚨🚨",
         AlertSeverity::Alert     => "🚨🚨",
@@ -23,19 +26,27 @@ fn emoji_map(severity: &AlertSeverity) -> &'static str {
     }
 }
 
-fn format_alert(event: &AlertEvent, device: &Device, rule_name: &str) -> String {
+/// `target` is the (name, hostname) of the device, or (label, link id) of the link that raised.
+/// Returns the message escaped for MarkdownV2. Events of rules with a template are the template rendered after a header
+fn format_alert(event: &AlertEvent, target: &(String, String), rule_name: &str) -> String {
 
     let tz : Tz = chrono_tz::Etc::GMTPlus6;
 
     let emoji = emoji_map(&event.severity);
+    let header = if event.recovery { "✅¡Recuperado!" } else { "¡Alerta!" };
+    if let Some((template, context)) = &event.template {
+        let header = TemplateFormat::MarkdownV2.escape(&format!("{emoji}{header}"));
+        return format!("*{header}*\n\n{}", template.render(context, TemplateFormat::MarkdownV2));
+    }
+
     // let ack = if event.requires_ack { "Sí" } else { "No" };
     let time_str = match event.alert_time {
         Some(t) => t.with_timezone(&tz).format("%Y-%m-%d %H:%M:%S UTC%Z").to_string(),
         None=> "Desconocido".to_string()
     };
 
-    format!(
-        "```{emoji}¡Alerta!\n\
+    let message = format!(
+        "```{emoji}{header}\n\
         🗓️ {time_str}\n\
         🖥️ {device_name}@{hostname}\n\
         Requiere ACK: {requires_ack}\
@@ -48,25 +59,36 @@ fn format_alert(event: &AlertEvent, device: &Device, rule_name: &str) -> String
         Evaluado={value}```",
         event.severity,
         requires_ack = event.requires_ack,
-        device_name = device.device_name,
-        hostname = device.management_hostname,
+        device_name = target.0,
+        hostname = target.1,
         message = event.message,
         rule_name = rule_name,
         value = event.value,
-    )
+    );
+    escape_message(&message)
+}
+
+/// (name, hostname) of the device, or (label, `link#id`) of the link an alert event targets
+pub(super) async fn target_label(target_id: AlertTargetId) -> Option<(String, String)> {
+    match Cache::instance().get_device(target_id).await {
+        Some(device) => Some((device.device_name, device.management_hostname)),
+        None => Cache::instance().get_link_label(target_id).await.map(|label| (label, format!("link#{target_id}"))),
+    }
 }
 
 pub struct TelegramBackend {
     client: Client,
     pub pool: sqlx::PgPool,
+    /// For the `/health` command
+    pub influx_client: influxdb2::Client,
 
     subscribed_chats: RwLock<HashSet<TelegramTypeId>>
 }
 static INSTANCE: OnceLock<Arc<TelegramBackend>> = OnceLock::new();
 impl TelegramBackend {
     /// Internal constructor
-    fn new(token: String, pool: sqlx::PgPool) -> Self {
-        TelegramBackend { pool, client: Client::new(token).expect("[FATAL]Telegram Bot should be created correctly"), subscribed_chats: RwLock::new(HashSet::new())}
+    fn new(token: String, pool: sqlx::PgPool, influx_client: influxdb2::Client) -> Self {
+        TelegramBackend { pool, influx_client, client: Client::new(token).expect("[FATAL]Telegram Bot should be created correctly"), subscribed_chats: RwLock::new(HashSet::new())}
     }
 
     /// Get singleton instance
@@ -81,22 +103,19 @@ impl TelegramBackend {
 
     /// Initializes the telegram backend. Must be init after config is loaded
     pub async fn init(pool: sqlx::PgPool, alert_receiver: Receiver<AlertEvent>) {
-        let enabled : bool = Config::instance().get("backend/controller/telegram/enabled", "/")
-            .expect("[FATAL]Telegram enable status MUST be present in the config file at path 'backend/controller/telegram/enabled', an must be of type boolean");
-
-        if !enabled {
+        // The token is guaranteed to be present when enabled, as the configuration is validated on load
+        let telegram = Config::instance().settings().backend.controller.telegram.clone();
+        if !telegram.enabled {
             return;
         }
-
-        let token: String = Config::instance().get("backend/controller/telegram/API-token", "/")
-            .expect("[FATAL]Telegram API token MUST be present in the config file at path 'backend/controller/telegram/API-token', typically imported from identity via $ref");
+        let token = telegram.api_token;
 
         // Try to set instance Arc with provided value
-        let backend = Arc::new(TelegramBackend::new(token, pool));
+        let backend = Arc::new(TelegramBackend::new(token, pool, init_influx_client().await));
         let innit_bruv = INSTANCE.set(backend);
 
         if innit_bruv.is_err() {
-            println!("[WARN][Telegram] Telegram backend was init more than once!. Ignoring second init...");
+            log::warn!(subsystem = "telegram"; "Telegram backend was init more than once!. Ignoring second init...");
             return;
         }
 
@@ -106,6 +125,12 @@ impl TelegramBackend {
         Self::update_user_cache().await;
     }
 
+    /// Whether Telegram is currently enabled. It can be paused and resumed by reloading the configuration,
+    /// as long as it was enabled at startup. While paused, alerts are dropped and bot updates ignored
+    pub fn is_enabled() -> bool {
+        Config::instance().settings().backend.controller.telegram.enabled
+    }
+
     pub async fn update_user_cache() {
         let instance = TelegramBackend::instance();
         let subscribed = telegram_operations::get_subscribed_users(&instance.pool).await;
@@ -116,92 +141,219 @@ impl TelegramBackend {
             w.extend(subscribed.into_iter().map(|id| id as TelegramTypeId));
         }
 
-        log::info!("[INFO ][ALERTS][TELEGRAM] Updated user cache")
+        log::info!(subsystem = "alerts.telegram"; "Updated user cache")
     }
 
     fn spawn_telegram_poller_task() {
-        rocket::tokio::spawn(async {
+        Supervisor::spawn("telegram_poller", Stage::Sources, |mut shutdown| async move {
             let new_instance = TelegramBackend::instance();
-            LongPoll::new(new_instance.client.clone(), Handler { client: new_instance.client.clone() }).run().await;
+            let poller = LongPoll::new(new_instance.client.clone(), Handler { client: new_instance.client.clone() });
+            tokio::select! {
+                _ = poller.run() => (),
+                _ = shutdown.requested() => log::info!(subsystem = "alerts.telegram"; "Stopping Telegram poller"),
+            }
         });
     }
 
-    fn spawn_telegram_alert_handler_task (mut alert_receiver: Receiver<AlertEvent>) {
-        rocket::tokio::spawn(async move { 
-            loop {
-                let event = match alert_receiver.recv().await {
+    fn spawn_telegram_alert_handler_task (alert_receiver: Receiver<AlertEvent>) {
+        let alert_receiver = Arc::new(Mutex::new(alert_receiver));
+        Supervisor::spawn("telegram_alerts", Stage::Notifications, move |shutdown| Self::alert_handler_loop(alert_receiver.clone(), shutdown));
+    }
+
+    /// Sends alert events as they arrive. On shutdown, the ones already queued are sent before stopping
+    async fn alert_handler_loop(alert_receiver: Arc<Mutex<Receiver<AlertEvent>>>, mut shutdown: ShutdownSignal) {
+        let mut alert_receiver = alert_receiver.lock().await;
+        loop {
+            let event = tokio::select! {
+                event = alert_receiver.recv() => match event {
                     None=> continue,
                     Some(monosodiumglutamate) => monosodiumglutamate
-                };
-
-                let instance = TelegramBackend::instance();
-                let pool_executor = &instance.pool;
-                log::info!("[INFO ][ALERTS][TELEGRAM] Received an alert event");
-
-                // 0.- Update the user cache and rule mapping to only send to auth'd users, and look up devices
-                TelegramBackend::update_user_cache().await;
-                let chats = instance.subscribed_chats.read().await;
-                let client = &instance.client;
-                let device = Cache::instance().get_device(event.target_id).await;
-                let rule = AlertBackend::instance().get_rule_name(event.rule_id.unwrap_or(-1)).await.unwrap_or("[Regla eliminada]".to_string());
-
-                // 1.- Make a string representation of the alert event's rule
-                let (device, rule) = match (device, rule) {
-                    (Some(device), rule) => (device, rule),
-                    (_, _) => {
-                        log::error!("[ERROR][ALERTS][TELEGRAM] Failed to create rule string representation. Device or Rule invalid");
-                        continue
-                    }
-                };
+                },
+                _ = shutdown.requested() => break,
+            };
+            Self::handle_alert_event(event).await;
+        }
 
-                let msg = format_alert(&event, &device, &rule);
+        while let Ok(event) = alert_receiver.try_recv() {
+            Self::handle_alert_event(event).await;
+        }
+        log::info!(subsystem = "alerts.telegram"; "Stopping Telegram alert handler");
+    }
 
-                // 2.- Update the Telegram listeners, and store their messages
-                if !event.requires_ack {
-                    for chat_id in chats.iter() {
-                        Handler::send_message(client, (*chat_id).into(), msg.as_str()).await;
-                    } 
-                    return;
-                } 
-                let futures = chats.iter().map(|chat_id| {
-                    Handler::send_message_button(
-                        client,
-                        (*chat_id).into(),
-                        msg.as_str(),
-                        event.alert_id,
-                    )
-                });
-                let msgs: Vec<(ChatPeerId, i64)> = join_all(futures).await.into_iter().filter_map(|f| f.ok()).collect();
-                if msgs.is_empty() { return; }
-
-                // 3.- Begin the transaction to store the Telegram messages as pending from Ack, so they can be later on updated
-                let mut transaction = match pool_executor.begin().await {
-                    Ok(t) => t,
-                    Err(e) => {
-                        log::error!("[ERROR][TELEGRAM] Failed to init ack message SQL transaction. SQL Error = '{}'", e);
-                        return;
-                    }
-                };
-
-                // 4.- Store the messages into the database
-                for msg in msgs {
-                    match telegram_operations::insert_unacked_message(event.alert_id, msg.0, msg.1, &mut transaction).await {
-                        Ok(_) => (),
-                        Err(e) => {
-                            log::error!("[ERROR][TELEGRAM] Failed to store message for future ack'ing. SQL Error = '{e}'")
-                        },
+    async fn handle_alert_event(event: AlertEvent) {
+        if !TelegramBackend::is_enabled() {
+            log::info!(subsystem = "alerts.telegram"; "Dropping alert event, as Telegram is disabled");
+            return;
+        }
+
+        let instance = TelegramBackend::instance();
+        let pool_executor = &instance.pool;
+        log::info!(subsystem = "alerts.telegram"; "Received an alert event");
+
+        // 0.- Update the user cache and rule mapping to only send to auth'd users, and look up devices
+        TelegramBackend::update_user_cache().await;
+        let subscribed = instance.subscribed_chats.read().await;
+        // Routed events only go to the chats of their routes, as long as they're subscribed
+        let mut chats: Vec<TelegramTypeId> = match &event.recipients {
+            Some(recipients) => subscribed.intersection(&recipients.telegram_chats).copied().collect(),
+            None => subscribed.iter().copied().collect(),
+        };
+        drop(subscribed);
+        // On-call members are paged in their private chat, subscribed or not
+        let (pages, escalations) = Self::resolve_oncall(&event, pool_executor).await;
+        for page in pages {
+            if !chats.contains(&page) {
+                chats.push(page);
+            }
+        }
+        if chats.is_empty() {
+            return;
+        }
+        let device = target_label(event.target_id).await;
+        let rule = AlertBackend::instance().get_rule_name(event.rule_id.unwrap_or(-1)).await.unwrap_or("[Regla eliminada]".to_string());
+
+        // 1.- Make a string representation of the alert event's rule
+        let (device, rule) = match (device, rule) {
+            (Some(device), rule) => (device, rule),
+            (_, _) => {
+                log::error!(subsystem = "alerts.telegram"; "Failed to create rule string representation. Device or Rule invalid");
+                return
+            }
+        };
+
+        let msg = format_alert(&event, &device, &rule);
+        Self::deliver(&event, &chats, &msg).await;
+
+        // 5.- Page the secondaries later on, unless someone acks it first
+        for (after_s, chat) in escalations {
+            if !chats.contains(&chat) {
+                Self::schedule_escalation(event.clone(), after_s, chat, msg.clone());
+            }
+        }
+    }
+
+    /// Sends the MarkdownV2 message to the chats. For alerts that require ack, the messages are stored so the ack updates them
+    async fn deliver(event: &AlertEvent, chats: &[TelegramTypeId], msg: &str) {
+        let instance = TelegramBackend::instance();
+        let client = &instance.client;
+
+        // 2.- Update the Telegram listeners, and store their messages
+        if !event.requires_ack {
+            for chat_id in chats.iter() {
+                Handler::send_markdown(client, (*chat_id).into(), msg).await;
+            } 
+            return;
+        } 
+        let futures = chats.iter().map(|chat_id| {
+            Handler::send_message_button(
+                client,
+                (*chat_id).into(),
+                msg,
+                event.alert_id,
+            )
+        });
+        let msgs: Vec<(ChatPeerId, i64)> = join_all(futures).await.into_iter().filter_map(|f| f.ok()).collect();
+        if msgs.is_empty() { return; }
+
+        // 3.- Begin the transaction to store the Telegram messages as pending from Ack, so they can be later on updated
+        let mut transaction = match instance.pool.begin().await {
+            Ok(t) => t,
+            Err(e) => {
+                log::error!(subsystem = "telegram"; "Failed to init ack message SQL transaction. SQL Error = '{}'", e);
+                return;
+            }
+        };
+
+        // 4.- Store the messages into the database
+        for msg in msgs {
+            match telegram_operations::insert_unacked_message(event.alert_id, msg.0, msg.1, &mut transaction).await {
+                Ok(_) => (),
+                Err(e) => {
+                    log::error!(subsystem = "telegram"; "Failed to store message for future ack'ing. SQL Error = '{e}'")
+                },
+            }
+        }
+
+        if let Err(e) = transaction.commit().await {
+            log::error!(subsystem = "telegram"; "Failed to commit transaction during ack of alert. SQL Error = '{e}'");
+        }
+    }
+
+    /// Telegram users on call in the schedules the event was routed to, and the (delay, user) of the secondaries
+    /// to page if it requires ack and nobody acks it in time. If the primary never authenticated with Telegram, the secondary is paged right away
+    async fn resolve_oncall(event: &AlertEvent, pool: &sqlx::PgPool) -> (Vec<TelegramTypeId>, Vec<(EpochSeconds, TelegramTypeId)>) {
+        let Some(recipients) = event.recipients.as_ref().filter(|r| !r.oncall.is_empty()) else { return (Vec::new(), Vec::new()) };
+
+        let shifts: Vec<(OnCallSchedule, Option<OnCallShift>)> = AlertBackend::instance().get_oncall_shifts().await.into_iter()
+            .filter(|(schedule, _)| recipients.oncall.contains(&schedule.name))
+            .collect();
+        for name in recipients.oncall.iter().filter(|name| !shifts.iter().any(|(schedule, _)| schedule.name == **name)) {
+            log::warn!(subsystem = "alerts.oncall"; "Alert {} was routed to unknown on-call schedule '{name}'", event.alert_id);
+        }
+
+        let members: Vec<String> = shifts.iter()
+            .filter_map(|(_, shift)| shift.as_ref())
+            .flat_map(|shift| std::iter::once(shift.primary.clone()).chain(shift.secondary.clone()))
+            .collect();
+        let users = match telegram_operations::get_telegram_users(&members, pool).await {
+            Ok(users) => users,
+            Err(_) => return (Vec::new(), Vec::new()),
+        };
+
+        let mut pages = Vec::new();
+        let mut escalations = Vec::new();
+        for (schedule, shift) in shifts {
+            let Some(shift) = shift else {
+                log::warn!(subsystem = "alerts.oncall"; "Nobody is on call in schedule '{}', its rotation hasn't started", schedule.name);
+                continue;
+            };
+            let secondary = shift.secondary.as_ref().and_then(|secondary| users.get(secondary)).copied();
+            match (users.get(&shift.primary).copied(), secondary) {
+                (Some(primary), secondary) => {
+                    pages.push(primary);
+                    if let Some(secondary) = secondary.filter(|_| event.requires_ack) {
+                        escalations.push((schedule.escalate_after_s, secondary));
                     }
-                }
+                },
+                (None, Some(secondary)) => {
+                    log::warn!(subsystem = "alerts.oncall"; "'{}' is on call in schedule '{}' but has no Telegram user, paging the secondary", shift.primary, schedule.name);
+                    pages.push(secondary);
+                },
+                (None, None) => log::error!(subsystem = "alerts.oncall"; "Nobody on call in schedule '{}' has a Telegram user, alert {} pages nobody", schedule.name, event.alert_id),
+            }
+        }
+        (pages, escalations)
+    }
+
+    /// Pages the chat after `after_s`, if the alert is still unacked by then.
+    /// Pending escalations are not persisted, the ones of alerts raised before a restart are dropped
+    fn schedule_escalation(event: AlertEvent, after_s: EpochSeconds, chat: TelegramTypeId, msg: String) {
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_secs(after_s)).await;
+            if !TelegramBackend::is_enabled() {
+                return;
+            }
+
+            match alert_operations::get_ack_state(event.alert_id, &TelegramBackend::instance().pool).await {
+                Ok(Some(false)) => (),
+                Ok(_) => return,
+                Err(e) => {
+                    log::error!(subsystem = "alerts.oncall"; "Failed to check whether alert {} was acked, not escalating it. SQL Error = '{e}'", event.alert_id);
+                    return;
+                },
+            }
 
-                if let Err(e) = transaction.commit().await {
-                    log::error!("[ERROR][TELEGRAM] Failed to commit transaction during ack of alert. SQL Error = '{e}'");
-                    return
-                }
-        }});
+            log::info!(subsystem = "alerts.oncall"; "Alert {} unacked after {after_s}s, escalating it to the secondary", event.alert_id);
+            Self::deliver(&event, &[chat], &format!("⏫ *Escalada sin ack*\n\n{msg}")).await;
+        });
     }
 
     pub async fn raw_send_message(msg: &str) {
-        let instance = TelegramBackend::instance();
+        // Not initialized if it was disabled at startup, even if enabled since
+        let instance = match TelegramBackend::try_instance() {
+            Some(instance) if TelegramBackend::is_enabled() => instance,
+            _ => return,
+        };
         let chats = instance.subscribed_chats.read().await;
         let client = &instance.client;
         for chat_id in chats.iter() {
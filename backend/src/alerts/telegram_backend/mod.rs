@@ -15,4 +15,5 @@ struct TelegramAckAction {
 }
 
 
-mod telegram_handler;
\ No newline at end of file
+mod telegram_handler;
+mod telegram_commands;
\ No newline at end of file
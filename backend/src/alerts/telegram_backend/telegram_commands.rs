@@ -0,0 +1,437 @@
+use chrono::{DateTime, TimeDelta, Utc};
+use chrono_tz::Tz;
+use tgbot::api::Client;
+use tgbot::types::{ChatPeerId, EditMessageReplyMarkup, InlineKeyboardMarkup, UserPeerId};
+
+use crate::alerts::telegram_backend::Handler;
+use crate::alerts::telegram_backend::backend::{TelegramBackend, emoji_map, target_label};
+use crate::alerts::{AlertSeverity, TemplateFormat};
+use crate::alerts::alert_backend::AlertBackend;
+use crate::config::Config;
+use crate::model::cache::Cache;
+use crate::model::data::device::Device;
+use crate::model::db::health_check::check_connections;
+use crate::model::db::operations::{alert_operations, syslog_operations, telegram_operations};
+use crate::types::AlertEventId;
+
+// Operational commands for authenticated chats. Replies are MarkdownV2, with every value escaped
+
+/// Telegram rejects messages longer than 4096 characters. Longer replies are split by lines
+const MAX_MESSAGE_LEN: usize = 4000;
+
+/// Alerts listed by `/alerts`, per section
+const ALERTS_LIMIT: usize = 15;
+
+/// Facts shown by `/device`
+const FACTS_LIMIT: usize = 20;
+
+/// Messages shown by `/syslog`, by default and at most. Each one is cut to `SYSLOG_MESSAGE_LEN` characters
+const SYSLOG_DEFAULT: i64 = 10;
+const SYSLOG_MAX: i64 = 25;
+const SYSLOG_MESSAGE_LEN: usize = 150;
+
+/// Longest silence `/silence` sets, in days
+const MAX_SILENCE_DAYS: i64 = 30;
+
+fn escape(text: &str) -> String {
+    TemplateFormat::MarkdownV2.escape(text)
+}
+
+/// Time in `backend/model/alerts/routing_timezone`
+fn local_time(time: DateTime<Utc>) -> String {
+    let timezone: Tz = Config::instance().settings().backend.model.alerts.routing_timezone.parse().unwrap_or(Tz::UTC);
+    time.with_timezone(&timezone).format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+/// Parses durations such as `90s`, `30m`, `2h` or `1d`. A number without unit is in minutes
+fn parse_duration(text: &str) -> Option<TimeDelta> {
+    let text = text.trim().to_lowercase();
+    let (amount, unit) = match text.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => text.split_at(i),
+        None => (text.as_str(), "m"),
+    };
+    let amount: i64 = amount.parse().ok()?;
+    match unit {
+        "s" => TimeDelta::try_seconds(amount),
+        "m" => TimeDelta::try_minutes(amount),
+        "h" => TimeDelta::try_hours(amount),
+        "d" => TimeDelta::try_days(amount),
+        _ => None,
+    }
+}
+
+/// End of a silence of `duration` starting at `now`. None for a duration of 0, which lifts the silence.
+/// Err if the duration is negative or longer than `MAX_SILENCE_DAYS`
+fn silence_until(now: DateTime<Utc>, duration: TimeDelta) -> Result<Option<DateTime<Utc>>, ()> {
+    if duration.is_zero() {
+        return Ok(None);
+    }
+    if duration < TimeDelta::zero() || duration > TimeDelta::days(MAX_SILENCE_DAYS) {
+        return Err(());
+    }
+    now.checked_add_signed(duration).map(Some).ok_or(())
+}
+
+/// Finds a device by name, ignoring case, or by management hostname
+async fn find_device(name: &str) -> Option<Device> {
+    Cache::instance().get_devices().await.into_iter()
+        .find(|device| device.device_name.eq_ignore_ascii_case(name) || device.management_hostname == name)
+}
+
+impl Handler {
+    /// Sends a MarkdownV2 reply, split in several messages if it's too long for one
+    async fn send_reply(client: &Client, chat_id: ChatPeerId, reply: &str) {
+        let mut message = String::new();
+        for line in reply.lines() {
+            if !message.is_empty() && message.len() + line.len() + 1 > MAX_MESSAGE_LEN {
+                Handler::send_markdown(client, chat_id, &message).await;
+                message.clear();
+            }
+            message.push_str(line);
+            message.push('\n');
+        }
+        if !message.is_empty() {
+            Handler::send_markdown(client, chat_id, &message).await;
+        }
+    }
+
+    /// Whether the chat is authenticated. Otherwise, lets the chat know
+    pub(super) async fn require_auth(client: &Client, chat_id: ChatPeerId) -> bool {
+        match telegram_operations::is_auth(chat_id, &TelegramBackend::instance().pool).await {
+            Ok(true) => true,
+            Ok(false) => {
+                Handler::send_message(client, chat_id, "Su chat NO está autenticado, utilice /auth Token").await;
+                false
+            },
+            Err(_) => {
+                Handler::send_message(client, chat_id, "Error inesperado al recuperar el estado de autenticación. Intente nuevamente más tarde").await;
+                false
+            },
+        }
+    }
+
+    /// Name of the identity the user authenticated with, if it's allowed to ack. Otherwise, lets the chat know
+    async fn require_ack_actor(client: &Client, chat_id: ChatPeerId, user_id: UserPeerId) -> Option<String> {
+        let mut transaction = match TelegramBackend::instance().pool.begin().await {
+            Ok(t) => t,
+            Err(e) => {
+                log::error!(subsystem = "telegram.db"; "Failed to init transaction with SQL Error = '{e}'");
+                Handler::send_message(client, chat_id, "Error inesperado al recuperar su usuario. Intente nuevamente más tarde").await;
+                return None;
+            }
+        };
+        match telegram_operations::get_user_name_from_peer_id(user_id, &mut transaction).await {
+            Some((name, true)) => Some(name),
+            Some((name, false)) => {
+                Handler::send_markdown(client, chat_id, &format!("Usuario `{}` No puede realizar ack", escape(&name))).await;
+                None
+            },
+            None => {
+                Handler::send_message(client, chat_id, "Su usuario no está asociado a un token, utilice /auth Token en un chat privado").await;
+                None
+            },
+        }
+    }
+
+    /// `/alerts [severity]`: alerts waiting for an ack, and the rules currently raised
+    pub async fn handle_alerts(client: &Client, chat_id: ChatPeerId, args: Option<&str>) {
+        if !Handler::require_auth(client, chat_id).await {
+            return;
+        }
+        let severity = match args.map(str::trim).filter(|a| !a.is_empty()) {
+            None => None,
+            Some(arg) => match arg.parse::<AlertSeverity>() {
+                Ok(severity) => Some(severity),
+                Err(_) => {
+                    Handler::send_message(client, chat_id, "Severidad desconocida. Uso: /alerts [emergency|alert|critical|error|warning|notice|info|debug]").await;
+                    return;
+                },
+            },
+        };
+
+        let unacked = match alert_operations::get_unacked(severity, ALERTS_LIMIT as i64 + 1, &TelegramBackend::instance().pool).await {
+            Ok(unacked) => unacked,
+            Err(e) => {
+                log::error!(subsystem = "telegram.db"; "Failed to query unacked alerts, with SQL Error = '{e}'");
+                Handler::send_message(client, chat_id, "Error inesperado al recuperar las alertas. Intente nuevamente más tarde").await;
+                return;
+            },
+        };
+        let mut raised = AlertBackend::instance().get_raised_items().await;
+        raised.retain(|(rule, _)| severity.is_none_or(|s| s == rule.severity));
+        raised.sort_by_key(|(rule, item)| (rule.severity.severity_level(), rule.rule_id, *item));
+
+        let mut reply = String::from("*Alertas sin ack*\n");
+        if unacked.is_empty() {
+            reply.push_str(&escape("Ninguna\n"));
+        }
+        for event in unacked.iter().take(ALERTS_LIMIT) {
+            let target = target_label(event.target_id).await.map(|t| t.0).unwrap_or(format!("#{}", event.target_id));
+            let time = event.alert_time.map(local_time).unwrap_or_default();
+            reply.push_str(&format!("{} `{}` {}\n", emoji_map(&event.severity), event.alert_id, escape(&format!("{target}: {} ({time})", event.message))));
+        }
+        if unacked.len() > ALERTS_LIMIT {
+            reply.push_str(&escape(&format!("... y más, vea el historial de alertas. Mostrando las {ALERTS_LIMIT} más recientes\n")));
+        }
+
+        reply.push_str("\n*Reglas activas*\n");
+        if raised.is_empty() {
+            reply.push_str(&escape("Ninguna\n"));
+        }
+        for (rule, item) in raised.iter().take(ALERTS_LIMIT) {
+            let target = target_label(*item).await.map(|t| t.0).unwrap_or(format!("#{item}"));
+            reply.push_str(&format!("{} {}\n", emoji_map(&rule.severity), escape(&format!("'{}' en {target}", rule.name))));
+        }
+        if raised.len() > ALERTS_LIMIT {
+            reply.push_str(&escape(&format!("... y {} más\n", raised.len() - ALERTS_LIMIT)));
+        }
+
+        Handler::send_reply(client, chat_id, &reply).await;
+    }
+
+    /// `/ack <id>`: acks the alert as the identity of the user, and removes the Ack button from the messages of every chat
+    pub async fn handle_ack(client: &Client, chat_id: ChatPeerId, user_id: UserPeerId, args: Option<&str>) {
+        if !Handler::require_auth(client, chat_id).await {
+            return;
+        }
+        let Some(alert_id) = args.and_then(|a| a.trim().parse::<AlertEventId>().ok()) else {
+            Handler::send_message(client, chat_id, "Uso: /ack <id de la alerta>").await;
+            return;
+        };
+        let Some(actor) = Handler::require_ack_actor(client, chat_id, user_id).await else { return };
+
+        let instance = TelegramBackend::instance();
+        match alert_operations::get_ack_state(alert_id, &instance.pool).await {
+            Ok(Some(false)) => (),
+            Ok(Some(true)) => {
+                Handler::send_message(client, chat_id, &format!("La alerta {alert_id} ya tiene ack")).await;
+                return;
+            },
+            Ok(None) => {
+                Handler::send_message(client, chat_id, &format!("No existe la alerta {alert_id}")).await;
+                return;
+            },
+            Err(e) => {
+                log::error!(subsystem = "telegram.db"; "Failed to query the ack state of alert {alert_id}, with SQL Error = '{e}'");
+                Handler::send_message(client, chat_id, "Error inesperado al recuperar la alerta. Intente nuevamente más tarde").await;
+                return;
+            },
+        }
+
+        let mut transaction = match instance.pool.begin().await {
+            Ok(t) => t,
+            Err(e) => {
+                log::error!(subsystem = "telegram"; "Failed to init transaction for alert ack, with SQL Error = '{e}'");
+                Handler::send_message(client, chat_id, "Error inesperado al realizar el ack. Intente nuevamente más tarde").await;
+                return;
+            }
+        };
+        if alert_operations::ack_alert(alert_id, &actor, &mut transaction).await.is_err() {
+            Handler::send_message(client, chat_id, "Error inesperado al realizar el ack. Intente nuevamente más tarde").await;
+            return;
+        }
+
+        // The messages keep their text, only the Ack button is removed
+        let messages = telegram_operations::get_unacked_messages(alert_id, &mut transaction).await.unwrap_or_default();
+        let mut updated = Vec::with_capacity(messages.len());
+        for (chat, message_id) in messages {
+            let method = EditMessageReplyMarkup::for_chat_message(chat, message_id)
+                .with_reply_markup(InlineKeyboardMarkup::default().add_row([]));
+            match client.execute(method).await {
+                Ok(_) => updated.push(chat.into()),
+                Err(e) => log::warn!(subsystem = "telegram"; "Failed to remove the Ack button after /ack! error = {e}"),
+            }
+        }
+        if telegram_operations::ack_messages(alert_id, updated, &mut transaction).await.is_err() {
+            log::error!(subsystem = "telegram"; "Failed to update acked messages in database");
+        }
+
+        if let Err(e) = transaction.commit().await {
+            log::error!(subsystem = "telegram"; "Failed to commit transaction during ack of alert. SQL Error = '{e}'");
+            Handler::send_message(client, chat_id, "Error inesperado al realizar el ack. Intente nuevamente más tarde").await;
+            return;
+        }
+
+        let notice = format!("Ack: {actor}\nAlerta: {alert_id}\nHora: {}", local_time(Utc::now()));
+        Handler::send_markdown(client, chat_id, &escape(&notice)).await;
+    }
+
+    /// `/device <name>`: status, silence and requested facts of the device
+    pub async fn handle_device(client: &Client, chat_id: ChatPeerId, args: Option<&str>) {
+        if !Handler::require_auth(client, chat_id).await {
+            return;
+        }
+        let Some(name) = args.map(str::trim).filter(|a| !a.is_empty()) else {
+            Handler::send_message(client, chat_id, "Uso: /device <nombre o hostname>").await;
+            return;
+        };
+        let Some(device) = find_device(name).await else {
+            Handler::send_markdown(client, chat_id, &format!("No se encontró el dispositivo `{}`", escape(name))).await;
+            return;
+        };
+
+        let mut reply = format!("🖥️ *{}*\n", escape(&device.device_name));
+        reply.push_str(&escape(&format!(
+            "Hostname: {}\nICMP: {}\nAnsible: {}\n",
+            device.management_hostname, device.state.icmp_status, device.state.ansible_status,
+        )));
+        if let Some(silence) = AlertBackend::instance().get_silence(device.device_id).await {
+            reply.push_str(&escape(&format!("🔕 Silenciado hasta {} por {}\n", local_time(silence.until), silence.actor)));
+        }
+
+        let facts = Cache::instance().get_facts(&device.management_hostname, &device.configuration.requested_metrics).await.unwrap_or_default();
+        let mut facts: Vec<_> = facts.into_iter().collect();
+        facts.sort_by(|a, b| a.0.cmp(&b.0));
+        reply.push_str("\n*Métricas*\n");
+        if facts.is_empty() {
+            reply.push_str(&escape("Sin datos\n"));
+        }
+        for (name, value) in facts.iter().take(FACTS_LIMIT) {
+            reply.push_str(&format!("`{}`: {}\n", escape(name), escape(&value.to_string())));
+        }
+        if facts.len() > FACTS_LIMIT {
+            reply.push_str(&escape(&format!("... y {} más\n", facts.len() - FACTS_LIMIT)));
+        }
+
+        Handler::send_reply(client, chat_id, &reply).await;
+    }
+
+    /// `/silence <device> <duration>`: stops notifying the alerts of the device for a while. A duration of 0 lifts the silence
+    pub async fn handle_silence(client: &Client, chat_id: ChatPeerId, user_id: UserPeerId, args: Option<&str>) {
+        if !Handler::require_auth(client, chat_id).await {
+            return;
+        }
+        let Some((name, duration)) = args.and_then(|a| a.trim().rsplit_once(' ')).and_then(|(n, d)| Some((n.trim(), parse_duration(d)?))) else {
+            Handler::send_message(client, chat_id, "Uso: /silence <dispositivo> <duración, como 30m, 2h o 1d. 0 para levantarlo>").await;
+            return;
+        };
+        let Some(device) = find_device(name).await else {
+            Handler::send_markdown(client, chat_id, &format!("No se encontró el dispositivo `{}`", escape(name))).await;
+            return;
+        };
+        let Some(actor) = Handler::require_ack_actor(client, chat_id, user_id).await else { return };
+
+        let Ok(until) = silence_until(Utc::now(), duration) else {
+            Handler::send_message(client, chat_id, &format!("Duración fuera de rango, el silencio máximo es de {MAX_SILENCE_DAYS} días")).await;
+            return;
+        };
+        if AlertBackend::instance().set_silence(device.device_id, until, &actor).await.is_err() {
+            Handler::send_message(client, chat_id, "Error inesperado al guardar el silencio. Intente nuevamente más tarde").await;
+            return;
+        }
+
+        let reply = match until {
+            Some(until) => format!("🔕 {} silenciado hasta {}. Sus alertas se registran, pero no se notifican", device.device_name, local_time(until)),
+            None => format!("🔔 Silencio de {} levantado", device.device_name),
+        };
+        Handler::send_markdown(client, chat_id, &escape(&reply)).await;
+    }
+
+    /// `/syslog <host> [n]`: latest syslog messages of a device, by name or hostname, or of any host
+    pub async fn handle_syslog(client: &Client, chat_id: ChatPeerId, args: Option<&str>) {
+        if !Handler::require_auth(client, chat_id).await {
+            return;
+        }
+        let args = args.unwrap_or_default().trim();
+        let (host, count) = match args.rsplit_once(' ').map(|(h, n)| (h.trim(), n.parse::<i64>())) {
+            Some((host, Ok(count))) => (host, count.clamp(1, SYSLOG_MAX)),
+            _ => (args, SYSLOG_DEFAULT),
+        };
+        if host.is_empty() {
+            Handler::send_message(client, chat_id, &format!("Uso: /syslog <dispositivo o host> [cantidad, hasta {SYSLOG_MAX}]")).await;
+            return;
+        }
+        let host = find_device(host).await.map(|d| d.management_hostname).unwrap_or(host.to_string());
+
+        let messages = match syslog_operations::get_latest_rows(&host, count, &TelegramBackend::instance().pool).await {
+            Ok(messages) => messages,
+            Err(e) => {
+                log::error!(subsystem = "telegram.db"; "Failed to query syslog messages of '{host}', with SQL Error = '{e}'");
+                Handler::send_message(client, chat_id, "Error inesperado al recuperar los mensajes. Intente nuevamente más tarde").await;
+                return;
+            },
+        };
+
+        let mut reply = format!("📜 *Syslog de {}*\n", escape(&host));
+        if messages.is_empty() {
+            reply.push_str(&escape("Sin mensajes\n"));
+        }
+        // Oldest first, as a log reads
+        for message in messages.iter().rev() {
+            let time = message.received_at.map(local_time).unwrap_or_default();
+            let text: String = message.msg.chars().take(SYSLOG_MESSAGE_LEN).collect();
+            reply.push_str(&format!("`{}` {}\n", escape(&time), escape(&format!("[{}] {text}", message.severity))));
+        }
+
+        Handler::send_reply(client, chat_id, &reply).await;
+    }
+
+    /// `/health`: the same checks as the backend health endpoint
+    pub async fn handle_health(client: &Client, chat_id: ChatPeerId) {
+        if !Handler::require_auth(client, chat_id).await {
+            return;
+        }
+        let instance = TelegramBackend::instance();
+        let health = check_connections(&instance.pool, &instance.influx_client).await;
+        let status = &health["status"];
+
+        let mark = |ok: bool| if ok { "✅" } else { "❌" };
+        let service = |name: &str, status: &serde_json::Value| {
+            let msg = status["msg"].as_str().unwrap_or_default();
+            let msg = if msg.is_empty() { String::new() } else { format!(" {msg}") };
+            format!("{name}: {}{msg}\n", mark(status["up"].as_bool().unwrap_or(false)))
+        };
+
+        let mut reply = String::from("🩺 *Estado del backend*\n");
+        let mut plain = service("PostgreSQL", &status["postgres"]);
+        plain.push_str(&service("InfluxDB", &status["influx"]));
+        let schema = &status["influx-schema"];
+        let drift = schema["drift"].as_array().map(Vec::len).unwrap_or_default();
+        plain.push_str(&format!("Esquema de InfluxDB: {}{}\n",
+            mark(schema["in-sync"].as_bool().unwrap_or(false)),
+            if drift > 0 { format!(" {drift} diferencias") } else { String::new() },
+        ));
+        plain.push_str(&format!("Solo lectura: {}\n", if status["backend"]["read-only"].as_bool().unwrap_or(false) { "sí" } else { "no" }));
+        reply.push_str(&escape(&plain));
+
+        reply.push_str("\n*Tareas*\n");
+        for (name, task) in status["tasks"].as_object().into_iter().flatten() {
+            let state = task["state"].as_str().unwrap_or("unknown");
+            let restarts = task["restarts"].as_u64().unwrap_or_default();
+            let line = format!("{} {name}: {state}, {restarts} reinicios\n", mark(state == "running"));
+            reply.push_str(&escape(&line));
+        }
+
+        Handler::send_reply(client, chat_id, &reply).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeDelta, TimeZone, Utc};
+
+    use super::{MAX_SILENCE_DAYS, parse_duration, silence_until};
+
+    #[test]
+    fn silence_durations() {
+        assert_eq!(parse_duration("90s"), Some(TimeDelta::seconds(90)));
+        assert_eq!(parse_duration("30m"), Some(TimeDelta::minutes(30)));
+        assert_eq!(parse_duration("2H"), Some(TimeDelta::hours(2)));
+        assert_eq!(parse_duration("1d"), Some(TimeDelta::days(1)));
+        assert_eq!(parse_duration("45"), Some(TimeDelta::minutes(45)));
+        assert_eq!(parse_duration("0"), Some(TimeDelta::zero()));
+
+        assert_eq!(parse_duration(""), None);
+        assert_eq!(parse_duration("2w"), None);
+        assert_eq!(parse_duration("h"), None);
+        assert_eq!(parse_duration("-5m"), None);
+
+        // Too long to add to a date, yet a valid duration
+        let now = Utc.with_ymd_and_hms(2026, 10, 19, 12, 0, 0).unwrap();
+        let huge = parse_duration("100000000d").expect("Duration should parse");
+        assert_eq!(silence_until(now, huge), Err(()));
+        assert_eq!(silence_until(now, TimeDelta::days(MAX_SILENCE_DAYS + 1)), Err(()));
+        assert_eq!(silence_until(now, TimeDelta::zero()), Ok(None));
+        assert_eq!(silence_until(now, TimeDelta::hours(2)), Ok(Some(now + TimeDelta::hours(2))));
+    }
+}
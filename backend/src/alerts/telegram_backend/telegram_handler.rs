@@ -6,6 +6,8 @@ use sqlx::{Postgres, Transaction};
 use tgbot::types::{AnswerCallbackQuery, CallbackQuery, EditMessageReplyMarkup, EditMessageText, InlineKeyboardMarkup, MaybeInaccessibleMessage, Message};
 use tgbot::{api::Client, handler::UpdateHandler, types::{ChatPeerId, ParseMode, UserPeerId}};
 
+use crate::alerts::TemplateFormat;
+use crate::alerts::alert_backend::AlertBackend;
 use crate::alerts::telegram_backend::backend::TelegramBackend;
 use crate::alerts::telegram_backend::{Handler, TelegramAckAction};
 use crate::model::db::operations::{alert_operations, telegram_operations};
@@ -33,26 +35,35 @@ fn escape_with_backslash(s: &str, to_escape: &[char]) -> String {
         .collect()
 }
 
+/// Escaping of plain messages, which may use code blocks and inline code
+pub(super) fn escape_message(s: &str) -> String {
+    escape_with_backslash(s, &['.', '=', '\\'])
+}
+
 impl Handler {
     pub async fn send_message(client: &Client, chat_id: ChatPeerId, message: &str) {
-        let message = escape_with_backslash(message, &['.', '=', '\\']);
-        let method = tgbot::types::SendMessage::new(chat_id, &message).with_parse_mode(ParseMode::MarkdownV2);
+        Handler::send_markdown(client, chat_id, &escape_message(message)).await;
+    }
+
+    /// Sends a message that's already escaped for MarkdownV2
+    pub async fn send_markdown(client: &Client, chat_id: ChatPeerId, message: &str) {
+        let method = tgbot::types::SendMessage::new(chat_id, message).with_parse_mode(ParseMode::MarkdownV2);
         match client.execute(method).await {
             Ok(_) => (),
             Err(e) => {
-                log::error!("[ERROR][ALERTS][TELEGRAM] failed to send message = '{}' with error = '{e}'. Message will be dropped", &message);
+                log::error!(subsystem = "alerts.telegram"; "failed to send message = '{}' with error = '{e}'. Message will be dropped", &message);
             }
         }
     }
 
+    /// Sends a message that's already escaped for MarkdownV2, with a button to ack the alert
     pub async fn send_message_button(client: &Client, chat_id: ChatPeerId, message: &str, alert_id: AlertEventId) -> Result<(ChatPeerId, i64), ()>{
-        let message = escape_with_backslash(message, &['.', '=', '\\']);
         use tgbot::types::*;
         let yes_btn = InlineKeyboardButton::for_callback_data_struct("Ack", &TelegramAckAction{ chat_id, alert_id, acked: true}).unwrap();
 
         let keyboard = InlineKeyboardMarkup::default().add_row(vec![yes_btn]);
 
-        let method = SendMessage::new(chat_id, &message)
+        let method = SendMessage::new(chat_id, message)
             .with_reply_markup(keyboard)
             .with_parse_mode(ParseMode::MarkdownV2);
         match client.execute(method).await {
@@ -60,7 +71,7 @@ impl Handler {
                 Ok((m.chat.get_id(), m.id))
             },
             Err(e) => {
-                log::error!("[ERROR][ALERTS][TELEGRAM] failed to send message with error = '{e}'. Message will be dropped");
+                log::error!(subsystem = "alerts.telegram"; "failed to send message with error = '{e}'. Message will be dropped");
                 Err(())
             }
         }
@@ -79,7 +90,7 @@ impl Handler {
             Ok(t) => t,
             Err(e) => {
                 Handler::send_message(client, chat_id, "Fallo en iniciar la transacción. Consulte con su administrador").await;
-                log::error!("[ERROR][TELEGRAM] Failed to init auth transaction. SQL Error = '{}'", e);
+                log::error!(subsystem = "telegram"; "Failed to init auth transaction. SQL Error = '{}'", e);
                 return;
             }
         };
@@ -128,11 +139,11 @@ impl Handler {
         // 6.- Commit transaction
         match transaction.commit().await {
             Ok(_) => {
-                log::info!("[INFO][TELEGRAM][AUTH] Commit on database with updated values");
+                log::info!(subsystem = "telegram.auth"; "Commit on database with updated values");
             },
             Err(e) => {
                 Handler::send_message(client, chat_id, "Error inesperado al realizar la autenticación. Intente nuevamente más tarde").await;
-                log::error!("[ERROR][TELEGRAM] Failed to commit auth transaction. SQL Error = '{e}'");
+                log::error!(subsystem = "telegram"; "Failed to commit auth transaction. SQL Error = '{e}'");
                 return; // early bail. rollback on the transaction
             }
         }
@@ -153,7 +164,7 @@ impl Handler {
         let mut transaction = match instance.begin().await {
             Ok(t) => t,
             Err(e) => {
-                log::error!("[ERROR][TELEGRAM][DB] Failed to init transaction with SQL Error = '{e}'");
+                log::error!(subsystem = "telegram.db"; "Failed to init transaction with SQL Error = '{e}'");
                 Handler::send_message(client, chat_id, "Error inesperado al recuperar el estado de autenticación. Intente nuevamente más tarde").await;
                 return;
             }
@@ -181,7 +192,7 @@ impl Handler {
         match transaction.commit().await {
             Ok(_) => {},
             Err(e) => {
-                log::error!("[ERROR][TELEGRAM][DB] Failed to commit transaction with SQL Error = '{e}'");
+                log::error!(subsystem = "telegram.db"; "Failed to commit transaction with SQL Error = '{e}'");
                 Handler::send_message(client, chat_id, "Error inesperado al recuperar el estado de autenticación. Intente nuevamente más tarde").await;
                 return;
             }
@@ -199,7 +210,7 @@ impl Handler {
         let mut transaction = match instance.begin().await {
             Ok(t) => t,
             Err(e) => {
-                log::error!("[ERROR][TELEGRAM][DB] Failed to init transaction with SQL Error = '{e}'");
+                log::error!(subsystem = "telegram.db"; "Failed to init transaction with SQL Error = '{e}'");
                 Handler::send_message(client, chat_id, "Error inesperado al recuperar el estado de autenticación. Intente nuevamente más tarde").await;
                 return;
             }
@@ -224,7 +235,7 @@ impl Handler {
         match transaction.commit().await {
             Ok(_) => {},
             Err(e) => {
-                log::error!("[ERROR][TELEGRAM][DB] Failed to commit transaction with SQL Error = '{e}'");
+                log::error!(subsystem = "telegram.db"; "Failed to commit transaction with SQL Error = '{e}'");
                 Handler::send_message(client, chat_id, "Error inesperado al recuperar el estado de autenticación. Intente nuevamente más tarde").await;
                 return;
             }
@@ -260,6 +271,35 @@ impl Handler {
         Handler::send_message(client, chat_id, &format!("{}\n{}", auth_status, sub_status).to_string()).await;
     }
 
+    /// Who is on call in each schedule, and until when. Only for authenticated chats, as it names the members
+    pub async fn handle_oncall(client: &Client, chat_id: ChatPeerId) {
+        if !Handler::require_auth(client, chat_id).await {
+            return;
+        }
+
+        let shifts = AlertBackend::instance().get_oncall_shifts().await;
+        if shifts.is_empty() {
+            Handler::send_message(client, chat_id, "No hay calendarios de guardia configurados").await;
+            return;
+        }
+
+        let escape = |text: &str| TemplateFormat::MarkdownV2.escape(text);
+        let mut message = String::from("📟 *Guardias actuales*\n");
+        for (schedule, shift) in shifts {
+            message.push_str(&format!("\n*{}*\n", escape(&schedule.name)));
+            match shift {
+                Some(shift) => message.push_str(&escape(&format!(
+                    "Guardia: {}\nRespaldo: {}\nHasta: {}\n",
+                    shift.primary,
+                    shift.secondary.as_deref().unwrap_or("Sin respaldo"),
+                    shift.until.format("%Y-%m-%d %H:%M"),
+                ))),
+                None => message.push_str(&escape("La rotación aún no comienza\n")),
+            }
+        }
+        Handler::send_markdown(client, chat_id, &message).await;
+    }
+
     async fn _handle_auth_private<'e>(client: &Client, chat_id: &ChatPeerId, user_id: UserPeerId, ack_token: String, transaction: &mut Transaction<'e, Postgres>, executor: &sqlx::Pool<Postgres>) -> Result<(), ()> {
         // 1.- Check if the user can be associated with the token
         // AKA: the token is not associated, or it's already been associated with THIS user
@@ -315,6 +355,13 @@ async fn _handle_incomming_message(client: &Client, chat_id: Option<ChatPeerId>,
         "/subscribe"   => Handler::handle_subscribe(client, chat_id).await,
         "/unsubscribe" => Handler::handle_unsubscribe(client, chat_id).await,
         "/chat_status" => Handler::handle_chat_status(client, chat_id).await,
+        "/oncall"      => Handler::handle_oncall(client, chat_id).await,
+        "/alerts"      => Handler::handle_alerts(client, chat_id, args).await,
+        "/ack"         => Handler::handle_ack(client, chat_id, user_id, args).await,
+        "/device"      => Handler::handle_device(client, chat_id, args).await,
+        "/silence"     => Handler::handle_silence(client, chat_id, user_id, args).await,
+        "/syslog"      => Handler::handle_syslog(client, chat_id, args).await,
+        "/health"      => Handler::handle_health(client, chat_id).await,
         _ => {}
     }
 }
@@ -325,14 +372,14 @@ async fn _handle_batch_message_ack(except: (ChatPeerId, i64), client: &Client, t
     let mut transaction = match instance.pool.begin().await {
         Ok(t) => t,
         Err(e) => {
-            log::error!("[ERROR][TELEGRAM] Failed to init transaction for alert ack, with SQL Error = '{e}'");
+            log::error!(subsystem = "telegram"; "Failed to init transaction for alert ack, with SQL Error = '{e}'");
             return Err(());
         }
     };
     let data : TelegramAckAction = match serde_json::from_str(&callback_query.data.unwrap_or_default()) {
         Ok(v) => v,
         Err(e) => {
-            log::error!("[ERROR][TELEGRAM] Failed to parse telegram ack action from callback query on ack button. Can't update ack status on messages on other chats! E = '{e}'");
+            log::error!(subsystem = "telegram"; "Failed to parse telegram ack action from callback query on ack button. Can't update ack status on messages on other chats! E = '{e}'");
             return Err(());
         }
     };
@@ -340,7 +387,7 @@ async fn _handle_batch_message_ack(except: (ChatPeerId, i64), client: &Client, t
     let data = match telegram_operations::get_unacked_messages(data.alert_id, &mut transaction).await  {
         Ok(d) => d,
         Err(e) => {
-            log::error!("[ERROR][TELEGRAM] Failed to get unacked telegram messages from database, e = '{e}'"); 
+            log::error!(subsystem = "telegram"; "Failed to get unacked telegram messages from database, e = '{e}'"); 
             return Err(())
         }
     };
@@ -350,19 +397,18 @@ async fn _handle_batch_message_ack(except: (ChatPeerId, i64), client: &Client, t
             ::for_chat_message(pair.0, pair.1, text)
             .with_parse_mode(ParseMode::MarkdownV2);
         if let Err(e) = client.execute(method).await {
-            log::warn!("[WARN ][TELEGRAM] Failed to update message contents after ack! error = {e}");
-            log::info!("                           ^ Chat Peer Id = {}, messageId= {}", pair.0, pair.1);
+            log::warn!(subsystem = "telegram"; "Failed to update message contents after ack! error = {e}, Chat Peer Id = {}, messageId= {}", pair.0, pair.1);
         } else {
             acked.push(pair.0.into());
         }
     }
     if (telegram_operations::ack_messages(alert_id, acked, &mut transaction).await).is_err() {
-        log::error!("[ERROR][TELEGRAM] Failed to update acked messages in database");
+        log::error!(subsystem = "telegram"; "Failed to update acked messages in database");
         return Err(());
     }
 
     if let Err(e) = transaction.commit().await {
-        log::error!("[ERROR][TELEGRAM] Failed to commit transaction during ack of alert. SQL Error = '{e}'");
+        log::error!(subsystem = "telegram"; "Failed to commit transaction during ack of alert. SQL Error = '{e}'");
         return Err(());
     }
 
@@ -384,7 +430,7 @@ async fn _handle_callback_query(client: &Client, callback_query: CallbackQuery)
     let mut transaction = match instance.pool.begin().await {
         Ok(t) => t,
         Err(e) => {
-            log::error!("[ERROR][TELEGRAM] Failed to init transaction for alert ack, with SQL Error = '{e}'");
+            log::error!(subsystem = "telegram"; "Failed to init transaction for alert ack, with SQL Error = '{e}'");
             return;
         }
     };
@@ -393,7 +439,7 @@ async fn _handle_callback_query(client: &Client, callback_query: CallbackQuery)
     let (ack_actor_name, can_ack) = match telegram_operations::get_user_name_from_peer_id(user, &mut transaction).await {
         Some(name) => name,
         None => {
-            log::error!("[ERROR][TELEGRAM] Failed to get user name from telegram peer id. This user might not be authed!");
+            log::error!(subsystem = "telegram"; "Failed to get user name from telegram peer id. This user might not be authed!");
             return;
         }
     };
@@ -406,20 +452,20 @@ async fn _handle_callback_query(client: &Client, callback_query: CallbackQuery)
             .with_show_alert(true);
 
         if let Err(e) = client.execute(method).await {
-            log::error!("[ERROR][TELEGRAM] Failed to send AnswerCallbackResponse after ack! error = {e}");
+            log::error!(subsystem = "telegram"; "Failed to send AnswerCallbackResponse after ack! error = {e}");
         };
         return;
     }
 
     // 5.- Ack the alert in the database
     if (alert_operations::ack_alert(telegram_ack_action.alert_id, &ack_actor_name, &mut transaction).await).is_err() {
-        log::error!("[ERROR][TELEGRAM] Failed to ack alert event! Rolling back...");
+        log::error!(subsystem = "telegram"; "Failed to ack alert event! Rolling back...");
         return;
     }
 
     // 6.- Commit the transaction
     if let Err(e) = transaction.commit().await {
-        log::error!("[ERROR][TELEGRAM] Failed to commit transaction during ack of alert. SQL Error = '{e}'");
+        log::error!(subsystem = "telegram"; "Failed to commit transaction during ack of alert. SQL Error = '{e}'");
         return
     }
 
@@ -429,7 +475,7 @@ async fn _handle_callback_query(client: &Client, callback_query: CallbackQuery)
         .with_text(&notice)
         .with_show_alert(true);
     if let Err(e) = client.execute(method).await {
-        log::error!("[ERROR][TELEGRAM] Failed to send AnswerCallbackResponse after ack! error = {e}");
+        log::error!(subsystem = "telegram"; "Failed to send AnswerCallbackResponse after ack! error = {e}");
         return;
     };
 
@@ -437,7 +483,7 @@ async fn _handle_callback_query(client: &Client, callback_query: CallbackQuery)
     let m = match &callback_query.message {
         Some(MaybeInaccessibleMessage::Message(m)) => m,
         _ => {
-            log::warn!("[WARN ][TELEGRAM] Ack'd for unavailable message. This is rare");
+            log::warn!(subsystem = "telegram"; "Ack'd for unavailable message. This is rare");
             return;
         }
     };
@@ -446,14 +492,14 @@ async fn _handle_callback_query(client: &Client, callback_query: CallbackQuery)
         .with_reply_markup(keyboard);
 
     if let Err(e) = client.execute(method).await {
-        log::error!("[ERROR][TELEGRAM] Failed to update message reply inline Keyboard after ack! error = {e}");
+        log::error!(subsystem = "telegram"; "Failed to update message reply inline Keyboard after ack! error = {e}");
         return;
     };
 
     let text = match m.get_text() {
         Some(t) => &t.data,
         None => {
-            log::error!("[ERROR][TELEGRAM] Failed to get text from message for update during ack");
+            log::error!(subsystem = "telegram"; "Failed to get text from message for update during ack");
             return;
         }
     };
@@ -462,13 +508,13 @@ async fn _handle_callback_query(client: &Client, callback_query: CallbackQuery)
         ::for_chat_message(telegram_ack_action.chat_id, m.id, text.clone())
         .with_parse_mode(ParseMode::MarkdownV2);
     if let Err(e) = client.execute(method).await {
-        log::error!("[ERROR][TELEGRAM] Failed to update message contents after ack! error = {e}");
+        log::error!(subsystem = "telegram"; "Failed to update message contents after ack! error = {e}");
         return;
     };
 
     // 9.- Update messages on other chats that reference the same alert
     if (_handle_batch_message_ack((m.chat.get_id(), m.id), client, &text, callback_query).await).is_err() {
-        log::error!("[ERROR][TELEGRAM] Failed to update messages after ack")
+        log::error!(subsystem = "telegram"; "Failed to update messages after ack")
     }
 
 
@@ -476,6 +522,9 @@ async fn _handle_callback_query(client: &Client, callback_query: CallbackQuery)
 
 impl UpdateHandler for Handler {
     async fn handle(&self, update: tgbot::types::Update) {
+        if !TelegramBackend::is_enabled() {
+            return;
+        }
         let chat_id = update.get_chat_id();
         let user_id = update.get_user_id();
         match update.update_type {
@@ -888,4 +888,252 @@ mod alert_backend_tests {
         assert!(device.clone().eval(&rulee, &dataset, &dataset).await.is_some()); // TRUE
     }
 
-}
\ No newline at end of file
+
+    #[tokio::test]
+    pub async fn test_link_alert_rules() {
+        use crate::model::{cache::Cache, data::{link::Link, link_state::{InterfaceState, LinkMessage, LinkState}}};
+
+        let link : Link = serde_json::from_value(serde_json::json!({
+            "id": 9001,
+            "side-a": 9002,
+            "side-b": 9003,
+            "side-a-iface": "eth0",
+            "side-b-iface": "eth1",
+            "link-type": "copper"
+        })).expect("Definition should be valid");
+        let link = EvaluableItem::Link(link);
+
+        let state = |up: bool| LinkState::new(
+            InterfaceState { up: Some(true), out_bps: Some(1000.0), ..Default::default() },
+            InterfaceState { up: Some(up), ..Default::default() },
+        );
+        Cache::instance().update_link_states(LinkMessage::from([(9001, state(true))])).await;
+        Cache::instance().update_link_states(LinkMessage::from([(9001, state(false))])).await;
+
+        let rule = |kind: &str, left: &str, op: &str, right: serde_json::Value| -> AlertRule {
+            serde_json::from_value(serde_json::json!({
+                "id": 1,
+                "name": "TEST RULE - link",
+                "requires-ack": false,
+                "severity": AlertSeverity::Debug,
+                "target": 9001,
+                "reduce-logic": AlertReduceLogic::All,
+                "rule-type": kind,
+                "data-source": "facts",
+                "predicates": [{ "left": left, "op": op, "right": right }]
+            })).expect("Definition should be valid")
+        };
+
+        let empty = FactMessage::new();
+        assert!(link.clone().eval(&rule("simple", "&link_status", "equal", serde_json::json!("down")), &empty, &empty).await.is_some()); // TRUE
+        assert!(link.clone().eval(&rule("simple", "&a_to_b_bps", "more_than", serde_json::json!(5000)), &empty, &empty).await.is_none()); // FALSE
+        assert!(link.clone().eval(&rule("delta", "&link_up", "not_equal", serde_json::json!("&link_up")), &empty, &empty).await.is_some()); // TRUE
+    }
+
+    #[test]
+    pub fn test_rule_preview() {
+        let dataset = HashMap::from([
+            ("icmp_rtt".to_string(), MetricValue::Integer(120)),
+            ("icmp_status".to_string(), MetricValue::String("Reachable".to_string())),
+        ]);
+
+        let rule: AlertRule = serde_json::from_value(serde_json::json!({
+            "severity": AlertSeverity::Debug,
+            "target": 10,
+            "reduce-logic": AlertReduceLogic::All,
+            "rule-type": "simple",
+            "data-source": "facts",
+            "predicates": [{
+                "left": "&icmp_rtt",
+                "left-modifier": {"mul": 0.5},
+                "op": "more_than",
+                "right": 50,
+            },
+            {
+                "left": "&icmp_status",
+                "op": "equal",
+                "right": "Unreachable",
+            },
+            {
+                "left": "&missing",
+                "op": "equal",
+                "right": 0,
+            }]
+        })).expect("Definition should be valid");
+
+        let (result, predicates) = rule.preview(&dataset, &dataset);
+        assert!(!result);
+        assert_eq!(predicates.iter().map(|p| p.result).collect::<Vec<_>>(), vec![true, false, false]);
+        assert_eq!(predicates[0].left, Some(MetricValue::Number(60.0.into())));
+        assert_eq!(predicates[1].left, Some(MetricValue::String("Reachable".to_string())));
+        assert_eq!(predicates[2].left, None);
+
+        // Delta rules compare the previous dataset against the current one
+        let delta: AlertRule = serde_json::from_value(serde_json::json!({
+            "severity": AlertSeverity::Debug,
+            "target": 10,
+            "reduce-logic": AlertReduceLogic::All,
+            "rule-type": "delta",
+            "data-source": "facts",
+            "predicates": [{"left": "&icmp_status", "op": "not_equal", "right": "&icmp_status"}]
+        })).expect("Definition should be valid");
+        let previous = HashMap::from([("icmp_status".to_string(), MetricValue::String("Unreachable".to_string()))]);
+
+        assert!(!delta.preview(&dataset, &dataset).0);
+        let (result, predicates) = delta.preview(&previous, &dataset);
+        assert!(result);
+        assert_eq!(predicates[0].left, Some(MetricValue::String("Unreachable".to_string())));
+        assert_eq!(predicates[0].right, Some(MetricValue::String("Reachable".to_string())));
+    }
+
+    #[test]
+    pub fn test_rule_fingerprint() {
+        let definition = serde_json::json!({
+            "name": "Slow ping",
+            "severity": AlertSeverity::Warning,
+            "target": 10,
+            "reduce-logic": AlertReduceLogic::All,
+            "rule-type": {"sustained": {"seconds": 60}},
+            "data-source": "facts",
+            "predicates": [{ "left": "&icmp_rtt", "op": "more_than", "right": 50 }]
+        });
+        let rule: AlertRule = serde_json::from_value(definition.clone()).expect("Definition should be valid");
+
+        // Renaming or changing how the alert is delivered keeps the timers of a rule
+        let mut renamed = definition.clone();
+        renamed["name"] = serde_json::json!("Slow ping on core");
+        renamed["severity"] = serde_json::json!(AlertSeverity::Critical);
+        let renamed: AlertRule = serde_json::from_value(renamed).expect("Definition should be valid");
+        assert_eq!(rule.fingerprint(), renamed.fingerprint());
+
+        // Changing what it evaluates doesn't
+        let mut changed = definition.clone();
+        changed["rule-type"] = serde_json::json!({"sustained": {"seconds": 120}});
+        let changed: AlertRule = serde_json::from_value(changed).expect("Definition should be valid");
+        assert_ne!(rule.fingerprint(), changed.fingerprint());
+
+        let mut changed = definition;
+        changed["predicates"][0]["right"] = serde_json::json!(80);
+        let changed: AlertRule = serde_json::from_value(changed).expect("Definition should be valid");
+        assert_ne!(rule.fingerprint(), changed.fingerprint());
+    }
+
+    #[test]
+    pub fn test_message_template() {
+        use crate::alerts::{MessageTemplate, TemplateContext, TemplateFormat};
+
+        let context = TemplateContext {
+            device: "core-1".to_string(),
+            hostname: "10.0.0.1".to_string(),
+            rule: "Slow ping".to_string(),
+            severity: AlertSeverity::Warning,
+            metrics: HashMap::from([("icmp_rtt".to_string(), MetricValue::Number(92.5.into()))]),
+            ..Default::default()
+        };
+        let template = MessageTemplate("{severity}: RTT of {device} ({hostname}) is {metric.icmp_rtt}ms, was {previous.icmp_rtt}. {{literal}}".to_string());
+        assert_eq!(template.metric_names(), HashSet::from(["icmp_rtt"]));
+        assert_eq!(template.render(&context, TemplateFormat::Plain), "warning: RTT of core-1 (10.0.0.1) is 92.5ms, was -. {literal}");
+        assert_eq!(template.render(&context, TemplateFormat::MarkdownV2), r"warning: RTT of core\-1 \(10\.0\.0\.1\) is 92\.5ms, was \-\. \{literal\}");
+
+        // Values are escaped as well as the template
+        let context = TemplateContext { device: "<b>&core</b>".to_string(), ..context };
+        assert_eq!(MessageTemplate("{device} > 80".to_string()).render(&context, TemplateFormat::Html), "&lt;b&gt;&amp;core&lt;/b&gt; &gt; 80");
+
+        assert!(MessageTemplate("{group} {rule} {value} {duration}".to_string()).validate().is_ok());
+        assert!(MessageTemplate("{devices}".to_string()).validate().is_err());
+        assert!(MessageTemplate("{metric.}".to_string()).validate().is_err());
+        assert!(MessageTemplate("{device".to_string()).validate().is_err());
+        assert!(MessageTemplate("device}".to_string()).validate().is_err());
+    }
+
+    #[test]
+    pub fn test_alert_routes() {
+        use chrono::TimeZone;
+        use crate::alerts::{AlertEvent, AlertRecipients, AlertRoute};
+
+        let routes: Vec<AlertRoute> = serde_json::from_value(serde_json::json!([
+            {
+                "id": 1, "name": "Core on-call, nights",
+                "match": {"severities": ["critical"], "targets": [100], "schedule": {"days": ["Fri"], "from": "22:00:00", "to": "06:00:00"}},
+                "destinations": [{"telegram": {"chats": [7, 8]}}, {"webhook": {"url": "https://hooks.example.com/aegis"}}]
+            },
+            {
+                "id": 2, "name": "Rule owners",
+                "match": {"rules": [5]},
+                "destinations": [{"email": {"recipients": ["noc@example.com"]}}, {"telegram": {"chats": [8]}}]
+            }
+        ])).expect("Routes should be valid");
+
+        let tz = chrono_tz::America::Mexico_City;
+        let friday_night = tz.with_ymd_and_hms(2026, 10, 16, 23, 30, 0).unwrap();
+        let saturday_morning = tz.with_ymd_and_hms(2026, 10, 17, 5, 0, 0).unwrap();
+        let saturday_noon = tz.with_ymd_and_hms(2026, 10, 17, 12, 0, 0).unwrap();
+
+        let event = AlertEvent::new(false, AlertSeverity::Critical, "down".to_string(), 10, None, 5, String::new(), None);
+        let in_group = HashSet::from([100]);
+
+        // Both routes match, across midnight as the window started on Friday
+        for now in [friday_night, saturday_morning] {
+            let recipients = AlertRoute::resolve(&routes, &event, &in_group, now).expect("Routes should match");
+            assert_eq!(recipients, AlertRecipients {
+                telegram_chats: HashSet::from([7, 8]),
+                webhooks: vec!["https://hooks.example.com/aegis".to_string()],
+                emails: vec!["noc@example.com".to_string()],
+                oncall: Vec::new(),
+            });
+        }
+
+        // Out of the window, or out of the group, only the rule route matches
+        let recipients = AlertRoute::resolve(&routes, &event, &in_group, saturday_noon).expect("Rule route should match");
+        assert_eq!(recipients.telegram_chats, HashSet::from([8]));
+        assert!(recipients.webhooks.is_empty());
+        assert!(AlertRoute::resolve(&routes, &event, &HashSet::new(), friday_night).is_some_and(|r| r.webhooks.is_empty()));
+
+        // Nothing matches, so the event goes to every subscribed chat
+        let warning = AlertEvent::new(false, AlertSeverity::Warning, "slow".to_string(), 10, None, 6, String::new(), None);
+        assert_eq!(AlertRoute::resolve(&routes, &warning, &in_group, friday_night), None);
+    }
+
+    #[test]
+    pub fn test_oncall_schedule() {
+        use chrono::NaiveDate;
+        use crate::alerts::{OnCallSchedule, OnCallShift};
+
+        let schedule: OnCallSchedule = serde_json::from_value(serde_json::json!({
+            "id": 1, "name": "Core network",
+            "rotation": {"members": ["ana", "beto", "carla"], "start": "2026-10-05T09:00:00"},
+            "overrides": [{"from": "2026-10-21T00:00:00", "to": "2026-10-22T00:00:00", "member": "beto"}]
+        })).expect("Schedule should be valid");
+        assert_eq!(schedule.escalate_after_s, 900);
+
+        let at = |day: u32, hour: u32| NaiveDate::from_ymd_opt(2026, 10, day).unwrap().and_hms_opt(hour, 0, 0).unwrap();
+        let shift = |primary: &str, secondary: &str, until| Some(OnCallShift { primary: primary.to_string(), secondary: Some(secondary.to_string()), until });
+
+        // Before it starts, nobody is on call
+        assert_eq!(schedule.on_call(at(5, 8)), None);
+
+        // Weekly handoffs at the start's weekday and time, with the next member as the secondary
+        assert_eq!(schedule.on_call(at(5, 9)), shift("ana", "beto", at(12, 9)));
+        assert_eq!(schedule.on_call(at(12, 8)), shift("ana", "beto", at(12, 9)));
+        assert_eq!(schedule.on_call(at(12, 9)), shift("beto", "carla", at(19, 9)));
+        assert_eq!(schedule.on_call(at(26, 9)), shift("ana", "beto", NaiveDate::from_ymd_opt(2026, 11, 2).unwrap().and_hms_opt(9, 0, 0).unwrap()));
+
+        // The upcoming override ends the shift early, and replaces the primary while it lasts
+        assert_eq!(schedule.on_call(at(20, 12)), shift("carla", "ana", at(21, 0)));
+        assert_eq!(schedule.on_call(at(21, 12)), shift("beto", "ana", at(22, 0)));
+        assert_eq!(schedule.on_call(at(22, 0)), shift("carla", "ana", at(26, 9)));
+
+        // A single member has no secondary to escalate to
+        let solo: OnCallSchedule = serde_json::from_value(serde_json::json!({
+            "name": "Solo", "rotation": {"members": ["ana"], "start": "2026-10-05T09:00:00", "shift-weeks": 2}
+        })).expect("Schedule should be valid");
+        assert_eq!(solo.on_call(at(20, 12)), Some(OnCallShift { primary: "ana".to_string(), secondary: None, until: at(5, 9) + chrono::TimeDelta::weeks(4) }));
+
+        // Out of range handoffs page nobody, instead of overflowing
+        let far: OnCallSchedule = serde_json::from_value(serde_json::json!({
+            "name": "Far", "rotation": {"members": ["ana"], "start": "2026-10-05T09:00:00", "shift-weeks": u32::MAX}
+        })).expect("Schedule should be valid");
+        assert!(far.on_call(at(20, 12)).is_some());
+        assert_eq!(far.on_call(chrono::NaiveDateTime::MAX), None);
+    }
+}
@@ -0,0 +1,680 @@
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
+
+use anyhow::{anyhow, Context, Result};
+use arc_swap::ArcSwap;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+use tokio::sync::watch;
+
+pub mod secrets;
+pub mod settings;
+
+use secrets::SecretResolver;
+use settings::Settings;
+
+/// Prefix of the environment variables that override configuration values. See `Config::apply_env_overrides`
+const ENV_PREFIX: &str = "AEGIS__";
+
+/// Environment variable that selects which block of the configuration files is loaded
+const PROFILE_VAR: &str = "AEGIS_PROFILE";
+
+/// Main configuration files, looked for in this order in the working directory
+const CONFIG_FILES: [&str; 4] = ["config.json", "config.yaml", "config.yml", "config.toml"];
+
+/// Format of a configuration file, given by its extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Json,
+    Yaml,
+    Toml,
+}
+
+impl ConfigFormat {
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let extension = path.extension().and_then(|e| e.to_str()).unwrap_or_default();
+        match extension.to_lowercase().as_str() {
+            "json" => Ok(ConfigFormat::Json),
+            "yaml" | "yml" => Ok(ConfigFormat::Yaml),
+            "toml" => Ok(ConfigFormat::Toml),
+            _ => Err(anyhow!("'{}' has an unknown config extension, expected .json, .yaml, .yml or .toml", path.display())),
+        }
+    }
+
+    /// Parses `content` into the same tree regardless of the format. Errors start with the line where they were found
+    pub fn parse(&self, content: &str) -> Result<Value, String> {
+        match self {
+            ConfigFormat::Json => serde_json::from_str(content)
+                .map_err(|e| located(e.line(), e.column(), &e.to_string())),
+            ConfigFormat::Yaml => serde_yaml::from_str(content)
+                .map_err(|e| match e.location() {
+                    Some(l) => located(l.line(), l.column(), &e.to_string()),
+                    None => e.to_string(),
+                }),
+            ConfigFormat::Toml => toml::from_str(content)
+                .map_err(|e| match e.span() {
+                    Some(span) => {
+                        let before = &content[..span.start.min(content.len())];
+                        let line = before.matches('\n').count() + 1;
+                        let column = before.len() - before.rfind('\n').map_or(0, |i| i + 1) + 1;
+                        located(line, column, e.message())
+                    },
+                    None => e.message().to_string(),
+                }),
+        }
+    }
+}
+
+/// Formats a parse error as `line L, column C: message`, dropping the location some parsers append to the message
+fn located(line: usize, column: usize, message: &str) -> String {
+    let message = message.trim_end_matches(&format!(" at line {line} column {column}"));
+    format!("line {line}, column {column}: {}", message.trim_end())
+}
+
+/// Block of the configuration files to load. Defaults to the kind of build, unless overriden via `AEGIS_PROFILE`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Profile {
+    Debug,
+    Release,
+}
+
+impl Profile {
+    pub fn current() -> Result<Self> {
+        match std::env::var(PROFILE_VAR) {
+            Ok(p) => match p.to_lowercase().as_str() {
+                "debug" => Ok(Profile::Debug),
+                "release" => Ok(Profile::Release),
+                _ => Err(anyhow!("{PROFILE_VAR}='{p}' is not valid, expected 'debug' or 'release'")),
+            },
+            Err(_) if cfg!(debug_assertions) => Ok(Profile::Debug),
+            Err(_) => Ok(Profile::Release),
+        }
+    }
+
+    pub fn key(&self) -> &'static str {
+        match self {
+            Profile::Debug => "debug",
+            Profile::Release => "release",
+        }
+    }
+}
+
+/// Singleton Config that loads a JSON, YAML or TOML file and resolves "$ref" imports inside objects.
+/// - Imported object keys overwrite existing keys (import prevails).
+/// - Import nesting is limited by `depth_limit`.
+/// - `AEGIS__SECTION__KEY` environment variables overwrite everything else.
+/// - `{"$secret": "..."}` values are resolved last, see `SecretResolver`. They're redacted from `Config::redacted`.
+///
+/// The result is validated into a typed `Settings` tree on load, so a bad configuration fails at startup
+pub struct Config {
+    config: Value,
+    config_path: String,
+    settings: Settings,
+
+    /// Paths of the values that were resolved from a secret
+    secret_paths: Vec<String>,
+}
+
+static CONFIG: OnceLock<ArcSwap<Config>> = OnceLock::new();
+
+/// Settings the process was started with. Some values are only read at startup, see `ReloadReport`
+static STARTUP: OnceLock<Settings> = OnceLock::new();
+
+/// Notifies subscribers of every successful reload. See `Config::subscribe`
+static CHANGES: OnceLock<watch::Sender<Arc<Config>>> = OnceLock::new();
+
+/// Credentials that are redacted from `Config::redacted` even when they're not a secret,
+/// as they may come from a plaintext import or an environment override
+const CREDENTIALS: &[&str] = &[
+    "backend/controller/postgres/password",
+    "backend/controller/influx/token",
+    "backend/controller/influx/operator_token",
+    "backend/controller/telegram/API-token",
+    "backend/controller/smtp/password",
+];
+
+/// Settings that are only read when the process starts, as `/`-separated path prefixes.
+/// Changing any of them has no effect until the backend is restarted
+const RESTART_REQUIRED: &[&str] = &[
+    "backend/controller/postgres/",
+    "backend/controller/influx/schema",
+    "backend/controller/influx/hostname",
+    "backend/controller/influx/port",
+    "backend/controller/influx/org",
+    "backend/controller/influx/token",
+    "backend/controller/influx/operator_token",
+    "backend/controller/telegram/API-token",
+];
+
+/// Outcome of a successful `Config::reload`
+#[derive(Debug, Default, Serialize)]
+pub struct ReloadReport {
+    /// Every setting whose value changed, as a `/`-separated path
+    pub changed: Vec<String>,
+
+    /// Settings that differ from the ones the process was started with, and that won't apply until a restart
+    #[serde(rename = "restart-required")]
+    pub restart_required: Vec<String>,
+}
+
+impl Config {
+    
+    /// Returns the global singleton instance, loading the first of `CONFIG_FILES` that exists on first call.
+    // Expect: if the config file is not present or is not valid, it _must_ panic
+    pub fn instance() -> Arc<Config> {
+        
+        CONFIG
+            .get_or_init(|| {
+                let path = CONFIG_FILES.iter().find(|p| Path::new(p).exists()).unwrap_or(&CONFIG_FILES[0]);
+                let cfg = Config::load(path)
+                    .unwrap_or_else(|e| panic!("failed to load {path}: {e:#}"));
+                let _ = STARTUP.set(cfg.settings.clone());
+                ArcSwap::from_pointee(cfg)
+            })
+            .load_full()
+}
+    pub fn init() {
+        let _ = Config::instance();
+        log::info!("Init config");
+    }
+
+    /// Reloads the configuration file and notifies subscribers. If the new configuration is not valid,
+    /// the current one is kept and the error is returned
+    pub fn reload() -> Result<ReloadReport> {
+        let current = Config::instance();
+        let new_cfg = Arc::new(Config::load(&current.config_path)?);
+
+        let startup = STARTUP.get().unwrap_or(&current.settings);
+        let report = ReloadReport::new(&current.settings, startup, &new_cfg.settings);
+
+        CONFIG.get().ok_or_else(|| anyhow!("configuration was never loaded"))?.store(new_cfg.clone());
+        for path in &report.changed {
+            log::info!(subsystem = "config"; "'{path}' changed on reload");
+        }
+        for path in &report.restart_required {
+            log::warn!(subsystem = "config"; "'{path}' changed, but won't apply until the backend is restarted");
+        }
+
+        // Subscribers are only woken up if something actually changed
+        Config::changes().send_if_modified(|config| {
+            *config = new_cfg;
+            !report.changed.is_empty()
+        });
+
+        Ok(report)
+    }
+
+    /// Returns a receiver that is notified with the new configuration after every reload that changed something.
+    /// Subsystems that cache settings for long-lived resources (sockets, timers) use it to apply changes in place
+    pub fn subscribe() -> watch::Receiver<Arc<Config>> {
+        Config::changes().subscribe()
+    }
+
+    fn changes() -> &'static watch::Sender<Arc<Config>> {
+        CHANGES.get_or_init(|| watch::Sender::new(Config::instance()))
+    }
+
+    /// Loads the configuration file at `path`, resolving imports and environment overrides, and validates it
+    pub fn load(path: &str) -> Result<Config> {
+        let mut config = Config::parse(path, true)?;
+        let overrides = Config::apply_env_overrides(&mut config, std::env::vars())?;
+        for applied in &overrides {
+            log::info!(subsystem = "config"; "'{applied}' overriden from environment");
+        }
+
+        let secret_paths = SecretResolver::new(&config).resolve_all(&mut config)?;
+        if !secret_paths.is_empty() {
+            log::info!(subsystem = "config"; "Resolved {} secrets", secret_paths.len());
+        }
+
+        let settings: Settings = serde_path_to_error::deserialize(&config)
+            .map_err(|e| {
+                let path = e.path().to_string().replace('.', "/");
+                // Type errors quote the value, which must not end up in the logs
+                if secret_paths.contains(&path) {
+                    anyhow!("{path}: secret has an invalid value")
+                } else if overrides.contains(&path) {
+                    anyhow!("{path}: value overriden from environment is invalid")
+                } else {
+                    anyhow!("{path}: {}", e.inner())
+                }
+            })
+            .context("configuration has an invalid value")?;
+
+        let errors = settings.validate();
+        if !errors.is_empty() {
+            return Err(anyhow!("configuration is not valid:\n  {}", errors.join("\n  ")));
+        }
+
+        Ok(Config { config, config_path: path.into(), settings, secret_paths })
+    }
+
+    /// Typed configuration. Prefer this over looking values up by path
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    /// Whole configuration as loaded, with every secret redacted. Safe to log or send to clients
+    pub fn redacted(&self) -> Value {
+        let credentials = CREDENTIALS.iter().map(|p| p.to_string()).filter(|p| self.has(p, "/"));
+        let paths: Vec<String> = self.secret_paths.iter().cloned().chain(credentials).collect();
+        secrets::redact(&self.config, &paths)
+    }
+
+    pub fn get_curr_config_path(&self) -> String {
+        self.config_path.clone()
+    }
+
+    /// Parse a JSON, YAML or TOML file at `path`, depending on its extension. If `resolve_imports` is true, resolves nested $ref imports.
+    /// Imported files don't need to be in the same format as the file importing them
+    pub fn parse<P: AsRef<Path>>(path: P, resolve_imports: bool) -> Result<Value> {
+        let format = ConfigFormat::from_path(path.as_ref())?;
+        let content = std::fs::read_to_string(&path).with_context(|| {
+            format!("failed to open config file '{}'", path.as_ref().display())
+        })?;
+        let mut v = format.parse(&content)
+            .map_err(|e| anyhow!("failed to parse {format:?} from '{}': {e}", path.as_ref().display()))?;
+
+        let profile = Profile::current()?;
+        log::info!(subsystem = "config"; "Loaded {} config from '{}'!", profile.key(), path.as_ref().display());
+        v = v.get_mut(profile.key())
+            .with_context(|| format!("[FATAL] Expected '{}' block in '{}'", profile.key(), path.as_ref().display()))?
+            .take();
+
+        if resolve_imports {
+            Config::resolve_imports(&mut v, 5).context("resolving imports failed")?;
+        }
+        
+        Ok(v)
+    }
+
+    /// Recursively traverse `value` and resolve "$ref" entries. Depth limit prevents infinite loops.
+    /// Imported object keys overwrite existing keys (import prevails).
+    pub fn resolve_imports(value: &mut Value, depth_limit: usize) -> Result<()> {
+        fn inner(value: &mut Value, remaining: &mut usize) -> Result<()> {
+            match value {
+                Value::Object(map) => {
+                    // If there's a $ref at this object level, handle it first.
+                    if let Some(ref_val) = map.remove("$ref") {
+                        if *remaining == 0 {
+                            // Log critical and return error
+                            log::error!(
+                                "Config file exceeds import depth. depth_limit={}",
+                                0usize
+                            );
+                            return Err(anyhow!(
+                                "[FATAL] Config file exceeds import depth."
+                            ));
+                        }
+
+                        // Decrement remaining depth and parse the referenced path
+                        *remaining -= 1;
+
+                        let path = ref_val
+                            .as_str()
+                            .ok_or_else(|| anyhow!("$ref value is not a string"))?;
+
+                        let mut imported =
+                            Config::parse(path, false).with_context(|| format!("parsing '{}'", path))?;
+
+                        // Only merge object imports into object; otherwise assign whole value
+                        if let Value::Object(import_map) = &mut imported {
+                            // take the map out so we own it, leaving an empty map behind
+                            let taken: Map<String, Value> = std::mem::take(import_map);
+
+                            // Imported keys overwrite existing keys (import prevails)
+                            for (k, v) in taken {
+                                map.insert(k, v);
+                            }
+                        } else {
+                            *value = imported;
+                        }
+
+                        // After merging/importing, continue traversal on this (possibly updated) object
+                        inner(value, remaining)?;
+                        return Ok(());
+                    }
+
+                    // No $ref here: traverse children
+                    // Collect keys to avoid borrow issues while mutating deeper
+                    let keys: Vec<String> = map.keys().cloned().collect();
+                    for k in keys {
+                        if let Some(child) = map.get_mut(&k) {
+                            inner(child, remaining)?;
+                        }
+                    }
+                    Ok(())
+                }
+                Value::Array(arr) => {
+                    for item in arr.iter_mut() {
+                        inner(item, remaining)?;
+                    }
+                    Ok(())
+                }
+                _ => Ok(()),
+            }
+        }
+
+        let mut rem = depth_limit;
+        inner(value, &mut rem)
+    }
+
+    /// Applies `AEGIS__SECTION__KEY=value` variables on top of the configuration, such that
+    /// `AEGIS__BACKEND__CONTROLLER__INFLUX__TOKEN` sets `backend/controller/influx/token`.
+    /// Sections are matched ignoring case, and treating `-` and `_` as the same character, against the keys
+    /// in the configuration and then against those `Settings` expects, so keys absent from the file get their proper spelling.
+    /// Values for string settings are kept as strings, others are parsed as JSON if possible.
+    /// Returns the paths that were overriden
+    pub fn apply_env_overrides(value: &mut Value, vars: impl IntoIterator<Item = (String, String)>) -> Result<Vec<String>> {
+        let normalize = |key: &str| key.to_lowercase().replace('-', "_");
+        // Every setting has a default, so the defaults hold every key along with its type
+        let schema = serde_json::to_value(Settings::default())?;
+        let mut applied = Vec::new();
+
+        for (var, raw) in vars {
+            let sections = match var.strip_prefix(ENV_PREFIX) { Some(s) => s, None => continue };
+            let sections: Vec<&str> = sections.split("__").collect();
+            if sections.iter().any(|s| s.is_empty()) {
+                return Err(anyhow!("{var}: malformed override, expected {ENV_PREFIX}SECTION__KEY"));
+            }
+
+            let mut current = &mut *value;
+            let mut expected = Some(&schema);
+            let mut path = Vec::new();
+            for section in sections {
+                let map = current.as_object_mut()
+                    .ok_or_else(|| anyhow!("{var}: '{}' is not a section", path.join("/")))?;
+                let schema_map = expected.and_then(Value::as_object);
+
+                // Use the existing spelling of the key, then the one the settings expect, if any
+                let key = map.keys()
+                    .chain(schema_map.into_iter().flat_map(|m| m.keys()))
+                    .find(|k| normalize(k) == normalize(section))
+                    .cloned()
+                    .unwrap_or_else(|| section.to_lowercase());
+
+                expected = schema_map.and_then(|m| m.get(&key));
+                path.push(key.clone());
+                current = map.entry(key).or_insert_with(|| Value::Object(Map::new()));
+            }
+
+            *current = match (&current, expected) {
+                (Value::String(_), _) | (_, Some(Value::String(_))) => Value::String(raw),
+                _ => serde_json::from_str(&raw).unwrap_or(Value::String(raw)),
+            };
+            applied.push(path.join("/"));
+        }
+
+        Ok(applied)
+    }
+
+    /// Get a Value for a `/`-separated path (default separator "/").
+    /// Returns `default` if the path is not found or types mismatch.
+    pub fn get_value(&self, path: &str, default: Option<Value>, sep: &str) -> Value {
+        match self.get_value_opt(path, sep) {
+            Some(v) => v,
+            None => {
+                log::warn!(
+                    subsystem = "config";
+                    "Using undefined config value '{}', returning default value {:?}",
+                    path,
+                    default
+                );
+                default.unwrap_or(Value::Null)
+            }
+        }
+    }
+
+    /// Try to get a Value by path; returns None if not found.
+    pub fn get_value_opt(&self, path: &str, sep: &str) -> Option<Value> {
+        let keys: Vec<&str> = if sep.is_empty() {
+            vec![path]
+        } else {
+            path.split(sep).collect()
+        };
+
+        let mut current = &self.config;
+        for key in keys {
+            match current {
+                Value::Object(map) => {
+                    current = map.get(key)?;
+                }
+                _ => return None,
+            }
+        }
+        Some(current.clone())
+    }
+
+    /// Generic get that attempts to deserialize the value at `path` into T.
+    /// Returns `Ok(T)` or an error describing the failure.
+    pub fn get<T: DeserializeOwned>(&self, path: &str, sep: &str) -> Result<T> {
+        let v = self
+            .get_value_opt(path, sep)
+            .ok_or_else(|| anyhow!("config path '{}' not found", path))?;
+        let t = serde_json::from_value(v).context("failed to deserialize config value")?;
+        Ok(t)
+    }
+
+    /// Checks whether a path exists in the configuration.
+    pub fn has(&self, path: &str, sep: &str) -> bool {
+        self.get_value_opt(path, sep).is_some()
+    }
+}
+
+impl ReloadReport {
+    /// Compares the settings leaf by leaf. Restart-bound settings are compared against the ones at `startup` instead,
+    /// so they're reported until the backend is restarted, even across several reloads
+    fn new(old: &Settings, startup: &Settings, new: &Settings) -> Self {
+        let to_value = |s: &Settings| serde_json::to_value(s).unwrap_or(Value::Null);
+        let new_value = to_value(new);
+
+        let mut changed = Vec::new();
+        diff_leaves("", &to_value(old), &new_value, &mut changed);
+
+        let mut restart_required = Vec::new();
+        let mut from_startup = Vec::new();
+        diff_leaves("", &to_value(startup), &new_value, &mut from_startup);
+        for path in from_startup {
+            if RESTART_REQUIRED.iter().any(|prefix| path == prefix.trim_end_matches('/') || path.starts_with(prefix)) {
+                restart_required.push(path);
+            }
+        }
+
+        // Telegram can be paused and resumed live, but the bot only exists if it was enabled at startup
+        let telegram = "backend/controller/telegram/enabled".to_string();
+        if new.backend.controller.telegram.enabled && !startup.backend.controller.telegram.enabled {
+            restart_required.push(telegram);
+        }
+
+        ReloadReport { changed, restart_required }
+    }
+}
+
+/// Pushes the path of every leaf that differs between `old` and `new`, including added and removed keys
+fn diff_leaves(path: &str, old: &Value, new: &Value, out: &mut Vec<String>) {
+    let join = |key: &str| if path.is_empty() { key.to_string() } else { format!("{path}/{key}") };
+    match (old, new) {
+        (Value::Object(old_map), Value::Object(new_map)) => {
+            let mut keys: Vec<&String> = old_map.keys().chain(new_map.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                let null = Value::Null;
+                let old_child = old_map.get(key).unwrap_or(&null);
+                let new_child = new_map.get(key).unwrap_or(&null);
+                diff_leaves(&join(key), old_child, new_child, out);
+            }
+        },
+        (old, new) if old != new => out.push(path.to_string()),
+        _ => (),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn env_overrides_and_validation() {
+        let mut config = serde_json::json!({
+            "backend": {
+                "controller": {
+                    "postgres": { "hostname": "localhost", "port": 5432 },
+                    "telegram": { "enabled": false }
+                }
+            }
+        });
+
+        let vars = [
+            ("AEGIS__BACKEND__CONTROLLER__POSTGRES__PORT", "6543"),
+            ("AEGIS__BACKEND__CONTROLLER__POSTGRES__PASSWORD", "1234"),
+            ("AEGIS__BACKEND__CONTROLLER__SYSLOG__RFC5424_PORT", "514"),
+            ("AEGIS__BACKEND__CONTROLLER__TELEGRAM__ENABLED", "true"),
+            ("AEGIS__BACKEND__CONTROLLER__TELEGRAM__API_TOKEN", "123456"),
+            ("AEGIS__BACKEND__MODEL__SSH_USER", "aegis"),
+            ("PATH", "/usr/bin"),
+        ].map(|(k, v)| (k.to_string(), v.to_string()));
+
+        let applied = Config::apply_env_overrides(&mut config, vars).expect("Overrides should apply");
+        assert_eq!(applied.len(), 6);
+        // Absent from the file, spelled as the settings expect
+        assert!(applied.contains(&"backend/controller/syslog/RFC5424-port".to_string()));
+        assert!(applied.contains(&"backend/controller/telegram/API-token".to_string()));
+
+        let settings: Settings = serde_json::from_value(config.clone()).expect("Settings should deserialize");
+        let postgres = &settings.backend.controller.postgres;
+        assert_eq!(postgres.port, 6543);
+        assert_eq!(postgres.password, "1234"); // Kept as a string, as the setting is one
+        assert_eq!(settings.backend.controller.syslog.rfc5424_port, 514);
+        assert_eq!(settings.backend.model.ssh_user, "aegis");
+        assert_eq!(settings.backend.controller.influx.port, 8086); // Default
+
+        let errors = settings.validate();
+        assert_eq!(settings.backend.controller.telegram.api_token, "123456");
+        assert!(errors.contains(&"backend/controller/influx/token: is required".to_string()));
+        assert!(!errors.iter().any(|e| e.starts_with("backend/controller/postgres/hostname")));
+
+        let malformed = [("AEGIS__BACKEND__CONTROLLER__POSTGRES__PORT__X".to_string(), "1".to_string())];
+        assert!(Config::apply_env_overrides(&mut config, malformed).is_err());
+    }
+
+    #[test]
+    fn redacts_credentials() {
+        let config = serde_json::json!({
+            "backend": {
+                "controller": {
+                    "postgres": { "hostname": "localhost", "password": "secret" },
+                    "smtp": { "hostname": "smtp.example.com", "username": "aegis", "password": "hunter2" }
+                }
+            }
+        });
+        let config = Config { config, config_path: String::new(), settings: Settings::default(), secret_paths: Vec::new() };
+
+        let redacted = config.redacted();
+        let controller = &redacted["backend"]["controller"];
+        assert_eq!(controller["postgres"]["password"], secrets::REDACTED);
+        assert_eq!(controller["smtp"]["password"], secrets::REDACTED);
+        assert_eq!(controller["smtp"]["username"], "aegis");
+        assert_eq!(controller["postgres"]["hostname"], "localhost");
+        // Credentials that aren't configured aren't added
+        assert!(controller["influx"].is_null());
+    }
+
+    #[test]
+    fn mixed_formats_and_parse_errors() {
+        let dir = std::env::temp_dir().join(format!("aegis-config-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("Temp dir should be writable");
+        let write = |name: &str, content: &str| {
+            let path = dir.join(name);
+            std::fs::write(&path, content).expect("Temp file should be writable");
+            path.display().to_string()
+        };
+
+        // Every file has a block per profile, so whichever is selected is found
+        let toml_path = write("influx.toml", "[debug]\ntoken = \"t0k3n\"\n[release]\ntoken = \"t0k3n\"\n");
+        let yaml_path = write("config.yaml", &format!(
+            "# Thresholds can be documented\n\
+            debug: &profile\n  influx:\n    port: 8086\n    $ref: \"{toml_path}\"\n\
+            release: *profile\n"
+        ));
+
+        let config = Config::parse(&yaml_path, true).expect("Mixed formats should load");
+        assert_eq!(config["influx"]["port"], 8086);
+        assert_eq!(config["influx"]["token"], "t0k3n");
+
+        let broken = write("broken.toml", "[debug]\nport = 8086\nhostname = \n");
+        let err = format!("{:#}", Config::parse(&broken, true).unwrap_err());
+        assert!(err.contains("broken.toml") && err.contains("line 3, column"), "{err}");
+
+        let broken = write("broken.json", "{\n  \"debug\": {\n    \"port\": 8086,\n  }\n}");
+        let err = format!("{:#}", Config::parse(&broken, true).unwrap_err());
+        assert!(err.contains("broken.json") && err.contains("line 4, column 3"), "{err}");
+
+        let broken = write("broken.yaml", "debug:\n  port: 8086\n  port: [\n");
+        let err = format!("{:#}", Config::parse(&broken, true).unwrap_err());
+        assert!(err.contains("broken.yaml") && err.contains("line 4"), "{err}");
+
+        assert!(Config::parse(write("config.ini", ""), true).is_err());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn reload_report() {
+        let startup = Settings::default();
+
+        let mut first = startup.clone();
+        first.backend.controller.postgres.port = 6543;
+        first.backend.controller.fact_gathering.polling_time_s = 60;
+        first.backend.controller.influx.array_encoding.insert("ansible_interfaces".to_string(), Default::default());
+
+        let report = ReloadReport::new(&startup, &startup, &first);
+        assert_eq!(report.changed, vec![
+            "backend/controller/fact_gathering/polling_time_s",
+            "backend/controller/influx/array_encoding/ansible_interfaces",
+            "backend/controller/postgres/port",
+        ]);
+        assert_eq!(report.restart_required, vec!["backend/controller/postgres/port"]);
+
+        // Restart-bound changes are still reported on the next reload, even if they didn't change since the last one
+        let mut second = first.clone();
+        second.backend.controller.syslog.rfc5424_port = 514;
+        second.backend.controller.telegram.enabled = true;
+        second.backend.controller.telegram.api_token = "token".to_string();
+
+        let report = ReloadReport::new(&first, &startup, &second);
+        assert_eq!(report.changed, vec![
+            "backend/controller/syslog/RFC5424-port",
+            "backend/controller/telegram/API-token",
+            "backend/controller/telegram/enabled",
+        ]);
+        assert_eq!(report.restart_required, vec![
+            "backend/controller/postgres/port",
+            "backend/controller/telegram/API-token",
+            "backend/controller/telegram/enabled",
+        ]);
+    }
+}
+
+/*
+Example usage:
+
+fn main() -> Result<()> {
+    // initialize logging if desired, e.g. env_logger::init();
+    let cfg = Config::instance();
+
+    // get a raw Value (or default)
+    let v = cfg.get_value("api/config/details/value", Some(Value::from(42)), "/");
+    println!("raw value: {}", v);
+
+    // get typed value
+    let num: i32 = cfg.get("api/config/details/value", "/")?;
+    println!("typed value: {}", num);
+
+    // check existence
+    let exists = cfg.has("api/config/details/value", "/");
+    println!("exists: {}", exists);
+
+    Ok(())
+}
+*/
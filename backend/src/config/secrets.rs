@@ -0,0 +1,178 @@
+use std::process::Command;
+
+use anyhow::{anyhow, Context, Result};
+use serde_json::Value;
+
+/// Key of the objects that are replaced by a secret at load time
+const SECRET_KEY: &str = "$secret";
+
+/// Placeholder shown instead of a resolved secret
+pub const REDACTED: &str = "********";
+
+/// Resolves `{"$secret": "<scheme>:<reference>"}` objects into the string they point to:
+/// - `env:NAME` reads the environment variable `NAME`.
+/// - `file:/path/to/secret` reads the file, without its trailing newline. Meant for Docker/Kubernetes secrets.
+/// - `ansible-vault:key` reads `key` from the Ansible vault at `security/vault`, decrypted with the
+///   password file at `security/vault_pass`. Nested keys are `/`-separated. The vault is decrypted once per load.
+pub struct SecretResolver {
+    vault: String,
+    vault_pass: String,
+    decrypted_vault: Option<Value>,
+}
+
+impl SecretResolver {
+    /// Reads the vault location from the `security` section of `config`
+    pub fn new(config: &Value) -> Self {
+        let security = |key: &str| config.pointer(&format!("/security/{key}")).and_then(Value::as_str).unwrap_or_default().to_string();
+        SecretResolver { vault: security("vault"), vault_pass: security("vault_pass"), decrypted_vault: None }
+    }
+
+    /// Replaces every secret in `value` by its resolved value. Returns the `/`-separated paths of the secrets,
+    /// so that they can be redacted later on
+    pub fn resolve_all(&mut self, value: &mut Value) -> Result<Vec<String>> {
+        let mut paths = Vec::new();
+        self.resolve_inner(value, &mut Vec::new(), &mut paths)?;
+        Ok(paths)
+    }
+
+    fn resolve_inner(&mut self, value: &mut Value, path: &mut Vec<String>, out: &mut Vec<String>) -> Result<()> {
+        match value {
+            Value::Object(map) if map.contains_key(SECRET_KEY) => {
+                let joined = path.join("/");
+                if map.len() > 1 {
+                    return Err(anyhow!("{joined}: a {SECRET_KEY} object can't have other keys"));
+                }
+                let reference = map[SECRET_KEY].as_str()
+                    .ok_or_else(|| anyhow!("{joined}: {SECRET_KEY} must be a string"))?
+                    .to_string();
+
+                let secret = self.resolve(&reference).with_context(|| format!("{joined}: failed to resolve secret '{reference}'"))?;
+                *value = Value::String(secret);
+                out.push(joined);
+            },
+            Value::Object(map) => {
+                for (key, child) in map.iter_mut() {
+                    path.push(key.clone());
+                    self.resolve_inner(child, path, out)?;
+                    path.pop();
+                }
+            },
+            Value::Array(items) => {
+                for (i, child) in items.iter_mut().enumerate() {
+                    path.push(i.to_string());
+                    self.resolve_inner(child, path, out)?;
+                    path.pop();
+                }
+            },
+            _ => (),
+        }
+        Ok(())
+    }
+
+    /// Resolves a single `<scheme>:<reference>`. Errors never include the secret itself
+    pub fn resolve(&mut self, reference: &str) -> Result<String> {
+        let (scheme, target) = reference.split_once(':')
+            .ok_or_else(|| anyhow!("expected '<scheme>:<reference>', with scheme 'env', 'file' or 'ansible-vault'"))?;
+
+        match scheme {
+            "env" => std::env::var(target).map_err(|_| anyhow!("environment variable '{target}' is not set")),
+
+            "file" => {
+                let content = std::fs::read_to_string(target).with_context(|| format!("failed to read '{target}'"))?;
+                Ok(content.trim_end_matches(['\r', '\n']).to_string())
+            },
+
+            "ansible-vault" => {
+                let mut current = self.vault()?;
+                for key in target.split('/') {
+                    current = current.get(key).ok_or_else(|| anyhow!("'{target}' is not in the vault"))?;
+                }
+                match current {
+                    Value::String(s) => Ok(s.clone()),
+                    Value::Number(n) => Ok(n.to_string()),
+                    Value::Bool(b) => Ok(b.to_string()),
+                    _ => Err(anyhow!("'{target}' in the vault is not a single value")),
+                }
+            },
+
+            _ => Err(anyhow!("unknown secret scheme '{scheme}', expected 'env', 'file' or 'ansible-vault'")),
+        }
+    }
+
+    /// Decrypts the vault with `ansible-vault`, on first use
+    fn vault(&mut self) -> Result<&Value> {
+        if self.decrypted_vault.is_none() {
+            if self.vault.is_empty() || self.vault_pass.is_empty() {
+                return Err(anyhow!("'security/vault' and 'security/vault_pass' must be set to read from the vault"));
+            }
+
+            let output = Command::new("ansible-vault")
+                .args(["view", "--vault-password-file", &self.vault_pass, &self.vault])
+                .output()
+                .context("failed to run ansible-vault")?;
+            if !output.status.success() {
+                return Err(anyhow!("ansible-vault could not decrypt '{}': {}", self.vault, String::from_utf8_lossy(&output.stderr).trim()));
+            }
+
+            let decrypted: Value = serde_yaml::from_slice(&output.stdout)
+                .map_err(|_| anyhow!("decrypted vault '{}' is not valid YAML", self.vault))?;
+            self.decrypted_vault = Some(decrypted);
+        }
+
+        Ok(self.decrypted_vault.as_ref().unwrap_or(&Value::Null))
+    }
+}
+
+/// Returns a copy of `value` with the secrets at `paths` replaced by `REDACTED`
+pub fn redact(value: &Value, paths: &[String]) -> Value {
+    let mut redacted = value.clone();
+    for path in paths {
+        let pointer = format!("/{}", path.split('/').map(|k| k.replace('~', "~0")).collect::<Vec<_>>().join("/"));
+        if let Some(secret) = redacted.pointer_mut(&pointer) {
+            *secret = Value::String(REDACTED.to_string());
+        }
+    }
+    redacted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_and_redact() {
+        let file = std::env::temp_dir().join(format!("aegis-secret-{}", std::process::id()));
+        std::fs::write(&file, "hunter2\n").expect("Temp file should be writable");
+
+        let mut config = serde_json::json!({
+            "security": { "vault": "vault.yml", "vault_pass": "pass.txt" },
+            "influx": {
+                "hostname": "localhost",
+                "token": { "$secret": "env:PATH" },
+                "operator_token": { "$secret": format!("file:{}", file.display()) }
+            },
+            "telegram": { "API-token": { "$secret": "ansible-vault:telegram/token" } }
+        });
+
+        // Pretend the vault was already decrypted, as ansible-vault may not be installed
+        let mut resolver = SecretResolver::new(&config);
+        resolver.decrypted_vault = Some(serde_json::json!({ "telegram": { "token": 1234 } }));
+
+        let paths = resolver.resolve_all(&mut config).expect("Secrets should resolve");
+        assert_eq!(paths.len(), 3);
+        assert_eq!(config["influx"]["token"], std::env::var("PATH").unwrap_or_default());
+        assert_eq!(config["influx"]["operator_token"], "hunter2");
+        assert_eq!(config["telegram"]["API-token"], "1234");
+
+        let redacted = redact(&config, &paths);
+        assert_eq!(redacted["influx"]["operator_token"], REDACTED);
+        assert_eq!(redacted["telegram"]["API-token"], REDACTED);
+        assert_eq!(redacted["influx"]["hostname"], "localhost");
+
+        let err = resolver.resolve("env:AEGIS_SURELY_NOT_SET").unwrap_err();
+        assert!(err.to_string().contains("AEGIS_SURELY_NOT_SET"));
+        assert!(resolver.resolve("vault:key").is_err());
+        assert!(resolver.resolve("ansible-vault:telegram").is_err());
+        let _ = std::fs::remove_file(&file);
+    }
+}
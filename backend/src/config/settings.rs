@@ -0,0 +1,462 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::types::ArrayEncoding;
+
+// Typed view of the configuration file, as selected for the current profile and with every import resolved.
+// Every field has a default, so a missing key is never a runtime panic. Keys that have no sensible default
+// are left empty, and reported by `Settings::validate` at startup instead.
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub security: SecuritySettings,
+    pub backend: BackendSettings,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SecuritySettings {
+    pub vault_pass: String,
+    pub vault: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BackendSettings {
+    pub model: ModelSettings,
+    pub controller: ControllerSettings,
+    pub logging: LoggingSettings,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// One human readable line per record
+    #[default]
+    Pretty,
+
+    /// One JSON object per record, for log collectors
+    Json,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LoggingSettings {
+    pub format: LogFormat,
+
+    /// Level of every module not listed in `modules`. One of off, error, warn, info, debug or trace
+    pub level: String,
+
+    /// Levels by module path prefix, such as `backend_aegis::syslog` or `sqlx`. The longest matching prefix wins
+    pub modules: HashMap<String, String>,
+}
+
+impl Default for LoggingSettings {
+    fn default() -> Self {
+        Self { format: LogFormat::Pretty, level: "info".to_string(), modules: HashMap::new() }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ModelSettings {
+    /// Directory given to ansible-runner, where playbooks are looked for
+    pub private_data_dir: String,
+
+    /// User used to connect to devices polled over SSH
+    pub ssh_user: String,
+
+    pub cache: RuleCacheSettings,
+    pub alerts: AlertSettings,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RuleCacheSettings {
+    pub rule_set_cache_invalidation_s: u64,
+}
+
+impl Default for RuleCacheSettings {
+    fn default() -> Self {
+        Self { rule_set_cache_invalidation_s: 3600 }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AlertSettings {
+    /// Longest time range a rule can be backtested against
+    pub backtest_max_range_s: i64,
+
+    /// Most syslog messages read when backtesting a syslog rule
+    pub backtest_max_syslog_rows: i64,
+
+    /// Most fact values read when backtesting a facts rule. Backtests over more are rejected
+    pub backtest_max_fact_rows: i64,
+
+    /// How often the evaluation state of rules is saved, so that Sustained timers and Delta datasets survive a restart
+    pub state_snapshot_s: u64,
+
+    /// Oldest Delta dataset restored on startup. Older ones would compare against stale data
+    pub state_max_age_s: u64,
+
+    /// Timezone the schedules of alert routes are in, such as `America/Mexico_City`
+    pub routing_timezone: String,
+}
+
+impl Default for AlertSettings {
+    fn default() -> Self {
+        Self { backtest_max_range_s: 604800, backtest_max_syslog_rows: 100000, backtest_max_fact_rows: 500000, state_snapshot_s: 60, state_max_age_s: 900, routing_timezone: "UTC".to_string() }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ControllerSettings {
+    pub configure: ConfigureSettings,
+    pub cache: CacheSettings,
+    pub syslog: SyslogSettings,
+    pub fact_gathering: FactGatheringSettings,
+    pub discovery: DiscoverySettings,
+    pub postgres: PostgresSettings,
+    pub influx: InfluxSettings,
+    pub telegram: TelegramSettings,
+    pub links: LinkSettings,
+    pub prometheus: PrometheusSettings,
+    pub supervisor: SupervisorSettings,
+    pub webhooks: WebhookSettings,
+    pub smtp: SmtpSettings,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ConfigureSettings {
+    /// Whether changes can be made through the API. The backend is read-only otherwise
+    pub enabled: bool,
+
+    /// Largest topology document accepted for import
+    pub import_limit_mib: u64,
+}
+
+impl Default for ConfigureSettings {
+    fn default() -> Self {
+        Self { enabled: false, import_limit_mib: 16 }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CacheSettings {
+    pub cache_invalidation_s: u64,
+}
+
+impl Default for CacheSettings {
+    fn default() -> Self {
+        Self { cache_invalidation_s: 60 }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SyslogSettings {
+    #[serde(rename = "RFC5424-port")]
+    pub rfc5424_port: u16,
+
+    pub bind_address: String,
+}
+
+impl Default for SyslogSettings {
+    fn default() -> Self {
+        Self { rfc5424_port: 1514, bind_address: "0.0.0.0".to_string() }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FactGatheringSettings {
+    pub polling_time_s: u64,
+}
+
+impl Default for FactGatheringSettings {
+    fn default() -> Self {
+        Self { polling_time_s: 15 }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SupervisorSettings {
+    /// Delay before restarting a crashed background task. Doubles on every crash, up to `backoff_max_s`
+    pub backoff_initial_s: u64,
+    pub backoff_max_s: u64,
+
+    /// Time given to background tasks to drain their queues on shutdown
+    pub shutdown_grace_s: u64,
+}
+
+impl Default for SupervisorSettings {
+    fn default() -> Self {
+        Self { backoff_initial_s: 1, backoff_max_s: 60, shutdown_grace_s: 10 }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DiscoverySettings {
+    pub enabled: bool,
+}
+
+impl Default for DiscoverySettings {
+    fn default() -> Self {
+        Self { enabled: true }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PostgresSettings {
+    pub schema: String,
+    pub hostname: String,
+    pub port: u16,
+    pub name: String,
+    pub user: String,
+    pub password: String,
+}
+
+impl Default for PostgresSettings {
+    fn default() -> Self {
+        Self {
+            schema: "postgresql://".to_string(),
+            hostname: String::new(),
+            port: 5432,
+            name: String::new(),
+            user: String::new(),
+            password: String::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InfluxSettings {
+    pub schema: String,
+    pub hostname: String,
+    pub port: u16,
+    pub org: String,
+    pub bucket: String,
+    pub token: String,
+
+    /// Token allowed to manage buckets and tasks. If empty, they must already exist
+    pub operator_token: String,
+
+    /// How array metrics are flattened when written, per metric name
+    pub array_encoding: HashMap<String, ArrayEncoding>,
+
+    /// Retention of each bucket. 0 means infinite retention
+    pub retention_s: HashMap<String, i32>,
+}
+
+impl Default for InfluxSettings {
+    fn default() -> Self {
+        Self {
+            schema: "http://".to_string(),
+            hostname: String::new(),
+            port: 8086,
+            org: String::new(),
+            bucket: String::new(),
+            token: String::new(),
+            operator_token: String::new(),
+            array_encoding: HashMap::new(),
+            retention_s: HashMap::new(),
+        }
+    }
+}
+
+impl InfluxSettings {
+    pub fn url(&self) -> String {
+        format!("{}{}:{}", self.schema, self.hostname, self.port)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TelegramSettings {
+    pub enabled: bool,
+
+    #[serde(rename = "API-token")]
+    pub api_token: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WebhookSettings {
+    /// Longest an alert route webhook can take to respond
+    pub timeout_s: u64,
+}
+
+impl Default for WebhookSettings {
+    fn default() -> Self {
+        Self { timeout_s: 10 }
+    }
+}
+
+/// Server alert routes send emails through
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SmtpSettings {
+    pub enabled: bool,
+    pub hostname: String,
+    pub port: u16,
+
+    /// Credentials, if the server requires them
+    pub username: String,
+    pub password: String,
+
+    /// Sender address, such as `Aegis <aegis@example.com>`
+    pub from: String,
+
+    /// Whether to upgrade the connection with STARTTLS. Connects in plain text otherwise
+    pub starttls: bool,
+}
+
+impl Default for SmtpSettings {
+    fn default() -> Self {
+        Self { enabled: false, hostname: String::new(), port: 587, username: String::new(), password: String::new(), from: String::new(), starttls: true }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LinkSettings {
+    /// Metric names from which each interface value is read, overriding the defaults. See `link_metrics`
+    pub interface_metrics: HashMap<String, Vec<String>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PrometheusSettings {
+    /// Endpoint scraped for devices with the `prometheus` data source. `{hostname}` is replaced by the management hostname
+    pub default_endpoint: String,
+
+    /// Endpoints scraped instead of the default one, by management hostname
+    pub targets: HashMap<String, Vec<String>>,
+
+    pub timeout_s: u64,
+
+    /// Series kept from the scraped endpoints, and the metric each is stored as. Series not listed are ignored
+    pub mappings: Vec<PrometheusMapping>,
+}
+
+impl Default for PrometheusSettings {
+    fn default() -> Self {
+        // node_exporter series. Interfaces are named such that link health picks them up, see `link_metrics`
+        let mapping = |series: &str, metric: &str| PrometheusMapping { series: series.to_string(), metric: metric.to_string(), match_labels: HashMap::new() };
+        Self {
+            default_endpoint: "http://{hostname}:9100/metrics".to_string(),
+            targets: HashMap::new(),
+            timeout_s: 5,
+            mappings: vec![
+                mapping("node_load1", "load1"),
+                mapping("node_load5", "load5"),
+                mapping("node_load15", "load15"),
+                mapping("node_memory_MemAvailable_bytes", "memory_available_bytes"),
+                mapping("node_memory_MemTotal_bytes", "memory_total_bytes"),
+                mapping("node_network_up", "if_{device}_oper_status"),
+                mapping("node_network_receive_bytes_total", "if_{device}_in_octets"),
+                mapping("node_network_transmit_bytes_total", "if_{device}_out_octets"),
+                mapping("node_network_receive_errs_total", "if_{device}_in_errors"),
+                mapping("node_network_transmit_errs_total", "if_{device}_out_errors"),
+            ],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PrometheusMapping {
+    /// Name of the scraped series, such as `node_load1`
+    pub series: String,
+
+    /// Name of the metric it's stored as. `{label}` is replaced by the value of that label, such that
+    /// `if_{device}_in_octets` stores `node_network_receive_bytes_total{device="eth0"}` as `if_eth0_in_octets`.
+    /// Defaults to the series name
+    pub metric: String,
+
+    /// Only series with these label values are kept
+    pub match_labels: HashMap<String, String>,
+}
+
+impl Settings {
+    /// Checks the values that have no sensible default. Returns every problem found, as `path: message`
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        let mut required = |path: &str, value: &str| {
+            if value.trim().is_empty() {
+                errors.push(format!("{path}: is required"));
+            }
+        };
+
+        let model = &self.backend.model;
+        required("backend/model/private_data_dir", &model.private_data_dir);
+        required("backend/model/ssh_user", &model.ssh_user);
+
+        let postgres = &self.backend.controller.postgres;
+        required("backend/controller/postgres/hostname", &postgres.hostname);
+        required("backend/controller/postgres/name", &postgres.name);
+        required("backend/controller/postgres/user", &postgres.user);
+        required("backend/controller/postgres/password", &postgres.password);
+
+        let influx = &self.backend.controller.influx;
+        required("backend/controller/influx/hostname", &influx.hostname);
+        required("backend/controller/influx/org", &influx.org);
+        required("backend/controller/influx/bucket", &influx.bucket);
+        required("backend/controller/influx/token", &influx.token);
+
+        let telegram = &self.backend.controller.telegram;
+        if telegram.enabled {
+            required("backend/controller/telegram/API-token", &telegram.api_token);
+        }
+
+        let smtp = &self.backend.controller.smtp;
+        if smtp.enabled {
+            required("backend/controller/smtp/hostname", &smtp.hostname);
+            required("backend/controller/smtp/from", &smtp.from);
+        }
+
+        for (i, mapping) in self.backend.controller.prometheus.mappings.iter().enumerate() {
+            required(&format!("backend/controller/prometheus/mappings/{i}/series"), &mapping.series);
+        }
+
+        let logging = &self.backend.logging;
+        let levels = std::iter::once(("backend/logging/level".to_string(), &logging.level))
+            .chain(logging.modules.iter().map(|(module, level)| (format!("backend/logging/modules/{module}"), level)));
+        for (path, level) in levels {
+            if level.parse::<log::LevelFilter>().is_err() {
+                errors.push(format!("{path}: '{level}' is not a log level"));
+            }
+        }
+
+        if model.alerts.routing_timezone.parse::<chrono_tz::Tz>().is_err() {
+            errors.push(format!("backend/model/alerts/routing_timezone: '{}' is not a timezone", model.alerts.routing_timezone));
+        }
+
+        let intervals = [
+            ("backend/model/cache/rule_set_cache_invalidation_s", model.cache.rule_set_cache_invalidation_s),
+            ("backend/controller/cache/cache_invalidation_s", self.backend.controller.cache.cache_invalidation_s),
+            ("backend/controller/fact_gathering/polling_time_s", self.backend.controller.fact_gathering.polling_time_s),
+            ("backend/controller/prometheus/timeout_s", self.backend.controller.prometheus.timeout_s),
+            ("backend/model/alerts/state_snapshot_s", model.alerts.state_snapshot_s),
+            ("backend/controller/supervisor/backoff_initial_s", self.backend.controller.supervisor.backoff_initial_s),
+            ("backend/controller/webhooks/timeout_s", self.backend.controller.webhooks.timeout_s),
+        ];
+        for (path, value) in intervals {
+            if value == 0 {
+                errors.push(format!("{path}: must be greater than 0"));
+            }
+        }
+
+        errors
+    }
+}
@@ -0,0 +1,81 @@
+use rocket::Request;
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome};
+
+use crate::model::db::operations::audit_operations::get_token_actor;
+
+/// Header holding the client token of whoever makes a request
+const TOKEN_HEADER: &str = "X-Aegis-Token";
+
+/// Who is making a request, for auditing. Identified by their client token, the same ones used to ack alerts.
+/// Requests without a known token are attributed to their remote address
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Actor {
+    /// Name of the identity the client token belongs to
+    User(String),
+    /// Remote address of the request, if known
+    Anonymous(Option<String>),
+}
+
+impl std::fmt::Display for Actor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Actor::User(name) => write!(f, "{name}"),
+            Actor::Anonymous(address) => write!(f, "anonymous@{}", address.as_deref().unwrap_or("unknown")),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Actor {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let anonymous = Actor::Anonymous(request.client_ip().map(|ip| ip.to_string()));
+
+        let token = match request.headers().get_one(TOKEN_HEADER) {
+            Some(t) => t,
+            None => return Outcome::Success(anonymous),
+        };
+        let pool = match request.rocket().state::<sqlx::PgPool>() {
+            Some(p) => p,
+            None => return Outcome::Success(anonymous),
+        };
+
+        match get_token_actor(pool, token).await {
+            Ok(Some(name)) => Outcome::Success(Actor::User(name)),
+            Ok(None) => {
+                log::warn!(subsystem = "api"; "Request from '{anonymous}' has an unknown client token, treating it as anonymous");
+                Outcome::Success(anonymous)
+            },
+            Err(e) => {
+                log::error!(subsystem = "api.db"; "Could not look up client token, treating request as anonymous. Error = '{e:?}'");
+                Outcome::Success(anonymous)
+            }
+        }
+    }
+}
+
+/// Same as `Actor`, but rejects requests without a known client token. For endpoints that must not be anonymous
+pub struct AuthenticatedActor(pub String);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AuthenticatedActor {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let actor = match Actor::from_request(request).await {
+            Outcome::Success(actor) => actor,
+            Outcome::Error(e) => return Outcome::Error(e),
+            Outcome::Forward(s) => return Outcome::Forward(s),
+        };
+
+        match actor {
+            Actor::User(name) => Outcome::Success(AuthenticatedActor(name)),
+            Actor::Anonymous(_) => {
+                log::warn!(subsystem = "api"; "Rejected request from '{actor}', a known client token is required");
+                Outcome::Error((Status::Unauthorized, ()))
+            },
+        }
+    }
+}
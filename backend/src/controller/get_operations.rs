@@ -1,6 +1,6 @@
 use sqlx::Postgres;
 
-use crate::{AegisError, alerts::alert_backend::AlertBackend, model::db::fetch_topology::get_topology_as_json};
+use crate::{AegisError, alerts::alert_backend::AlertBackend, model::{db::{fetch_topology::get_topology_as_json, operations::{audit_operations::{self, AuditFilters}, discovery_operations, topology_document}}, discovery::LinkProposalStatus}};
 
 pub async fn api_get_topology(pool: &sqlx::Pool<Postgres>) -> Result<serde_json::Value, AegisError> {
     get_topology_as_json(pool).await
@@ -8,4 +8,26 @@ pub async fn api_get_topology(pool: &sqlx::Pool<Postgres>) -> Result<serde_json:
 
 pub async fn api_get_rules() -> Result<serde_json::Value, rocket::http::Status> {
     Ok(AlertBackend::get_rules_as_json().await)
-}
\ No newline at end of file
+}
+
+pub async fn api_get_link_proposals(pool: &sqlx::Pool<Postgres>, status: Option<LinkProposalStatus>) -> Result<serde_json::Value, AegisError> {
+    let proposals = discovery_operations::get_proposals(pool, status).await?;
+    serde_json::to_value(proposals).map_err(AegisError::Serde)
+}
+
+/// Exports the whole topology as a document, serialized as YAML if `yaml` is set, JSON otherwise
+pub async fn api_export_topology(pool: &sqlx::Pool<Postgres>, yaml: bool) -> Result<String, (String, i16)> {
+    let document = topology_document::export(pool).await?;
+
+    let serialized = if yaml {
+        topology_document::to_yaml(&document)
+    } else {
+        serde_json::to_string_pretty(&document).map_err(|e| e.to_string())
+    };
+    serialized.map_err(|e| (format!("Could not serialize topology document, error = '{e}'"), 500))
+}
+
+pub async fn api_get_audit_log(pool: &sqlx::Pool<Postgres>, filters: &AuditFilters) -> Result<serde_json::Value, AegisError> {
+    let entries = audit_operations::get_entries(pool, filters).await?;
+    serde_json::to_value(entries).map_err(AegisError::Serde)
+}
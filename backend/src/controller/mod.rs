@@ -1,4 +1,5 @@
 pub mod server;
 mod ws_operations;
 mod get_operations;
-mod post_operations;
\ No newline at end of file
+mod post_operations;
+mod actor;
\ No newline at end of file
@@ -1,8 +1,44 @@
 use sqlx::Postgres;
+use sqlx::types::chrono::{DateTime, Utc};
 
+use crate::alerts::AlertRule;
+use crate::alerts::backtest::{self, BacktestReport};
+use crate::model::db::operations::audit_operations::{self, AuditSource, Revision};
 use crate::model::db::operations::commit_changes::commit;
+use crate::model::db::operations::commit_validation::{self, FieldError, KnownTopology};
+use crate::model::db::operations::topology_document::{self, DocumentDiff};
+use crate::model::discovery::LinkProposalId;
+use crate::model::discovery::discovery_backend::DiscoveryBackend;
 
 
-pub async fn api_configure(data: serde_json::Value, pool: &sqlx::Pool<Postgres>) -> Result<(), (String, i16)> {
-    commit(data, pool).await
-}
\ No newline at end of file
+pub async fn api_configure(data: serde_json::Value, pool: &sqlx::Pool<Postgres>, actor: &str) -> Result<(), (String, i16)> {
+    commit(data, pool, actor, AuditSource::Configure).await
+}
+
+pub async fn api_validate_configure(data: &serde_json::Value) -> Vec<FieldError> {
+    commit_validation::validate(data, &KnownTopology::from_cache().await)
+}
+
+pub async fn api_approve_link_proposal(id: LinkProposalId, pool: &sqlx::Pool<Postgres>, actor: &str) -> Result<(), (String, i16)> {
+    DiscoveryBackend::approve_proposal(pool, id, actor).await
+}
+
+pub async fn api_reject_link_proposal(id: LinkProposalId, pool: &sqlx::Pool<Postgres>) -> Result<(), (String, i16)> {
+    DiscoveryBackend::reject_proposal(pool, id).await
+}
+
+pub async fn api_import_topology(content: &str, dry_run: bool, prune: bool, pool: &sqlx::Pool<Postgres>, actor: &str) -> Result<DocumentDiff, (String, i16)> {
+    let document = topology_document::parse(content)?;
+    topology_document::import(pool, document, dry_run, prune, actor).await
+}
+
+pub async fn api_rollback(revision: Revision, pool: &sqlx::Pool<Postgres>, actor: &str) -> Result<DocumentDiff, (String, i16)> {
+    audit_operations::rollback(pool, revision, actor).await
+}
+
+pub async fn api_backtest_rule(definition: serde_json::Value, start: DateTime<Utc>, end: DateTime<Utc>, limit: usize, pool: &sqlx::Pool<Postgres>, influx_client: &influxdb2::Client) -> Result<BacktestReport, (String, i16)> {
+    let rule: AlertRule = serde_json::from_value(definition)
+        .map_err(|e| (format!("Malformed Request: rule definition is invalid, error = '{e}'"), 400))?;
+
+    backtest::backtest(&rule, start, end, limit, pool, influx_client).await
+}
@@ -1,7 +1,9 @@
 use rocket::response::status;
 use tokio::sync::mpsc::{self, Sender};
 use rocket::futures::SinkExt;
-use rocket::{State, response};
+use rocket::{Data, State, response};
+use rocket::data::ToByteUnit;
+use rocket::http::ContentType;
 use rocket::futures::stream::{SplitSink, SplitStream};
 use rocket::{futures::StreamExt, get, post};
 use rocket_ws::{Message, WebSocket, stream::DuplexStream};
@@ -10,9 +12,14 @@ use tokio::task::JoinHandle;
 use crate::alerts::{AlertEvent, AlertFilters};
 use crate::alerts::alert_backend::AlertBackend;
 use crate::config::Config;
+use crate::logging;
+use crate::telemetry::Telemetry;
+use crate::controller::actor::{Actor, AuthenticatedActor};
 use crate::controller::get_operations::{self, api_get_topology};
 use crate::controller::post_operations;
-use crate::controller::ws_operations::{WsMsg, ws_alerts_rt, ws_check_backend_ws, ws_device_health_rt, ws_get_dashboards, ws_get_topology, ws_get_topology_view, ws_handle_alerts, ws_handle_syslog, ws_query_facts, ws_query_metadata, ws_query_metrics, ws_send_error_msg, ws_syslog_rt};
+use crate::controller::ws_operations::{WsMsg, ws_alerts_rt, ws_check_backend_ws, ws_device_health_rt, ws_get_dashboards, ws_get_topology, ws_get_topology_view, ws_handle_alerts, ws_handle_syslog, ws_preview_rule, ws_query_facts, ws_query_metadata, ws_query_metrics, ws_send_error_msg, ws_syslog_rt};
+use crate::model::db::operations::audit_operations::{AuditFilters, AuditSource, Revision};
+use crate::model::discovery::{LinkProposalId, LinkProposalStatus};
 use crate::model::facts::fact_gathering_backend::{FactGatheringBackend, FactMessage};
 use crate::syslog::{SyslogFilters, SyslogMessage};
 use crate::syslog::syslog_backend::SyslogBackend;
@@ -31,25 +38,61 @@ use crate::syslog::syslog_backend::SyslogBackend;
 //                               $$/
 #[get("/heartbeat")]
 pub fn heartbeat() -> &'static str {
-    #[cfg(debug_assertions)] {print!("[DEBUG]Heartbeat!");}
+    #[cfg(debug_assertions)] {log::debug!(subsystem = "api"; "Heartbeat!");}
     "Bip bop"
 }
 
+/// Backend self-observability, in the Prometheus text format
+#[get("/metrics")]
+pub async fn get_metrics() -> (rocket::http::ContentType, String) {
+    let content_type = rocket::http::ContentType::new("text", "plain").with_params([("version", "0.0.4"), ("charset", "utf-8")]);
+    (content_type, Telemetry::render().await)
+}
+
+/// Log levels in effect
+#[get("/api/logging")]
+pub async fn get_log_levels(_actor: AuthenticatedActor) -> RocketJson {
+    RocketJson::from(serde_json::json!(logging::levels()))
+}
+
+/// Changes log levels until the next restart, or until a reload changes them in the configuration.
+/// Body: `{"level": "debug", "modules": {"backend_aegis::syslog": "trace", "sqlx": null}}`, both optional
+#[post("/api/logging", data = "<data>")]
+pub async fn set_log_levels(data: RocketJson, actor: AuthenticatedActor) -> status::Custom<RocketJson> {
+    match logging::apply_change(&logging::levels(), &data.0) {
+        Ok(levels) => {
+            logging::set_levels(levels.clone());
+            log::warn!(subsystem = "api.logging", actor = actor.0.as_str(); "Log levels changed at runtime by '{}'", actor.0);
+            status::Custom(rocket::http::Status::Ok, RocketJson::from(serde_json::json!(levels)))
+        },
+        Err(msg) => {
+            let err_body = serde_json::json!({
+                "code": "400",
+                "message": msg
+            });
+            status::Custom(rocket::http::Status::BadRequest, RocketJson::from(err_body))
+        },
+    }
+}
+
 #[get("/api/reload_config")]
 pub async fn get_reload_config() -> status::Custom<RocketJson>{
     match Config::reload() {
-        Some(_) => {
+        Ok(report) => {
             let ok_body = serde_json::json!({
                 "code": 200,
-                "message": "Success"
+                "message": "Success",
+                "changed": report.changed,
+                "restart-required": report.restart_required,
             });
 
             status::Custom(rocket::http::Status::Ok, RocketJson::from(ok_body))
         },
-        None => {
+        Err(e) => {
+            log::error!(subsystem = "config"; "Failed to reload configuration, keeping the current one. e = '{e:#}'");
             let err_body = serde_json::json!({
                 "code": 500,
-                "message": "Failed to load rules"
+                "message": format!("Failed to reload configuration: {e:#}")
             });
 
             status::Custom(rocket::http::Status::InternalServerError, RocketJson::from(err_body))
@@ -59,7 +102,7 @@ pub async fn get_reload_config() -> status::Custom<RocketJson>{
 
 #[get("/api/topology")]
 pub async fn get_topology(pool: &State<sqlx::PgPool>) -> Result<response::content::RawJson<String>, rocket::http::Status> {
-    #[cfg(debug_assertions)] {log::debug!("[DEBUG]Get topology!");}
+    #[cfg(debug_assertions)] {log::debug!("Get topology!");}
     
     let topology = api_get_topology(pool).await.map_err(|_|rocket::http::Status::BadRequest)?;
     let topology = topology.to_string();
@@ -85,60 +128,339 @@ pub async fn get_rules() -> status::Custom<RocketJson> {
     }
 }
 
-#[post("/api/configure", data = "<data>")]
-pub async fn api_configure(data: RocketJson, pool: &State<sqlx::PgPool>) -> status::Custom<RocketJson> {
+/// Replays historical data between `start` and `end` (epoch seconds) through the given rule definition,
+/// returning the alerts it would have raised. Nothing is stored
+#[post("/api/rules/backtest?<start>&<end>&<limit>", data = "<data>")]
+pub async fn backtest_rule(
+    data: RocketJson,
+    start: i64,
+    end: i64,
+    limit: Option<usize>,
+    pool: &State<sqlx::PgPool>,
+    influx_client: &State<influxdb2::Client>
+) -> status::Custom<RocketJson> {
+    let (start, end) = match (chrono::DateTime::from_timestamp(start, 0), chrono::DateTime::from_timestamp(end, 0)) {
+        (Some(start), Some(end)) => (start, end),
+        _ => {
+            let err_body = serde_json::json!({
+                "code": "400",
+                "message": "Malformed Request: 'start' and 'end' should be epoch seconds"
+            });
+            return status::Custom(rocket::http::Status::BadRequest, RocketJson::from(err_body));
+        }
+    };
+    let limit = limit.unwrap_or(1000).clamp(1, 10000);
+
+    match post_operations::api_backtest_rule(data.0, start, end, limit, pool.inner(), influx_client.inner()).await {
+        Ok(report) => {
+            let ok_body = serde_json::json!({
+                "code": "200",
+                "message": "",
+                "report": report,
+            });
+            status::Custom(rocket::http::Status::Ok, RocketJson::from(ok_body))
+        },
+        Err((msg, code)) => {
+            log::error!(subsystem = "post"; "Post on 'api/rules/backtest' resulted in an error = '{msg}'");
+            let err_body = serde_json::json!({
+                "code": code.to_string(),
+                "message": msg
+            });
+            let status = rocket::http::Status::from_code(code as u16).unwrap_or(rocket::http::Status::BadRequest);
+            status::Custom(status, RocketJson::from(err_body))
+        }
+    }
+}
+
+#[post("/api/configure?<dry_run>", data = "<data>")]
+pub async fn api_configure(data: RocketJson, dry_run: Option<bool>, pool: &State<sqlx::PgPool>, actor: Actor) -> status::Custom<RocketJson> {
     
     #[cfg(debug_assertions)] {
-        log::info!("[INFO ][API][RX] {}", data.0);
+        log::info!(subsystem = "api.rx"; "{}", data.0);
     }
 
-    if !Config::instance().get("backend/controller/configure/enabled", "/").unwrap_or(false) {
-        log::warn!("[WARN ][API] Tried to configure, while read-only!");
+    // A dry run never touches the database, so it's allowed even when read-only
+    let dry_run = dry_run.unwrap_or(false);
+    if !dry_run && let Some(err) = read_only_error() {
+        return err;
+    }
 
+    if data.0.as_object().is_some_and(|map| map.is_empty()) {
         let err_body = serde_json::json!({
-            "code": "403",
-            "message": "Cannot make changes while backend is in read only mode!"
+            "code": "400",
+            "message": "Malformed Request: Content is empty"
         });
         return status::Custom(rocket::http::Status::BadRequest, RocketJson::from(err_body));
     }
 
-    match &data.0 {
-        serde_json::Value::Object(map) => {
-            if map.is_empty() {
-                let err_body = serde_json::json!({
-                    "code": "400",
-                    "message": "Malformed Request: Content is empty"
-                });
-                return status::Custom(rocket::http::Status::BadRequest, RocketJson::from(err_body));
-            }
+    let errors = post_operations::api_validate_configure(&data.0).await;
+    if !errors.is_empty() {
+        let err_body = serde_json::json!({
+            "code": "400",
+            "message": format!("Malformed Request: Found {} invalid field(s)", errors.len()),
+            "errors": errors
+        });
+        return status::Custom(rocket::http::Status::BadRequest, RocketJson::from(err_body));
+    }
+
+    if dry_run {
+        let ok_body = serde_json::json!({
+            "code": "200",
+            "message": "",
+            "errors": []
+        });
+        return status::Custom(rocket::http::Status::Ok, RocketJson::from(ok_body));
+    }
+
+    let response = post_operations::api_configure(data.0, pool.inner(), &actor.to_string()).await;
+
+    match response {
+        Ok(_) => {
+            let ok_body = serde_json::json!({
+                "code": "202",
+                "message": ""
+            });
+            status::Custom(rocket::http::Status::Accepted, RocketJson::from(ok_body))
         },
+        Err(e) => {
+            let err_body = serde_json::json!({
+                "code": "400",
+                "message": e.0
+            });
+            log::error!(subsystem = "post"; "Post on 'api/configure' resulted in an error = '{}'", e.0);
+            status::Custom(rocket::http::Status::BadRequest, RocketJson::from(err_body))
+        }
+    }
+}
 
+#[get("/api/topology/export?<format>")]
+pub async fn export_topology(format: Option<&str>, pool: &State<sqlx::PgPool>) -> status::Custom<(ContentType, String)> {
+    let yaml = match format.unwrap_or("json") {
+        "json" => false,
+        "yaml" | "yml" => true,
         _ => {
             let err_body = serde_json::json!({
                 "code": "400",
-                "message": "Malformed Request: Content should be a JSON Object"
+                "message": "Malformed Request: 'format' should be one of 'json' or 'yaml'"
             });
-            return status::Custom(rocket::http::Status::BadRequest, RocketJson::from(err_body));
+            return status::Custom(rocket::http::Status::BadRequest, (ContentType::JSON, err_body.to_string()));
+        }
+    };
+
+    match get_operations::api_export_topology(pool.inner(), yaml).await {
+        Ok(document) => {
+            let content_type = if yaml { ContentType::new("application", "yaml") } else { ContentType::JSON };
+            status::Custom(rocket::http::Status::Ok, (content_type, document))
+        },
+        Err((msg, code)) => {
+            log::error!(subsystem = "api"; "Failed to export topology, error = '{msg}'");
+            let err_body = serde_json::json!({
+                "code": code.to_string(),
+                "message": msg
+            });
+            let status = rocket::http::Status::from_code(code as u16).unwrap_or(rocket::http::Status::InternalServerError);
+            status::Custom(status, (ContentType::JSON, err_body.to_string()))
         }
     }
+}
 
-    let response = post_operations::api_configure(data.0, pool.inner()).await;
+/// Imports a topology document, in YAML or JSON. With `dry_run`, only the changes that would be made are returned.
+/// With `prune`, items not present in the document are deleted
+#[post("/api/topology/import?<dry_run>&<prune>", data = "<data>")]
+pub async fn import_topology(data: Data<'_>, dry_run: Option<bool>, prune: Option<bool>, pool: &State<sqlx::PgPool>, actor: Actor) -> status::Custom<RocketJson> {
+    let dry_run = dry_run.unwrap_or(false);
+    if !dry_run && let Some(err) = read_only_error() {
+        return err;
+    }
 
-    match response {
+    let limit_mib = Config::instance().settings().backend.controller.configure.import_limit_mib;
+    let content = match data.open(limit_mib.mebibytes()).into_string().await {
+        Ok(c) if c.is_complete() => c.into_inner(),
         Ok(_) => {
+            let err_body = serde_json::json!({
+                "code": "413",
+                "message": format!("Topology document is larger than {limit_mib} MiB")
+            });
+            return status::Custom(rocket::http::Status::PayloadTooLarge, RocketJson::from(err_body));
+        },
+        Err(e) => {
+            let err_body = serde_json::json!({
+                "code": "400",
+                "message": format!("Malformed Request: could not read body, error = '{e}'")
+            });
+            return status::Custom(rocket::http::Status::BadRequest, RocketJson::from(err_body));
+        }
+    };
+
+    match post_operations::api_import_topology(&content, dry_run, prune.unwrap_or(false), pool.inner(), &actor.to_string()).await {
+        Ok(diff) => {
+            let ok_body = serde_json::json!({
+                "code": if dry_run { "200" } else { "202" },
+                "message": "",
+                "diff": diff,
+            });
+            let status = if dry_run { rocket::http::Status::Ok } else { rocket::http::Status::Accepted };
+            status::Custom(status, RocketJson::from(ok_body))
+        },
+        Err((msg, code)) => {
+            log::error!(subsystem = "post"; "Post on 'api/topology/import' resulted in an error = '{msg}'");
+            let err_body = serde_json::json!({
+                "code": code.to_string(),
+                "message": msg
+            });
+            let status = rocket::http::Status::from_code(code as u16).unwrap_or(rocket::http::Status::BadRequest);
+            status::Custom(status, RocketJson::from(err_body))
+        }
+    }
+}
+
+/// Audit log of configuration commits, newest first. `since` and `until` are epoch seconds
+#[get("/api/audit?<actor>&<source>&<since>&<until>&<limit>")]
+pub async fn get_audit_log(
+    actor: Option<String>,
+    source: Option<&str>,
+    since: Option<i64>,
+    until: Option<i64>,
+    limit: Option<i64>,
+    pool: &State<sqlx::PgPool>
+) -> status::Custom<RocketJson> {
+    let source = match source.map(|s| serde_json::from_value::<AuditSource>(serde_json::json!(s))).transpose() {
+        Ok(s) => s,
+        Err(_) => {
+            let err_body = serde_json::json!({
+                "code": "400",
+                "message": "Malformed Request: 'source' should be one of 'configure', 'import', 'discovery' or 'rollback'"
+            });
+            return status::Custom(rocket::http::Status::BadRequest, RocketJson::from(err_body));
+        }
+    };
+
+    let filters = AuditFilters {
+        actor,
+        source,
+        since: since.and_then(|s| chrono::DateTime::from_timestamp(s, 0)),
+        until: until.and_then(|s| chrono::DateTime::from_timestamp(s, 0)),
+        limit: limit.unwrap_or(100).clamp(1, 1000),
+    };
+
+    match get_operations::api_get_audit_log(pool.inner(), &filters).await {
+        Ok(json) => status::Custom(rocket::http::Status::Ok, RocketJson::from(json)),
+        Err(e) => {
+            log::error!(subsystem = "api"; "Failed to get audit log, e = '{e:?}'");
+            let err_body = serde_json::json!({
+                "code": 500,
+                "message": "Failed to load audit log"
+            });
+            status::Custom(rocket::http::Status::InternalServerError, RocketJson::from(err_body))
+        }
+    }
+}
+
+/// Restores the configuration as it was right after the given revision
+#[post("/api/audit/<revision>/rollback")]
+pub async fn rollback_revision(revision: Revision, pool: &State<sqlx::PgPool>, actor: Actor) -> status::Custom<RocketJson> {
+    if let Some(err) = read_only_error() {
+        return err;
+    }
+
+    match post_operations::api_rollback(revision, pool.inner(), &actor.to_string()).await {
+        Ok(diff) => {
             let ok_body = serde_json::json!({
                 "code": "202",
-                "message": ""
+                "message": "",
+                "diff": diff,
             });
             status::Custom(rocket::http::Status::Accepted, RocketJson::from(ok_body))
         },
-        Err(e) => {
+        Err((msg, code)) => {
+            log::error!(subsystem = "post"; "Rollback to revision {revision} resulted in an error = '{msg}'");
+            let err_body = serde_json::json!({
+                "code": code.to_string(),
+                "message": msg
+            });
+            let status = rocket::http::Status::from_code(code as u16).unwrap_or(rocket::http::Status::BadRequest);
+            status::Custom(status, RocketJson::from(err_body))
+        }
+    }
+}
+
+/// Returns the error response for write endpoints if the backend is in read-only mode
+fn read_only_error() -> Option<status::Custom<RocketJson>> {
+    if Config::instance().settings().backend.controller.configure.enabled {
+        return None;
+    }
+
+    log::warn!(subsystem = "api"; "Tried to configure, while read-only!");
+    let err_body = serde_json::json!({
+        "code": "403",
+        "message": "Cannot make changes while backend is in read only mode!"
+    });
+    Some(status::Custom(rocket::http::Status::BadRequest, RocketJson::from(err_body)))
+}
+
+#[get("/api/discovery/proposals?<status>")]
+pub async fn get_link_proposals(status: Option<&str>, pool: &State<sqlx::PgPool>) -> status::Custom<RocketJson> {
+    let status = match status.map(|s| serde_json::from_value::<LinkProposalStatus>(serde_json::json!(s))).transpose() {
+        Ok(s) => s,
+        Err(_) => {
             let err_body = serde_json::json!({
                 "code": "400",
-                "message": e.0
+                "message": "Malformed Request: 'status' should be one of 'pending', 'approved' or 'rejected'"
             });
-            log::error!("[POST] Post on 'api/configure' resulted in an error = '{}'", e.0);
-            status::Custom(rocket::http::Status::BadRequest, RocketJson::from(err_body))
+            return status::Custom(rocket::http::Status::BadRequest, RocketJson::from(err_body));
+        }
+    };
+
+    match get_operations::api_get_link_proposals(pool.inner(), status).await {
+        Ok(json) => status::Custom(rocket::http::Status::Ok, RocketJson::from(json)),
+        Err(e) => {
+            log::error!(subsystem = "api"; "Failed to get link proposals, e = '{e}'");
+            let err_body = serde_json::json!({
+                "code": 500,
+                "message": "Failed to load link proposals"
+            });
+            status::Custom(rocket::http::Status::InternalServerError, RocketJson::from(err_body))
+        }
+    }
+}
+
+#[post("/api/discovery/proposals/<id>/approve")]
+pub async fn approve_link_proposal(id: LinkProposalId, pool: &State<sqlx::PgPool>, actor: Actor) -> status::Custom<RocketJson> {
+    if let Some(err) = read_only_error() {
+        return err;
+    }
+
+    let response = post_operations::api_approve_link_proposal(id, pool.inner(), &actor.to_string()).await;
+    resolution_response(response, "approve")
+}
+
+#[post("/api/discovery/proposals/<id>/reject")]
+pub async fn reject_link_proposal(id: LinkProposalId, pool: &State<sqlx::PgPool>) -> status::Custom<RocketJson> {
+    if let Some(err) = read_only_error() {
+        return err;
+    }
+
+    let response = post_operations::api_reject_link_proposal(id, pool.inner()).await;
+    resolution_response(response, "reject")
+}
+
+fn resolution_response(response: Result<(), (String, i16)>, action: &str) -> status::Custom<RocketJson> {
+    match response {
+        Ok(_) => {
+            let ok_body = serde_json::json!({
+                "code": "200",
+                "message": ""
+            });
+            status::Custom(rocket::http::Status::Ok, RocketJson::from(ok_body))
+        },
+        Err((msg, code)) => {
+            log::error!(subsystem = "post"; "Failed to {action} link proposal, error = '{msg}'");
+            let err_body = serde_json::json!({
+                "code": code.to_string(),
+                "message": msg
+            });
+            let status = rocket::http::Status::from_code(code as u16).unwrap_or(rocket::http::Status::BadRequest);
+            status::Custom(status, RocketJson::from(err_body))
         }
     }
 }
@@ -155,9 +477,10 @@ pub async fn api_configure(data: RocketJson, pool: &State<sqlx::PgPool>) -> stat
 
 #[get("/ws/router")]
 pub fn ws_router<'a>(ws: WebSocket, pool: &'a State<sqlx::PgPool>, influx_client : &'a State<influxdb2::Client>) -> rocket_ws::Channel<'a> {
-    log::info!("[INFO ][WS] Websocket initiated connection, {}", ws.accept_key());
+    log::info!(subsystem = "ws"; "Websocket initiated connection, {}", ws.accept_key());
     ws.channel(move |stream| Box::pin(async move {
         let (ws_sender, ws_receiver) = stream.split();
+        Telemetry::instance().ws_sessions.inc();
 
         // Channels (bounded capacity 64)
         let (data_to_socket_tx, data_to_socket_rx) = mpsc::channel::<String>(64);
@@ -179,17 +502,18 @@ pub fn ws_router<'a>(ws: WebSocket, pool: &'a State<sqlx::PgPool>, influx_client
 
         // Wait until any task finishes, then let the rest drop
         tokio::select! {
-            _ = tx_task               => log::warn!("[WARN ][WS] tx_task finished!"),
-            _ = rx_task               => log::warn!("[WARN ][WS] rx_task finished!"),
-            _ = syslog_rt_task        => log::warn!("[WARN ][WS] syslog_rt_task finished!"),
-            _ = alerts_rt_task        => log::warn!("[WARN ][WS] alerts_rt_task finished!"),
-            _ = device_health_rt_task => log::warn!("[WARN ][WS] device_health_rt_task finished!"),
+            _ = tx_task               => log::warn!(subsystem = "ws"; "tx_task finished!"),
+            _ = rx_task               => log::warn!(subsystem = "ws"; "rx_task finished!"),
+            _ = syslog_rt_task        => log::warn!(subsystem = "ws"; "syslog_rt_task finished!"),
+            _ = alerts_rt_task        => log::warn!(subsystem = "ws"; "alerts_rt_task finished!"),
+            _ = device_health_rt_task => log::warn!(subsystem = "ws"; "device_health_rt_task finished!"),
         }
 
         // Explicitly remove the listeners, to avoid having ghost listeners
         SyslogBackend::instance().remove_listener(syslog_listener_id).await;
         AlertBackend::instance().remove_listener(alerts_listener_id).await;
         FactGatheringBackend::instance().remove_listener(facts_listener_id).await;
+        Telemetry::instance().ws_sessions.dec();
 
         Ok(())
     }))
@@ -202,10 +526,10 @@ async fn ws_send_task(
     while let Some(msg) = data_to_socket.recv().await {
         #[cfg(debug_assertions)] {
             let truncated = msg.chars().take(500).collect::<String>();
-            log::info!("\x1b[33m[DEBUG][WS][TX] Sending msg='{}...'\x1b[0m", truncated);}
+            log::info!(subsystem = "ws.tx"; "\x1b[33mSending msg='{}...'\x1b[0m", truncated);}
         let result = ws_sender.send(Message::Text(msg)).await;
         if result.is_err() {
-            log::error!("[ERROR][WS][TX] Encountered an error, sender is closing!");
+            log::error!(subsystem = "ws.tx"; "Encountered an error, sender is closing!");
             break;
         }
     }
@@ -225,15 +549,15 @@ async fn ws_receive_task(
     while let Some(msg) = ws_receiver.next().await {
         match msg {
             Err(e) => {
-                log::error!("[ERROR][WS][RX] Encountered an error, receiver is closing!");
+                log::error!(subsystem = "ws.rx"; "Encountered an error, receiver is closing!");
                 let error = e.to_string();
                 if let Err(e) = data_to_socket_tx.send(serde_json::json![{"type": "error", "msg": error}].to_string()).await {
                     let e = e.to_string();
-                    log::error!("[ERROR][WS][RX] Failed to send error message to websocket, e = '{e}'")
+                    log::error!(subsystem = "ws.rx"; "Failed to send error message to websocket, e = '{e}'")
                 }
             },
             Ok(msg) => {
-                #[cfg(debug_assertions)] {log::info!("\x1b[35m[DEBUG][WS][RX] Received msg='{}'\x1b[0m", msg);}
+                #[cfg(debug_assertions)] {log::info!(subsystem = "ws.rx"; "\x1b[35mReceived msg='{}'\x1b[0m", msg);}
                 ws_handle_message(&pool, &influx_client, &mut data_to_socket_tx, &mut syslog_filters, &mut alert_filters, msg).await;
             }
         }
@@ -288,6 +612,7 @@ async fn ws_route_message(
         "metrics" => ws_query_metrics(data_to_socket, influx_client, msg).await.unwrap_or(()),
         "facts"    => ws_query_facts(data_to_socket, msg).await.unwrap_or(()),
         "metadata" => ws_query_metadata(data_to_socket, msg).await.unwrap_or(()),
+        "rule-preview" => ws_preview_rule(data_to_socket, msg).await.unwrap_or(()),
         "topology" => ws_get_topology(data_to_socket, pool).await.unwrap_or(()),
         "topology-view" => ws_get_topology_view(data_to_socket, pool).await.unwrap_or(()),
         _ => {
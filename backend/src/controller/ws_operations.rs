@@ -4,7 +4,7 @@ use std::fmt::Display;
 use sqlx::Postgres;
 use tokio::sync::mpsc;
 
-use crate::alerts::{AlertEvent, AlertFilters};
+use crate::alerts::{AlertEvent, AlertFilters, AlertRule, AlertRuleKind, EvaluableItem};
 use crate::model::cache::Cache;
 use crate::model::data::device_state::DeviceStatus;
 use crate::model::db::fetch_topology::{get_topology_as_json, get_topology_view_as_json};
@@ -13,7 +13,7 @@ use crate::model::db::operations::dashboard_operations::get_dashboards_as_json;
 use crate::model::db::operations::influx_operations::{self, InfluxFilter};
 use crate::model::facts::fact_gathering_backend::FactMessage;
 use crate::syslog::{SyslogFilters, SyslogMessage};
-use crate::types::{DeviceId, ItemId, DeviceHostname, MetricValue, Metrics};
+use crate::types::{DeviceId, ItemId, DeviceHostname, MetricSet, MetricValue, Metrics};
 
 
 #[derive(serde::Deserialize, serde::Serialize, Debug)]
@@ -42,11 +42,11 @@ impl Display for WsMsg {
 
 pub async fn ws_send_error_msg(data_to_socket: &mut mpsc::Sender<String>, msg: &str,) {
     let msg = format!(r#"{{"type": "error", "msg":"{msg}"}}"#);
-    log::error!("[ERROR][WS]Encountered error= '{msg}'");
+    log::error!(subsystem = "ws"; "Encountered error= '{msg}'");
     let result = data_to_socket.send(msg).await;
 
     if let Err(e) = result {
-        log::error!("[ERROR][WS]Failed to notify via websocket='{e}'")
+        log::error!(subsystem = "ws"; "Failed to notify via websocket='{e}'")
     }
 }
 
@@ -168,7 +168,7 @@ pub async fn ws_query_facts(data_to_socket: &mut mpsc::Sender<String>, msg: WsMs
     for device in device_ids {
         let id: DeviceId = match device.as_i64() { Some(v) => v, None => continue };
         if cache.has_group(id).await {
-            let members = match cache.get_group_members(id).await {
+            let members = match cache.get_group_device_ids(id).await {
                 Some(m) => m,
                 None => {
                     ws_send_error_msg(data_to_socket, 
@@ -205,7 +205,92 @@ pub async fn ws_query_facts(data_to_socket: &mut mpsc::Sender<String>, msg: WsMs
         }
     });
     if let Err(e) = data_to_socket.send(msg.to_string()).await {
-        log::error!("[ERROR][WS] Failed to send message with send error = {e}");
+        log::error!(subsystem = "ws"; "Failed to send message with send error = {e}");
+        Err(())
+    } else {
+        Ok(())
+    }
+}
+
+/// Evaluates a draft rule against the latest facts in cache, without raising anything.
+/// For each target, returns the resolved values of every predicate and whether it held.
+/// Delta rules compare the latest facts against the previous ones, targets without previous facts are reported as not previewable.
+/// There's no elapsed time in a preview, so Sustained rules report whether they would start counting
+pub async fn ws_preview_rule(data_to_socket: &mut mpsc::Sender<String>, msg: WsMsg) -> Result<(), ()> {
+    let rule: AlertRule = match msg.body.get("rule").map(|r| serde_json::from_value(r.clone())) {
+        Some(Ok(r)) => r,
+        Some(Err(e)) => {
+            ws_send_error_msg(data_to_socket, &format!("[BACKEND] Rule preview message contains an invalid 'rule', error = '{e}'")).await;
+            return Err(())
+        },
+        None => {
+            ws_send_error_msg(data_to_socket, "[BACKEND] Rule preview message contains no 'rule' component to evaluate").await;
+            return Err(())
+        }
+    };
+
+    let cache = Cache::instance();
+    // (id, name, previous metrics, metrics)
+    let mut datasets: Vec<(ItemId, String, Option<MetricSet>, Option<MetricSet>)> = Vec::new();
+    match cache.get_evaluable_item(rule.target_item).await {
+        Some(EvaluableItem::Device(device)) => {
+            let previous = cache.get_previous_metrics(&device.management_hostname).await;
+            let facts = cache.facts.read().await;
+            datasets.push((device.device_id, device.device_name, previous, facts.get(&device.management_hostname).map(|f| f.metrics.clone())));
+        },
+        Some(EvaluableItem::Group(group)) => {
+            let members = cache.get_group_device_ids(group.group_id).await.unwrap_or_default();
+            for member in members {
+                let device = match cache.get_device(member).await { Some(d) => d, None => continue };
+                let previous = cache.get_previous_metrics(&device.management_hostname).await;
+                let facts = cache.facts.read().await;
+                datasets.push((device.device_id, device.device_name, previous, facts.get(&device.management_hostname).map(|f| f.metrics.clone())));
+            }
+        },
+        Some(EvaluableItem::Link(link)) => {
+            let label = cache.get_link_label(link.link_id).await.unwrap_or(link.link_id.to_string());
+            let (previous, metrics) = cache.get_link_metrics(link.link_id).await;
+            datasets.push((link.link_id, label, previous, metrics));
+        },
+        None => {
+            ws_send_error_msg(data_to_socket, &format!("[BACKEND] Rule preview target with id={} does not exist", rule.target_item)).await;
+            return Err(())
+        }
+    };
+
+    let targets: Vec<serde_json::Value> = datasets.into_iter().map(|(id, name, previous, metrics)| {
+        // Only Delta rules look at the previous dataset
+        let previous = match rule.rule_kind {
+            AlertRuleKind::Delta => previous.as_ref(),
+            _ => metrics.as_ref(),
+        };
+        // Targets without facts are skipped by live evaluation, they're reported so the user knows why
+        let (result, predicates) = match (previous, &metrics) {
+            (Some(previous), Some(metrics)) => {
+                let (result, predicates) = rule.preview(previous, metrics);
+                (Some(result), predicates)
+            },
+            _ => (None, Vec::new()),
+        };
+        serde_json::json!({
+            "target-id": id,
+            "name": name,
+            "has-facts": metrics.is_some(),
+            "previewable": previous.is_some() && metrics.is_some(),
+            "result": result,
+            "predicates": predicates,
+        })
+    }).collect();
+
+    let msg = serde_json::json!({
+        "type": msg.kind,
+        "msg": {
+            "rule-type": rule.rule_kind.to_string(),
+            "targets": targets,
+        }
+    });
+    if let Err(e) = data_to_socket.send(msg.to_string()).await {
+        log::error!(subsystem = "ws"; "Failed to send message with send error = {e}");
         Err(())
     } else {
         Ok(())
@@ -303,7 +388,7 @@ pub async fn ws_query_metadata(data_to_socket: &mut mpsc::Sender<String>, msg: W
         }
     });
     if let Err(e) = data_to_socket.send(msg.to_string()).await {
-        log::error!("[ERROR][WS] Failed to send message with send error = {e}");
+        log::error!(subsystem = "ws"; "Failed to send message with send error = {e}");
         Err(())
     } else {
         Ok(())
@@ -333,7 +418,7 @@ pub async fn ws_query_metrics(data_to_socket: &mut mpsc::Sender<String>, influx_
     match data_to_socket.send(msg.to_string()).await {
         Ok(_) => return Ok(()),
         Err(e) => {
-            log::error!("[ERROR][WS][METRICS] Failed to send metrics message with e = {}, msg='{}'", e, msg)
+            log::error!(subsystem = "ws.metrics"; "Failed to send metrics message with e = {}, msg='{}'", e, msg)
         }
     }
 
@@ -355,8 +440,8 @@ pub async fn ws_handle_syslog(
         | serde_json::Value::Bool(_) 
         | serde_json::Value::Number(_) 
         | serde_json::Value::String(_) => {
-            let e = format!("[ERROR][WS][SYSLOG] Could not parse inner syslog message. Expected message types = (Object, Array). Message was = '{}'", msg);
-            log::error!("{}", e);
+            let e = format!("Could not parse inner syslog message. Expected message types = (Object, Array). Message was = '{}'", msg);
+            log::error!(subsystem = "ws.syslog"; "{}", e);
             ws_send_error_msg(data_to_socket, &e).await;
             return Err(());
         },
@@ -389,8 +474,8 @@ pub async fn ws_handle_alerts(
         | serde_json::Value::Bool(_) 
         | serde_json::Value::Number(_) 
         | serde_json::Value::String(_) => {
-            let e = format!("[ERROR][WS][ALERTS] Could not parse inner Alert message. Expected message types = (Object, Array). Message was = '{}'", msg);
-            log::error!("{}", e);
+            let e = format!("Could not parse inner Alert message. Expected message types = (Object, Array). Message was = '{}'", msg);
+            log::error!(subsystem = "ws.alerts"; "{}", e);
             ws_send_error_msg(data_to_socket, &e).await;
             return Err(());
         },
@@ -417,7 +502,7 @@ pub async fn ws_check_backend_ws(data_to_socket: &mut mpsc::Sender<String>, pool
     match data_to_socket.send(msg.to_string()).await {
         Ok(_) => (),
         Err(e) => {
-            log::error!("[ERROR][WS][HEALTH] Failed to send message to websocket listener! error= '{e}'. Message will be dropped");
+            log::error!(subsystem = "ws.health"; "Failed to send message to websocket listener! error= '{e}'. Message will be dropped");
         }
     }
 
@@ -439,7 +524,7 @@ pub async fn ws_syslog_rt(data_to_socket: mpsc::Sender<String>, mut receiver: mp
         match data_to_socket.send(msg.to_string()).await {
             Ok(_) => (),
             Err(e) => {
-                log::error!("[ERROR][WS][SYSLOG][REALTIME] Failed to send message to websocket listener! error='{e}'. Message will be ignored");
+                log::error!(subsystem = "ws.syslog.realtime"; "Failed to send message to websocket listener! error='{e}'. Message will be ignored");
             }
         }
     }
@@ -459,7 +544,7 @@ pub async fn ws_alerts_rt(data_to_socket: mpsc::Sender<String>, mut receiver: mp
         match data_to_socket.send(msg.to_string()).await {
             Ok(_) => (),
             Err(e) => {
-                log::error!("[ERROR][WS][ALERTS][REALTIME] Failed to send message to websocket listener! error='{e}'. Message will be ignored");
+                log::error!(subsystem = "ws.alerts.realtime"; "Failed to send message to websocket listener! error='{e}'. Message will be ignored");
             }
         }
     }
@@ -481,7 +566,21 @@ pub async fn ws_device_health_rt(data_to_socket: mpsc::Sender<String>, mut recei
         match data_to_socket.send(msg.to_string()).await {
             Ok(_) => (),
             Err(e) => {
-                log::error!("[ERROR][WS][DEV-HEALTH][REALTIME] Failed to send message to websocket listener! error='{e}'. Channel will be closed!");
+                log::error!(subsystem = "ws.dev-health.realtime"; "Failed to send message to websocket listener! error='{e}'. Channel will be closed!");
+                break;
+            }
+        }
+
+        // Link states are derived before facts are broadcast, so they're in sync with the device status above
+        let links = Cache::instance().get_link_states().await;
+        let msg = serde_json::json!({
+            "type": "link-health-rt", "msg": serde_json::json!(links)
+        });
+
+        match data_to_socket.send(msg.to_string()).await {
+            Ok(_) => (),
+            Err(e) => {
+                log::error!(subsystem = "ws.link-health.realtime"; "Failed to send message to websocket listener! error='{e}'. Channel will be closed!");
                 break;
             }
         }
@@ -506,7 +605,7 @@ fn ws_route_syslog_set_filters(syslog_filters: &mut Option<SyslogFilters>, msg:
     *syslog_filters = match serde_json::from_value(msg.body) {
         Ok(filters) => Some(filters),
         Err(e) => {
-            log::error!("[ERROR][WS][SYSLOG] Failed to parse syslog filters with error = '{e}'");
+            log::error!(subsystem = "ws.syslog"; "Failed to parse syslog filters with error = '{e}'");
             return Err(())
         },
     };
@@ -517,8 +616,8 @@ async fn ws_route_syslog_check_filters<'a>(data_to_socket: &'a mut mpsc::Sender<
     match filters {
         Some(filters) => Ok(filters),
         None => {
-            let e = format!("[ERROR][WS][SYSLOG] Requested size/data before setting filters = '{}'", msg.kind);
-            log::error!("{}", e);
+            let e = format!("Requested size/data before setting filters = '{}'", msg.kind);
+            log::error!(subsystem = "ws.syslog"; "{}", e);
             ws_send_error_msg(data_to_socket, &e).await;
             Err(())
         }
@@ -540,7 +639,7 @@ async fn ws_route_syslog_request_data(
         match data_to_socket.send(msg.to_string()).await {
             Ok(_) => (),
             Err(e) => {
-                log::error!("[ERROR][WS][Syslog] Failed to send syslog message with e = {}, msg='{}'", e, msg)
+                log::error!(subsystem = "ws.syslog"; "Failed to send syslog message with e = {}, msg='{}'", e, msg)
             }
         }
     }
@@ -566,7 +665,7 @@ async fn ws_route_syslog_request_size(
     match data_to_socket.send(msg.to_string()).await {
         Ok(_) => return Ok(()),
         Err(e) => {
-            log::error!("[ERROR][WS][Syslog] Failed to send syslog message with e = {}, msg='{}'", e, msg)
+            log::error!(subsystem = "ws.syslog"; "Failed to send syslog message with e = {}, msg='{}'", e, msg)
         }
     }
 
@@ -586,8 +685,8 @@ async fn ws_route_syslog(
     let msg = match msg {
         Some(m) => m,
         None => {
-            let e = format!("[ERROR][WS][SYSLOG] Could not parse inner syslog message. Message was = '{}'", msg_in);
-            log::error!("{}", e);
+            let e = format!("Could not parse inner syslog message. Message was = '{}'", msg_in);
+            log::error!(subsystem = "ws.syslog"; "{}", e);
             ws_send_error_msg(data_to_socket, &e).await;
             return Err(());
         }
@@ -598,8 +697,8 @@ async fn ws_route_syslog(
         "request-data" => ws_route_syslog_request_data(data_to_socket, pool, syslog_filters, msg).await?,
         "request-size" => ws_route_syslog_request_size(data_to_socket, pool, syslog_filters, msg).await?,
         _ => {
-            let e = format!("[ERROR][WS][SYSLOG] Inner syslog message contains invalid type. Expected ('set-filters', 'request-data', request-size') Actual = '{}'", msg.kind);
-            log::error!("{}", e);
+            let e = format!("Inner syslog message contains invalid type. Expected ('set-filters', 'request-data', request-size') Actual = '{}'", msg.kind);
+            log::error!(subsystem = "ws.syslog"; "{}", e);
             ws_send_error_msg(data_to_socket, &e).await;
             return Err(())
         }
@@ -612,7 +711,7 @@ fn ws_route_alerts_set_filters(filters: &mut Option<AlertFilters>, msg: WsMsg) -
     *filters = match serde_json::from_value(msg.body) {
         Ok(filters) => Some(filters),
         Err(e) => {
-            log::error!("[ERROR][WS][ALERT] Failed to parse alert filters with error = '{e}'");
+            log::error!(subsystem = "ws.alert"; "Failed to parse alert filters with error = '{e}'");
             return Err(())
         },
     };
@@ -623,8 +722,8 @@ async fn ws_route_alerts_check_filters<'a>(data_to_socket: &'a mut mpsc::Sender<
     match filters {
         Some(filters) => Ok(filters),
         None => {
-            let e = format!("[ERROR][WS][ALERTS] Requested size before setting filters = '{}'", msg.kind);
-            log::error!("{}", e);
+            let e = format!("Requested size before setting filters = '{}'", msg.kind);
+            log::error!(subsystem = "ws.alerts"; "{}", e);
             ws_send_error_msg(data_to_socket, &e).await;
             Err(())
         }
@@ -647,7 +746,7 @@ async fn ws_route_alerts_request_data(
         match data_to_socket.send(msg.to_string()).await {
             Ok(_) => (),
             Err(e) => {
-                log::error!("[ERROR][WS][Syslog] Failed to send syslog message with e = {}, msg='{}'", e, msg)
+                log::error!(subsystem = "ws.syslog"; "Failed to send syslog message with e = {}, msg='{}'", e, msg)
             }
         }
     }
@@ -674,7 +773,7 @@ async fn ws_route_alerts_request_size(
     match data_to_socket.send(msg.to_string()).await {
         Ok(_) => return Ok(()),
         Err(e) => {
-            log::error!("[ERROR][WS][Syslog] Failed to send syslog message with e = {}, msg='{}'", e, msg)
+            log::error!(subsystem = "ws.syslog"; "Failed to send syslog message with e = {}, msg='{}'", e, msg)
         }
     }
 
@@ -695,8 +794,8 @@ async fn ws_route_alerts (
     let msg = match msg {
         Some(m) => m,
         None => {
-            let e = format!("[ERROR][WS][ALERTS] Could not parse inner alerts message. Message was = '{}'", msg_in);
-            log::error!("{}", e);
+            let e = format!("Could not parse inner alerts message. Message was = '{}'", msg_in);
+            log::error!(subsystem = "ws.alerts"; "{}", e);
             ws_send_error_msg(data_to_socket, &e).await;
             return Err(());
         }
@@ -707,8 +806,8 @@ async fn ws_route_alerts (
         "request-data" => ws_route_alerts_request_data(data_to_socket, pool, alert_filters, msg).await?,
         "request-size" => ws_route_alerts_request_size(data_to_socket, pool, alert_filters, msg).await?,
         _ => {
-            let e = format!("[ERROR][WS][ALERTS] Inner alerts message contains invalid type. Expected ('set-filters', 'request-data', request-size') Actual = '{}'", msg.kind);
-            log::error!("{}", e);
+            let e = format!("Inner alerts message contains invalid type. Expected ('set-filters', 'request-data', request-size') Actual = '{}'", msg.kind);
+            log::error!(subsystem = "ws.alerts"; "{}", e);
             ws_send_error_msg(data_to_socket, &e).await;
             return Err(())
         }
@@ -7,6 +7,9 @@ pub mod alerts;
 pub mod misc;
 
 pub mod config;
+pub mod logging;
+pub mod supervisor;
+pub mod telemetry;
 pub mod types;
 
 
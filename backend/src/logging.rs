@@ -0,0 +1,274 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::sync::RwLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use chrono::{SecondsFormat, Utc};
+use log::kv::{Key, Value as KvValue, VisitSource};
+use log::{LevelFilter, Log, Metadata, Record};
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::config::Config;
+use crate::config::settings::{LogFormat, LoggingSettings};
+
+// Process wide logger behind the `log` macros.
+// Records carry structured fields as key-values, such as `log::warn!(subsystem = "facts.icmp", device = hostname.as_str(); "...")`.
+// The subsystem groups records across modules, records without one fall back to their target.
+// Levels can be set per module from `backend/logging`, and changed at runtime through the API.
+
+/// Level filters currently in effect
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LogLevels {
+    pub format: LogFormat,
+
+    #[serde(serialize_with = "serialize_level")]
+    pub level: LevelFilter,
+
+    #[serde(serialize_with = "serialize_modules")]
+    pub modules: BTreeMap<String, LevelFilter>,
+}
+
+fn serialize_level<S: serde::Serializer>(level: &LevelFilter, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_str(&level.as_str().to_lowercase())
+}
+
+fn serialize_modules<S: serde::Serializer>(modules: &BTreeMap<String, LevelFilter>, s: S) -> Result<S::Ok, S::Error> {
+    s.collect_map(modules.iter().map(|(k, v)| (k, v.as_str().to_lowercase())))
+}
+
+impl Default for LogLevels {
+    fn default() -> Self {
+        LogLevels { format: LogFormat::Pretty, level: LevelFilter::Info, modules: BTreeMap::new() }
+    }
+}
+
+impl LogLevels {
+    /// Levels are validated when the configuration is loaded, invalid ones fall back to `info`
+    pub fn from_settings(settings: &LoggingSettings) -> Self {
+        let parse = |level: &str| level.parse().unwrap_or(LevelFilter::Info);
+        LogLevels {
+            format: settings.format,
+            level: parse(&settings.level),
+            modules: settings.modules.iter().map(|(module, level)| (module.clone(), parse(level))).collect(),
+        }
+    }
+
+    /// Level for records of `target`, from the longest module prefix that matches it
+    pub fn level_for(&self, target: &str) -> LevelFilter {
+        self.modules.iter()
+            .filter(|(module, _)| target == module.as_str() || target.strip_prefix(module.as_str()).is_some_and(|rest| rest.starts_with("::")))
+            .max_by_key(|(module, _)| module.len())
+            .map(|(_, level)| *level)
+            .unwrap_or(self.level)
+    }
+
+    fn max_level(&self) -> LevelFilter {
+        self.modules.values().copied().chain([self.level]).max().unwrap_or(self.level)
+    }
+}
+
+struct Logger {
+    levels: RwLock<LogLevels>,
+}
+
+static LOGGER: Logger = Logger { levels: RwLock::new(LogLevels { format: LogFormat::Pretty, level: LevelFilter::Info, modules: BTreeMap::new() }) };
+static INSTALLED: AtomicBool = AtomicBool::new(false);
+
+/// Installs the logger with the default levels. Must be called first thing, so that nothing logged while starting up is lost
+pub fn init() {
+    if INSTALLED.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    if log::set_logger(&LOGGER).is_err() {
+        eprintln!("[WARN ][LOGGING] A logger was already installed, structured logging is disabled");
+        return;
+    }
+    log::set_max_level(LevelFilter::Info);
+}
+
+/// Applies the levels from the configuration, and keeps them in sync on every reload that changes them.
+/// Levels set at runtime through `set_levels` are kept until then
+pub fn init_from_config() {
+    let settings = Config::instance().settings().backend.logging.clone();
+    set_levels(LogLevels::from_settings(&settings));
+    log::info!(subsystem = "logging"; "Logging {:?} records at level {}", settings.format, settings.level);
+
+    let mut config_changes = Config::subscribe();
+    rocket::tokio::spawn(async move {
+        let mut current = settings;
+        while config_changes.changed().await.is_ok() {
+            let new = config_changes.borrow_and_update().settings().backend.logging.clone();
+            if new != current {
+                set_levels(LogLevels::from_settings(&new));
+                log::info!(subsystem = "logging"; "Log levels reloaded from configuration");
+                current = new;
+            }
+        }
+    });
+}
+
+pub fn levels() -> LogLevels {
+    LOGGER.levels.read().map(|l| l.clone()).unwrap_or_default()
+}
+
+pub fn set_levels(levels: LogLevels) {
+    log::set_max_level(levels.max_level());
+    if let Ok(mut current) = LOGGER.levels.write() {
+        *current = levels;
+    }
+}
+
+struct Fields(Map<String, Value>);
+
+impl<'kvs> VisitSource<'kvs> for Fields {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: KvValue<'kvs>) -> Result<(), log::kv::Error> {
+        let value = if let Some(b) = value.to_bool() {
+            Value::from(b)
+        } else if let Some(i) = value.to_i64() {
+            Value::from(i)
+        } else if let Some(f) = value.to_f64() {
+            Value::from(f)
+        } else {
+            Value::from(value.to_string())
+        };
+        self.0.insert(key.as_str().to_string(), value);
+        Ok(())
+    }
+}
+
+/// Formats a record as a single line, without the trailing newline
+pub fn format_record(record: &Record, format: LogFormat, time: &str) -> String {
+    let message = record.args().to_string();
+
+    let mut fields = Fields(Map::new());
+    let _ = record.key_values().visit(&mut fields);
+    let subsystem = match fields.0.remove("subsystem") {
+        Some(Value::String(s)) => Some(s),
+        _ => None,
+    };
+
+    match format {
+        LogFormat::Json => {
+            let mut line = Map::new();
+            line.insert("time".to_string(), Value::from(time));
+            line.insert("level".to_string(), Value::from(record.level().as_str()));
+            line.insert("target".to_string(), Value::from(record.target()));
+            if let Some(subsystem) = subsystem {
+                line.insert("subsystem".to_string(), Value::from(subsystem));
+            }
+            line.insert("message".to_string(), Value::from(message));
+            for (key, value) in fields.0 {
+                line.entry(key).or_insert(value);
+            }
+            Value::Object(line).to_string()
+        },
+        LogFormat::Pretty => {
+            let subsystem = subsystem.unwrap_or_else(|| record.target().to_string());
+            let mut line = format!("{time} {:<5} [{subsystem}] {message}", record.level());
+            for (key, value) in fields.0 {
+                match value {
+                    Value::String(s) => line.push_str(&format!(" {key}={s:?}")),
+                    v => line.push_str(&format!(" {key}={v}")),
+                }
+            }
+            line
+        },
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        match self.levels.read() {
+            Ok(levels) => metadata.level() <= levels.level_for(metadata.target()),
+            Err(_) => true,
+        }
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let format = self.levels.read().map(|l| l.format).unwrap_or_default();
+        let time = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
+        let line = format_record(record, format, &time);
+
+        let mut stdout = std::io::stdout().lock();
+        let _ = writeln!(stdout, "{line}");
+    }
+
+    fn flush(&self) {
+        let _ = std::io::stdout().flush();
+    }
+}
+
+/// Parses a level change requested through the API: `{"level": "debug", "modules": {"sqlx": "warn", "rocket": null}}`.
+/// Modules set to null go back to the default level
+pub fn apply_change(current: &LogLevels, change: &Value) -> Result<LogLevels, String> {
+    let parse = |path: &str, v: &Value| -> Result<LevelFilter, String> {
+        v.as_str().and_then(|s| s.parse().ok()).ok_or(format!("{path}: '{v}' is not a log level"))
+    };
+
+    let mut levels = current.clone();
+    if let Some(level) = change.get("level") {
+        levels.level = parse("level", level)?;
+    }
+    if let Some(modules) = change.get("modules") {
+        let modules: HashMap<String, Value> = serde_json::from_value(modules.clone())
+            .map_err(|_| "modules: expected an object of module paths to levels".to_string())?;
+        for (module, level) in modules {
+            match level {
+                Value::Null => { levels.modules.remove(&module); },
+                level => { levels.modules.insert(module.clone(), parse(&format!("modules/{module}"), &level)?); },
+            }
+        }
+    }
+    Ok(levels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn levels_and_format() {
+        let levels = LogLevels {
+            format: LogFormat::Json,
+            level: LevelFilter::Info,
+            modules: BTreeMap::from([
+                ("backend_aegis::syslog".to_string(), LevelFilter::Warn),
+                ("backend_aegis::syslog::syslog_filters".to_string(), LevelFilter::Trace),
+            ]),
+        };
+        assert_eq!(levels.level_for("backend_aegis::syslog::syslog_backend"), LevelFilter::Warn);
+        assert_eq!(levels.level_for("backend_aegis::syslog::syslog_filters"), LevelFilter::Trace);
+        assert_eq!(levels.level_for("backend_aegis::syslogx"), LevelFilter::Info);
+        assert_eq!(levels.max_level(), LevelFilter::Trace);
+
+        let kvs: [(&str, log::kv::Value); 3] = [("subsystem", "alerts".into()), ("device", "router1".into()), ("rule_id", 7.into())];
+        let record = Record::builder()
+            .args(format_args!("Alert raised"))
+            .level(log::Level::Warn)
+            .target("backend_aegis::alerts")
+            .key_values(&kvs)
+            .build();
+
+        let json: Value = serde_json::from_str(&format_record(&record, LogFormat::Json, "t")).expect("Line should be JSON");
+        assert_eq!(json, serde_json::json!({
+            "time": "t", "level": "WARN", "target": "backend_aegis::alerts", "subsystem": "alerts",
+            "message": "Alert raised", "device": "router1", "rule_id": 7
+        }));
+        assert_eq!(format_record(&record, LogFormat::Pretty, "t"), "t WARN  [alerts] Alert raised device=\"router1\" rule_id=7");
+
+        // Without a subsystem, the target stands in for it
+        let record = Record::builder().args(format_args!("[here]")).level(log::Level::Info).target("backend_aegis::cache").build();
+        assert_eq!(format_record(&record, LogFormat::Pretty, "t"), "t INFO  [backend_aegis::cache] [here]");
+
+        let changed = apply_change(&levels, &serde_json::json!({"level": "debug", "modules": {"backend_aegis::syslog": null, "sqlx": "warn"}}))
+            .expect("Change should apply");
+        assert_eq!(changed.level, LevelFilter::Debug);
+        assert_eq!(changed.level_for("backend_aegis::syslog::syslog_backend"), LevelFilter::Debug);
+        assert_eq!(changed.level_for("sqlx::query"), LevelFilter::Warn);
+        assert!(apply_change(&levels, &serde_json::json!({"level": "loud"})).is_err());
+    }
+}
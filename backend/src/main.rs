@@ -4,27 +4,35 @@ use backend_aegis::alerts::alert_backend::AlertBackend;
 use backend_aegis::model::cache::Cache;
 use backend_aegis::model::facts::fact_gathering_backend::FactGatheringBackend;
 use backend_aegis::syslog::syslog_backend::SyslogBackend;
+use rocket::fairing::AdHoc;
 use sqlx::{Pool, Postgres};
 use std::env;
 
 use backend_aegis::controller::server;
 use backend_aegis::config::Config;
+use backend_aegis::logging;
+use backend_aegis::supervisor::Supervisor;
+use backend_aegis::model::db::influx_setup;
 use backend_aegis::model::db::pools::{init_influx_client, init_posgres_pool};
 
 #[launch]
 async fn launch() -> _ {
-    log::set_max_level(log::LevelFilter::Debug);
+    // First, so that nothing logged while starting up is lost
+    logging::init();
 
     // CWD, to know where files will be looked for
     // if the current directory can't be determined for some weird reason, it _must_ panic
     let cwd = env::current_dir().expect("[FATAL]Failed to get current working directory");
-    println!("cwd: {}", cwd.display());
+    log::info!(subsystem = "main"; "cwd: {}", cwd.display());
 
     // init - failfast if something can't init
     // expect: failfast on postgres client. It _must_ be present
     Config::init();
+    logging::init_from_config();
     let postgres_pool : Pool<Postgres> = init_posgres_pool().await.expect("Postgres database could not init");
     let influx_client : influxdb2::Client = init_influx_client().await;
+    influx_setup::reconcile().await;
+    influx_setup::watch_retention();
     Cache::init(&postgres_pool).await;
     AlertBackend::init(&postgres_pool).await;
     FactGatheringBackend::init(&influx_client);
@@ -37,14 +45,27 @@ async fn launch() -> _ {
     rocket::build()
         .manage(postgres_pool)
         .manage(influx_client)
+        // On SIGTERM, drain queued alert events and let the fact gathering cycle in progress write its results
+        .attach(AdHoc::on_shutdown("Stop background tasks", |_| Box::pin(async { Supervisor::instance().shutdown().await })))
         .mount("/", 
             routes![
                 // API HTTP Endpoints
                 server::heartbeat, 
+                server::get_metrics,
                 server::get_topology,
                 server::get_rules,
+                server::backtest_rule,
                 server::api_configure,
                 server::get_reload_config,
+                server::get_log_levels,
+                server::set_log_levels,
+                server::get_link_proposals,
+                server::approve_link_proposal,
+                server::reject_link_proposal,
+                server::export_topology,
+                server::import_topology,
+                server::get_audit_log,
+                server::rollback_revision,
 
                 // Websocket
                 server::ws_router,
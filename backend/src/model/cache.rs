@@ -13,11 +13,12 @@ use crate::model::data::DataSource;
 use crate::model::data::device::Device;
 use crate::model::data::device_state::DeviceStatus;
 use crate::model::data::link::Link;
+use crate::model::data::link_state::{LinkMessage, LinkState};
 use crate::model::data::group::Group;
 use crate::model::db::fetch_topology::Playbook;
 use crate::model::db::update_topology::update_topology_cache;
-use crate::model::facts::fact_gathering_backend::FactMessage;
-use crate::types::{DeviceId, EpochSeconds, EvaluableItemId, ExposedFields, GroupId, ItemId, LinkId, MetricValue, PlaybookId};
+use crate::model::facts::fact_gathering_backend::{DeviceFacts, FactMessage};
+use crate::types::{DeviceId, EpochSeconds, EvaluableItemId, ExposedFields, GroupId, ItemId, LinkId, MetricSet, MetricValue, Metrics, PlaybookId};
 
 async fn serialize_map<T: Serialize>(lock: &RwLock<HashMap<i64, T>>) -> Result<String, serde_json::Error> {
     serde_json::to_string(&*lock.read().await)
@@ -25,6 +26,10 @@ async fn serialize_map<T: Serialize>(lock: &RwLock<HashMap<i64, T>>) -> Result<S
 
 pub struct Cache {
     pub facts: RwLock<FactMessage>,
+    /// Metrics of the facts before the latest ones, so Delta rules can be previewed
+    previous_metrics: RwLock<Metrics>,
+    /// Derived link states, (previous, current). Previous is kept for Delta rules
+    link_states: RwLock<(LinkMessage, LinkMessage)>,
     devices: RwLock<HashMap<DeviceId, Device>>,
     playbooks: RwLock<HashMap<PlaybookId, Playbook>>,
     links: RwLock<HashMap<LinkId, Link>>,
@@ -41,6 +46,8 @@ impl Cache {
             links: RwLock::new(HashMap::new()),
             groups: RwLock::new(HashMap::new()),
             facts: RwLock::new(HashMap::new()),
+            previous_metrics: RwLock::new(HashMap::new()),
+            link_states: RwLock::new((HashMap::new(), HashMap::new())),
             last_update: RwLock::new(0),
         }
     }
@@ -52,7 +59,7 @@ impl Cache {
     }
 
     pub async fn init(pool: &sqlx::PgPool) {
-        println!("[INFO] Attempting to init cache (requires Postgres connection)...");
+        log::info!("Attempting to init cache (requires Postgres connection)...");
         // Force creation of instance
         let _ = Cache::instance();
 
@@ -87,6 +94,11 @@ impl Cache {
         r.get(&id).cloned()
     }
 
+    pub async fn get_devices(&self) -> Vec<Device> {
+        let r = self.devices.read().await;
+        r.values().cloned().collect()
+    }
+
     pub async fn get_device_hostname(&self, id: DeviceId) -> Option<String> {
         let r = self.devices.read().await;
 
@@ -157,6 +169,11 @@ impl Cache {
         r.get(&id).cloned()
     }
 
+    pub async fn get_links(&self) -> Vec<Link> {
+        let r = self.links.read().await;
+        r.values().cloned().collect()
+    }
+
     pub async fn has_link(&self, id: LinkId) -> bool {
         let r = self.links.read().await;
         r.contains_key(&id)
@@ -171,6 +188,34 @@ impl Cache {
         removed
     }
 
+    /// Human readable representation of a link, as `device:iface <-> device:iface`
+    pub async fn get_link_label(&self, id: LinkId) -> Option<String> {
+        let link = self.get_link(id).await?;
+        let devices = self.devices.read().await;
+        let name = |id: DeviceId| devices.get(&id).map(|d| d.device_name.clone()).unwrap_or(id.to_string());
+
+        Some(format!("{}:{} <-> {}:{}", name(link.side_a), link.side_a_iface, name(link.side_b), link.side_b_iface))
+    }
+
+    pub async fn update_link_states(&self, states: LinkMessage) {
+        let mut w = self.link_states.write().await;
+        w.0 = std::mem::replace(&mut w.1, states);
+    }
+
+    pub async fn get_link_states(&self) -> LinkMessage {
+        self.link_states.read().await.1.clone()
+    }
+
+    pub async fn get_link_state(&self, id: LinkId) -> Option<LinkState> {
+        self.link_states.read().await.1.get(&id).cloned()
+    }
+
+    /// Metrics of a link for rule evaluation, as (previous, current)
+    pub async fn get_link_metrics(&self, id: LinkId) -> (Option<MetricSet>, Option<MetricSet>) {
+        let r = self.link_states.read().await;
+        (r.0.get(&id).map(|s| s.to_metrics()), r.1.get(&id).map(|s| s.to_metrics()))
+    }
+
     pub async fn links_json(&self) -> Result<String, serde_json::Error> {
         serialize_map(&self.links).await
     }
@@ -187,13 +232,18 @@ impl Cache {
         r.get(&id).cloned()
     }
 
+    pub async fn get_groups(&self) -> Vec<Group> {
+        let r = self.groups.read().await;
+        r.values().cloned().collect()
+    }
+
     pub async fn get_group_members(&self, id: GroupId) -> Option<Vec<ItemId>> {
         let r = self.groups.read().await;
         r.get(&id)?.members.clone()
     }
 
-    // TODO: Enforce no loops are held for any given group. Db already enforces this, but the backend should enforce this also, just in case
-    /// Recursively iterates into inner groups to get the device ids that are held within this group and this group's subgroups
+    /// Recursively iterates into inner groups to get the device ids that are held within this group and this group's subgroups.
+    /// Commits reject membership cycles, but every group is visited at most once anyway, so a cycle can't loop forever
     pub async fn get_group_device_ids(&self, id: GroupId) -> Option<HashSet<DeviceId>> {
         // quick existence check for the start group (match original behavior)
         {
@@ -268,13 +318,15 @@ impl Cache {
     pub async fn get_evaluable_item(&self, id: EvaluableItemId) -> Option<EvaluableItem> {
         let r = self.devices.read().await;
 
-        match r.get(&id) {
-            Some(d) => Some(EvaluableItem::Device(d.clone())),
-            None=> {
-                let r = self.groups.read().await;
-                r.get(&id).map(|g| EvaluableItem::Group(g.clone()))
-            }
+        if let Some(d) = r.get(&id) {
+            return Some(EvaluableItem::Device(d.clone()));
+        }
+
+        if let Some(g) = self.groups.read().await.get(&id) {
+            return Some(EvaluableItem::Group(g.clone()));
         }
+
+        self.links.read().await.get(&id).map(|l| EvaluableItem::Link(l.clone()))
     }
 
     // Cache API
@@ -293,34 +345,83 @@ impl Cache {
 
     // Update API
     pub async fn update_all(&self, devices: HashMap<DeviceId, Device>, links: HashMap<LinkId, Link>, groups: HashMap<GroupId, Group>, playbooks: HashMap<PlaybookId, Playbook>) {
+        {
+            // Update devices
+            let mut w = self.devices.write().await;
+            w.clear();
+            w.extend(devices);
+
+            // Update playbooks
+            let mut w = self.playbooks.write().await;
+            w.clear();
+            w.extend(playbooks);
+            w.insert(-1, Playbook { playbook_id: -1, playbook_name: "default".to_string(), is_enabled: true });
+
+            // Update links
+            let mut w = self.links.write().await;
+            w.clear();
+            w.extend(links);
+
+
+            // Update groups
+            let mut w = self.groups.write().await;
+            w.clear();
+            w.extend(groups);
+        }
 
-        // Update devices
-        let mut w = self.devices.write().await;
-        w.clear();
-        w.extend(devices);
+        self.resolve_dynamic_groups().await;
+    }
 
-        // Update playbooks
-        let mut w = self.playbooks.write().await;
-        w.clear();
-        w.extend(playbooks);
-        w.insert(-1, Playbook { playbook_id: -1, playbook_name: "default".to_string(), is_enabled: true });
+    /// Restores the metrics of a previous run, as the dataset Delta rules compare the first new facts against.
+    /// Devices no longer in the topology are skipped. Their status is unknown until the next fact gathering cycle
+    pub async fn restore_facts(&self, metrics: Metrics) {
+        let mut restored = FactMessage::new();
+        for (hostname, metrics) in metrics {
+            if self.get_device_id(&hostname).await.is_none() {
+                continue;
+            }
+            restored.insert(hostname, DeviceFacts { metrics, status: DeviceStatus::empty(), exposed_fields: ExposedFields::new() });
+        }
+        self.update_facts(restored).await;
+    }
 
-        // Update links
-        let mut w = self.links.write().await;
-        w.clear();
-        w.extend(links);
+    /// Metrics of the device in the facts before the latest ones
+    pub async fn get_previous_metrics(&self, management_hostname: &str) -> Option<MetricSet> {
+        self.previous_metrics.read().await.get(management_hostname).cloned()
+    }
 
+    pub async fn update_facts(&self, facts: FactMessage) {
+        let previous = {
+            let mut w = self.facts.write().await;
+            std::mem::replace(&mut *w, facts)
+        };
+        *self.previous_metrics.write().await = previous.into_iter().map(|(hostname, f)| (hostname, f.metrics)).collect();
 
-        // Update groups
-        let mut w = self.groups.write().await;
-        w.clear();
-        w.extend(groups);
+        // Filters may depend on facts
+        self.resolve_dynamic_groups().await;
     }
 
-    pub async fn update_facts(&self, facts: FactMessage) {
-        let mut w = self.facts.write().await;
-        w.clear();
-        w.extend(facts);
+    /// Recomputes the members of every dynamic group from its filter, against the current devices and facts
+    async fn resolve_dynamic_groups(&self) {
+        let devices = self.devices.read().await;
+        let facts = self.facts.read().await;
+        let facts: HashMap<String, &MetricSet> = facts.iter().map(|(host, f)| (host.clone(), &f.metrics)).collect();
+
+        let mut groups = self.groups.write().await;
+        for group in groups.values_mut() {
+            let filter = match &group.filter { Some(f) => f, None => continue };
+
+            match filter.matching_devices(devices.values(), &facts) {
+                Ok(mut members) => {
+                    members.sort();
+                    group.members = Some(members);
+                },
+                Err(e) => {
+                    log::error!(subsystem = "cache"; "Could not evaluate filter of group with id = {}, error = '{e}'", group.group_id);
+                    group.members = Some(Vec::new());
+                },
+            }
+        }
     }
 
 
@@ -347,7 +448,7 @@ impl Cache {
     ///
     /// Returns None if an update is not needed (someone else updated recently).
     pub async fn try_claim_update(&self, forced: bool) -> Option<RwLockWriteGuard<'_, EpochSeconds>> {
-        let interval_secs: EpochSeconds = Config::instance().get_value_opt ("backend/controller/cache/cache_invalidation_s", "/").unwrap_or_default().as_u64().unwrap_or(60);
+        let interval_secs: EpochSeconds = Config::instance().settings().backend.controller.cache.cache_invalidation_s;
 
         // fast read-only check
         let now: EpochSeconds = SystemTime::now()
@@ -416,10 +517,16 @@ impl Cache {
             .collect::<Vec<String>>()
     }
 
+    pub async fn prometheus_inventory(&self) -> Vec<String> {
+        self.devices.read().await
+            .values()
+            .filter(|d| d.configuration.data_sources.contains(&DataSource::Prometheus))
+            .map(|d| d.management_hostname.as_str().to_string())
+            .collect::<Vec<String>>()
+    }
+
     pub async fn ansible_inventory(&self, playbook: &Playbook) -> Vec<String> {
-        let config = Config::instance();
-        let user: String = config.get("backend/model/ssh_user", "/")
-            .expect("[FATAL] Config file should specify an ssh user for remote acess under backend/model/ssh_user, and be of type String");
+        let user = Config::instance().settings().backend.model.ssh_user.clone();
 
         self.devices.read().await
             .values()
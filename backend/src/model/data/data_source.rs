@@ -14,6 +14,7 @@ impl From<Option<&str>> for DataSource {
             Some("ssh") => DataSource::Ssh,
             Some("snmp") => DataSource::Snmp,
             Some("icmp") => DataSource::Ssh,
+            Some("prometheus") => DataSource::Prometheus,
             _ => DataSource::Ssh
         }
     }
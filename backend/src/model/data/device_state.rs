@@ -69,6 +69,13 @@ impl DeviceStatus {
         };
     }
 
+    /// Whether any source tried to reach the device and failed. Unknown and skipped don't count as failures
+    pub fn is_failure(&self) -> bool {
+        matches!(self.ansible_status, AnsibleStatus::Dark(_))
+            || matches!(self.icmp_status,
+                IcmpStatus::Unreachable(_) | IcmpStatus::Timeout(_) | IcmpStatus::HostNotFound(_) | IcmpStatus::NameResolutionError(_))
+    }
+
     /// Safe conversion from JSON-like dict
     pub fn make_from_dict(status: &HashMap<String, Value>) -> Result<Self, String> {
         let ansible = status.get("ansible_status").and_then(|v| v.as_object()).cloned().unwrap_or_default();
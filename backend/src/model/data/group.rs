@@ -1,8 +1,9 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
+use regex::Regex;
 use serde::{Deserialize, Serialize};
 
-use crate::{model::data::device::Device, types::{GroupId, ItemId}};
+use crate::{model::data::{DataSource, device::Device}, types::{DeviceId, GroupId, ItemId, MetricSet, MetricValue, PlaybookId}};
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Group {
@@ -16,6 +17,139 @@ pub struct Group {
     #[serde(rename = "is-display-group")]
 
     pub is_display_group: bool,
+
+    /// If present, the group is dynamic: its members are the devices that match the filter,
+    /// and are recomputed every time the Cache is refreshed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filter: Option<GroupFilter>,
+}
+
+/// Geographic area, in degrees. Bounds are inclusive
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BoundingBox {
+    #[serde(rename = "min-latitude")]
+    pub min_latitude: f64,
+
+    #[serde(rename = "max-latitude")]
+    pub max_latitude: f64,
+
+    #[serde(rename = "min-longitude")]
+    pub min_longitude: f64,
+
+    #[serde(rename = "max-longitude")]
+    pub max_longitude: f64,
+}
+
+/// Saved filter over device attributes that defines the membership of a dynamic group.
+/// A device matches if it matches every condition present. An empty filter matches every device
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct GroupFilter {
+    #[serde(rename = "name-regex", default, skip_serializing_if = "Option::is_none")]
+    pub name_regex: Option<String>,
+
+    #[serde(rename = "hostname-regex", default, skip_serializing_if = "Option::is_none")]
+    pub hostname_regex: Option<String>,
+
+    /// The device must have all of these data sources enabled
+    #[serde(rename = "data-sources", default, skip_serializing_if = "Option::is_none")]
+    pub data_sources: Option<Vec<DataSource>>,
+
+    /// The device must run any of these playbooks
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub playbooks: Option<Vec<PlaybookId>>,
+
+    /// Gathered facts the device must have, with the given value. e.g. `{"ansible_distribution": "Debian"}`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub facts: Option<HashMap<String, MetricValue>>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bbox: Option<BoundingBox>,
+}
+
+impl GroupFilter {
+    fn compile(pattern: &Option<String>) -> Result<Option<Regex>, String> {
+        pattern.as_deref()
+            .map(|p| Regex::new(p).map_err(|e| format!("Invalid regex '{p}', error = '{e}'")))
+            .transpose()
+    }
+
+    /// Checks that the filter can be evaluated, i.e. its regexes compile
+    pub fn validate(&self) -> Result<(), String> {
+        Self::compile(&self.name_regex)?;
+        Self::compile(&self.hostname_regex)?;
+        Ok(())
+    }
+
+    /// Returns the ids of the devices that match the filter. `facts` are the last gathered facts of each device, by hostname
+    pub fn matching_devices<'a>(
+        &self,
+        devices: impl Iterator<Item = &'a Device>,
+        facts: &HashMap<String, &MetricSet>
+    ) -> Result<Vec<DeviceId>, String> {
+        let name_regex = Self::compile(&self.name_regex)?;
+        let hostname_regex = Self::compile(&self.hostname_regex)?;
+
+        let matching = devices.filter(|d| {
+            name_regex.as_ref().is_none_or(|r| r.is_match(&d.device_name))
+            && hostname_regex.as_ref().is_none_or(|r| r.is_match(&d.management_hostname))
+            && self.data_sources.as_ref().is_none_or(|ds| ds.iter().all(|s| d.configuration.data_sources.contains(s)))
+            && self.playbooks.as_ref().is_none_or(|pb| pb.iter().any(|p| d.playbooks.contains(p)))
+            && self.bbox.as_ref().is_none_or(|b|
+                (b.min_latitude..=b.max_latitude).contains(&d.latitude)
+                && (b.min_longitude..=b.max_longitude).contains(&d.longitude))
+            && self.facts.as_ref().is_none_or(|expected| {
+                let gathered = match facts.get(&d.management_hostname) { Some(f) => f, None => return false };
+                expected.iter().all(|(name, value)| gathered.get(name).is_some_and(|v| same_value(v, value)))
+            })
+        });
+
+        Ok(matching.map(|d| d.device_id).collect())
+    }
+}
+
+/// Equality that doesn't care whether a number was parsed as integer or float
+fn same_value(a: &MetricValue, b: &MetricValue) -> bool {
+    match (a, b) {
+        (MetricValue::Integer(i), MetricValue::Number(n)) | (MetricValue::Number(n), MetricValue::Integer(i)) => *i as f64 == n.into_inner(),
+        _ => a == b,
+    }
+}
+
+/// Looks for a membership cycle among groups, given the subgroups held by each group.
+/// Returns the groups that form the first cycle found, starting and ending on the same group
+pub fn find_group_cycle(subgroups: &HashMap<GroupId, Vec<GroupId>>) -> Option<Vec<GroupId>> {
+    fn visit(
+        group: GroupId,
+        subgroups: &HashMap<GroupId, Vec<GroupId>>,
+        done: &mut HashSet<GroupId>,
+        path: &mut Vec<GroupId>
+    ) -> Option<Vec<GroupId>> {
+        if let Some(start) = path.iter().position(|g| *g == group) {
+            let mut cycle = path[start..].to_vec();
+            cycle.push(group);
+            return Some(cycle);
+        }
+        if done.contains(&group) {
+            return None;
+        }
+
+        path.push(group);
+        for sub in subgroups.get(&group).into_iter().flatten() {
+            if let Some(cycle) = visit(*sub, subgroups, done, path) {
+                return Some(cycle);
+            }
+        }
+        path.pop();
+        done.insert(group);
+
+        None
+    }
+
+    let mut done = HashSet::new();
+    let mut roots: Vec<&GroupId> = subgroups.keys().collect();
+    roots.sort(); // Deterministic reporting
+
+    roots.into_iter().find_map(|g| visit(*g, subgroups, &mut done, &mut Vec::new()))
 }
 
 /// The eval result: (any_matched, matched_devices)
@@ -28,6 +162,7 @@ impl Group {
             "name": self.name,
             "is-display-group": self.is_display_group,
             "members": self.members,
+            "filter": self.filter,
         })
     }
 
@@ -82,3 +217,49 @@ impl Group {
         (any, matched)
     } */
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nested_group_cycles() {
+        let mut subgroups: HashMap<GroupId, Vec<GroupId>> = HashMap::new();
+        subgroups.insert(1, vec![2, 3]);
+        subgroups.insert(2, vec![3]);
+        subgroups.insert(3, vec![]);
+        assert_eq!(find_group_cycle(&subgroups), None);
+
+        subgroups.insert(3, vec![4]);
+        subgroups.insert(4, vec![2]);
+        assert_eq!(find_group_cycle(&subgroups), Some(vec![2, 3, 4, 2]));
+    }
+
+    #[test]
+    fn dynamic_group_filter() {
+        let mut core = Device::new(1, "core-sw1".to_string(), 40.4, -3.7, "10.0.0.1".to_string(), Default::default());
+        core.configuration.data_sources.insert(DataSource::Ssh);
+        let edge = Device::new(2, "edge-rt1".to_string(), 48.8, 2.3, "10.0.1.1".to_string(), Default::default());
+        let devices = [core, edge];
+
+        let mut core_facts = MetricSet::new();
+        core_facts.insert("ansible_distribution".to_string(), MetricValue::String("Debian".to_string()));
+        let facts = HashMap::from([("10.0.0.1".to_string(), &core_facts)]);
+
+        let filter: GroupFilter = serde_json::from_value(serde_json::json!({"name-regex": "^core-"})).unwrap();
+        assert_eq!(filter.matching_devices(devices.iter(), &facts), Ok(vec![1]));
+
+        let filter: GroupFilter = serde_json::from_value(serde_json::json!({
+            "bbox": {"min-latitude": 40.0, "max-latitude": 50.0, "min-longitude": -5.0, "max-longitude": 5.0},
+            "data-sources": ["ssh"],
+            "facts": {"ansible_distribution": "Debian"}
+        })).unwrap();
+        assert_eq!(filter.matching_devices(devices.iter(), &facts), Ok(vec![1]));
+
+        let filter = GroupFilter { hostname_regex: Some("^10\\.0\\.".to_string()), ..Default::default() };
+        assert_eq!(filter.matching_devices(devices.iter(), &facts).map(|v| v.len()), Ok(2));
+
+        let filter = GroupFilter { name_regex: Some("(".to_string()), ..Default::default() };
+        assert!(filter.validate().is_err());
+    }
+}
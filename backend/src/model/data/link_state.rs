@@ -0,0 +1,116 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::types::{LinkId, MetricSet, MetricValue};
+
+/// All the derived link states, in the order of link -> LinkState
+pub type LinkMessage = HashMap<LinkId, LinkState>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkStatus {
+    Up,
+    Down,
+    #[default]
+    Unknown,
+}
+
+impl LinkStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LinkStatus::Up => "up",
+            LinkStatus::Down => "down",
+            LinkStatus::Unknown => "unknown",
+        }
+    }
+}
+
+/// State of one of the ends of a link, derived from the interface metrics of its device.
+/// Any value that couldn't be derived is None
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+pub struct InterfaceState {
+    pub up: Option<bool>,
+
+    #[serde(rename = "in-bps")]
+    pub in_bps: Option<f64>,
+
+    #[serde(rename = "out-bps")]
+    pub out_bps: Option<f64>,
+
+    #[serde(rename = "in-errors")]
+    pub in_errors: Option<i64>,
+
+    #[serde(rename = "out-errors")]
+    pub out_errors: Option<i64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+pub struct LinkState {
+    pub status: LinkStatus,
+
+    #[serde(rename = "side-a")]
+    pub side_a: InterfaceState,
+
+    #[serde(rename = "side-b")]
+    pub side_b: InterfaceState,
+}
+
+impl LinkState {
+    pub fn new(side_a: InterfaceState, side_b: InterfaceState) -> Self {
+        // A single side down is enough to consider the link down
+        let status = match (side_a.up, side_b.up) {
+            (Some(false), _) | (_, Some(false)) => LinkStatus::Down,
+            (Some(true), _) | (_, Some(true)) => LinkStatus::Up,
+            (None, None) => LinkStatus::Unknown,
+        };
+
+        Self { status, side_a, side_b }
+    }
+
+    /// Traffic going from side A to side B. Prefers what A sent, falls back to what B received
+    pub fn a_to_b_bps(&self) -> Option<f64> {
+        self.side_a.out_bps.or(self.side_b.in_bps)
+    }
+
+    /// Traffic going from side B to side A. Prefers what B sent, falls back to what A received
+    pub fn b_to_a_bps(&self) -> Option<f64> {
+        self.side_b.out_bps.or(self.side_a.in_bps)
+    }
+
+    /// Metric set used to evaluate alert rules that target the link. Values that are unknown are left out
+    pub fn to_metrics(&self) -> MetricSet {
+        let mut metrics = MetricSet::new();
+        metrics.insert("link_status".to_string(), MetricValue::String(self.status.as_str().to_string()));
+        if self.status != LinkStatus::Unknown {
+            metrics.insert("link_up".to_string(), MetricValue::Boolean(self.status == LinkStatus::Up));
+        }
+
+        let optional = [
+            ("a_to_b_bps", self.a_to_b_bps().map(|v| MetricValue::Number(v.into()))),
+            ("b_to_a_bps", self.b_to_a_bps().map(|v| MetricValue::Number(v.into()))),
+        ];
+        for (name, value) in optional {
+            if let Some(value) = value {
+                metrics.insert(name.to_string(), value);
+            }
+        }
+
+        for (prefix, side) in [("side_a", &self.side_a), ("side_b", &self.side_b)] {
+            let values = [
+                ("up", side.up.map(MetricValue::Boolean)),
+                ("in_bps", side.in_bps.map(|v| MetricValue::Number(v.into()))),
+                ("out_bps", side.out_bps.map(|v| MetricValue::Number(v.into()))),
+                ("in_errors", side.in_errors.map(MetricValue::Integer)),
+                ("out_errors", side.out_errors.map(MetricValue::Integer)),
+            ];
+            for (name, value) in values {
+                if let Some(value) = value {
+                    metrics.insert(format!("{prefix}_{name}"), value);
+                }
+            }
+        }
+
+        metrics
+    }
+}
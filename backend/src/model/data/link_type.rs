@@ -1,7 +1,7 @@
 use sqlx::Type;
 use serde::{Serialize, Deserialize};
 
-#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
 #[sqlx(type_name = "link_type", rename_all = "lowercase")]
 #[serde(rename_all="lowercase")]
 pub enum LinkType {
@@ -9,6 +9,7 @@ pub mod device_configuration;
 pub mod device_state;
 pub mod item_type;
 pub mod link_type;
+pub mod link_state;
 pub mod data_source;
 pub mod dashboard;
 
@@ -24,4 +25,7 @@ pub enum DataSource {
 
     #[serde(rename="icmp")]
     Icmp,
+
+    #[serde(rename="prometheus")]
+    Prometheus,
 }
\ No newline at end of file
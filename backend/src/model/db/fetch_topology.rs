@@ -1,8 +1,7 @@
 use std::collections::{HashMap, HashSet};
 
 use rocket::futures::TryFutureExt;
-use sqlx::Postgres;
-use sqlx::pool::PoolConnection;
+use sqlx::{PgConnection, Postgres};
 
 use crate::AegisError;
 use crate::misc::parse_json_array;
@@ -10,7 +9,7 @@ use crate::model::cache::Cache;
 use crate::model::data::device::Device;
 use crate::model::data::device_state::DeviceStatus;
 use crate::model::data::link::Link;
-use crate::model::data::group::Group;
+use crate::model::data::group::{Group, GroupFilter};
 use crate::model::data::device_configuration::DeviceConfiguration;
 use crate::model::data::DataSource;
 use crate::model::db::update_topology::update_topology_cache;
@@ -86,13 +85,13 @@ pub async fn get_topology_view_as_json(pool: &sqlx::Pool<Postgres>) -> Result<se
     Ok(serde_json::Value::Array(rows))
 }
 
-pub async fn query_playbooks(conn: &mut PoolConnection<Postgres>) -> Result<HashMap<PlaybookId, Playbook>, AegisError> {
+pub async fn query_playbooks(conn: &mut PgConnection) -> Result<HashMap<PlaybookId, Playbook>, AegisError> {
     let playbooks = sqlx::query_as!(Playbook, 
         "SELECT playbook_id, playbook_name, is_enabled FROM Analytics.playbooks WHERE TRUE;"
-    ).fetch_all(&mut **conn)
+    ).fetch_all(&mut *conn)
     .await
     .map_err(|e| {
-        println!("[ERROR][DB]Failed to SELECT device_playbooks from database with error = '{}'", &e.to_string());
+        log::error!(subsystem = "db"; "Failed to SELECT device_playbooks from database with error = '{}'", &e.to_string());
         AegisError::Sql(e)
     })?;
 
@@ -105,7 +104,7 @@ pub async fn query_playbooks(conn: &mut PoolConnection<Postgres>) -> Result<Hash
     Ok(_playbooks)
 }
 
-pub async fn query_devices(conn: &mut PoolConnection<Postgres>) -> Result<HashMap<DeviceId, Device>, AegisError>{
+pub async fn query_devices(conn: &mut PgConnection) -> Result<HashMap<DeviceId, Device>, AegisError>{
     // Query datasources
     let mut datasources : HashMap<DeviceId, HashSet<DataSource>> = HashMap::new();
     let rows = sqlx::query_as!(
@@ -116,7 +115,7 @@ pub async fn query_devices(conn: &mut PoolConnection<Postgres>) -> Result<HashMa
             FROM Analytics.device_data_sources
             "#
     )
-        .fetch_all(&mut **conn)
+        .fetch_all(&mut *conn)
         .await
         .map_err(AegisError::Sql)?;
 
@@ -138,10 +137,10 @@ pub async fn query_devices(conn: &mut PoolConnection<Postgres>) -> Result<HashMa
         GROUP BY device_id;
         "
     )
-    .fetch_all(&mut **conn)
+    .fetch_all(&mut *conn)
     .await
     .map_err(|e| {
-        println!("[ERROR][DB]Failed to SELECT device_playbooks from database with error = '{}'", &e.to_string());
+        log::error!(subsystem = "db"; "Failed to SELECT device_playbooks from database with error = '{}'", &e.to_string());
         AegisError::Sql(e)
     })?;
     let mut playbooks: HashMap<DeviceId, Vec<PlaybookId>> = HashMap::new();
@@ -156,10 +155,10 @@ pub async fn query_devices(conn: &mut PoolConnection<Postgres>) -> Result<HashMa
         "SELECT Analytics.devices.device_id, device_name, latitude, longitude, management_hostname, requested_metadata, requested_metrics, available_values 
         FROM Analytics.devices;"
     )
-    .fetch_all(&mut **conn)
+    .fetch_all(&mut *conn)
     .await
     .map_err(|e| {
-        println!("[ERROR][DB]Failed to SELECT devices from database with error = '{}'", &e.to_string());
+        log::error!(subsystem = "db"; "Failed to SELECT devices from database with error = '{}'", &e.to_string());
         AegisError::Sql(e)
     })?;
 
@@ -197,12 +196,12 @@ pub async fn query_devices(conn: &mut PoolConnection<Postgres>) -> Result<HashMa
 }
 
 
-pub async fn query_links(conn: &mut PoolConnection<Postgres>) -> Result<HashMap<LinkId, Link>, AegisError> {
+pub async fn query_links(conn: &mut PgConnection) -> Result<HashMap<LinkId, Link>, AegisError> {
     let rows = sqlx::query_as!(
         Link,
         r#"SELECT link_id, side_a, side_b, side_a_iface, side_b_iface, link_type::TEXT as "link_type: LinkType", link_subtype FROM Analytics.links;"#
     )
-    .fetch_all(&mut **conn)
+    .fetch_all(&mut *conn)
     .await
     .map_err(AegisError::Sql)?;
 
@@ -214,24 +213,40 @@ pub async fn query_links(conn: &mut PoolConnection<Postgres>) -> Result<HashMap<
     Ok(links)
 }
 
-pub async fn query_groups(conn: &mut PoolConnection<Postgres>) -> Result<HashMap<GroupId, Group>, AegisError>{
-    let rows = sqlx::query_as!(
-        Group,
+pub async fn query_groups(conn: &mut PgConnection) -> Result<HashMap<GroupId, Group>, AegisError>{
+    // Dynamic groups have no stored members, hence the LEFT JOIN
+    let rows = sqlx::query!(
         r#"
-            SELECT g.group_id, g.group_name as name, g.is_display_group, array_agg(gm.item_id) as members
+            SELECT g.group_id, g.group_name, g.is_display_group,
+                   array_agg(gm.item_id) FILTER (WHERE gm.item_id IS NOT NULL) as members,
+                   g.filter
             FROM Analytics.groups as g
-            JOIN Analytics.group_members gm on gm.group_id = g.group_id
+            LEFT JOIN Analytics.group_members gm on gm.group_id = g.group_id
             GROUP BY g.group_id, g.group_name;
         "#
     )
-    .fetch_all(&mut **conn)
+    .fetch_all(&mut *conn)
     .map_err(AegisError::Sql)
     .await?;
 
     let mut groups = HashMap::new();
 
-    for group in rows {
-        groups.insert(group.group_id, group);
+    for row in rows {
+        let group_id = row.group_id;
+        let filter = match row.filter.map(serde_json::from_value::<GroupFilter>).transpose() {
+            Ok(f) => f,
+            Err(e) => {
+                log::error!(subsystem = "cache.db"; "Group with id = {group_id} has an invalid filter, skipping it. Error = '{e}'");
+                continue;
+            }
+        };
+
+        // Static groups without members are skipped, as they've always been
+        if filter.is_none() && row.members.is_none() {
+            continue;
+        }
+
+        groups.insert(group_id, Group { group_id, name: row.group_name, members: row.members, is_display_group: row.is_display_group, filter });
     }
 
     Ok(groups)
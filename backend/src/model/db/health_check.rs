@@ -1,6 +1,8 @@
 use sqlx::Postgres;
 
 use crate::config::Config;
+use crate::model::db::influx_setup;
+use crate::supervisor::Supervisor;
 
 
 pub async fn check_connections(pool: &sqlx::Pool<Postgres>, influx_client: &influxdb2::Client) -> serde_json::Value {
@@ -27,19 +29,32 @@ pub async fn check_connections(pool: &sqlx::Pool<Postgres>, influx_client: &infl
         }
     };
 
+    let influx_schema_status =
+    match influx_setup::check_drift(influx_client).await {
+        Ok(drift) => {
+            serde_json::json!({"in-sync": drift.is_empty(), "drift": drift})
+        },
+        Err(e) => {
+            serde_json::json!({"in-sync": false, "drift": [], "msg": e})
+        }
+    };
+
     let backend_status = {
         serde_json::json!({
-            "read-only": !Config::instance().get("backend/controller/configure/enabled", "/").unwrap_or(true)
+            "read-only": !Config::instance().settings().backend.controller.configure.enabled,
+            "config": Config::instance().redacted(),
         })
     };
 
     let telegram_status = {
         serde_json::json!({
-            "enabled": Config::instance().get("backend/controller/telegram/enabled", "/").unwrap_or(true)
+            "enabled": Config::instance().settings().backend.controller.telegram.enabled
         })
     };
     
+    let tasks_status = serde_json::json!(Supervisor::instance().statuses());
+
     serde_json::json!({
-        "status": { "postgres": postgres_status, "influx": influx_status , "backend": backend_status, "telegram": telegram_status}
+        "status": { "postgres": postgres_status, "influx": influx_status, "influx-schema": influx_schema_status, "backend": backend_status, "telegram": telegram_status, "tasks": tasks_status}
     })
 }
\ No newline at end of file
@@ -0,0 +1,278 @@
+use influxdb2::api::buckets::ListBucketsRequest;
+use influxdb2::api::organization::ListOrganizationRequest;
+use influxdb2::api::task::{CreateTaskRequest, ListTasksRequest};
+use influxdb2::models::{PostBucketRequest, RetentionRule};
+use influxdb2::models::retention_rule::Type as RetentionType;
+
+use crate::config::Config;
+
+/// Buckets the backend writes and reads from. Metrics land in `analytics`, and the baseline tasks aggregate them into `baselines`
+pub const REQUIRED_BUCKETS: [&str; 2] = ["analytics", "baselines"];
+
+/// Windows for which a `task_baseline_<window>` aggregate task must exist
+pub const BASELINE_WINDOWS: [&str; 8] = ["1m", "15m", "1h", "1d", "15d", "30d", "180d", "365d"];
+
+/// How often each baseline task runs
+const BASELINE_TASK_EVERY: &str = "15m";
+
+/// Flux definition for the baseline task of a given window.
+/// Aggregates the numeric metrics in `analytics` into `baselines`, tagged with the window
+pub fn baseline_task_flux(window: &str) -> String {
+    format!(r#"option task = {{name: "task_baseline_{window}", every: {BASELINE_TASK_EVERY}}}
+
+import "types"
+
+numeric =
+    from(bucket: "analytics")
+        |> range(start: -{window})
+        |> filter(fn: (r) => r._measurement == "metrics")
+        |> filter(fn: (r) => types.isNumeric(v: r._value))
+
+numeric
+    |> aggregateWindow(every: {window}, fn: mean, createEmpty: false)
+    |> map(fn: (r) => ({{
+        r with
+        window: "{window}",
+        device_id: r.device_id
+    }}))
+    |> to(
+        bucket: "baselines",
+        tagColumns: ["window", "device_id"]
+    )
+"#)
+}
+
+/// Retention configured for a bucket at `backend/controller/influx/retention_s/<bucket>`. 0 means infinite retention
+fn configured_retention_s(bucket: &str) -> i32 {
+    Config::instance().settings().backend.controller.influx.retention_s
+        .get(bucket)
+        .copied()
+        .unwrap_or(0)
+}
+
+/// Calls an endpoint of the Influx API the client doesn't cover, with the operator token
+async fn operator_request(method: reqwest::Method, path: &str, body: Option<serde_json::Value>) -> Result<(), String> {
+    let influx = Config::instance().settings().backend.controller.influx.clone();
+    let mut request = reqwest::Client::new()
+        .request(method, format!("{}{path}", influx.url()))
+        .header("Authorization", format!("Token {}", influx.operator_token));
+    if let Some(body) = body {
+        request = request.json(&body);
+    }
+
+    request.send().await
+        .and_then(|r| r.error_for_status())
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+/// Sets the retention of the required buckets to the configured one, in place. Their data is kept,
+/// besides what a shorter retention expires
+async fn reconcile_retention(client: &influxdb2::Client, org_id: &str) {
+    let existing = match client.list_buckets(Some(ListBucketsRequest { limit: Some(100), org_id: Some(org_id.to_string()), ..Default::default() })).await {
+        Ok(b) => b.buckets,
+        Err(e) => {
+            log::error!(subsystem = "influx.setup"; "Failed to list buckets, e = '{e}'");
+            return;
+        }
+    };
+
+    for bucket in existing.iter().filter(|b| REQUIRED_BUCKETS.contains(&b.name.as_str())) {
+        let expected = configured_retention_s(&bucket.name);
+        let actual = bucket.retention_rules.first().map(|r| r.every_seconds).unwrap_or(0);
+        let id = match &bucket.id {
+            Some(id) if expected != actual => id,
+            _ => continue,
+        };
+
+        let body = serde_json::json!({"retentionRules": [{"type": "expire", "everySeconds": expected}]});
+        match operator_request(reqwest::Method::PATCH, &format!("/api/v2/buckets/{id}"), Some(body)).await {
+            Ok(()) => log::info!(subsystem = "influx.setup"; "Changed retention of bucket '{}' from {actual}s to {expected}s", bucket.name),
+            Err(e) => log::error!(subsystem = "influx.setup"; "Failed to change retention of bucket '{}', e = '{e}'", bucket.name),
+        }
+    }
+}
+
+/// Creates a client with the operator token, which is allowed to manage buckets and tasks.
+/// Returns None if the token isn't configured, in which case the structures must already exist
+fn operator_client() -> Option<influxdb2::Client> {
+    let influx = Config::instance().settings().backend.controller.influx.clone();
+    if influx.operator_token.is_empty() {
+        return None;
+    }
+
+    Some(influxdb2::Client::new(influx.url(), influx.org, influx.operator_token))
+}
+
+/// Compares the buckets and baseline tasks in Influx with the expected definitions.
+/// Returns a human readable list of every difference found. An empty list means no drift
+pub async fn check_drift(influx_client: &influxdb2::Client) -> Result<Vec<String>, String> {
+    let mut drift = Vec::new();
+
+    let buckets = influx_client.list_buckets(Some(ListBucketsRequest { limit: Some(100), ..Default::default() })).await
+        .map_err(|e| format!("Could not list buckets, e = '{e}'"))?;
+
+    for required in REQUIRED_BUCKETS {
+        let bucket = match buckets.buckets.iter().find(|b| b.name == required) {
+            Some(b) => b,
+            None => {
+                drift.push(format!("bucket '{required}' is missing"));
+                continue;
+            }
+        };
+
+        let expected = configured_retention_s(required);
+        let actual = bucket.retention_rules.first().map(|r| r.every_seconds).unwrap_or(0);
+        if expected != actual {
+            drift.push(format!("bucket '{required}' has retention {actual}s, expected {expected}s"));
+        }
+    }
+
+    for window in BASELINE_WINDOWS {
+        let name = format!("task_baseline_{window}");
+        let tasks = influx_client.list_tasks(ListTasksRequest { name: Some(name.clone()), ..Default::default() }).await
+            .map_err(|e| format!("Could not list tasks, e = '{e}'"))?;
+
+        match tasks.tasks.iter().find(|t| t.name == name) {
+            None => drift.push(format!("task '{name}' is missing")),
+            Some(task) if task.flux.trim() != baseline_task_flux(window).trim() => {
+                drift.push(format!("task '{name}' definition differs from the expected one"))
+            },
+            Some(_) => (),
+        }
+    }
+
+    Ok(drift)
+}
+
+/// Looks up the id of the configured organization
+async fn org_id(client: &influxdb2::Client) -> Option<String> {
+    let org = Config::instance().settings().backend.controller.influx.org.clone();
+    let org_id = match client.list_organizations(ListOrganizationRequest { org: Some(org.clone()), ..Default::default() }).await {
+        Ok(orgs) => orgs.orgs.into_iter().find(|o| o.name == org).and_then(|o| o.id),
+        Err(e) => {
+            log::error!(subsystem = "influx.setup"; "Failed to look up organization '{org}', e = '{e}'");
+            None
+        }
+    };
+    if org_id.is_none() {
+        log::error!(subsystem = "influx.setup"; "Organization '{org}' was not found. Buckets and tasks will not be reconciled");
+    }
+    org_id
+}
+
+/// Verifies and creates the required buckets and baseline tasks. Tasks whose definition drifted are replaced,
+/// and buckets whose retention drifted are updated in place
+pub async fn reconcile() {
+    log::info!("Attempting to reconcile influx buckets and tasks...");
+    let client = match operator_client() {
+        Some(c) => c,
+        None => {
+            log::info!(subsystem = "influx.setup"; "Skipping creation of buckets and tasks. 'backend/controller/influx/operator_token' is not found in config file.");
+            log::warn!(subsystem = "influx.setup"; "THIS SHOULD ONLY BE DONE IF THE STRUCTS ALREADY EXIST!");
+            return;
+        }
+    };
+
+    let org_id = match org_id(&client).await { Some(id) => id, None => return };
+
+    // Buckets
+    let existing = match client.list_buckets(Some(ListBucketsRequest { limit: Some(100), org_id: Some(org_id.clone()), ..Default::default() })).await {
+        Ok(b) => b.buckets,
+        Err(e) => {
+            log::error!(subsystem = "influx.setup"; "Failed to list buckets, e = '{e}'");
+            return;
+        }
+    };
+
+    for required in REQUIRED_BUCKETS {
+        if existing.iter().any(|b| b.name == required) {
+            log::info!(subsystem = "influx.setup"; "Bucket '{required}' already exists. Skipping creation.");
+            continue;
+        }
+
+        let request = PostBucketRequest {
+            retention_rules: vec![RetentionRule::new(RetentionType::Expire, configured_retention_s(required))],
+            ..PostBucketRequest::new(org_id.clone(), required.to_string())
+        };
+        match client.create_bucket(Some(request)).await {
+            Ok(_) => log::info!(subsystem = "influx.setup"; "Created bucket: {required}"),
+            Err(e) => log::error!(subsystem = "influx.setup"; "Failed to create bucket '{required}', e = '{e}'"),
+        }
+    }
+    reconcile_retention(&client, &org_id).await;
+
+    // Baseline tasks
+    for window in BASELINE_WINDOWS {
+        let name = format!("task_baseline_{window}");
+        let flux = baseline_task_flux(window);
+
+        let tasks = match client.list_tasks(ListTasksRequest { name: Some(name.clone()), org_id: Some(org_id.clone()), ..Default::default() }).await {
+            Ok(t) => t.tasks,
+            Err(e) => {
+                log::error!(subsystem = "influx.setup"; "Failed to list tasks, e = '{e}'");
+                return;
+            }
+        };
+
+        let mut up_to_date = false;
+        for task in tasks.iter().filter(|t| t.name == name) {
+            if task.flux.trim() == flux.trim() && !up_to_date {
+                up_to_date = true;
+                continue;
+            }
+
+            // Drifted or duplicated, replace it
+            log::warn!(subsystem = "influx.setup"; "Task '{name}' drifted from its expected definition. Replacing...");
+            if let Err(e) = client.delete_task(&task.id).await {
+                log::error!(subsystem = "influx.setup"; "Failed to delete drifted task '{name}', e = '{e}'");
+            }
+        }
+
+        if up_to_date {
+            log::info!(subsystem = "influx.setup"; "Task '{name}' already exists. Skipping creation.");
+            continue;
+        }
+
+        let request = CreateTaskRequest { org_id: Some(org_id.clone()), ..CreateTaskRequest::new(flux) };
+        match client.create_task(request).await {
+            Ok(_) => log::info!(subsystem = "influx.setup"; "Created task: {name}"),
+            Err(e) => {
+                log::error!(subsystem = "influx.setup"; "Failed to create task '{name}', e = '{e}'");
+                continue;
+            },
+        }
+
+        // New tasks are run right away, so baselines are available before their first scheduled run
+        let created = client.list_tasks(ListTasksRequest { name: Some(name.clone()), org_id: Some(org_id.clone()), ..Default::default() }).await
+            .map(|t| t.tasks.into_iter().find(|t| t.name == name));
+        let result = match created {
+            Ok(Some(task)) => operator_request(reqwest::Method::POST, &format!("/api/v2/tasks/{}/runs", task.id), None).await,
+            Ok(None) => Err("it was not found after creating it".to_string()),
+            Err(e) => Err(e.to_string()),
+        };
+        if let Err(e) = result {
+            log::warn!(subsystem = "influx.setup"; "Failed to run new task '{name}', it will run on schedule. e = '{e}'");
+        }
+    }
+}
+
+/// Keeps the retention of the buckets in sync with the configuration, applying it again on every reload that changes it
+pub fn watch_retention() {
+    let mut config_changes = Config::subscribe();
+    rocket::tokio::spawn(async move {
+        let mut current = Config::instance().settings().backend.controller.influx.retention_s.clone();
+        while config_changes.changed().await.is_ok() {
+            let new = config_changes.borrow_and_update().settings().backend.controller.influx.retention_s.clone();
+            if new == current {
+                continue;
+            }
+            current = new;
+
+            let client = match operator_client() { Some(c) => c, None => continue };
+            if let Some(org_id) = org_id(&client).await {
+                reconcile_retention(&client, &org_id).await;
+            }
+        }
+    });
+}
@@ -2,4 +2,5 @@ pub mod fetch_topology;
 pub mod pools;
 pub mod operations;
 pub mod update_topology;
-pub mod health_check;
\ No newline at end of file
+pub mod health_check;
+pub mod influx_setup;
\ No newline at end of file
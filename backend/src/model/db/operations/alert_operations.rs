@@ -2,7 +2,7 @@
 use chrono::Utc;
 use sqlx::{Pool, Postgres, QueryBuilder, Transaction};
 
-use crate::{alerts::{AlertEvent, AlertFilters, AlertRule, AlertSeverity}, model::db::operations::RowCount, types::AlertEventId};
+use crate::{alerts::{AlertEvent, AlertFilters, AlertRoute, AlertRule, AlertSeverity, OnCallSchedule}, model::db::operations::RowCount, types::AlertEventId};
 
 pub async fn ack_alert<'e>(alert_id : AlertEventId, ack_actor: &str, transaction: &mut Transaction<'e, Postgres>) -> Result<(), ()>{
     let mut ack_actor = ack_actor.to_string();
@@ -18,22 +18,63 @@ pub async fn ack_alert<'e>(alert_id : AlertEventId, ack_actor: &str, transaction
     match result {
         Ok(_) => Ok(()),
         Err(e) => {
-            log::error!("[ERROR][ALERTS][DB] Failed to ack alert event with SQL Error = '{e}'");
+            log::error!(subsystem = "alerts.db"; "Failed to ack alert event with SQL Error = '{e}'");
             Err(())
         }
     }
 }
 
+/// Whether the alert event was acked. None if there's no such event
+pub async fn get_ack_state(alert_id: AlertEventId, pool: &sqlx::PgPool) -> Result<Option<bool>, sqlx::Error> {
+    sqlx::query_scalar!(r#"SELECT ack_time IS NOT NULL as "acked!" FROM Analytics.alerts WHERE alert_id = $1;"#, alert_id)
+        .fetch_optional(pool).await
+}
+
+/// Alert events that require ack and weren't acked yet, newest first, optionally of a single severity
+pub async fn get_unacked(severity: Option<AlertSeverity>, limit: i64, pool: &sqlx::PgPool) -> Result<Vec<AlertEvent>, sqlx::Error> {
+    let rows = sqlx::query!(r#"
+        SELECT
+            alert_id, alert_time, ack_time, requires_ack, severity as "severity: AlertSeverity", message as "message!", target_id as "target_id!",
+            ack_actor, rule_id, value, recovery
+        FROM Analytics.alerts
+        WHERE requires_ack AND ack_time IS NULL AND ($1::AlertSeverity IS NULL OR severity = $1)
+        ORDER BY alert_time DESC
+        LIMIT $2"#,
+        severity as Option<AlertSeverity>, limit
+    ).fetch_all(pool).await?;
+
+    Ok(rows.into_iter()
+        .map(|r| AlertEvent {
+            alert_id: r.alert_id,
+            alert_time: Some(r.alert_time),
+            ack_time: r.ack_time,
+            requires_ack: r.requires_ack,
+            severity: r.severity,
+            message: r.message,
+            target_id: r.target_id,
+            ws_notified: true,
+            db_notified: true,
+            acked: false,
+            ack_actor: r.ack_actor,
+            rule_id: r.rule_id,
+            value: r.value,
+            recovery: r.recovery,
+            template: None,
+            recipients: None,
+        })
+        .collect())
+}
+
 pub async fn insert_alert(alert: &AlertEvent, pool : &sqlx::Pool<sqlx::Postgres>) -> Result<AlertEventId, sqlx::Error> {
     let alert_time = alert.alert_time.unwrap_or_default();
     let severity: AlertSeverity = alert.severity;
 
     let result = sqlx::query!(r#"
-        INSERT INTO Analytics.alerts(alert_time, requires_ack, severity, message, target_id, rule_id, value)
-        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        INSERT INTO Analytics.alerts(alert_time, requires_ack, severity, message, target_id, rule_id, value, recovery)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
         RETURNING alert_id;
         "#,
-        alert_time, alert.requires_ack, severity as AlertSeverity, alert.message, alert.target_id, alert.rule_id, alert.value
+        alert_time, alert.requires_ack, severity as AlertSeverity, alert.message, alert.target_id, alert.rule_id, alert.value, alert.recovery
     ).fetch_one(pool).await;
 
     match result {
@@ -42,7 +83,7 @@ pub async fn insert_alert(alert: &AlertEvent, pool : &sqlx::Pool<sqlx::Postgres>
             Ok(id)
         }
         Err(e) => {
-            log::error!("[ERROR][ALERTS][DB] Failed to insert alert into database. SQL Error = '{e}'");
+            log::error!(subsystem = "alerts.db"; "Failed to insert alert into database. SQL Error = '{e}'");
             Err(e)
         },
     }
@@ -102,7 +143,7 @@ pub async fn get_row_count(filters: &AlertFilters, postgres_pool: &Pool<Postgres
 
     append_where_clause(filters, &mut query, None);
 
-    #[cfg(debug_assertions)] { log::info!("[DEBUG][DB][ALERTS] query={}", query.sql()); }
+    #[cfg(debug_assertions)] { log::info!(subsystem = "db.alerts"; "query={}", query.sql()); }
 
     let query = query.build_query_as::<RowCount>();
     let result = query.fetch_one(postgres_pool).await;
@@ -110,7 +151,7 @@ pub async fn get_row_count(filters: &AlertFilters, postgres_pool: &Pool<Postgres
     match result {
         Ok(v) => v.total,
         Err(e) => {
-            log::error!("[ERROR][DB][ALERTS] Failed to query for row count with error = '{e}'");
+            log::error!(subsystem = "db.alerts"; "Failed to query for row count with error = '{e}'");
             0
         }
     }
@@ -120,13 +161,13 @@ pub async fn get_rows(row_count_fetch: i64, filters: &AlertFilters, postgres_poo
     let mut query = QueryBuilder::<Postgres>::new(concat! (
         "SELECT ",
             "alert_id, alert_time, ack_time, requires_ack, severity, message, target_id, ",
-            "TRUE as ws_notified, TRUE as db_notified, (ack_actor IS NOT NULL) as acked, ack_actor, rule_id, value ",
+            "TRUE as ws_notified, TRUE as db_notified, (ack_actor IS NOT NULL) as acked, ack_actor, rule_id, value, recovery ",
         "FROM Analytics.alerts "
     ));
 
     append_where_clause(filters, &mut query, Some(row_count_fetch));
 
-    #[cfg(debug_assertions)] { log::info!("[DEBUG][DB][ALERTS] query={}", query.sql()); }
+    #[cfg(debug_assertions)] { log::info!(subsystem = "db.alerts"; "query={}", query.sql()); }
 
     let query = query.build_query_as::<AlertEvent>();
     let result = query.fetch_all(postgres_pool).await;
@@ -135,7 +176,7 @@ pub async fn get_rows(row_count_fetch: i64, filters: &AlertFilters, postgres_poo
         Ok(r) => r,
         Err(e) => {
             // Don't propagate the error outwards. Print it, and allow to recover
-            log::error!("[ERROR][DB][SYSLOG] Failed to query database with error = '{e}'");
+            log::error!(subsystem = "db.syslog"; "Failed to query database with error = '{e}'");
             Vec::new()
         }
     }
@@ -145,8 +186,7 @@ pub async fn get_rows(row_count_fetch: i64, filters: &AlertFilters, postgres_poo
 pub async fn get_alert_rules(postgres_pool: &Pool<Postgres>) -> Result<Vec<AlertRule>, ()> {
     let rules = sqlx::query!("SELECT * FROM Analytics.alert_rules;").fetch_all(postgres_pool).await;
     let rules = match rules { Ok(r) => r, Err(e) => {
-        log::error!("[ERROR][ALERTS][LOADS] Failed to load rules from database with error = '{e}'");
-        println!("[ERROR][ALERTS][LOADS] Failed to load rules from database with error = '{e}'");
+        log::error!(subsystem = "alerts.loads"; "Failed to load rules from database with error = '{e}'");
         return Err(());
     }};
 
@@ -155,8 +195,7 @@ pub async fn get_alert_rules(postgres_pool: &Pool<Postgres>) -> Result<Vec<Alert
         let mut rule: AlertRule = match serde_json::from_value(record.rule_definition) {
             Ok(r) => r,
             Err(e) => {
-                log::warn!("[WARN ][ALERTS][LOADS] Failed to load rule {}-'{}' from database- Definition is invalid. Error = '{e}'. Ignoring...", record.rule_id, record.rule_name);
-                println!("[WARN ][ALERTS][LOADS] Failed to load rule {}-'{}' from database- Definition is invalid. Error = '{e}'. Ignoring...", record.rule_id, record.rule_name);
+                log::warn!(subsystem = "alerts.loads"; "Failed to load rule {}-'{}' from database- Definition is invalid. Error = '{e}'. Ignoring...", record.rule_id, record.rule_name);
                 continue;
             }
         };
@@ -172,4 +211,64 @@ pub async fn get_alert_rules(postgres_pool: &Pool<Postgres>) -> Result<Vec<Alert
     }
 
     Ok(result)
-}
\ No newline at end of file
+}
+
+pub async fn get_alert_routes(postgres_pool: &sqlx::PgPool) -> Result<Vec<AlertRoute>, ()> {
+    let routes = match sqlx::query!("SELECT route_id, route_name, route_definition FROM Analytics.alert_routes ORDER BY route_id;")
+        .fetch_all(postgres_pool).await {
+        Ok(r) => r,
+        Err(e) => {
+            log::error!(subsystem = "alerts.loads"; "Failed to load routes from database with error = '{e}'");
+            return Err(());
+        }
+    };
+
+    let mut result = Vec::with_capacity(routes.len());
+    for record in routes {
+        let mut route: AlertRoute = match serde_json::from_value(record.route_definition) {
+            Ok(r) => r,
+            Err(e) => {
+                log::warn!(subsystem = "alerts.loads"; "Failed to load route {}-'{}' from database- Definition is invalid. Error = '{e}'. Ignoring...", record.route_id, record.route_name);
+                continue;
+            }
+        };
+
+        // Database rows win over the definition
+        route.route_id = record.route_id;
+        route.name = record.route_name;
+
+        result.push(route);
+    }
+
+    Ok(result)
+}
+
+pub async fn get_oncall_schedules(postgres_pool: &sqlx::PgPool) -> Result<Vec<OnCallSchedule>, ()> {
+    let schedules = match sqlx::query!("SELECT schedule_id, schedule_name, schedule_definition FROM Analytics.oncall_schedules ORDER BY schedule_id;")
+        .fetch_all(postgres_pool).await {
+        Ok(r) => r,
+        Err(e) => {
+            log::error!(subsystem = "alerts.loads"; "Failed to load on-call schedules from database with error = '{e}'");
+            return Err(());
+        }
+    };
+
+    let mut result = Vec::with_capacity(schedules.len());
+    for record in schedules {
+        let mut schedule: OnCallSchedule = match serde_json::from_value(record.schedule_definition) {
+            Ok(s) => s,
+            Err(e) => {
+                log::warn!(subsystem = "alerts.loads"; "Failed to load on-call schedule {}-'{}' from database- Definition is invalid. Error = '{e}'. Ignoring...", record.schedule_id, record.schedule_name);
+                continue;
+            }
+        };
+
+        // Database rows win over the definition
+        schedule.schedule_id = record.schedule_id;
+        schedule.name = record.schedule_name;
+
+        result.push(schedule);
+    }
+
+    Ok(result)
+}
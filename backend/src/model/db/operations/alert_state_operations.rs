@@ -0,0 +1,152 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use sqlx::{Pool, Postgres, QueryBuilder};
+
+use crate::AegisError;
+use crate::model::data::link_state::LinkMessage;
+use crate::types::{AlertRuleId, AlertTargetId, DeviceHostname, EvaluableItemId, MetricSet};
+
+/// Rows inserted per statement, well under the bind parameter limit
+const INSERT_CHUNK: usize = 1000;
+
+/// Timer of a Sustained rule for an item, with the fingerprint of the rule it was started with
+#[derive(Debug, Clone, PartialEq)]
+pub struct SustainedRecord {
+    pub rule_id: AlertRuleId,
+    pub item_id: EvaluableItemId,
+    pub first_raised: i64,
+    pub rule_fingerprint: serde_json::Value,
+}
+
+/// Item a rule with a clear condition is raised for, with the fingerprint of the rule that raised it
+#[derive(Debug, Clone, PartialEq)]
+pub struct RaisedRecord {
+    pub rule_id: AlertRuleId,
+    pub item_id: EvaluableItemId,
+    pub rule_fingerprint: serde_json::Value,
+}
+
+/// Previous dataset of Delta rules
+#[derive(Debug, Clone)]
+pub struct DeltaSnapshot {
+    pub taken_at: DateTime<Utc>,
+    pub facts: HashMap<DeviceHostname, MetricSet>,
+    pub link_states: LinkMessage,
+}
+
+/// Devices whose alert events are not notified until `until`
+#[derive(Debug, Clone, PartialEq)]
+pub struct SilenceRecord {
+    pub target_id: AlertTargetId,
+    pub until: DateTime<Utc>,
+    pub actor: String,
+}
+
+pub async fn get_sustained_state(pool: &Pool<Postgres>) -> Result<Vec<SustainedRecord>, AegisError> {
+    sqlx::query_as!(SustainedRecord, "SELECT rule_id, item_id, first_raised, rule_fingerprint FROM Analytics.alert_sustained_state;")
+        .fetch_all(pool).await
+        .map_err(AegisError::Sql)
+}
+
+/// Replaces the stored timers with `records`, atomically
+pub async fn replace_sustained_state(pool: &Pool<Postgres>, records: &[SustainedRecord]) -> Result<(), AegisError> {
+    let mut transaction = pool.begin().await.map_err(AegisError::Sql)?;
+
+    sqlx::query!("DELETE FROM Analytics.alert_sustained_state;")
+        .execute(&mut *transaction).await
+        .map_err(AegisError::Sql)?;
+
+    for chunk in records.chunks(INSERT_CHUNK) {
+        let mut query = QueryBuilder::<Postgres>::new("INSERT INTO Analytics.alert_sustained_state (rule_id, item_id, first_raised, rule_fingerprint) ");
+        query.push_values(chunk, |mut row, record| {
+            row.push_bind(record.rule_id)
+                .push_bind(record.item_id)
+                .push_bind(record.first_raised)
+                .push_bind(&record.rule_fingerprint);
+        });
+        query.build().execute(&mut *transaction).await.map_err(AegisError::Sql)?;
+    }
+
+    transaction.commit().await.map_err(AegisError::Sql)
+}
+
+pub async fn get_raised_state(pool: &Pool<Postgres>) -> Result<Vec<RaisedRecord>, AegisError> {
+    sqlx::query_as!(RaisedRecord, "SELECT rule_id, item_id, rule_fingerprint FROM Analytics.alert_raised_state;")
+        .fetch_all(pool).await
+        .map_err(AegisError::Sql)
+}
+
+/// Replaces the stored raised items with `records`, atomically
+pub async fn replace_raised_state(pool: &Pool<Postgres>, records: &[RaisedRecord]) -> Result<(), AegisError> {
+    let mut transaction = pool.begin().await.map_err(AegisError::Sql)?;
+
+    sqlx::query!("DELETE FROM Analytics.alert_raised_state;")
+        .execute(&mut *transaction).await
+        .map_err(AegisError::Sql)?;
+
+    for chunk in records.chunks(INSERT_CHUNK) {
+        let mut query = QueryBuilder::<Postgres>::new("INSERT INTO Analytics.alert_raised_state (rule_id, item_id, rule_fingerprint) ");
+        query.push_values(chunk, |mut row, record| {
+            row.push_bind(record.rule_id)
+                .push_bind(record.item_id)
+                .push_bind(&record.rule_fingerprint);
+        });
+        query.build().execute(&mut *transaction).await.map_err(AegisError::Sql)?;
+    }
+
+    transaction.commit().await.map_err(AegisError::Sql)
+}
+
+pub async fn get_delta_snapshot(pool: &Pool<Postgres>) -> Result<Option<DeltaSnapshot>, AegisError> {
+    let row = sqlx::query!("
+        SELECT taken_at, facts, link_states FROM Analytics.alert_delta_snapshot WHERE snapshot_id = 1;")
+        .fetch_optional(pool).await
+        .map_err(AegisError::Sql)?;
+
+    let Some(row) = row else { return Ok(None) };
+    Ok(Some(DeltaSnapshot {
+        taken_at: row.taken_at,
+        facts: serde_json::from_value(row.facts).map_err(AegisError::Serde)?,
+        link_states: serde_json::from_value(row.link_states).map_err(AegisError::Serde)?,
+    }))
+}
+
+pub async fn upsert_delta_snapshot(pool: &Pool<Postgres>, facts: &HashMap<DeviceHostname, MetricSet>, link_states: &LinkMessage) -> Result<(), AegisError> {
+    sqlx::query!("
+        INSERT INTO Analytics.alert_delta_snapshot (snapshot_id, taken_at, facts, link_states)
+        VALUES (1, NOW(), $1, $2)
+        ON CONFLICT (snapshot_id) DO UPDATE
+        SET taken_at = EXCLUDED.taken_at, facts = EXCLUDED.facts, link_states = EXCLUDED.link_states;",
+        serde_json::json!(facts), serde_json::json!(link_states)
+    ).execute(pool).await
+        .map_err(AegisError::Sql)?;
+    Ok(())
+}
+
+/// Silences that haven't expired yet
+pub async fn get_silences(pool: &Pool<Postgres>) -> Result<Vec<SilenceRecord>, AegisError> {
+    sqlx::query_as!(SilenceRecord, "SELECT target_id, silenced_until as until, silenced_by as actor FROM Analytics.alert_silences WHERE silenced_until > NOW();")
+        .fetch_all(pool).await
+        .map_err(AegisError::Sql)
+}
+
+/// Sets the silence of the target, replacing the one it had
+pub async fn upsert_silence(pool: &Pool<Postgres>, silence: &SilenceRecord) -> Result<(), AegisError> {
+    sqlx::query!("
+        INSERT INTO Analytics.alert_silences (target_id, silenced_until, silenced_by)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (target_id) DO UPDATE
+        SET silenced_until = EXCLUDED.silenced_until, silenced_by = EXCLUDED.silenced_by;",
+        silence.target_id, silence.until, silence.actor
+    ).execute(pool).await
+        .map_err(AegisError::Sql)?;
+    Ok(())
+}
+
+pub async fn delete_silence(pool: &Pool<Postgres>, target_id: AlertTargetId) -> Result<(), AegisError> {
+    sqlx::query!("DELETE FROM Analytics.alert_silences WHERE target_id = $1;", target_id)
+        .execute(pool).await
+        .map_err(AegisError::Sql)?;
+    Ok(())
+}
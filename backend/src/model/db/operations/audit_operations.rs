@@ -0,0 +1,155 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres, Transaction, Type};
+use tokio::sync::{Mutex, MutexGuard};
+
+use crate::AegisError;
+use crate::model::db::operations::commit_changes::refresh_after_commit;
+use crate::model::db::operations::topology_document::{self, DocumentDiff, TopologyDocument};
+
+type E = (String, i16);
+
+pub type Revision = i64;
+
+/// Serializes commits, so the snapshots taken around each one only contain its own changes
+static COMMIT_LOCK: Mutex<()> = Mutex::const_new(());
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "auditsource", rename_all = "lowercase")]
+pub enum AuditSource {
+    Configure,
+    Import,
+    Discovery,
+    Rollback,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditEntry {
+    pub revision: Revision,
+
+    pub actor: String,
+
+    pub source: AuditSource,
+
+    #[serde(rename = "committed-at")]
+    pub committed_at: DateTime<Utc>,
+
+    #[serde(rename = "rolled-back-to")]
+    pub rolled_back_to: Option<Revision>,
+
+    pub summary: serde_json::Value,
+
+    pub changes: serde_json::Value,
+}
+
+/// Filters for audit entries being recalled from the database. All of them are optional
+#[derive(Debug, Default)]
+pub struct AuditFilters {
+    pub actor: Option<String>,
+    pub source: Option<AuditSource>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub limit: i64,
+}
+
+/// A commit in progress. Holds the commit lock and the state of the topology before the commit
+pub struct PendingAudit {
+    _lock: MutexGuard<'static, ()>,
+    pub before: TopologyDocument,
+}
+
+/// Takes the commit lock and a snapshot of the current state, within the commit's transaction.
+/// Must be called before applying any change
+pub async fn begin<'t>(transaction: &mut Transaction<'t, Postgres>) -> Result<PendingAudit, E> {
+    let lock = COMMIT_LOCK.lock().await;
+    let before = topology_document::snapshot(transaction).await?;
+
+    Ok(PendingAudit { _lock: lock, before })
+}
+
+impl PendingAudit {
+    /// Stores the changes made since `begin` as a new revision, in the same transaction as the changes themselves,
+    /// so a commit that can't be audited is not applied either. Commits that changed nothing are not stored.
+    /// The commit lock is released once the caller commits or drops the transaction
+    pub async fn record<'t>(&self, transaction: &mut Transaction<'t, Postgres>, actor: &str, source: AuditSource, rolled_back_to: Option<Revision>) -> Result<Option<Revision>, E> {
+        let after = topology_document::snapshot(transaction).await?;
+
+        let changes = topology_document::changes(&self.before, &after);
+        if changes.is_empty() {
+            return Ok(None);
+        }
+        let summary = topology_document::diff(&self.before, &after, true).unwrap_or_default();
+
+        let mut actor = actor.to_string();
+        actor.truncate(254);
+
+        let revision = sqlx::query_scalar!("
+            INSERT INTO Analytics.audit_log
+                (actor, source, rolled_back_to, summary, changes, snapshot)
+            VALUES
+                ($1, $2, $3, $4, $5, $6)
+            RETURNING revision;",
+            actor, source as AuditSource, rolled_back_to,
+            serde_json::json!(summary), serde_json::json!(changes), serde_json::json!(after)
+        ).fetch_one(&mut **transaction).await
+            .map_err(|e| (format!("Could not store audit entry for commit by '{actor}'. SQL Error = '{e}'"), 500))?;
+
+        log::info!(subsystem = "audit"; "Recorded revision {revision}, by '{actor}' via {source:?}");
+        Ok(Some(revision))
+    }
+}
+
+pub async fn get_entries(pool: &Pool<Postgres>, filters: &AuditFilters) -> Result<Vec<AuditEntry>, AegisError> {
+    sqlx::query_as!(AuditEntry, r#"
+        SELECT revision, actor, source as "source: AuditSource", committed_at, rolled_back_to, summary, changes
+        FROM Analytics.audit_log
+        WHERE ($1::TEXT IS NULL OR actor = $1)
+          AND ($2::AuditSource IS NULL OR source = $2)
+          AND ($3::TIMESTAMPTZ IS NULL OR committed_at >= $3)
+          AND ($4::TIMESTAMPTZ IS NULL OR committed_at <= $4)
+        ORDER BY revision DESC
+        LIMIT $5;"#,
+        filters.actor, filters.source as Option<AuditSource>, filters.since, filters.until, filters.limit
+    ).fetch_all(pool).await
+        .map_err(AegisError::Sql)
+}
+
+pub async fn get_snapshot(pool: &Pool<Postgres>, revision: Revision) -> Result<Option<serde_json::Value>, AegisError> {
+    sqlx::query_scalar!("SELECT snapshot FROM Analytics.audit_log WHERE revision = $1;", revision)
+        .fetch_optional(pool).await
+        .map_err(AegisError::Sql)
+}
+
+/// Name of the actor that owns the given client token, if any
+pub async fn get_token_actor(pool: &Pool<Postgres>, token: &str) -> Result<Option<String>, AegisError> {
+    sqlx::query_scalar!("SELECT ack_actor_name FROM ClientIdentity.ack_tokens WHERE ack_token = $1;", token)
+        .fetch_optional(pool).await
+        .map_err(AegisError::Sql)
+}
+
+/// Restores the topology as it was right after the given revision, in a single transaction.
+/// The rollback is itself recorded as a new revision
+pub async fn rollback(pool: &Pool<Postgres>, revision: Revision, actor: &str) -> Result<DocumentDiff, E> {
+    let snapshot = get_snapshot(pool, revision).await
+        .map_err(|e| (format!("Could not fetch revision {revision}, error = '{e:?}'"), 500))?
+        .ok_or((format!("Revision {revision} does not exist"), 404))?;
+
+    let document: TopologyDocument = serde_json::from_value(snapshot)
+        .map_err(|e| (format!("Snapshot of revision {revision} is not valid, error = '{e}'"), 500))?;
+
+    let mut transaction = pool.begin().await
+        .map_err(|e| (format!("Could not begin rollback transaction. Err = '{e}'"), 500))?;
+
+    let audit = begin(&mut transaction).await?;
+    let diff = topology_document::diff(&audit.before, &document, true)?;
+    topology_document::apply_document(&mut transaction, &document, &diff).await?;
+    audit.record(&mut transaction, actor, AuditSource::Rollback, Some(revision)).await?;
+
+    transaction.commit().await.map_err(|e| (format!("Could not commit rollback transaction, error = '{e}'"), 500))?;
+
+    log::info!(subsystem = "audit"; "'{actor}' rolled back to revision {revision}");
+    refresh_after_commit(pool).await?;
+
+    Ok(diff)
+}
@@ -1,12 +1,17 @@
+use std::collections::HashMap;
+
 use serde_json::Map;
 use sqlx::{Postgres, Transaction};
 
-use crate::alerts::{AlertReduceLogic, AlertSeverity};
+use crate::alerts::{AlertReduceLogic, AlertRoute, AlertSeverity, OnCallSchedule};
+use crate::model::db::operations::audit_operations::{self, AuditSource};
+use crate::types::GroupId;
 #[allow(unused)] // Needs to be allowed. Needed for compilation, but the compiler complains of a type casting needed if it's removed
-use crate::{types::DeviceId, alerts::{AlertRule, alert_backend::AlertBackend}, misc::hashset_to_json_array, model::{cache::Cache, data::{DataSource, device::Device, group::Group, link::Link, link_type::LinkType}}};
+use crate::{types::DeviceId, alerts::{AlertRule, alert_backend::AlertBackend}, misc::hashset_to_json_array, model::{cache::Cache, data::{DataSource, device::Device, group::{Group, find_group_cycle}, link::Link, link_type::LinkType}}};
 
 type E = (String, i16);
-pub async fn commit(mut data: serde_json::Value, pool: &sqlx::Pool<Postgres>) -> Result<(), E> {
+/// Applies the changes in a single transaction, and records them in the audit log as made by `actor`
+pub async fn commit(data: serde_json::Value, pool: &sqlx::Pool<Postgres>, actor: &str, source: AuditSource) -> Result<(), E> {
     // Start a transaction. Either everything succeedes, or it fails altogether
     // once this transaction goes out of scope, if a commit hasn't been performed
     // sqlx will automatically rollback. RAII
@@ -15,15 +20,35 @@ pub async fn commit(mut data: serde_json::Value, pool: &sqlx::Pool<Postgres>) ->
         let mut transaction: sqlx::Transaction<'_, Postgres> = pool.begin().await
             .map_err(|err| (format!("Could not begin commit transaction. Err = '{err}'").to_string(), 500))?;
 
-        let data = data.as_object_mut().ok_or(("'data' is not Json Object".to_string(), 400))?;
-        
-        insert_and_update(data, &mut transaction).await?;
-        delete_items(data, &mut transaction).await?;
+        commit_in_transaction(data, &mut transaction, actor, source).await?;
 
         transaction.commit().await.map_err(|err|  (format!("Could not commit transaction, error = '{err}'"), 500))?;
     }
     // End of database transaction block, release transaction
 
+    refresh_after_commit(pool).await
+}
+
+/// Applies the changes and records them in the audit log, within an already open transaction that the caller commits.
+/// Allows callers to make the commit depend on changes of their own, e.g. resolving link proposals
+pub async fn commit_in_transaction<'t>(data: serde_json::Value, transaction: &mut Transaction<'t, Postgres>, actor: &str, source: AuditSource) -> Result<(), E> {
+    let audit = audit_operations::begin(transaction).await?;
+    apply_changes(data, transaction).await?;
+    audit.record(transaction, actor, source, None).await?;
+    Ok(())
+}
+
+/// Applies the changes and deletions of a commit within an already open transaction, without commiting it.
+/// Allows callers to apply several dependent changes at once, e.g. topology imports
+pub async fn apply_changes<'t>(mut data: serde_json::Value, transaction: &mut Transaction<'t, Postgres>) -> Result<(), E> {
+    let data = data.as_object_mut().ok_or(("'data' is not Json Object".to_string(), 400))?;
+
+    insert_and_update(data, transaction).await?;
+    delete_items(data, transaction).await
+}
+
+/// Reloads everything that mirrors the database, once a transaction has been commited
+pub async fn refresh_after_commit(pool: &sqlx::Pool<Postgres>) -> Result<(), E> {
     Cache::instance().update_topology(pool, true).await
         .map_err(|_| ("Could not update topology Cache. Values might be out of sync with database!".to_string(), 500))?;
 
@@ -37,12 +62,16 @@ async fn insert_and_update<'t>(data: &mut serde_json::Map<String, serde_json::Va
     let empty = serde_json::Value::Object(Map::new());
     let topology_changes   = data.remove("topology-changes"  ).unwrap_or(empty.clone());
     let ruleset_changes    = data.remove("ruleset-changes"   ).unwrap_or(empty.clone());
+    let routing_changes    = data.remove("routing-changes"   ).unwrap_or(empty.clone());
     let mut topology_changes = if let serde_json::Value::Object(v) = topology_changes { v } 
         else { return Err(("topology-changes is found, but it's not object!".to_string(), 400)) };
 
     let mut ruleset_changes = if let serde_json::Value::Object(v) = ruleset_changes { v } 
         else { return Err(("ruleset-changes is found, but it's not object!".to_string(), 400)) };
 
+    let mut routing_changes = if let serde_json::Value::Object(v) = routing_changes { v }
+        else { return Err(("routing-changes is found, but it's not object!".to_string(), 400)) };
+
     // Update devices
     if let Some(devices) = topology_changes.remove("devices") {
         let devices = if let serde_json::Value::Array(arr) = devices { arr }
@@ -75,6 +104,22 @@ async fn insert_and_update<'t>(data: &mut serde_json::Map<String, serde_json::Va
         update_rules(rules, transaction).await?;
     }
 
+    // Routes
+    if let Some(routes) = routing_changes.remove("routes") {
+        let routes = if let serde_json::Value::Array(arr) = routes { arr }
+            else { return Err(("routing-changes/routes is found, but is not array".to_string(), 400)) };
+
+        update_routes(routes, transaction).await?;
+    }
+
+    // On-call schedules
+    if let Some(schedules) = routing_changes.remove("schedules") {
+        let schedules = if let serde_json::Value::Array(arr) = schedules { arr }
+            else { return Err(("routing-changes/schedules is found, but is not array".to_string(), 400)) };
+
+        update_schedules(schedules, transaction).await?;
+    }
+
     Ok(())
 }
 
@@ -82,12 +127,26 @@ async fn delete_items<'t>(data: &mut serde_json::Map<String, serde_json::Value>,
     let empty = serde_json::Value::Object(Map::new());
     let topology_deletions = data.remove("topology-deletions").unwrap_or(empty.clone());
     let ruleset_deletions  = data.remove("ruleset-deletions" ).unwrap_or(empty.clone());
+    let routing_deletions  = data.remove("routing-deletions" ).unwrap_or(empty.clone());
 
         
     let mut topology_deletions = if let serde_json::Value::Object(v) = topology_deletions { v } 
         else { return Err(("topology-deletions is found, but it's not object!".to_string(), 400)) };
     let mut ruleset_deletions = if let serde_json::Value::Object(v) = ruleset_deletions { v } 
         else { return Err(("ruleset-deletions found, but it's not object!".to_string(), 400)) };
+    let mut routing_deletions = if let serde_json::Value::Object(v) = routing_deletions { v }
+        else { return Err(("routing-deletions found, but it's not object!".to_string(), 400)) };
+
+    // Rules go first, so those deleted along with their target don't hold the deletion back
+    if let Some(rules) = ruleset_deletions.remove("rules") {
+        let rules = if let serde_json::Value::Array(arr) = rules { arr }
+            else { return Err(("truleset-deletions/rules is found, but is not array".to_string(), 400)) };
+
+        delete_rules(rules, transaction).await?;
+    }
+
+    check_rule_targets(&topology_deletions, transaction).await?;
+
     if let Some(groups) = topology_deletions.remove("groups") {
         let groups = if let serde_json::Value::Array(arr) = groups { arr }
             else { return Err(("topology-deletions/groups is found, but is not array".to_string(), 400)) };
@@ -109,11 +168,18 @@ async fn delete_items<'t>(data: &mut serde_json::Map<String, serde_json::Value>,
         delete_devices(devices, transaction).await?;
     }
 
-    if let Some(rules) = ruleset_deletions.remove("rules") {
-        let rules = if let serde_json::Value::Array(arr) = rules { arr }
-            else { return Err(("truleset-deletions/rules is found, but is not array".to_string(), 400)) };
+    if let Some(routes) = routing_deletions.remove("routes") {
+        let routes = if let serde_json::Value::Array(arr) = routes { arr }
+            else { return Err(("routing-deletions/routes is found, but is not array".to_string(), 400)) };
 
-        delete_rules(rules, transaction).await?;
+        delete_routes(routes, transaction).await?;
+    }
+
+    if let Some(schedules) = routing_deletions.remove("schedules") {
+        let schedules = if let serde_json::Value::Array(arr) = schedules { arr }
+            else { return Err(("routing-deletions/schedules is found, but is not array".to_string(), 400)) };
+
+        delete_schedules(schedules, transaction).await?;
     }
 
     Ok(())
@@ -186,24 +252,31 @@ async fn update_devices<'t>(devices: Vec<serde_json::Value>, transaction: &mut T
 async fn update_groups<'t>(groups: Vec<serde_json::Value>, transaction: &mut Transaction<'t, Postgres>) -> Result<(), E> {
     for group in groups {
         let mut group : Group = serde_json::from_value(group).map_err(|e| (format!("Could not update group. Parsing failed with error = '{e}'"), 400))?;
+
+        let filter = match &group.filter {
+            Some(filter) => {
+                filter.validate().map_err(|e| (format!("Could not update group '{}'. {e}", group.name), 400))?;
+                Some(serde_json::json!(filter))
+            },
+            None => None,
+        };
+
         if group.group_id <= 0 {
-            let result = sqlx::query!("
+            group.group_id = sqlx::query_scalar!("
                 INSERT INTO Analytics.groups
-                    (group_name, is_display_group)
+                    (group_name, is_display_group, filter)
                 VALUES
-                    ($1, $2)
+                    ($1, $2, $3)
                 RETURNING group_id;",
-                group.name, group.is_display_group
+                group.name, group.is_display_group, filter
             ).fetch_one(&mut **transaction).await
             .map_err(|e| (format!("Could not insert group. SQL Error = '{e}'"), 500))?;
-
-            group.group_id = result.group_id;
         } else {
             sqlx::query!("
                 UPDATE Analytics.groups
-                SET group_name = $1, is_display_group = $2
-                WHERE group_id = $3;",
-                group.name, group.is_display_group, group.group_id
+                SET group_name = $1, is_display_group = $2, filter = $3
+                WHERE group_id = $4;",
+                group.name, group.is_display_group, filter, group.group_id
             ).execute(&mut **transaction).await
             .map_err(|e| (format!("Could not update group. SQL Error = '{e}'"), 500))?;
 
@@ -213,6 +286,11 @@ async fn update_groups<'t>(groups: Vec<serde_json::Value>, transaction: &mut Tra
             .map_err(|e| (format!("Could not delete group members during update. SQL Error = '{e}'"), 500))?;
         }
 
+        // Members of dynamic groups are computed from the filter. The ones received are the last computed, ignore them
+        if group.filter.is_some() {
+            continue;
+        }
+
         for member in group.members.unwrap_or_default() {
             sqlx::query!("
                 INSERT INTO Analytics.group_members
@@ -226,7 +304,31 @@ async fn update_groups<'t>(groups: Vec<serde_json::Value>, transaction: &mut Tra
         }
     }
 
-    Ok(())
+    check_group_cycles(transaction).await
+}
+
+/// Rejects the commit if group membership, as left by this transaction, contains a cycle.
+/// The database only forbids a group from directly holding itself
+async fn check_group_cycles<'t>(transaction: &mut Transaction<'t, Postgres>) -> Result<(), E> {
+    let edges = sqlx::query!("
+        SELECT m.group_id, m.item_id
+        FROM Analytics.group_members m
+        JOIN Analytics.groups g ON g.group_id = m.item_id;")
+        .fetch_all(&mut **transaction).await
+        .map_err(|e| (format!("Could not fetch nested groups. SQL Error = '{e}'"), 500))?;
+
+    let mut subgroups: HashMap<GroupId, Vec<GroupId>> = HashMap::new();
+    for edge in edges {
+        subgroups.entry(edge.group_id).or_default().push(edge.item_id);
+    }
+
+    match find_group_cycle(&subgroups) {
+        Some(cycle) => {
+            let cycle = cycle.iter().map(|g| g.to_string()).collect::<Vec<_>>().join(" -> ");
+            Err((format!("Group membership would contain a cycle: {cycle}"), 400))
+        },
+        None => Ok(()),
+    }
 }
 
 /// Updates the links table with new information
@@ -279,7 +381,7 @@ async fn update_rules<'t>(rules: Vec<serde_json::Value>, transaction: &mut Trans
             return Err(("Could not update rule. Alert Severity can't be unknown".to_string(), 400));
         }
 
-        log::info!("[INFO ][DB][UPDATES] Updating rule= {}", rule.rule_id);
+        log::info!(subsystem = "db.updates"; "Updating rule= {}", rule.rule_id);
 
         if rule.rule_id <= 0 {
             let id = sqlx::query!("
@@ -294,7 +396,7 @@ async fn update_rules<'t>(rules: Vec<serde_json::Value>, transaction: &mut Trans
 
             rule.rule_id = id.rule_id;
         } else {
-            log::info!("[INFO ][DB][UPDATES] Definition= {}", &definition);
+            log::info!(subsystem = "db.updates"; "Definition= {}", &definition);
             sqlx::query!("
                 UPDATE Analytics.alert_rules
                 SET rule_name=$1, requires_ack=$2, rule_definition=$3
@@ -308,6 +410,57 @@ async fn update_rules<'t>(rules: Vec<serde_json::Value>, transaction: &mut Trans
     Ok(())
 }
 
+/// Inserts the routes with a non positive id, and updates the rest. The whole item is stored as the route definition
+async fn update_routes<'t>(routes: Vec<serde_json::Value>, transaction: &mut Transaction<'t, Postgres>) -> Result<(), E> {
+    for definition in routes {
+        let route: AlertRoute = serde_json::from_value(definition.clone())
+            .map_err(|e| (format!("Could not update route. Parsing failed with error = '{e}'"), 400))?;
+
+        log::info!(subsystem = "db.updates"; "Updating route= {}", route.route_id);
+
+        if route.route_id <= 0 {
+            sqlx::query!("INSERT INTO Analytics.alert_routes (route_name, route_definition) VALUES ($1, $2);", route.name, definition)
+                .execute(&mut **transaction).await
+                .map_err(|e| (format!("Could not insert route. SQL error = '{e}'"), 500))?;
+        } else {
+            sqlx::query!("UPDATE Analytics.alert_routes SET route_name=$1, route_definition=$2 WHERE route_id=$3;", route.name, definition, route.route_id)
+                .execute(&mut **transaction).await
+                .map_err(|e| (format!("Could not update route. SQL Error = '{e}'"), 500))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Refuses to delete items that rules still target, including the links of deleted devices.
+/// Rules changed earlier in the transaction are checked with their new target
+async fn check_rule_targets<'t>(topology_deletions: &Map<String, serde_json::Value>, transaction: &mut Transaction<'t, Postgres>) -> Result<(), E> {
+    let ids: Vec<i64> = ["devices", "links", "groups"].iter()
+        .filter_map(|kind| topology_deletions.get(*kind).and_then(|items| items.as_array()))
+        .flatten()
+        .filter_map(|item| item.get("id").and_then(|id| id.as_i64()))
+        .collect();
+    if ids.is_empty() {
+        return Ok(());
+    }
+
+    let rules = sqlx::query_scalar!(r#"
+        SELECT rule_name FROM Analytics.alert_rules
+        WHERE (CASE WHEN jsonb_typeof(rule_definition->'target') = 'number' THEN (rule_definition->>'target')::BIGINT END) IN (
+            SELECT id FROM UNNEST($1::BIGINT[]) AS id
+            UNION SELECT link_id FROM Analytics.links WHERE side_a = ANY($1) OR side_b = ANY($1)
+        )
+        ORDER BY rule_name;"#,
+        &ids
+    ).fetch_all(&mut **transaction).await
+        .map_err(|e| (format!("Could not check the rules of deleted items. SQL Error = '{e}'"), 500))?;
+
+    if !rules.is_empty() {
+        return Err((format!("Could not delete items targeted by rules '{}'. Delete or retarget the rules in this same commit", rules.join("', '")), 400));
+    }
+    Ok(())
+}
+
 /// Deletes any entries in the groups table, that match the passed values
 async fn delete_groups<'t>(groups: Vec<serde_json::Value>, transaction: &mut Transaction<'t, Postgres>) -> Result<(), E> {
     for group in groups {
@@ -364,3 +517,53 @@ async fn delete_rules<'t>(rules: Vec<serde_json::Value>, transaction: &mut Trans
     }
     Ok(())
 }
+
+/// Inserts the on-call schedules with a non positive id, and updates the rest. The whole item is stored as the schedule definition
+async fn update_schedules<'t>(schedules: Vec<serde_json::Value>, transaction: &mut Transaction<'t, Postgres>) -> Result<(), E> {
+    for definition in schedules {
+        let schedule: OnCallSchedule = serde_json::from_value(definition.clone())
+            .map_err(|e| (format!("Could not update on-call schedule. Parsing failed with error = '{e}'"), 400))?;
+
+        log::info!(subsystem = "db.updates"; "Updating on-call schedule= {}", schedule.schedule_id);
+
+        if schedule.schedule_id <= 0 {
+            sqlx::query!("INSERT INTO Analytics.oncall_schedules (schedule_name, schedule_definition) VALUES ($1, $2);", schedule.name, definition)
+                .execute(&mut **transaction).await
+                .map_err(|e| (format!("Could not insert on-call schedule. SQL error = '{e}'"), 500))?;
+        } else {
+            sqlx::query!("UPDATE Analytics.oncall_schedules SET schedule_name=$1, schedule_definition=$2 WHERE schedule_id=$3;", schedule.name, definition, schedule.schedule_id)
+                .execute(&mut **transaction).await
+                .map_err(|e| (format!("Could not update on-call schedule. SQL Error = '{e}'"), 500))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Deletes any entries in the oncall_schedules table, that match the passed values
+async fn delete_schedules<'t>(schedules: Vec<serde_json::Value>, transaction: &mut Transaction<'t, Postgres>) -> Result<(), E> {
+    for schedule in schedules {
+        let id = schedule.get("id")
+            .ok_or( ("Could not delete on-call schedule. 'id' is not present".to_string(), 400))?;
+        let id = id.as_i64()
+            .ok_or(("Could not delete on-call schedule. 'id' is not of type i64".to_string(), 400))?;
+
+        sqlx::query!("DELETE FROM Analytics.oncall_schedules WHERE schedule_id = $1", id).execute(&mut **transaction).await
+            .map_err(|e| (format!("Could not delete on-call schedule. SQL Error = '{e}'"), 500))?;
+    }
+    Ok(())
+}
+
+/// Deletes any entries in the alert_routes table, that match the passed values
+async fn delete_routes<'t>(routes: Vec<serde_json::Value>, transaction: &mut Transaction<'t, Postgres>) -> Result<(), E> {
+    for route in routes {
+        let id = route.get("id")
+            .ok_or( ("Could not delete route. 'id' is not present".to_string(), 400))?;
+        let id = id.as_i64()
+            .ok_or(("Could not delete route. 'id' is not of type i64".to_string(), 400))?;
+
+        sqlx::query!("DELETE FROM Analytics.alert_routes WHERE route_id = $1", id).execute(&mut **transaction).await
+            .map_err(|e| (format!("Could not delete route. SQL Error = '{e}'"), 500))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,527 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use crate::alerts::alert_backend::AlertBackend;
+use crate::alerts::oncall_schedule::MAX_SHIFT_WEEKS;
+use crate::alerts::{AlertDataSource, AlertReduceLogic, AlertRoute, AlertRule, AlertSeverity, OnCallSchedule, RouteDestination};
+use crate::model::cache::Cache;
+use crate::model::data::device::Device;
+use crate::model::data::group::{Group, find_group_cycle};
+use crate::model::data::link::Link;
+use crate::types::{AlertRuleId, DeviceId, GroupId, ItemId, LinkId};
+
+/// A problem found in a commit, at the given path of the request. e.g. `topology-changes/links/0/side-a`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FieldError {
+    pub path: String,
+    pub message: String,
+}
+
+/// Sections of a commit, and the kinds of items each one accepts
+const SECTIONS: [(&str, &[&str]); 6] = [
+    ("topology-changes", &["devices", "groups", "links"]),
+    ("ruleset-changes", &["rules"]),
+    ("routing-changes", &["routes", "schedules"]),
+    ("topology-deletions", &["devices", "groups", "links"]),
+    ("ruleset-deletions", &["rules"]),
+    ("routing-deletions", &["routes", "schedules"]),
+];
+
+/// The topology a commit is validated against
+#[derive(Default)]
+pub struct KnownTopology {
+    pub devices: HashMap<DeviceId, Device>,
+    pub links: HashMap<LinkId, Link>,
+    pub groups: HashMap<GroupId, Group>,
+    pub rules: Vec<AlertRule>,
+}
+
+impl KnownTopology {
+    pub async fn from_cache() -> Self {
+        let cache = Cache::instance();
+        Self {
+            devices: cache.get_devices().await.into_iter().map(|d| (d.device_id, d)).collect(),
+            links: cache.get_links().await.into_iter().map(|l| (l.link_id, l)).collect(),
+            groups: cache.get_groups().await.into_iter().map(|g| (g.group_id, g)).collect(),
+            rules: AlertBackend::instance().get_rules().await,
+        }
+    }
+
+    fn has_item(&self, id: ItemId) -> bool {
+        self.devices.contains_key(&id) || self.links.contains_key(&id) || self.groups.contains_key(&id)
+    }
+}
+
+struct Validator<'a> {
+    known: &'a KnownTopology,
+    errors: Vec<FieldError>,
+    /// Items deleted by the commit, with the path of the deletion that removes them
+    deleted: HashMap<ItemId, String>,
+}
+
+impl Validator<'_> {
+    fn error(&mut self, path: impl Into<String>, message: impl Into<String>) {
+        self.errors.push(FieldError { path: path.into(), message: message.into() });
+    }
+
+    /// Deserializes an item, reporting the exact field that failed
+    fn parse<T: DeserializeOwned>(&mut self, value: &serde_json::Value, path: &str) -> Option<T> {
+        match serde_path_to_error::deserialize::<_, T>(value) {
+            Ok(v) => Some(v),
+            Err(e) => {
+                let field: Vec<String> = e.path().iter()
+                    .filter_map(|segment| match segment {
+                        serde_path_to_error::Segment::Seq { index } => Some(index.to_string()),
+                        serde_path_to_error::Segment::Map { key } => Some(key.clone()),
+                        serde_path_to_error::Segment::Enum { variant } => Some(variant.clone()),
+                        serde_path_to_error::Segment::Unknown => None,
+                    })
+                    .collect();
+                let field_path = if field.is_empty() { path.to_string() } else { format!("{path}/{}", field.join("/")) };
+                self.error(field_path, e.into_inner().to_string());
+                None
+            }
+        }
+    }
+
+    /// Checks that an item referenced by a change exists, and is not deleted by the same commit
+    fn check_reference(&mut self, path: String, id: ItemId, exists: bool, kind: &str) {
+        if !exists {
+            self.error(path, format!("{kind} with id={id} does not exist"));
+        } else if self.deleted.contains_key(&id) {
+            self.error(path, format!("{kind} with id={id} is deleted in this same commit"));
+        }
+    }
+
+    fn check_existing(&mut self, path: String, id: ItemId, exists: bool, kind: &str) {
+        // Non positive ids are inserted as new items
+        if id > 0 && !exists {
+            self.error(path, format!("{kind} with id={id} does not exist, use an id <= 0 to create a new one"));
+        }
+    }
+
+    fn devices(&mut self, items: &[serde_json::Value], base: &str) {
+        let mut hostnames: HashMap<String, DeviceId> = self.known.devices.values()
+            .map(|d| (d.management_hostname.clone(), d.device_id))
+            .collect();
+
+        for (i, item) in items.iter().enumerate() {
+            let path = format!("{base}/{i}");
+            let device: Device = match self.parse(item, &path) { Some(d) => d, None => continue };
+
+            self.check_existing(format!("{path}/id"), device.device_id, self.known.devices.contains_key(&device.device_id), "Device");
+
+            if !(-90.0..=90.0).contains(&device.latitude) {
+                self.error(format!("{path}/latitude"), "Latitude must be within [-90, 90]");
+            }
+            if !(-180.0..=180.0).contains(&device.longitude) {
+                self.error(format!("{path}/longitude"), "Longitude must be within [-180, 180]");
+            }
+
+            let hostname = device.management_hostname.trim();
+            if hostname.is_empty() {
+                self.error(format!("{path}/management-hostname"), "Management hostname can't be empty");
+                continue;
+            }
+            // Facts are keyed by hostname, two devices can't share one
+            match hostnames.get(hostname) {
+                Some(other) if *other != device.device_id || device.device_id <= 0 =>
+                    self.error(format!("{path}/management-hostname"), format!("Management hostname '{hostname}' is already used by device with id={other}")),
+                _ => { hostnames.insert(hostname.to_string(), device.device_id); },
+            }
+        }
+    }
+
+    fn links(&mut self, items: &[serde_json::Value], base: &str) {
+        // Only one link may exist between two devices, regardless of order
+        let mut pairs: HashMap<(DeviceId, DeviceId), LinkId> = self.known.links.values()
+            .map(|l| ((l.side_a.min(l.side_b), l.side_a.max(l.side_b)), l.link_id))
+            .collect();
+
+        for (i, item) in items.iter().enumerate() {
+            let path = format!("{base}/{i}");
+            let link: Link = match self.parse(item, &path) { Some(l) => l, None => continue };
+
+            self.check_existing(format!("{path}/id"), link.link_id, self.known.links.contains_key(&link.link_id), "Link");
+
+            for (side, id) in [("side-a", link.side_a), ("side-b", link.side_b)] {
+                self.check_reference(format!("{path}/{side}"), id, self.known.devices.contains_key(&id), "Device");
+            }
+            for (side, iface) in [("side-a-iface", &link.side_a_iface), ("side-b-iface", &link.side_b_iface)] {
+                if iface.trim().is_empty() {
+                    self.error(format!("{path}/{side}"), "Interface can't be empty");
+                }
+            }
+
+            if link.side_a == link.side_b {
+                self.error(format!("{path}/side-b"), "Both sides of a link can't be the same device");
+                continue;
+            }
+
+            let pair = (link.side_a.min(link.side_b), link.side_a.max(link.side_b));
+            match pairs.get(&pair) {
+                Some(other) if *other != link.link_id || link.link_id <= 0 =>
+                    self.error(path, format!("Devices {} and {} are already joined by link with id={other}", pair.0, pair.1)),
+                _ => { pairs.insert(pair, link.link_id); },
+            }
+        }
+    }
+
+    fn groups(&mut self, items: &[serde_json::Value], base: &str) {
+        let mut subgroups: HashMap<GroupId, Vec<GroupId>> = self.known.groups.values()
+            .map(|g| (g.group_id, g.members.iter().flatten().copied().filter(|m| self.known.groups.contains_key(m)).collect()))
+            .collect();
+
+        for (i, item) in items.iter().enumerate() {
+            let path = format!("{base}/{i}");
+            let group: Group = match self.parse(item, &path) { Some(g) => g, None => continue };
+
+            self.check_existing(format!("{path}/id"), group.group_id, self.known.groups.contains_key(&group.group_id), "Group");
+
+            if let Some(filter) = &group.filter {
+                if let Err(e) = filter.validate() {
+                    self.error(format!("{path}/filter"), e);
+                }
+                // Members of dynamic groups are ignored
+                continue;
+            }
+
+            let members = group.members.unwrap_or_default();
+            for (j, member) in members.iter().enumerate() {
+                if *member == group.group_id {
+                    self.error(format!("{path}/members/{j}"), "A group can't contain itself");
+                    continue;
+                }
+                self.check_reference(format!("{path}/members/{j}"), *member, self.known.has_item(*member), "Item");
+            }
+
+            if group.group_id > 0 {
+                subgroups.insert(group.group_id, members.into_iter().filter(|m| self.known.groups.contains_key(m)).collect());
+            }
+        }
+
+        if let Some(cycle) = find_group_cycle(&subgroups) {
+            let cycle = cycle.iter().map(|g| g.to_string()).collect::<Vec<_>>().join(" -> ");
+            self.error(base, format!("Group membership would contain a cycle: {cycle}"));
+        }
+    }
+
+    fn rules(&mut self, items: &[serde_json::Value], base: &str) {
+        for (i, item) in items.iter().enumerate() {
+            let path = format!("{base}/{i}");
+
+            // Rules are loaded from their definition, so both must be valid
+            let definition = match item.get("rule-definition") {
+                Some(d) => d,
+                None => {
+                    self.error(format!("{path}/rule-definition"), "Rule definition is missing");
+                    continue;
+                }
+            };
+            let rule: Option<AlertRule> = self.parse(item, &path);
+            let defined: Option<AlertRule> = self.parse(definition, &format!("{path}/rule-definition"));
+            let rule = match rule.or(defined) { Some(r) => r, None => continue };
+
+            if rule.reduce_logic == AlertReduceLogic::Unknown {
+                self.error(format!("{path}/reduce-logic"), "Reduce logic can't be unknown");
+            }
+            if rule.severity == AlertSeverity::Unknown {
+                self.error(format!("{path}/severity"), "Alert severity can't be unknown");
+            }
+            if rule.predicates.is_empty() {
+                self.error(format!("{path}/predicates"), "A rule needs at least one predicate");
+            }
+            if let Some(clear) = &rule.clear {
+                if matches!(rule.data_source, AlertDataSource::Syslog) {
+                    self.error(format!("{path}/clear"), "Syslog rules can't have a clear condition");
+                }
+                if clear.reduce_logic == AlertReduceLogic::Unknown {
+                    self.error(format!("{path}/clear/reduce-logic"), "Reduce logic can't be unknown");
+                }
+                if clear.predicates.is_empty() {
+                    self.error(format!("{path}/clear/predicates"), "A clear condition needs at least one predicate");
+                }
+            }
+            if let Some(Err(e)) = rule.template.as_ref().map(|t| t.validate()) {
+                self.error(format!("{path}/message-template"), e);
+            }
+            self.check_reference(format!("{path}/target"), rule.target_item, self.known.has_item(rule.target_item), "Item");
+        }
+    }
+
+    fn routes(&mut self, items: &[serde_json::Value], base: &str) {
+        for (i, item) in items.iter().enumerate() {
+            let path = format!("{base}/{i}");
+            let route: AlertRoute = match self.parse(item, &path) { Some(r) => r, None => continue };
+
+            if route.destinations.is_empty() {
+                self.error(format!("{path}/destinations"), "A route needs at least one destination");
+            }
+            for (j, destination) in route.destinations.iter().enumerate() {
+                let path = format!("{path}/destinations/{j}");
+                match destination {
+                    RouteDestination::Telegram { chats } if chats.is_empty() => self.error(path, "At least one chat is required"),
+                    RouteDestination::Webhook { url } if !(url.starts_with("http://") || url.starts_with("https://")) => {
+                        self.error(format!("{path}/url"), "Must be an http:// or https:// URL");
+                    },
+                    RouteDestination::Email { recipients } if recipients.is_empty() => self.error(path, "At least one recipient is required"),
+                    RouteDestination::Email { recipients } => {
+                        for (k, recipient) in recipients.iter().enumerate() {
+                            if recipient.parse::<lettre::Address>().is_err() {
+                                self.error(format!("{path}/recipients/{k}"), format!("'{recipient}' is not an email address"));
+                            }
+                        }
+                    },
+                    RouteDestination::OnCall { schedule } if schedule.trim().is_empty() => self.error(format!("{path}/schedule"), "A schedule name is required"),
+                    _ => (),
+                }
+            }
+
+            let matcher = &route.matcher;
+            if matcher.severities.as_ref().is_some_and(|s| s.contains(&AlertSeverity::Unknown)) {
+                self.error(format!("{path}/match/severities"), "Alert severity can't be unknown");
+            }
+            for target in matcher.targets.iter().flatten() {
+                self.check_reference(format!("{path}/match/targets"), *target, self.known.has_item(*target), "Item");
+            }
+            if matcher.schedule.as_ref().is_some_and(|s| s.from == s.to) {
+                self.error(format!("{path}/match/schedule"), "'from' and 'to' can't be the same time");
+            }
+        }
+    }
+
+    fn schedules(&mut self, items: &[serde_json::Value], base: &str) {
+        let mut names = HashSet::new();
+        for (i, item) in items.iter().enumerate() {
+            let path = format!("{base}/{i}");
+            let schedule: OnCallSchedule = match self.parse(item, &path) { Some(s) => s, None => continue };
+
+            if schedule.name.trim().is_empty() {
+                self.error(format!("{path}/name"), "A schedule name is required");
+            } else if !names.insert(schedule.name.clone()) {
+                self.error(format!("{path}/name"), format!("Schedule '{}' is defined more than once", schedule.name));
+            }
+
+            let rotation = &schedule.rotation;
+            if rotation.members.is_empty() {
+                self.error(format!("{path}/rotation/members"), "A rotation needs at least one member");
+            }
+            if rotation.members.iter().any(|member| member.trim().is_empty()) {
+                self.error(format!("{path}/rotation/members"), "Member names can't be empty");
+            }
+            if !(1..=MAX_SHIFT_WEEKS).contains(&rotation.shift_weeks) {
+                self.error(format!("{path}/rotation/shift-weeks"), format!("Shifts last between 1 and {MAX_SHIFT_WEEKS} weeks"));
+            }
+            for (j, o) in schedule.overrides.iter().enumerate() {
+                if o.from >= o.to {
+                    self.error(format!("{path}/overrides/{j}"), "'from' must be before 'to'");
+                }
+                if o.member.trim().is_empty() {
+                    self.error(format!("{path}/overrides/{j}/member"), "Member names can't be empty");
+                }
+            }
+        }
+    }
+
+    /// Collects the ids to delete, and checks they refer to existing items
+    fn deletions(&mut self, kind: &str, items: &[serde_json::Value], base: &str) {
+        for (i, item) in items.iter().enumerate() {
+            let path = format!("{base}/{i}/id");
+            let id = match item.get("id").and_then(|id| id.as_i64()) {
+                Some(id) => id,
+                None => {
+                    self.error(path, "'id' is missing or is not an integer");
+                    continue;
+                }
+            };
+
+            let exists = match kind {
+                "devices" => self.known.devices.contains_key(&id),
+                "links" => self.known.links.contains_key(&id),
+                "groups" => self.known.groups.contains_key(&id),
+                _ => true, // Deleting a rule, route or schedule that doesn't exist is harmless
+            };
+            if !exists {
+                self.error(path, format!("Can't delete {kind} with id={id}, it does not exist"));
+                continue;
+            }
+
+            // Deleted devices take their links along
+            if kind == "devices" {
+                let links: Vec<LinkId> = self.known.links.values().filter(|l| l.side_a == id || l.side_b == id).map(|l| l.link_id).collect();
+                self.deleted.extend(links.into_iter().map(|link| (link, path.clone())));
+            }
+            if kind != "rules" && kind != "routes" && kind != "schedules" {
+                self.deleted.insert(id, path);
+            }
+        }
+    }
+
+    /// Checks that no rule is left targeting a deleted item. Rules deleted or changed by the commit are skipped,
+    /// changed ones have their new target checked along with the rest of the rule
+    fn orphaned_rules(&mut self, deleted_rules: &HashSet<AlertRuleId>, changed_rules: &HashSet<AlertRuleId>) {
+        let mut orphaned: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+        for rule in &self.known.rules {
+            if deleted_rules.contains(&rule.rule_id) || changed_rules.contains(&rule.rule_id) {
+                continue;
+            }
+            if let Some(path) = self.deleted.get(&rule.target_item) {
+                orphaned.entry(path).or_default().push(&rule.name);
+            }
+        }
+
+        let errors: Vec<(String, String)> = orphaned.into_iter()
+            .map(|(path, rules)| (path.to_string(), format!("Item is targeted by rules '{}', delete or retarget them in this same commit", rules.join("', '"))))
+            .collect();
+        for (path, message) in errors {
+            self.error(path, message);
+        }
+    }
+}
+
+/// Checks a commit request without touching the database: the structure of every section,
+/// that every item deserializes, and that every referenced item exists in `known`.
+/// Returns every problem found, or an empty list if the commit is valid
+pub fn validate(data: &serde_json::Value, known: &KnownTopology) -> Vec<FieldError> {
+    let mut validator = Validator { known, errors: Vec::new(), deleted: HashMap::new() };
+
+    let data = match data.as_object() {
+        Some(d) => d,
+        None => {
+            validator.error("", "Content should be a JSON Object");
+            return validator.errors;
+        }
+    };
+
+    for key in data.keys() {
+        if !SECTIONS.iter().any(|(section, _)| section == key) {
+            validator.error(key.as_str(), format!("Unknown section, expected one of {:?}", SECTIONS.map(|(s, _)| s)));
+        }
+    }
+
+    // Deletions first, so changes referencing deleted items are caught
+    let mut arrays: HashMap<(&str, &str), &Vec<serde_json::Value>> = HashMap::new();
+    for (section, kinds) in SECTIONS {
+        let content = match data.get(section) {
+            Some(serde_json::Value::Object(o)) => o,
+            Some(_) => { validator.error(section, "Section should be a JSON Object"); continue; },
+            None => continue,
+        };
+
+        for (kind, items) in content {
+            let path = format!("{section}/{kind}");
+            if !kinds.contains(&kind.as_str()) {
+                validator.error(path, format!("Unknown item kind, expected one of {kinds:?}"));
+                continue;
+            }
+            match items {
+                serde_json::Value::Array(arr) => { arrays.insert((section, kind.as_str()), arr); },
+                _ => validator.error(path, "Should be a JSON Array"),
+            }
+        }
+    }
+
+    for ((section, kind), items) in &arrays {
+        if section.ends_with("-deletions") {
+            validator.deletions(kind, items, &format!("{section}/{kind}"));
+        }
+    }
+
+    for ((section, kind), items) in &arrays {
+        let path = format!("{section}/{kind}");
+        match (*section, *kind) {
+            ("topology-changes", "devices") => validator.devices(items, &path),
+            ("topology-changes", "links") => validator.links(items, &path),
+            ("topology-changes", "groups") => validator.groups(items, &path),
+            ("ruleset-changes", "rules") => validator.rules(items, &path),
+            ("routing-changes", "routes") => validator.routes(items, &path),
+            ("routing-changes", "schedules") => validator.schedules(items, &path),
+            _ => (),
+        }
+    }
+
+    let rule_ids = |section| arrays.get(&(section, "rules")).into_iter()
+        .flat_map(|items| items.iter())
+        .filter_map(|item| item.get("id").and_then(|id| id.as_i64()))
+        .collect::<HashSet<AlertRuleId>>();
+    validator.orphaned_rules(&rule_ids("ruleset-deletions"), &rule_ids("ruleset-changes"));
+
+    validator.errors.sort_by(|a, b| a.path.cmp(&b.path));
+    validator.errors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commit_field_errors() {
+        let mut known = KnownTopology::default();
+        for id in [1, 2] {
+            known.devices.insert(id, Device::new(id, format!("sw{id}"), 0.0, 0.0, format!("10.0.0.{id}"), Default::default()));
+        }
+
+        let device = |id: i64, hostname: &str, latitude: f64| serde_json::json!({
+            "id": id, "name": "sw", "latitude": latitude, "longitude": 0.0, "management-hostname": hostname,
+            "configuration": {"data-sources": [], "available-values": [], "requested-metadata": [], "requested-metrics": []}
+        });
+
+        let data = serde_json::json!({
+            "topology-changes": {
+                "devices": [device(-1, "10.0.0.3", 0.0), device(-1, "10.0.0.1", 91.0), {"id": 2}],
+                "links": [{"id": -1, "side-a": 1, "side-b": 9, "side-a-iface": "eth0", "side-b-iface": "", "link-type": "copper"}],
+                "groups": [{"id": -1, "name": "g", "is-display-group": false, "members": [1, 2]}]
+            },
+            "topology-deletions": {"devices": [{"id": 2}]},
+            "extra": {}
+        });
+
+        let errors = validate(&data, &known);
+        let paths: Vec<&str> = errors.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, vec![
+            "extra",
+            "topology-changes/devices/1/latitude",
+            "topology-changes/devices/1/management-hostname",
+            "topology-changes/devices/2",
+            "topology-changes/groups/0/members/1",
+            "topology-changes/links/0/side-b",
+            "topology-changes/links/0/side-b-iface",
+        ]);
+
+        let valid = serde_json::json!({"topology-changes": {"devices": [device(1, "10.0.0.1", 10.0)]}});
+        assert!(validate(&valid, &known).is_empty());
+    }
+
+    #[test]
+    fn deleting_rule_targets() {
+        let mut known = KnownTopology::default();
+        for id in [1, 2] {
+            known.devices.insert(id, Device::new(id, format!("sw{id}"), 0.0, 0.0, format!("10.0.0.{id}"), Default::default()));
+        }
+        let rule = |id: i64, name: &str, target: i64| -> AlertRule {
+            serde_json::from_value(serde_json::json!({
+                "id": id, "name": name, "severity": "warning", "target": target, "reduce-logic": "all", "data-source": "facts",
+                "rule-type": "simple", "predicates": [{"left": "&icmp_rtt", "op": "more_than", "right": 100}]
+            })).expect("Rule should be valid")
+        };
+        known.rules = vec![rule(1, "rtt", 2), rule(2, "down", 2), rule(3, "other", 1)];
+
+        let data = serde_json::json!({"topology-deletions": {"devices": [{"id": 2}]}});
+        let errors = validate(&data, &known);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].path, "topology-deletions/devices/0/id");
+        assert!(errors[0].message.contains("'rtt', 'down'"));
+
+        // Unless the rules go away, or point elsewhere, in the same commit
+        let mut retargeted = serde_json::to_value(rule(2, "down", 1)).unwrap();
+        retargeted["rule-definition"] = retargeted.clone();
+        let data = serde_json::json!({
+            "topology-deletions": {"devices": [{"id": 2}]},
+            "ruleset-deletions": {"rules": [{"id": 1}]},
+            "ruleset-changes": {"rules": [retargeted]},
+        });
+        assert_eq!(validate(&data, &known), vec![]);
+    }
+}
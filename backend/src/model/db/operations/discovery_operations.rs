@@ -0,0 +1,77 @@
+use sqlx::{Pool, Postgres, Transaction};
+
+use crate::AegisError;
+use crate::model::discovery::{LinkProposal, LinkProposalId, LinkProposalStatus};
+use crate::types::{DeviceId, LinkId};
+
+/// Inserts a pending proposal, unless an identical one is already pending or was rejected before.
+/// Returns whether a new proposal was inserted
+pub async fn insert_proposal(
+    pool: &Pool<Postgres>,
+    link_id: Option<LinkId>,
+    (side_a, side_a_iface): (DeviceId, &str),
+    (side_b, side_b_iface): (DeviceId, &str),
+    protocol: &str
+) -> Result<bool, AegisError> {
+    let result = sqlx::query!(r#"
+        INSERT INTO Analytics.link_proposals
+            (link_id, side_a, side_b, side_a_iface, side_b_iface, protocol)
+        SELECT $1::BIGINT, $2::BIGINT, $3::BIGINT, $4::VARCHAR, $5::VARCHAR, $6::VARCHAR
+        WHERE NOT EXISTS (
+            SELECT 1 FROM Analytics.link_proposals
+            WHERE side_a = $2 AND side_b = $3 AND side_a_iface = $4 AND side_b_iface = $5
+              AND status IN ('pending', 'rejected')
+        );"#,
+        link_id, side_a, side_b, side_a_iface, side_b_iface, protocol
+    ).execute(pool).await
+        .map_err(AegisError::Sql)?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn get_proposals(pool: &Pool<Postgres>, status: Option<LinkProposalStatus>) -> Result<Vec<LinkProposal>, AegisError> {
+    sqlx::query_as!(LinkProposal, r#"
+        SELECT proposal_id, link_id, side_a, side_b, side_a_iface, side_b_iface, protocol, status as "status: LinkProposalStatus", discovered_at, resolved_at
+        FROM Analytics.link_proposals
+        WHERE ($1::LinkProposalStatus IS NULL OR status = $1)
+        ORDER BY proposal_id;"#,
+        status as Option<LinkProposalStatus>
+    ).fetch_all(pool).await
+        .map_err(AegisError::Sql)
+}
+
+pub async fn get_proposal(pool: &Pool<Postgres>, id: LinkProposalId) -> Result<Option<LinkProposal>, AegisError> {
+    sqlx::query_as!(LinkProposal, r#"
+        SELECT proposal_id, link_id, side_a, side_b, side_a_iface, side_b_iface, protocol, status as "status: LinkProposalStatus", discovered_at, resolved_at
+        FROM Analytics.link_proposals
+        WHERE proposal_id = $1;"#,
+        id
+    ).fetch_optional(pool).await
+        .map_err(AegisError::Sql)
+}
+
+/// Marks a pending proposal as resolved. Returns false if the proposal doesn't exist or was already resolved
+pub async fn resolve_proposal(pool: &Pool<Postgres>, id: LinkProposalId, status: LinkProposalStatus) -> Result<bool, AegisError> {
+    let result = sqlx::query!("
+        UPDATE Analytics.link_proposals
+        SET status = $1, resolved_at = NOW()
+        WHERE proposal_id = $2 AND status = 'pending';",
+        status as LinkProposalStatus, id
+    ).execute(pool).await
+        .map_err(AegisError::Sql)?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Marks a pending proposal as approved within the transaction, and returns it. The row stays locked until the transaction ends,
+/// so concurrent approvals can't both apply it. None if the proposal doesn't exist or was already resolved
+pub async fn claim_proposal<'t>(transaction: &mut Transaction<'t, Postgres>, id: LinkProposalId) -> Result<Option<LinkProposal>, AegisError> {
+    sqlx::query_as!(LinkProposal, r#"
+        UPDATE Analytics.link_proposals
+        SET status = 'approved', resolved_at = NOW()
+        WHERE proposal_id = $1 AND status = 'pending'
+        RETURNING proposal_id, link_id, side_a, side_b, side_a_iface, side_b_iface, protocol, status as "status: LinkProposalStatus", discovered_at, resolved_at;"#,
+        id
+    ).fetch_optional(&mut **transaction).await
+        .map_err(AegisError::Sql)
+}
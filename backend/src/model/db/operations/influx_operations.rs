@@ -1,12 +1,11 @@
-use std::collections::HashMap;
-use std::env;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use influxdb2::{api::query::FluxRecord, models::ast::{Dialect, dialect::Annotations}};
 use serde::{Deserialize, Serialize};
 
 use influxdb2::models::DataPoint;
 use rocket::futures::stream;
 
-use crate::{config::Config, model::{cache::Cache, facts::fact_gathering_backend::FactMessage}, types::{DeviceHostname, DeviceId, MetricName, MetricValue, Metrics}};
+use crate::{config::Config, model::{cache::Cache, facts::fact_gathering_backend::FactMessage}, telemetry::Telemetry, types::{ArrayEncoding, DeviceHostname, DeviceId, MetricName, MetricValue, Metrics}};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct InfluxFilter {
@@ -16,6 +15,8 @@ pub struct InfluxFilter {
     pub device_id: String,
     #[serde(rename = "aggregate-interval")]
     pub aggregate_interval: String,
+    #[serde(rename = "as-array", default)]
+    pub as_array: bool,
 }
 
 impl InfluxFilter {
@@ -24,12 +25,14 @@ impl InfluxFilter {
         let metric = json.get("metric")?.as_str()?.to_string();
         let device_id = json.get("device-id")?.as_i64()?.to_string();
         let aggregate_interval = json.get("aggregate-interval")?.as_str()?.to_string();
+        let as_array = json.get("as-array").and_then(|v| v.as_bool()).unwrap_or(false);
 
         Some(Self {
             start,
             metric,
             device_id,
             aggregate_interval,
+            as_array,
         })
     }
 
@@ -39,6 +42,7 @@ impl InfluxFilter {
         map.insert("metric".into(), self.metric.clone());
         map.insert("device_id".into(), self.device_id.clone());
         map.insert("aggregate_interval".into(), self.aggregate_interval.clone());
+        map.insert("as_array".into(), self.as_array.to_string());
         map
     }
 }
@@ -125,7 +129,96 @@ pub async fn get_metric_range(influx_client : &influxdb2::Client, influx_filter:
     })
 }
 
+/// Returns how the given array metric is flattened when written into Influx
+pub fn array_encoding(metric: &str) -> ArrayEncoding {
+    Config::instance().settings().backend.controller.influx.array_encoding
+        .get(metric)
+        .copied()
+        .unwrap_or_default()
+}
+
+/// Queries an array metric and rebuilds it from its flattened fields. Arrays can't be averaged,
+/// so each window keeps the last value written in it
+async fn get_array_metric_data(influx_client : &influxdb2::Client, influx_filter: &InfluxFilter) -> serde_json::Value {
+    let encoding = array_encoding(&influx_filter.metric);
+    let field_filter = match encoding {
+        ArrayEncoding::Json => format!(r#"r["_field"] == "{}""#, influx_filter.metric),
+        ArrayEncoding::Indexed => format!(r#"r["_field"] =~ /^{}(_[0-9]+)+$/"#, regex::escape(&influx_filter.metric)),
+    };
+
+    let query = format!(
+        r#"
+        from(bucket: "analytics")
+            |> range(start: {})
+            |> filter(fn: (r) => r["_measurement"] == "metrics")
+            |> filter(fn: (r) => {})
+            |> filter(fn: (r) => r["device_id"] == "{}")
+            |> aggregateWindow(every: {}, fn: last, createEmpty: false)
+            |> yield(name: "last")
+        "#,
+        influx_filter.start,
+        field_filter,
+        influx_filter.device_id,
+        influx_filter.aggregate_interval
+    );
+    let data = execute_query(influx_client, query).await;
+
+    let data_map: Vec<serde_json::Value> = match encoding {
+        ArrayEncoding::Json => data.into_iter().map(|object| {
+            let value = match object.get("_value") {
+                Some(serde_json::Value::String(s)) => serde_json::from_str(s).unwrap_or(serde_json::Value::String(s.clone())),
+                Some(v) => v.clone(),
+                None => serde_json::Value::Null,
+            };
+            serde_json::json!({ "time": object.get("_time"), "value": value })
+        }).collect(),
+
+        ArrayEncoding::Indexed => {
+            let prefix = format!("{}_", influx_filter.metric);
+            let mut by_time: BTreeMap<i64, serde_json::Value> = BTreeMap::new();
+            for object in data {
+                let time = match object.get("_time").and_then(|t| t.as_i64()) { Some(t) => t, None => continue };
+                let indexes: Option<Vec<usize>> = object.get("_field")
+                    .and_then(|f| f.as_str())
+                    .and_then(|f| f.strip_prefix(&prefix))
+                    .map(|f| f.split('_').map(|i| i.parse().ok()).collect::<Option<Vec<usize>>>())
+                    .unwrap_or_default();
+                let indexes = match indexes { Some(i) => i, None => continue };
+
+                let entry = by_time.entry(time).or_insert(serde_json::Value::Array(Vec::new()));
+                insert_indexed(entry, &indexes, object.get("_value").cloned().unwrap_or_default());
+            }
+            by_time.into_iter().map(|(time, value)| serde_json::json!({ "time": time, "value": value })).collect()
+        },
+    };
+
+    serde_json::json!({
+        "range": { "min-y": 0, "max-y": 0 },
+        "data": data_map,
+    })
+}
+
+/// Places `value` into the nested array `target` at the position given by `indexes`.
+/// Missing positions are filled with nulls, as nulls are never written into Influx
+fn insert_indexed(target: &mut serde_json::Value, indexes: &[usize], value: serde_json::Value) {
+    let (idx, rest) = match indexes.split_first() { Some(v) => v, None => { *target = value; return } };
+
+    if !target.is_array() {
+        *target = serde_json::Value::Array(Vec::new());
+    }
+    if let serde_json::Value::Array(arr) = target {
+        if arr.len() <= *idx {
+            arr.resize(*idx + 1, serde_json::Value::Null);
+        }
+        insert_indexed(&mut arr[*idx], rest, value);
+    }
+}
+
 pub async fn get_metric_data(influx_client : &influxdb2::Client, influx_filter: &InfluxFilter ) -> serde_json::Value {
+    if influx_filter.as_array {
+        return get_array_metric_data(influx_client, influx_filter).await;
+    }
+
     let query = if influx_filter.metric.starts_with("baseline_") {
         
         let stripped = influx_filter.metric.strip_prefix("baseline_").unwrap_or_default(); 
@@ -189,7 +282,7 @@ pub async fn get_metric_data(influx_client : &influxdb2::Client, influx_filter:
 pub async fn get_baseline_metrics(influx_client: &influxdb2::Client, device_hostnames: &HashMap<DeviceId, DeviceHostname>) -> Metrics {
 
     if device_hostnames.is_empty() {
-        log::info!("[INFO ][FACTS][BASELINE] Baseline could not emit facts, as hostnames are not in cache yet...");
+        log::info!(subsystem = "facts.baseline"; "Baseline could not emit facts, as hostnames are not in cache yet...");
         return Metrics::new()
     }
 
@@ -207,7 +300,7 @@ pub async fn get_baseline_metrics(influx_client: &influxdb2::Client, device_host
         let point = match point {
             serde_json::Value::Object(map) => map,
             _ => {
-                log::warn!("[WARN ][FACTS][BASELINE] Found a non-map value while extracting baselines into facts. Value will be ignored...");
+                log::warn!(subsystem = "facts.baseline"; "Found a non-map value while extracting baselines into facts. Value will be ignored...");
                 continue;
             }
         };
@@ -217,7 +310,7 @@ pub async fn get_baseline_metrics(influx_client: &influxdb2::Client, device_host
                 match s.parse() {
                     Ok(id) => id,
                     Err(_) => {
-                        log::warn!("[WARN ][FACTS][BASELINE] 'device_id' was found, but could not be converted into i64. Value will be ignored...");
+                        log::warn!(subsystem = "facts.baseline"; "'device_id' was found, but could not be converted into i64. Value will be ignored...");
                         continue;
                     }
                 }
@@ -226,13 +319,13 @@ pub async fn get_baseline_metrics(influx_client: &influxdb2::Client, device_host
                 match n.as_i64() {
                     Some(n) => n,
                     None => {
-                        log::warn!("[WARN ][FACTS][BASELINE] 'device_id' was found to be a number, but could not be converted ino i64. Value will be ignored...");
+                        log::warn!(subsystem = "facts.baseline"; "'device_id' was found to be a number, but could not be converted ino i64. Value will be ignored...");
                         continue;
                     }
                 }
             },
             _ => {
-                log::warn!("[WARN ][FACTS][BASELINE] 'device_id' was not found to be string/number while extracting baseline. Value will be ignored...");
+                log::warn!(subsystem = "facts.baseline"; "'device_id' was not found to be string/number while extracting baseline. Value will be ignored...");
                 continue;
             }
         };
@@ -240,7 +333,7 @@ pub async fn get_baseline_metrics(influx_client: &influxdb2::Client, device_host
         let hostname = match device_hostnames.get(&device_id) {
             Some(h) => h,
             None => {
-                log::warn!("[WARN ][FACTS][BASELINE] 'device_id'({device_id}) was found, but could not resolve into a device_hostname from cache. Value will be ignored...");
+                log::warn!(subsystem = "facts.baseline"; "'device_id'({device_id}) was found, but could not resolve into a device_hostname from cache. Value will be ignored...");
                 continue;
             }
         };
@@ -248,8 +341,7 @@ pub async fn get_baseline_metrics(influx_client: &influxdb2::Client, device_host
         let field = match point.get("_field")  {
             Some(serde_json::Value::String(s)) => s,
             _ => {
-                log::warn!("[WARN ][FACTS][BASELINE] '_field' wasn't found in the influx cache. Value will be ignored...");
-                log::info!("                         ^ This message should be unreachable if Influx is behaving like it should.");
+                log::warn!(subsystem = "facts.baseline"; "'_field' wasn't found in the influx cache. Value will be ignored. This should be unreachable if Influx is behaving like it should");
                 continue;
             }
         };
@@ -257,8 +349,7 @@ pub async fn get_baseline_metrics(influx_client: &influxdb2::Client, device_host
         let window = match point.get("window") {
             Some(serde_json::Value::String(s)) => s,
             _ => {
-                log::warn!("[WARN ][FACTS][BASELINE] 'window' wasn't found in the influx cache. Value will be ignored...");
-                log::info!("                         ^ This message should be unreachable if Influx is behaving like it should.");
+                log::warn!(subsystem = "facts.baseline"; "'window' wasn't found in the influx cache. Value will be ignored. This should be unreachable if Influx is behaving like it should");
                 continue;
             }
         };
@@ -268,14 +359,13 @@ pub async fn get_baseline_metrics(influx_client: &influxdb2::Client, device_host
                 match n.as_f64() {
                     Some(n) => n,
                     None => {
-                        log::warn!("[WARN][FACTS][BASELINE] '_value' cannot be converted into f64. This means either it's not a number, or number is out of bounds. n='{n}'");
+                        log::warn!(subsystem = "facts.baseline"; "'_value' cannot be converted into f64. This means either it's not a number, or number is out of bounds. n='{n}'");
                         continue;
                     }
                 }
             },
             _ => {
-                log::warn!("[WARN ][FACTS][BASELINE] '_value' wasn't found in the influx cache. Value will be ignored...");
-                log::info!("                         ^ This message should be unreachable if Influx is behaving like it should.");
+                log::warn!(subsystem = "facts.baseline"; "'_value' wasn't found in the influx cache. Value will be ignored. This should be unreachable if Influx is behaving like it should");
                 continue;
             }
         };
@@ -292,6 +382,70 @@ pub async fn get_baseline_metrics(influx_client: &influxdb2::Client, device_host
     metrics
 }
 
+/// Facts written into the `analytics` bucket for the given devices and fields, between `start` and `stop` (epoch seconds).
+/// Grouped by the time they were written at, in order. Arrays stored with the `Json` encoding are decoded,
+/// arrays stored with the `Indexed` encoding are kept as their flattened fields.
+/// Fails if there are more than `max_rows` values in the range, rather than returning a partial history,
+/// and if Influx can't be read, rather than returning an empty one
+pub async fn get_facts_history(influx_client: &influxdb2::Client, device_hostnames: &HashMap<DeviceId, DeviceHostname>, fields: &HashSet<&str>, start: i64, stop: i64, max_rows: i64)
+    -> Result<BTreeMap<i64, Metrics>, (String, i16)> {
+    let mut history: BTreeMap<i64, Metrics> = BTreeMap::new();
+    if device_hostnames.is_empty() || fields.is_empty() {
+        return Ok(history);
+    }
+
+    let device_ids = device_hostnames.keys().map(|id| format!(r#""{id}""#)).collect::<Vec<_>>().join(", ");
+    let fields = fields.iter().map(|f| serde_json::json!(f).to_string()).collect::<Vec<_>>().join(", ");
+    // One more than the limit, to know whether it was exceeded
+    let query = format!(
+        r#"
+        from(bucket: "analytics")
+            |> range(start: {start}, stop: {stop})
+            |> filter(fn: (r) => r["_measurement"] == "metrics")
+            |> filter(fn: (r) => contains(value: r["device_id"], set: [{device_ids}]))
+            |> filter(fn: (r) => contains(value: r["_field"], set: [{fields}]))
+            |> group()
+            |> limit(n: {})
+        "#,
+        max_rows.saturating_add(1)
+    );
+
+    let points = try_execute_query(influx_client, query).await
+        .map_err(|e| {
+            Telemetry::influx_error();
+            (format!("Could not read facts from Influx, error = '{e}'"), 500)
+        })?;
+    if points.len() as i64 > max_rows {
+        return Err((format!("There are more than {max_rows} facts in the time range, narrow it down"), 400));
+    }
+
+    for point in points {
+        let time = match point.get("_time").and_then(|t| t.as_i64()) { Some(t) => t, None => continue };
+        let field = match point.get("_field").and_then(|f| f.as_str()) { Some(f) => f, None => continue };
+        let hostname = match point.get("device_id").and_then(|d| d.as_str()).and_then(|d| d.parse().ok()).and_then(|d: DeviceId| device_hostnames.get(&d)) {
+            Some(h) => h,
+            None => continue,
+        };
+
+        let value = match point.get("_value") {
+            Some(serde_json::Value::Number(n)) if n.is_i64() => MetricValue::Integer(n.as_i64().unwrap_or_default()),
+            Some(serde_json::Value::String(s)) if array_encoding(field) == ArrayEncoding::Json && s.starts_with('[') => {
+                serde_json::from_str::<serde_json::Value>(s).map(MetricValue::from).unwrap_or(MetricValue::String(s.clone()))
+            },
+            Some(v) => MetricValue::from(v.clone()),
+            None => continue,
+        };
+
+        history.entry(time)
+            .or_default()
+            .entry(hostname.clone())
+            .or_default()
+            .insert(field.to_string(), value);
+    }
+
+    Ok(history)
+}
+
 fn flux_value_to_json(fv: &influxdb2_structmap::value::Value) -> serde_json::Value {
     match fv {
         influxdb2_structmap::value::Value::Unknown => serde_json::Value::Null,
@@ -322,6 +476,18 @@ pub fn flux_records_to_vec(records: Vec<FluxRecord>) -> Vec<serde_json::Value> {
 
 
 async fn execute_query(influx_client : &influxdb2::Client, influx_script: String) -> Vec<serde_json::Value> {
+    match try_execute_query(influx_client, influx_script).await {
+        Ok(records) => records,
+        Err(e) => {
+            log::error!(subsystem = "influx"; "Failed to read data from Influx Database with error = '{e}'");
+            Telemetry::influx_error();
+            vec![serde_json::Value::Null]
+        }
+    }
+}
+
+/// Same as `execute_query`, but fails instead of returning a null record, for callers that must tell an outage apart from no data
+async fn try_execute_query(influx_client : &influxdb2::Client, influx_script: String) -> Result<Vec<serde_json::Value>, influxdb2::RequestError> {
     let query = influxdb2::models::Query {
         query: influx_script.to_string(),
         dialect: Some(Dialect {
@@ -334,40 +500,22 @@ async fn execute_query(influx_client : &influxdb2::Client, influx_script: String
         ..Default::default()
     };
 
-    let response = match influx_client.query_raw(Some(query)).await {
-        Ok(r) => r,
-        Err(e) => {
-            log::error!("[ERROR][INFLUX] Failed to read data from Influx Database with error = '{e}'");
-            return vec![serde_json::Value::Null]
-        }
-    };
-
-    flux_records_to_vec(response)
+    let response = influx_client.query_raw(Some(query)).await?;
+    Ok(flux_records_to_vec(response))
 }
 
 /// Inserts datapoints into influxdb bucket.
 pub async fn update_device_analytics(influx_client : &influxdb2::Client, message : &FactMessage) {
 
-    let bucket  = Config::instance().get::<String>("backend/controller/influx/bucket", "/");
-
-    let bucket = match bucket {
-        Ok(b) => b,
-        Err(e) => {
-            log::error!("[FATAL] Influx bucket isn't specified in configuration file, e='{e}'\n Expected 'backend/controller/influx/bucket' to be present");
-            let cwd = env::current_dir().expect("[FATAL]Failed to get current working directory");
-            log::info!("[INFO ] Current working directory was={}", cwd.display());
-            log::info!("[INFO ] Current config file was={}", Config::instance().get_curr_config_path());
-            panic!("[FATAL] Influx bucket isn't specified in configuration file, e='{e}'\n Expected 'backend/controller/influx/bucket' to be present");
-        }
-    };
+    let bucket = Config::instance().settings().backend.controller.influx.bucket.clone();
 
     let mut points = Vec::new();
 
     #[cfg(debug_assertions)] {
-        log::info!("[DEBUG][FACTS][INFLUX] THIS is a bucket = '{}'", &bucket);
-        log::info!("[DEBUG][FACTS][INFLUX] Dear god...");
-        log::info!("[DEBUG][FACTS][INFLUX] There's more...");
-        log::info!("[DEBUG][FACTS][INFLUX] Nooo...");
+        log::info!(subsystem = "facts.influx"; "THIS is a bucket = '{}'", &bucket);
+        log::info!(subsystem = "facts.influx"; "Dear god...");
+        log::info!(subsystem = "facts.influx"; "There's more...");
+        log::info!(subsystem = "facts.influx"; "Nooo...");
     }
 
 
@@ -377,7 +525,7 @@ pub async fn update_device_analytics(influx_client : &influxdb2::Client, message
         let mut point = DataPoint::builder("metrics").tag("device_id", device_id.to_string());
 
         #[cfg(debug_assertions)] {
-            log::info!("[DEBUG][FACTS][INFLUX] It contains 'metrics'>'device_id'>{}", device_id);
+            log::info!(subsystem = "facts.influx"; "It contains 'metrics'>'device_id'>{}", device_id);
         }
 
         let device_requested_metrics = match Cache::instance().get_device_requested_metrics(device_id).await {
@@ -393,9 +541,11 @@ pub async fn update_device_analytics(influx_client : &influxdb2::Client, message
             };
 
             #[cfg(debug_assertions)] {
-                log::info!("                       {} -> {}", &metric, value);
+                log::info!(subsystem = "facts.influx"; "{} -> {}", &metric, value);
+            }
+            for (field, field_value) in value.to_influx_fields(&metric, array_encoding(&metric)) {
+                point = point.field(field, field_value);
             }
-            point = point.field(metric, value.clone());
         }
 
         let point = match point.build() { Ok(v) => v, Err(_) => continue };
@@ -405,6 +555,7 @@ pub async fn update_device_analytics(influx_client : &influxdb2::Client, message
     let result = influx_client.write(&bucket, stream::iter(points)).await;
 
     if let Err(e) = result {
-        log::error!("[ERROR][FACTS][INFLUX] Failed to update database with gathered metrics with InfluxError = '{e}'");
+        log::error!(subsystem = "facts.influx"; "Failed to update database with gathered metrics with InfluxError = '{e}'");
+        Telemetry::influx_error();
     }
 }
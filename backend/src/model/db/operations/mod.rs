@@ -4,8 +4,13 @@ pub mod dashboard_operations;
 pub mod syslog_operations;
 pub mod influx_operations;
 pub mod alert_operations;
+pub mod alert_state_operations;
 pub mod telegram_operations;
 pub mod commit_changes;
+pub mod commit_validation;
+pub mod discovery_operations;
+pub mod topology_document;
+pub mod audit_operations;
 
 #[derive(FromRow)]
 struct RowCount {
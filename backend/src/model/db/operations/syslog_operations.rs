@@ -1,5 +1,6 @@
 
 use sqlx::{Pool, Postgres, QueryBuilder};
+use sqlx::types::chrono::{DateTime, Utc};
 
 #[allow(unused)] // Needs to be allowed. Needed for compilation, but the compiler complains of a type casting needed if it's removed
 use crate::{model::db::operations::RowCount, syslog::{SyslogFacility, SyslogFilters, SyslogMessage, SyslogSeverity}};
@@ -25,7 +26,7 @@ pub async fn update_database(postgres_pool: &Pool<Postgres>, msg: &SyslogMessage
     ).execute(postgres_pool).await;
 
     if let Err(e) = result {
-        log::error!("[ERROR][SYSLOG][DB] Failed to insert into database, sql error = '{e}'");
+        log::error!(subsystem = "syslog.db"; "Failed to insert into database, sql error = '{e}'");
     }
 
     Ok(())
@@ -84,7 +85,7 @@ pub async fn get_row_count(filters: &SyslogFilters, postgres_pool: &Pool<Postgre
 
     append_where_clause(filters, &mut query, None, false);
 
-    #[cfg(debug_assertions)] { log::info!("[DEBUG][DB][SYSLOG] query={}", query.sql()); }
+    #[cfg(debug_assertions)] { log::info!(subsystem = "db.syslog"; "query={}", query.sql()); }
 
     let query = query.build_query_as::<RowCount>();
     let result = query.fetch_one(postgres_pool).await;
@@ -92,7 +93,7 @@ pub async fn get_row_count(filters: &SyslogFilters, postgres_pool: &Pool<Postgre
     match result {
         Ok(v) => v.total,
         Err(e) => {
-            log::error!("[ERROR][DB][SYSLOG] Failed to query for row count with error = '{e}'");
+            log::error!(subsystem = "db.syslog"; "Failed to query for row count with error = '{e}'");
             0
         }
     }
@@ -107,7 +108,7 @@ pub async fn get_rows(row_count_fetch: i64, filters: &SyslogFilters, postgres_po
     ));
 
     append_where_clause(filters, &mut query, Some(row_count_fetch), true);
-    #[cfg(debug_assertions)] { log::info!("[DEBUG][DB][SYSLOG] query={}", query.sql()); }
+    #[cfg(debug_assertions)] { log::info!(subsystem = "db.syslog"; "query={}", query.sql()); }
 
     let query = query.build_query_as::<SyslogMessage>();
     let result = query.fetch_all(postgres_pool).await;
@@ -116,8 +117,36 @@ pub async fn get_rows(row_count_fetch: i64, filters: &SyslogFilters, postgres_po
         Ok(r) => r,
         Err(e) => {
             // Don't propagate the error outwards. Print it, and allow to recover
-            log::error!("[ERROR][DB][SYSLOG] Failed to query database with error = '{e}'");
+            log::error!(subsystem = "db.syslog"; "Failed to query database with error = '{e}'");
             Vec::new()
         }
     }
-}
\ No newline at end of file
+}
+
+/// Messages received between `start` and `end` from any of the given hosts, oldest first. At most `limit` rows are returned
+pub async fn get_rows_between(start: DateTime<Utc>, end: DateTime<Utc>, hosts: &[String], limit: i64, postgres_pool: &Pool<Postgres>) -> Result<Vec<SyslogMessage>, sqlx::Error> {
+    sqlx::query_as!(SyslogMessage, r#"
+        SELECT
+            id, facility as "facility: SyslogFacility", severity as "severity: SyslogSeverity", from_host as source, received_at,
+            process_id as procid, message as "msg!", NULL::text as appname, NULL::text as msgid
+        FROM Syslog.system_events
+        WHERE received_at BETWEEN $1 AND $2 AND from_host = ANY($3)
+        ORDER BY received_at, id
+        LIMIT $4"#,
+        start, end, hosts, limit
+    ).fetch_all(postgres_pool).await
+}
+
+/// Latest messages received from the host, newest first. At most `limit` rows are returned
+pub async fn get_latest_rows(host: &str, limit: i64, postgres_pool: &Pool<Postgres>) -> Result<Vec<SyslogMessage>, sqlx::Error> {
+    sqlx::query_as!(SyslogMessage, r#"
+        SELECT
+            id, facility as "facility: SyslogFacility", severity as "severity: SyslogSeverity", from_host as source, received_at,
+            process_id as procid, message as "msg!", NULL::text as appname, NULL::text as msgid
+        FROM Syslog.system_events
+        WHERE from_host = $1
+        ORDER BY received_at DESC, id DESC
+        LIMIT $2"#,
+        host, limit
+    ).fetch_all(postgres_pool).await
+}
@@ -1,6 +1,8 @@
 use sqlx::{FromRow, Postgres, Transaction};
 use tgbot::types::{ChatPeerId, UserPeerId};
 
+use std::collections::HashMap;
+
 use crate::{AegisError, types::{AlertEventId, TelegramTypeId}};
 
 
@@ -17,7 +19,7 @@ pub async fn get_subscribed_users(pool: &sqlx::PgPool) -> Vec<TelegramTypeId> {
     let items = match items {
         Ok(i) => i,
         Err(e) => {
-            log::error!("[ERROR][ALERTS][TELEGRAM] Failed to query subscribed users from database with error = e '{e}'. Alert events that depend on this will not be sent");
+            log::error!(subsystem = "alerts.telegram"; "Failed to query subscribed users from database with error = e '{e}'. Alert events that depend on this will not be sent");
             return Vec::new()
         }
     };
@@ -25,6 +27,20 @@ pub async fn get_subscribed_users(pool: &sqlx::PgPool) -> Vec<TelegramTypeId> {
     items.iter().map(|i| i.telegram_chat_id).collect()
 }
 
+/// Telegram users the given identities authenticated with, by `ack_actor_name`. Identities without one are left out
+pub async fn get_telegram_users(names: &[String], pool: &sqlx::PgPool) -> Result<HashMap<String, TelegramTypeId>, AegisError> {
+    let users = sqlx::query!(r#"
+        SELECT ack_actor_name, telegram_user_id as "telegram_user_id!" FROM ClientIdentity.ack_tokens
+        WHERE telegram_user_id IS NOT NULL AND ack_actor_name = ANY($1)
+    "#, names).fetch_all(pool).await
+        .map_err(|e| {
+            log::error!(subsystem = "alerts.telegram.db"; "Failed to query the Telegram users of on-call members, with error = '{e}'");
+            AegisError::Sql(e)
+        })?;
+
+    Ok(users.into_iter().map(|u| (u.ack_actor_name, u.telegram_user_id)).collect())
+}
+
 pub async fn auth_chat<'e>(chat: ChatPeerId, auth: bool, subscribed: bool, ack_token: String, transaction: &mut Transaction<'e, Postgres>) -> Result<(), ()> {
     let id : i64 = serde_json::json!(chat).as_i64().ok_or(())?;
     let result = sqlx::query!("
@@ -37,7 +53,7 @@ pub async fn auth_chat<'e>(chat: ChatPeerId, auth: bool, subscribed: bool, ack_t
     match result {
         Ok(_) => Ok(()),
         Err(e) => {
-            log::error!("[ERROR][ALERTS][TELEGRAM][DB] Failed to update user request to create/update auth status into database, with error = '{e}'");
+            log::error!(subsystem = "alerts.telegram.db"; "Failed to update user request to create/update auth status into database, with error = '{e}'");
             Err(())
         }
     }
@@ -59,7 +75,7 @@ pub async fn get_user_name_from_peer_id<'e>(user: UserPeerId, transaction: &mut
             Some((id.ack_actor_name, id.can_ack))
         },
         Err(e) => {
-            log::error!("[ERROR][TELEGRAM][DB] Failed to query user telegram association from db with SQL error = {e}");
+            log::error!(subsystem = "telegram.db"; "Failed to query user telegram association from db with SQL error = {e}");
             None
         }
     }
@@ -84,7 +100,7 @@ pub async fn can_match_user_with_token(user: UserPeerId, ack_token: &str, pool:
             } 
         },
         Err(e) => {
-            log::error!("[ERROR][TELEGRAM][DB] Failed to query user token association from db with SQL error = {e}");
+            log::error!(subsystem = "telegram.db"; "Failed to query user token association from db with SQL error = {e}");
             Err(())
         }
     }
@@ -102,7 +118,7 @@ pub async fn match_user_with_token<'e>(user: UserPeerId, ack_token: &str, transa
     match result {
         Ok(_) => Ok(()),
         Err(e) => {
-            log::error!("[ERROR][ALERTS][TELEGRAM][DB] Failed to update user request to create/update auth status into database, with error = '{e}'");
+            log::error!(subsystem = "alerts.telegram.db"; "Failed to update user request to create/update auth status into database, with error = '{e}'");
             Err(())
         }
     }
@@ -117,7 +133,7 @@ pub async fn subscribe_chat<'e>(chat: ChatPeerId, subscribed: bool, transaction:
     match result {
         Ok(_) => Ok(()),
         Err(e) => {
-            log::error!("[ERROR][ALERTS][TELEGRAM][DB] Failed to update user request to create/update auth status into database, with error = '{e}'");
+            log::error!(subsystem = "alerts.telegram.db"; "Failed to update user request to create/update auth status into database, with error = '{e}'");
             Err(())
         }
     }
@@ -131,7 +147,7 @@ pub async fn ack_token_exists(ack_token: String, pool: &sqlx::PgPool) -> Result<
     match result {
         Ok(_) => Ok(()),
         Err(e) => {
-            log::error!("[ERROR][ALERTS][TELEGRAM][DB] Failed to update user request to create/update auth status into database, with error = '{e}'");
+            log::error!(subsystem = "alerts.telegram.db"; "Failed to update user request to create/update auth status into database, with error = '{e}'");
             Err(())
         }
     }
@@ -153,7 +169,7 @@ pub async fn is_auth(chat: ChatPeerId, pool: &sqlx::PgPool) -> Result<bool, ()>
     match result {
         Ok(result) => Ok(result.authenticated.unwrap_or(false)),
         Err(e) => {
-            log::error!("[ERROR][ALERTS][TELEGRAM][DB] Failed to request auth state for user from database, with error = '{e}'");
+            log::error!(subsystem = "alerts.telegram.db"; "Failed to request auth state for user from database, with error = '{e}'");
             Err(())
         }
     }
@@ -171,7 +187,7 @@ pub async fn is_subscribed(chat: ChatPeerId, pool: &sqlx::PgPool) -> Result<bool
     match result {
         Ok(result) => Ok(result.authenticated.unwrap_or(false)),
         Err(e) => {
-            log::error!("[ERROR][ALERTS][TELEGRAM][DB] Failed to request subscription state for user from database, with error = '{e}'");
+            log::error!(subsystem = "alerts.telegram.db"; "Failed to request subscription state for user from database, with error = '{e}'");
             Err(())
         }
     }
@@ -186,7 +202,7 @@ pub async fn insert_unacked_message<'e>(alert_id: AlertEventId, chat: ChatPeerId
     match result {
         Ok(_) => Ok(()),
         Err(e) => {
-            log::error!("[ERROR][ALERTS][TELEGRAM][DB] Failed to insert unacked message into pending to update messages, with error = '{e}'");
+            log::error!(subsystem = "alerts.telegram.db"; "Failed to insert unacked message into pending to update messages, with error = '{e}'");
             Err(AegisError::Sql(e))
         },
     }
@@ -203,7 +219,7 @@ pub async fn get_unacked_messages<'e>(alert_id: AlertEventId, transaction: &mut
     let result: Vec<M> = match result {
         Ok(v) => v,
         Err(e) => {
-            log::error!("[ERROR][ALERTS][TELEGRAM][DB] Failed to insert unacked message into pending to update messages, with error = '{e}'");
+            log::error!(subsystem = "alerts.telegram.db"; "Failed to insert unacked message into pending to update messages, with error = '{e}'");
             return Err(AegisError::Sql(e))
         },
     };
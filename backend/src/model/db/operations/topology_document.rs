@@ -0,0 +1,1232 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Deserialize, Serialize};
+use sqlx::{PgConnection, Pool, Postgres, Transaction};
+
+use crate::model::data::device_configuration::DeviceConfiguration;
+use crate::model::data::group::GroupFilter;
+use crate::model::data::link_type::LinkType;
+use crate::model::db::fetch_topology::{query_devices, query_groups, query_links, query_playbooks};
+use crate::model::db::operations::audit_operations::{self, AuditSource};
+use crate::model::db::operations::commit_changes::{apply_changes, refresh_after_commit};
+use crate::types::{AlertRouteId, AlertRuleId, DeviceHostname, ItemId, OnCallScheduleId, PlaybookId};
+
+type E = (String, i16);
+
+/// Version of the document produced by exports. Imports reject any other version
+pub const DOCUMENT_VERSION: u32 = 1;
+
+/// Reference to a topology item by its natural key, so documents can be moved across databases.
+/// Links are identified by the hostnames of both ends, as only one link may exist between two devices
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ItemRef {
+    Device(DeviceHostname),
+    Group(String),
+    Link([DeviceHostname; 2]),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlaybookEntry {
+    pub name: String,
+
+    #[serde(rename = "is-enabled")]
+    pub is_enabled: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeviceEntry {
+    pub name: String,
+
+    #[serde(rename = "management-hostname")]
+    pub management_hostname: DeviceHostname,
+
+    pub latitude: f64,
+
+    pub longitude: f64,
+
+    pub configuration: DeviceConfiguration,
+
+    /// Playbook names
+    #[serde(default)]
+    pub playbooks: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LinkEntry {
+    #[serde(rename = "side-a")]
+    pub side_a: DeviceHostname,
+
+    #[serde(rename = "side-a-iface")]
+    pub side_a_iface: String,
+
+    #[serde(rename = "side-b")]
+    pub side_b: DeviceHostname,
+
+    #[serde(rename = "side-b-iface")]
+    pub side_b_iface: String,
+
+    #[serde(rename = "link-type")]
+    pub link_type: LinkType,
+
+    #[serde(rename = "link-subtype", default)]
+    pub link_subtype: Option<String>,
+}
+
+impl LinkEntry {
+    /// Swaps the sides if needed, so side A is always the lowest hostname. Links are not directional
+    fn normalized(mut self) -> Self {
+        if self.side_a > self.side_b {
+            std::mem::swap(&mut self.side_a, &mut self.side_b);
+            std::mem::swap(&mut self.side_a_iface, &mut self.side_b_iface);
+        }
+        self
+    }
+
+    fn key(&self) -> [DeviceHostname; 2] {
+        [self.side_a.clone(), self.side_b.clone()]
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GroupEntry {
+    pub name: String,
+
+    #[serde(rename = "is-display-group")]
+    pub is_display_group: bool,
+
+    /// Ignored for dynamic groups
+    #[serde(default)]
+    pub members: Vec<ItemRef>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filter: Option<GroupFilter>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ViewMember {
+    pub item: ItemRef,
+    pub x: f64,
+    pub y: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ViewEntry {
+    pub name: String,
+
+    #[serde(default)]
+    pub members: Vec<ViewMember>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WidgetEntry {
+    #[serde(rename = "row-start")]
+    pub row_start: i16,
+
+    #[serde(rename = "row-span")]
+    pub row_span: i16,
+
+    #[serde(rename = "col-start")]
+    pub col_start: i16,
+
+    #[serde(rename = "col-span")]
+    pub col_span: i16,
+
+    #[serde(rename = "style-definition", default)]
+    pub style_definition: serde_json::Value,
+
+    /// Polled devices or groups, under `device-ids`, are references instead of ids
+    #[serde(rename = "polling-definition")]
+    pub polling_definition: serde_json::Value,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DashboardEntry {
+    pub name: String,
+
+    #[serde(default)]
+    pub widgets: Vec<WidgetEntry>,
+}
+
+/// An alert rule definition, with its target as a reference instead of an id
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RuleEntry {
+    pub name: String,
+
+    #[serde(rename = "requires-ack", default)]
+    pub requires_ack: bool,
+
+    /// None when the rule targets an item that no longer exists. Such rules are exported, but can't be imported
+    #[serde(default)]
+    pub target: Option<ItemRef>,
+
+    /// Rest of the rule definition, as accepted by `/api/configure`
+    #[serde(flatten)]
+    pub definition: serde_json::Map<String, serde_json::Value>,
+}
+
+/// An alert route definition. The rules and targets it matches are referenced by name instead of by id
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RouteEntry {
+    pub name: String,
+
+    /// Rest of the route definition, as accepted by `/api/configure`
+    #[serde(flatten)]
+    pub definition: serde_json::Map<String, serde_json::Value>,
+}
+
+/// An on-call schedule definition. Routes page it by name
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScheduleEntry {
+    pub name: String,
+
+    /// Rest of the schedule definition, as accepted by `/api/configure`
+    #[serde(flatten)]
+    pub definition: serde_json::Map<String, serde_json::Value>,
+}
+
+/// Database id of a device, link or group, as of the snapshot a document was taken from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ItemIdEntry {
+    pub item: ItemRef,
+    pub id: ItemId,
+}
+
+/// The whole configuration of the backend, as a single document
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TopologyDocument {
+    pub version: u32,
+
+    #[serde(default)]
+    pub playbooks: Vec<PlaybookEntry>,
+
+    #[serde(default)]
+    pub devices: Vec<DeviceEntry>,
+
+    #[serde(default)]
+    pub links: Vec<LinkEntry>,
+
+    #[serde(default)]
+    pub groups: Vec<GroupEntry>,
+
+    #[serde(rename = "topology-views", default)]
+    pub topology_views: Vec<ViewEntry>,
+
+    #[serde(default)]
+    pub dashboards: Vec<DashboardEntry>,
+
+    #[serde(default)]
+    pub rules: Vec<RuleEntry>,
+
+    #[serde(default)]
+    pub routes: Vec<RouteEntry>,
+
+    #[serde(rename = "oncall-schedules", default)]
+    pub schedules: Vec<ScheduleEntry>,
+
+    /// Only kept in audit snapshots, so a rollback brings items back under the ids alerts, metrics and
+    /// dashboards know them by. Left out of exports, and ignored by imports
+    #[serde(rename = "item-ids", default, skip_serializing_if = "Vec::is_empty")]
+    pub item_ids: Vec<ItemIdEntry>,
+}
+
+/// Changes an import would make to a single kind of entity, by natural key
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct EntityDiff {
+    pub create: Vec<String>,
+    pub update: Vec<String>,
+    pub delete: Vec<String>,
+    pub unchanged: usize,
+}
+
+impl EntityDiff {
+    fn is_empty(&self) -> bool {
+        self.create.is_empty() && self.update.is_empty() && self.delete.is_empty()
+    }
+}
+
+/// Entity kind -> changes
+pub type DocumentDiff = BTreeMap<&'static str, EntityDiff>;
+
+fn diff_entries<T: PartialEq>(current: &[T], incoming: &[T], key: impl Fn(&T) -> String, prune: bool) -> Result<EntityDiff, String> {
+    let current: HashMap<String, &T> = current.iter().map(|e| (key(e), e)).collect();
+
+    let mut diff = EntityDiff::default();
+    let mut seen = std::collections::HashSet::new();
+    for entry in incoming {
+        let k = key(entry);
+        if !seen.insert(k.clone()) {
+            return Err(format!("'{k}' is present more than once"));
+        }
+
+        match current.get(&k) {
+            None => diff.create.push(k),
+            Some(existing) if *existing != entry => diff.update.push(k),
+            Some(_) => diff.unchanged += 1,
+        }
+    }
+
+    if prune {
+        diff.delete = current.keys().filter(|k| !seen.contains(*k)).cloned().collect();
+        diff.delete.sort();
+    }
+
+    Ok(diff)
+}
+
+/// Compares a document against the current state. Deletions are only reported if `prune` is set
+pub fn diff(current: &TopologyDocument, incoming: &TopologyDocument, prune: bool) -> Result<DocumentDiff, E> {
+    fn named(kind: &'static str, r: Result<EntityDiff, String>) -> Result<(&'static str, EntityDiff), E> {
+        r.map(|d| (kind, d)).map_err(|e| (format!("Invalid document, {kind}: {e}"), 400))
+    }
+
+    let links: Vec<LinkEntry> = incoming.links.iter().cloned().map(LinkEntry::normalized).collect();
+
+    Ok(DocumentDiff::from([
+        named("playbooks", diff_entries(&current.playbooks, &incoming.playbooks, |p| p.name.clone(), prune))?,
+        named("devices", diff_entries(&current.devices, &incoming.devices, |d| d.management_hostname.clone(), prune))?,
+        named("links", diff_entries(&current.links, &links, |l| l.key().join(" <-> "), prune))?,
+        named("groups", diff_entries(&current.groups, &incoming.groups, |g| g.name.clone(), prune))?,
+        named("topology-views", diff_entries(&current.topology_views, &incoming.topology_views, |v| v.name.clone(), prune))?,
+        named("dashboards", diff_entries(&current.dashboards, &incoming.dashboards, |d| d.name.clone(), prune))?,
+        named("rules", diff_entries(&current.rules, &incoming.rules, |r| r.name.clone(), prune))?,
+        named("routes", diff_entries(&current.routes, &incoming.routes, |r| r.name.clone(), prune))?,
+        named("oncall-schedules", diff_entries(&current.schedules, &incoming.schedules, |s| s.name.clone(), prune))?,
+    ]))
+}
+
+fn entry_changes<T: PartialEq + Serialize>(before: &[T], after: &[T], key: impl Fn(&T) -> String) -> Vec<serde_json::Value> {
+    let before: BTreeMap<String, &T> = before.iter().map(|e| (key(e), e)).collect();
+    let after: BTreeMap<String, &T> = after.iter().map(|e| (key(e), e)).collect();
+
+    let mut keys: Vec<&String> = before.keys().chain(after.keys()).collect();
+    keys.sort();
+    keys.dedup();
+
+    keys.into_iter()
+        .filter_map(|k| match (before.get(k), after.get(k)) {
+            (Some(b), Some(a)) if b == a => None,
+            (b, a) => Some(serde_json::json!({"key": k, "before": b, "after": a})),
+        })
+        .collect()
+}
+
+/// Every entry that differs between two documents, with its value before and after, per entity kind.
+/// Kinds without changes are left out
+pub fn changes(before: &TopologyDocument, after: &TopologyDocument) -> BTreeMap<&'static str, Vec<serde_json::Value>> {
+    let all = [
+        ("playbooks", entry_changes(&before.playbooks, &after.playbooks, |p| p.name.clone())),
+        ("devices", entry_changes(&before.devices, &after.devices, |d| d.management_hostname.clone())),
+        ("links", entry_changes(&before.links, &after.links, |l| l.key().join(" <-> "))),
+        ("groups", entry_changes(&before.groups, &after.groups, |g| g.name.clone())),
+        ("topology-views", entry_changes(&before.topology_views, &after.topology_views, |v| v.name.clone())),
+        ("dashboards", entry_changes(&before.dashboards, &after.dashboards, |d| d.name.clone())),
+        ("rules", entry_changes(&before.rules, &after.rules, |r| r.name.clone())),
+        ("routes", entry_changes(&before.routes, &after.routes, |r| r.name.clone())),
+        ("oncall-schedules", entry_changes(&before.schedules, &after.schedules, |s| s.name.clone())),
+    ];
+
+    all.into_iter().filter(|(_, c)| !c.is_empty()).collect()
+}
+
+/// Ids of every item in the database, by natural key
+#[derive(Default)]
+struct ItemIds {
+    devices: HashMap<DeviceHostname, ItemId>,
+    groups: HashMap<String, ItemId>,
+    links: HashMap<[DeviceHostname; 2], ItemId>,
+}
+
+impl ItemIds {
+    async fn load(conn: &mut sqlx::PgConnection) -> Result<Self, sqlx::Error> {
+        let devices = sqlx::query!("SELECT device_id, management_hostname FROM Analytics.devices;")
+            .fetch_all(&mut *conn).await?;
+        let groups = sqlx::query!("SELECT group_id, group_name FROM Analytics.groups;")
+            .fetch_all(&mut *conn).await?;
+        let links = sqlx::query!("
+            SELECT l.link_id, a.management_hostname as side_a, b.management_hostname as side_b
+            FROM Analytics.links l
+            JOIN Analytics.devices a ON a.device_id = l.side_a
+            JOIN Analytics.devices b ON b.device_id = l.side_b;")
+            .fetch_all(&mut *conn).await?;
+
+        let mut links_by_key = HashMap::new();
+        for link in links {
+            let (a, b) = (link.side_a, link.side_b);
+            let key = if a <= b { [a, b] } else { [b, a] };
+            links_by_key.insert(key, link.link_id);
+        }
+
+        Ok(Self {
+            devices: devices.into_iter().map(|d| (d.management_hostname, d.device_id)).collect(),
+            groups: groups.into_iter().map(|g| (g.group_name, g.group_id)).collect(),
+            links: links_by_key,
+        })
+    }
+
+    fn resolve(&self, item: &ItemRef) -> Option<ItemId> {
+        match item {
+            ItemRef::Device(hostname) => self.devices.get(hostname).copied(),
+            ItemRef::Group(name) => self.groups.get(name).copied(),
+            ItemRef::Link([a, b]) => {
+                let key = if a <= b { [a.clone(), b.clone()] } else { [b.clone(), a.clone()] };
+                self.links.get(&key).copied()
+            },
+        }
+    }
+
+    fn reference(&self, id: ItemId) -> Option<ItemRef> {
+        if let Some((h, _)) = self.devices.iter().find(|(_, i)| **i == id) {
+            return Some(ItemRef::Device(h.clone()));
+        }
+        if let Some((n, _)) = self.groups.iter().find(|(_, i)| **i == id) {
+            return Some(ItemRef::Group(n.clone()));
+        }
+        self.links.iter().find(|(_, i)| **i == id).map(|(k, _)| ItemRef::Link(k.clone()))
+    }
+
+    fn require(&self, item: &ItemRef, context: &str) -> Result<ItemId, E> {
+        self.resolve(item).ok_or((format!("{context} references {item:?}, which does not exist"), 400))
+    }
+
+    fn entries(&self) -> Vec<ItemIdEntry> {
+        let devices = self.devices.iter().map(|(h, id)| ItemIdEntry { item: ItemRef::Device(h.clone()), id: *id });
+        let groups = self.groups.iter().map(|(n, id)| ItemIdEntry { item: ItemRef::Group(n.clone()), id: *id });
+        let links = self.links.iter().map(|(k, id)| ItemIdEntry { item: ItemRef::Link(k.clone()), id: *id });
+        let mut entries: Vec<ItemIdEntry> = devices.chain(groups).chain(links).collect();
+        entries.sort_by_key(|e| e.id);
+        entries
+    }
+
+    fn from_entries(entries: &[ItemIdEntry]) -> Self {
+        let mut ids = Self::default();
+        for entry in entries {
+            match &entry.item {
+                ItemRef::Device(hostname) => ids.devices.insert(hostname.clone(), entry.id),
+                ItemRef::Group(name) => ids.groups.insert(name.clone(), entry.id),
+                ItemRef::Link([a, b]) => ids.links.insert(if a <= b { [a.clone(), b.clone()] } else { [b.clone(), a.clone()] }, entry.id),
+            };
+        }
+        ids
+    }
+}
+
+/// Builds the document that represents the current state of the database
+pub async fn export(pool: &Pool<Postgres>) -> Result<TopologyDocument, E> {
+    let mut conn = pool.acquire().await
+        .map_err(|e| (format!("Could not export topology. SQL Error = '{e}'"), 500))?;
+    let mut document = snapshot(&mut conn).await?;
+    // Ids mean nothing to another database
+    document.item_ids.clear();
+    Ok(document)
+}
+
+/// Same as `export`, over the given connection. Within a transaction, it includes the changes made so far
+pub async fn snapshot(conn: &mut PgConnection) -> Result<TopologyDocument, E> {
+    let sql_err = |e: sqlx::Error| (format!("Could not export topology. SQL Error = '{e}'"), 500);
+    let fetch_err = |e: crate::AegisError| (format!("Could not export topology, error = '{e:?}'"), 500);
+
+    let ids = ItemIds::load(conn).await.map_err(sql_err)?;
+    let playbooks = query_playbooks(conn).await.map_err(fetch_err)?;
+    let devices = query_devices(conn).await.map_err(fetch_err)?;
+    let links = query_links(conn).await.map_err(fetch_err)?;
+    let groups = query_groups(conn).await.map_err(fetch_err)?;
+
+    let hostname = |id| devices.get(&id).map(|d| d.management_hostname.clone()).unwrap_or_default();
+    // Dangling references are not worth failing the export for, they're dropped with a warning
+    let reference = |id: ItemId, context: &str| {
+        let item = ids.reference(id);
+        if item.is_none() {
+            log::warn!(subsystem = "api.export"; "{context} references item with id = {id}, which does not exist");
+        }
+        item
+    };
+
+    let mut document = TopologyDocument {
+        version: DOCUMENT_VERSION,
+        playbooks: playbooks.values()
+            .map(|p| PlaybookEntry { name: p.playbook_name.clone(), is_enabled: p.is_enabled })
+            .collect(),
+        devices: devices.values()
+            .map(|d| DeviceEntry {
+                name: d.device_name.clone(),
+                management_hostname: d.management_hostname.clone(),
+                latitude: d.latitude,
+                longitude: d.longitude,
+                configuration: d.configuration.clone(),
+                playbooks: d.playbooks.iter().filter_map(|p| playbooks.get(p)).map(|p| p.playbook_name.clone()).collect(),
+            })
+            .collect(),
+        links: links.values()
+            .map(|l| LinkEntry {
+                side_a: hostname(l.side_a),
+                side_a_iface: l.side_a_iface.clone(),
+                side_b: hostname(l.side_b),
+                side_b_iface: l.side_b_iface.clone(),
+                link_type: l.link_type.clone(),
+                link_subtype: l.link_subtype.clone(),
+            }.normalized())
+            .collect(),
+        groups: Vec::with_capacity(groups.len()),
+        topology_views: Vec::new(),
+        dashboards: Vec::new(),
+        rules: Vec::new(),
+        routes: Vec::new(),
+        schedules: Vec::new(),
+        item_ids: ids.entries(),
+    };
+
+    for group in groups.values() {
+        let members = match group.filter {
+            Some(_) => Vec::new(),
+            None => group.members.iter().flatten().filter_map(|m| reference(*m, &format!("Group '{}'", group.name))).collect(),
+        };
+        document.groups.push(GroupEntry { name: group.name.clone(), is_display_group: group.is_display_group, members, filter: group.filter.clone() });
+    }
+
+    let views = sqlx::query!("SELECT topology_views_id, name FROM Analytics.topology_views;")
+        .fetch_all(&mut *conn).await.map_err(sql_err)?;
+    for view in views {
+        let members = sqlx::query!(r#"
+            SELECT item_id, COALESCE(position_x, 0.0) as "x!", COALESCE(position_y, 0.0) as "y!"
+            FROM Analytics.topology_views_member WHERE topology_views_id = $1;"#,
+            view.topology_views_id
+        ).fetch_all(&mut *conn).await.map_err(sql_err)?;
+
+        let context = format!("Topology view '{}'", view.name);
+        let members = members.into_iter()
+            .filter_map(|m| reference(m.item_id, &context).map(|item| ViewMember { item, x: m.x, y: m.y }))
+            .collect();
+        document.topology_views.push(ViewEntry { name: view.name, members });
+    }
+
+    let dashboards = sqlx::query!("SELECT dashboard_id, dashboard_name FROM Analytics.dashboard;")
+        .fetch_all(&mut *conn).await.map_err(sql_err)?;
+    for dashboard in dashboards {
+        let mut widgets = sqlx::query_as!(WidgetEntry, "
+            SELECT row_start, row_span, col_start, col_span, style_definition, polling_definition
+            FROM Analytics.dashboard_items WHERE dashboard_id = $1
+            ORDER BY row_start, col_start;",
+            dashboard.dashboard_id as i16
+        ).fetch_all(&mut *conn).await.map_err(sql_err)?;
+
+        let context = format!("Dashboard '{}'", dashboard.dashboard_name);
+        for widget in &mut widgets {
+            export_polling(&mut widget.polling_definition, &context, |id, context| reference(id, context));
+        }
+        document.dashboards.push(DashboardEntry { name: dashboard.dashboard_name, widgets });
+    }
+
+    let rules = sqlx::query!("SELECT rule_id, rule_name, requires_ack, rule_definition FROM Analytics.alert_rules;")
+        .fetch_all(&mut *conn).await.map_err(sql_err)?;
+    let rule_names: HashMap<AlertRuleId, String> = rules.iter().map(|r| (r.rule_id, r.rule_name.clone())).collect();
+    for rule in rules {
+        let (name, requires_ack) = (rule.rule_name, rule.requires_ack);
+        let mut definition = match rule.rule_definition {
+            serde_json::Value::Object(o) => o,
+            _ => {
+                log::warn!(subsystem = "api.export"; "Rule '{name}' has an invalid definition, skipping it");
+                continue;
+            }
+        };
+        for key in ["id", "name", "requires-ack", "rule-definition"] {
+            definition.remove(key);
+        }
+
+        let target = match definition.remove("target").and_then(|t| t.as_i64()) {
+            Some(t) => reference(t, &format!("Rule '{name}'")),
+            None => {
+                log::warn!(subsystem = "api.export"; "Rule '{name}' has no valid target, skipping it");
+                continue;
+            }
+        };
+        document.rules.push(RuleEntry { name, requires_ack, target, definition });
+    }
+
+    let routes = sqlx::query!("SELECT route_name, route_definition FROM Analytics.alert_routes;")
+        .fetch_all(&mut *conn).await.map_err(sql_err)?;
+    for route in routes {
+        let name = route.route_name;
+        let mut definition = match route.route_definition {
+            serde_json::Value::Object(o) => o,
+            _ => {
+                log::warn!(subsystem = "api.export"; "Route '{name}' has an invalid definition, skipping it");
+                continue;
+            }
+        };
+        definition.remove("id");
+        definition.remove("name");
+
+        export_matcher(&mut definition, &format!("Route '{name}'"), &rule_names, |id, context| reference(id, context));
+        document.routes.push(RouteEntry { name, definition });
+    }
+
+    let schedules = sqlx::query!("SELECT schedule_name, schedule_definition FROM Analytics.oncall_schedules;")
+        .fetch_all(&mut *conn).await.map_err(sql_err)?;
+    for schedule in schedules {
+        let name = schedule.schedule_name;
+        let mut definition = match schedule.schedule_definition {
+            serde_json::Value::Object(o) => o,
+            _ => {
+                log::warn!(subsystem = "api.export"; "On-call schedule '{name}' has an invalid definition, skipping it");
+                continue;
+            }
+        };
+        definition.remove("id");
+        definition.remove("name");
+        document.schedules.push(ScheduleEntry { name, definition });
+    }
+
+    // Stable order, so exports can be kept under version control
+    document.playbooks.sort_by(|a, b| a.name.cmp(&b.name));
+    document.devices.sort_by(|a, b| a.management_hostname.cmp(&b.management_hostname));
+    document.links.sort_by_key(|l| l.key());
+    document.groups.sort_by(|a, b| a.name.cmp(&b.name));
+    document.topology_views.sort_by(|a, b| a.name.cmp(&b.name));
+    document.dashboards.sort_by(|a, b| a.name.cmp(&b.name));
+    document.rules.sort_by(|a, b| a.name.cmp(&b.name));
+    document.routes.sort_by(|a, b| a.name.cmp(&b.name));
+    document.schedules.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(document)
+}
+
+/// Replaces the ids of the rules and targets a route matches with their names, dropping those that don't exist.
+/// Conditions that are absent are left absent, as they match anything
+fn export_matcher(
+    definition: &mut serde_json::Map<String, serde_json::Value>, context: &str,
+    rule_names: &HashMap<AlertRuleId, String>, reference: impl Fn(ItemId, &str) -> Option<ItemRef>,
+) {
+    let Some(matcher) = definition.get_mut("match").and_then(|m| m.as_object_mut()) else { return };
+
+    if let Some(serde_json::Value::Array(rules)) = matcher.get_mut("rules") {
+        let mut names: Vec<&String> = rules.iter().filter_map(|id| id.as_i64())
+            .filter_map(|id| {
+                let name = rule_names.get(&id);
+                if name.is_none() {
+                    log::warn!(subsystem = "api.export"; "{context} references rule with id = {id}, which does not exist");
+                }
+                name
+            })
+            .collect();
+        names.sort();
+        *rules = names.into_iter().map(|n| serde_json::json!(n)).collect();
+    }
+
+    if let Some(serde_json::Value::Array(targets)) = matcher.get_mut("targets") {
+        let mut items: Vec<ItemRef> = targets.iter().filter_map(|id| id.as_i64()).filter_map(|id| reference(id, context)).collect();
+        items.sort();
+        *targets = items.into_iter().map(|i| serde_json::json!(i)).collect();
+    }
+}
+
+/// Reverse of `export_matcher`. Fails if the route matches a rule or target that doesn't exist
+fn import_matcher(
+    definition: &mut serde_json::Map<String, serde_json::Value>, context: &str,
+    rule_ids: &HashMap<String, AlertRuleId>, ids: &ItemIds,
+) -> Result<(), E> {
+    let Some(matcher) = definition.get_mut("match").and_then(|m| m.as_object_mut()) else { return Ok(()) };
+
+    if let Some(serde_json::Value::Array(rules)) = matcher.get_mut("rules") {
+        for rule in rules.iter_mut() {
+            let name = rule.as_str().ok_or((format!("{context} matches rule {rule}, expected a rule name"), 400))?;
+            let id = rule_ids.get(name).ok_or((format!("{context} matches rule '{name}', which does not exist"), 400))?;
+            *rule = serde_json::json!(id);
+        }
+    }
+
+    if let Some(serde_json::Value::Array(targets)) = matcher.get_mut("targets") {
+        for target in targets.iter_mut() {
+            let item: ItemRef = serde_json::from_value(target.clone())
+                .map_err(|e| (format!("{context} matches target {target}, which is not a valid reference. Error = '{e}'"), 400))?;
+            *target = serde_json::json!(ids.require(&item, context)?);
+        }
+    }
+
+    Ok(())
+}
+
+/// Replaces the ids of the devices or groups a dashboard widget polls with references.
+/// Ids that don't exist are dropped, along with `device-ids` itself if it held a single one
+fn export_polling(definition: &mut serde_json::Value, context: &str, reference: impl Fn(ItemId, &str) -> Option<ItemRef>) {
+    let Some(definition) = definition.as_object_mut() else { return };
+    let export = |id: &serde_json::Value| id.as_i64().and_then(|id| reference(id, context)).map(|item| serde_json::json!(item));
+
+    let exported = match definition.get("device-ids") {
+        None => return,
+        Some(serde_json::Value::Array(ids)) => Some(serde_json::Value::Array(ids.iter().filter_map(export).collect())),
+        Some(id) => export(id),
+    };
+    match exported {
+        Some(ids) => definition.insert("device-ids".to_string(), ids),
+        None => definition.remove("device-ids"),
+    };
+}
+
+/// Reverse of `export_polling`. Fails if the widget polls a device or group that doesn't exist
+fn import_polling(definition: &mut serde_json::Value, context: &str, ids: &ItemIds) -> Result<(), E> {
+    let Some(polled) = definition.get_mut("device-ids") else { return Ok(()) };
+    let import = |item: &serde_json::Value| {
+        let item: ItemRef = serde_json::from_value(item.clone())
+            .map_err(|e| (format!("{context} polls {item}, which is not a valid reference. Error = '{e}'"), 400))?;
+        Ok::<_, E>(serde_json::json!(ids.require(&item, context)?))
+    };
+
+    *polled = match &*polled {
+        serde_json::Value::Array(items) => serde_json::Value::Array(items.iter().map(import).collect::<Result<_, _>>()?),
+        item => import(item)?,
+    };
+    Ok(())
+}
+
+/// Serializes a document as YAML, with the same shape as its JSON form
+pub fn to_yaml(document: &TopologyDocument) -> Result<String, String> {
+    let value = serde_json::to_value(document).map_err(|e| e.to_string())?;
+    serde_yaml::to_string(&value).map_err(|e| e.to_string())
+}
+
+/// Parses a document, in either YAML or JSON (as JSON is valid YAML)
+pub fn parse(content: &str) -> Result<TopologyDocument, E> {
+    // Through a JSON value, so enums are read as maps in YAML too, rather than as YAML tags
+    let value: serde_json::Value = serde_yaml::from_str(content)
+        .map_err(|e| (format!("Could not parse topology document, error = '{e}'"), 400))?;
+    let mut document: TopologyDocument = serde_json::from_value(value)
+        .map_err(|e| (format!("Invalid topology document, error = '{e}'"), 400))?;
+    document.links = document.links.into_iter().map(LinkEntry::normalized).collect();
+
+    if document.version != DOCUMENT_VERSION {
+        return Err((format!("Unsupported topology document version {}, expected {DOCUMENT_VERSION}", document.version), 400));
+    }
+
+    Ok(document)
+}
+
+/// Compares the document against the database, and applies it unless `dry_run` is set.
+/// Items are matched by natural key, those present are created or updated. Items missing
+/// from the document are only deleted if `prune` is set.
+/// Everything is applied in a single transaction, through the same path as `/api/configure` commits
+pub async fn import(pool: &Pool<Postgres>, mut document: TopologyDocument, dry_run: bool, prune: bool, actor: &str) -> Result<DocumentDiff, E> {
+    // Imports go by natural key only, ids are those of whichever database the document came from
+    document.item_ids.clear();
+
+    let mut transaction = pool.begin().await
+        .map_err(|e| (format!("Could not begin import transaction. Err = '{e}'"), 500))?;
+
+    let audit = audit_operations::begin(&mut transaction).await?;
+    let diff = diff(&audit.before, &document, prune)?;
+
+    if dry_run || diff.values().all(EntityDiff::is_empty) {
+        return Ok(diff);
+    }
+
+    apply_document(&mut transaction, &document, &diff).await?;
+    audit.record(&mut transaction, actor, AuditSource::Import, None).await?;
+
+    transaction.commit().await.map_err(|e| (format!("Could not commit import transaction, error = '{e}'"), 500))?;
+
+    log::info!(subsystem = "api.import"; "Imported topology document: {}", serde_json::json!(diff));
+    refresh_after_commit(pool).await?;
+
+    Ok(diff)
+}
+
+/// Applies the `diff` of a document within the transaction, without commiting it.
+/// Neither records the change nor refreshes the Cache, that's up to the caller.
+/// Documents with item ids, such as audit snapshots, first bring their items back under those ids
+pub async fn apply_document<'t>(transaction: &mut Transaction<'t, Postgres>, document: &TopologyDocument, diff: &DocumentDiff) -> Result<(), E> {
+    let sql_err = |e: sqlx::Error| (format!("Could not import topology. SQL Error = '{e}'"), 500);
+
+    if !document.item_ids.is_empty() {
+        restore_item_ids(transaction, document).await?;
+    }
+
+    // Playbooks, by name
+    let existing = sqlx::query!("SELECT playbook_id, playbook_name FROM Analytics.playbooks;")
+        .fetch_all(&mut **transaction).await.map_err(sql_err)?;
+    let mut playbooks: HashMap<String, PlaybookId> = existing.into_iter().map(|p| (p.playbook_name, p.playbook_id)).collect();
+    for playbook in &document.playbooks {
+        match playbooks.get(&playbook.name) {
+            Some(id) => {
+                sqlx::query!("UPDATE Analytics.playbooks SET is_enabled = $1 WHERE playbook_id = $2;", playbook.is_enabled, id)
+                    .execute(&mut **transaction).await.map_err(sql_err)?;
+            },
+            None => {
+                let id = sqlx::query_scalar!("INSERT INTO Analytics.playbooks (playbook_name, is_enabled) VALUES ($1, $2) RETURNING playbook_id;",
+                    playbook.name, playbook.is_enabled
+                ).fetch_one(&mut **transaction).await.map_err(sql_err)?;
+                playbooks.insert(playbook.name.clone(), id);
+            },
+        }
+    }
+
+    // Devices first, everything else references them
+    let ids = ItemIds::load(transaction).await.map_err(sql_err)?;
+    let devices: Vec<serde_json::Value> = document.devices.iter()
+        .map(|d| serde_json::json!({
+            "id": ids.devices.get(&d.management_hostname).copied().unwrap_or(-1),
+            "name": d.name,
+            "latitude": d.latitude,
+            "longitude": d.longitude,
+            "management-hostname": d.management_hostname,
+            "configuration": d.configuration,
+        }))
+        .collect();
+    apply_changes(serde_json::json!({"topology-changes": {"devices": devices}}), transaction).await?;
+
+    let ids = ItemIds::load(transaction).await.map_err(sql_err)?;
+    for device in &document.devices {
+        let device_id = ids.require(&ItemRef::Device(device.management_hostname.clone()), "Device")?;
+        sqlx::query!("DELETE FROM Analytics.devices_playbooks WHERE device_id = $1;", device_id)
+            .execute(&mut **transaction).await.map_err(sql_err)?;
+
+        for name in &device.playbooks {
+            let playbook_id = playbooks.get(name)
+                .ok_or((format!("Device '{}' references playbook '{name}', which does not exist", device.management_hostname), 400))?;
+            sqlx::query!("INSERT INTO Analytics.devices_playbooks (device_id, playbook_id) VALUES ($1, $2);", device_id, playbook_id)
+                .execute(&mut **transaction).await.map_err(sql_err)?;
+        }
+    }
+
+    // Links, by the pair of devices they join
+    let mut links = Vec::with_capacity(document.links.len());
+    for link in &document.links {
+        let context = format!("Link '{} <-> {}'", link.side_a, link.side_b);
+        links.push(serde_json::json!({
+            "id": ids.resolve(&ItemRef::Link([link.side_a.clone(), link.side_b.clone()])).unwrap_or(-1),
+            "side-a": ids.require(&ItemRef::Device(link.side_a.clone()), &context)?,
+            "side-b": ids.require(&ItemRef::Device(link.side_b.clone()), &context)?,
+            "side-a-iface": link.side_a_iface,
+            "side-b-iface": link.side_b_iface,
+            "link-type": link.link_type,
+            "link-subtype": link.link_subtype,
+        }));
+    }
+
+    // Groups may contain each other, so new ones are created empty before filling any members in
+    let new_groups: Vec<serde_json::Value> = document.groups.iter()
+        .filter(|g| !ids.groups.contains_key(&g.name))
+        .map(|g| serde_json::json!({"id": -1, "name": g.name, "is-display-group": g.is_display_group, "members": []}))
+        .collect();
+    apply_changes(serde_json::json!({"topology-changes": {"links": links, "groups": new_groups}}), transaction).await?;
+
+    let ids = ItemIds::load(transaction).await.map_err(sql_err)?;
+    let mut groups = Vec::with_capacity(document.groups.len());
+    for group in &document.groups {
+        let context = format!("Group '{}'", group.name);
+        let members = match group.filter {
+            Some(_) => Vec::new(),
+            None => group.members.iter().map(|m| ids.require(m, &context)).collect::<Result<_, _>>()?,
+        };
+        groups.push(serde_json::json!({
+            "id": ids.require(&ItemRef::Group(group.name.clone()), &context)?,
+            "name": group.name,
+            "is-display-group": group.is_display_group,
+            "members": members,
+            "filter": group.filter,
+        }));
+    }
+
+    // Rules, by name. Uniqueness isn't enforced by the database, the first one found is updated
+    let rule_ids = load_rule_ids(transaction).await.map_err(sql_err)?;
+
+    let mut rules = Vec::with_capacity(document.rules.len());
+    for rule in &document.rules {
+        let mut definition = rule.definition.clone();
+        definition.insert("name".to_string(), serde_json::json!(rule.name));
+        definition.insert("requires-ack".to_string(), serde_json::json!(rule.requires_ack));
+        // A rule whose target is gone can't be written back. If it's already there, it's left as it is
+        let Some(target) = &rule.target else {
+            if !rule_ids.contains_key(&rule.name) {
+                return Err((format!("Rule '{}' has no target", rule.name), 400));
+            }
+            log::warn!(subsystem = "api.import"; "Rule '{}' has no target, leaving it unchanged", rule.name);
+            continue;
+        };
+        definition.insert("target".to_string(), serde_json::json!(ids.require(target, &format!("Rule '{}'", rule.name))?));
+
+        let mut rule_in = definition.clone();
+        rule_in.insert("id".to_string(), serde_json::json!(rule_ids.get(&rule.name).copied().unwrap_or(-1)));
+        rule_in.insert("rule-definition".to_string(), serde_json::Value::Object(definition));
+        rules.push(serde_json::Value::Object(rule_in));
+    }
+
+    // Deletions of the items managed by commits
+    let deleted = |kind: &str| diff.get(kind).map(|d| d.delete.clone()).unwrap_or_default();
+    let as_ids = |refs: Vec<Option<ItemId>>| refs.into_iter().flatten().map(|id| serde_json::json!({"id": id})).collect::<Vec<_>>();
+
+    let deleted_devices = as_ids(deleted("devices").into_iter().map(|h| ids.resolve(&ItemRef::Device(h))).collect());
+    let deleted_groups = as_ids(deleted("groups").into_iter().map(|n| ids.resolve(&ItemRef::Group(n))).collect());
+    let deleted_links = as_ids(ids.links.iter()
+        .filter(|(k, _)| deleted("links").contains(&k.join(" <-> ")))
+        .map(|(_, id)| Some(*id))
+        .collect());
+    let deleted_rules: Vec<serde_json::Value> = deleted("rules").iter()
+        .filter_map(|n| rule_ids.get(n))
+        .map(|id| serde_json::json!({"id": id}))
+        .collect();
+
+    apply_changes(serde_json::json!({
+        "topology-changes": {"groups": groups},
+        "ruleset-changes": {"rules": rules},
+        "topology-deletions": {"devices": deleted_devices, "links": deleted_links, "groups": deleted_groups},
+        "ruleset-deletions": {"rules": deleted_rules},
+    }), transaction).await?;
+
+    // Routes, by name like rules. They match rules by name, so they go once the rules are in place
+    let ids = ItemIds::load(transaction).await.map_err(sql_err)?;
+    let rule_ids = load_rule_ids(transaction).await.map_err(sql_err)?;
+    let existing = sqlx::query!("SELECT route_id, route_name FROM Analytics.alert_routes ORDER BY route_id;")
+        .fetch_all(&mut **transaction).await.map_err(sql_err)?;
+    let mut route_ids: HashMap<String, AlertRouteId> = HashMap::new();
+    for route in existing {
+        route_ids.entry(route.route_name).or_insert(route.route_id);
+    }
+
+    let mut routes = Vec::with_capacity(document.routes.len());
+    for route in &document.routes {
+        let mut definition = route.definition.clone();
+        import_matcher(&mut definition, &format!("Route '{}'", route.name), &rule_ids, &ids)?;
+        definition.insert("id".to_string(), serde_json::json!(route_ids.get(&route.name).copied().unwrap_or(-1)));
+        definition.insert("name".to_string(), serde_json::json!(route.name));
+        routes.push(serde_json::Value::Object(definition));
+    }
+    let deleted_routes: Vec<serde_json::Value> = deleted("routes").iter()
+        .filter_map(|n| route_ids.get(n))
+        .map(|id| serde_json::json!({"id": id}))
+        .collect();
+
+    // On-call schedules, by their unique name
+    let existing = sqlx::query!("SELECT schedule_id, schedule_name FROM Analytics.oncall_schedules;")
+        .fetch_all(&mut **transaction).await.map_err(sql_err)?;
+    let schedule_ids: HashMap<String, OnCallScheduleId> = existing.into_iter().map(|s| (s.schedule_name, s.schedule_id)).collect();
+
+    let schedules: Vec<serde_json::Value> = document.schedules.iter()
+        .map(|schedule| {
+            let mut definition = schedule.definition.clone();
+            definition.insert("id".to_string(), serde_json::json!(schedule_ids.get(&schedule.name).copied().unwrap_or(-1)));
+            definition.insert("name".to_string(), serde_json::json!(schedule.name));
+            serde_json::Value::Object(definition)
+        })
+        .collect();
+    let deleted_schedules: Vec<serde_json::Value> = deleted("oncall-schedules").iter()
+        .filter_map(|n| schedule_ids.get(n))
+        .map(|id| serde_json::json!({"id": id}))
+        .collect();
+
+    apply_changes(serde_json::json!({
+        "routing-changes": {"routes": routes, "schedules": schedules},
+        "routing-deletions": {"routes": deleted_routes, "schedules": deleted_schedules},
+    }), transaction).await?;
+
+    // Topology views and dashboards, which commits don't handle. Their contents are replaced whole
+    for view in &document.topology_views {
+        let existing = sqlx::query_scalar!("SELECT topology_views_id FROM Analytics.topology_views WHERE name = $1 ORDER BY topology_views_id LIMIT 1;", view.name)
+            .fetch_optional(&mut **transaction).await.map_err(sql_err)?;
+
+        let view_id = match existing {
+            Some(id) => {
+                sqlx::query!("DELETE FROM Analytics.topology_views_member WHERE topology_views_id = $1;", id)
+                    .execute(&mut **transaction).await.map_err(sql_err)?;
+                id
+            },
+            None => {
+                sqlx::query_scalar!("INSERT INTO Analytics.topology_views (name) VALUES ($1) RETURNING topology_views_id;", view.name)
+                    .fetch_one(&mut **transaction).await.map_err(sql_err)?
+            },
+        };
+
+        for member in &view.members {
+            let item_id = ids.require(&member.item, &format!("Topology view '{}'", view.name))?;
+            sqlx::query!("
+                INSERT INTO Analytics.topology_views_member (topology_views_id, item_id, position_x, position_y)
+                VALUES ($1, $2, $3, $4)
+                ON CONFLICT (topology_views_id, item_id) DO UPDATE SET position_x = $3, position_y = $4;",
+                view_id, item_id, member.x, member.y
+            ).execute(&mut **transaction).await.map_err(sql_err)?;
+        }
+    }
+
+    for dashboard in &document.dashboards {
+        let existing = sqlx::query_scalar!("SELECT dashboard_id FROM Analytics.dashboard WHERE dashboard_name = $1 ORDER BY dashboard_id LIMIT 1;", dashboard.name)
+            .fetch_optional(&mut **transaction).await.map_err(sql_err)?;
+
+        let dashboard_id = match existing {
+            Some(id) => {
+                sqlx::query!("DELETE FROM Analytics.dashboard_items WHERE dashboard_id = $1;", id as i16)
+                    .execute(&mut **transaction).await.map_err(sql_err)?;
+                id
+            },
+            None => {
+                sqlx::query_scalar!("INSERT INTO Analytics.dashboard (dashboard_name) VALUES ($1) RETURNING dashboard_id;", dashboard.name)
+                    .fetch_one(&mut **transaction).await.map_err(sql_err)?
+            },
+        };
+
+        for widget in &dashboard.widgets {
+            let mut polling_definition = widget.polling_definition.clone();
+            import_polling(&mut polling_definition, &format!("Dashboard '{}'", dashboard.name), &ids)?;
+            sqlx::query!("
+                INSERT INTO Analytics.dashboard_items
+                    (dashboard_id, row_start, row_span, col_start, col_span, style_definition, polling_definition)
+                VALUES ($1, $2, $3, $4, $5, $6, $7);",
+                dashboard_id as i16,
+                widget.row_start, widget.row_span,
+                widget.col_start, widget.col_span,
+                widget.style_definition, polling_definition
+            ).execute(&mut **transaction).await.map_err(sql_err)?;
+        }
+    }
+
+    for name in deleted("topology-views") {
+        sqlx::query!("DELETE FROM Analytics.topology_views WHERE name = $1;", name)
+            .execute(&mut **transaction).await.map_err(sql_err)?;
+    }
+    for name in deleted("dashboards") {
+        sqlx::query!("DELETE FROM Analytics.dashboard WHERE dashboard_name = $1;", name)
+            .execute(&mut **transaction).await.map_err(sql_err)?;
+    }
+    for name in deleted("playbooks") {
+        sqlx::query!("DELETE FROM Analytics.playbooks WHERE playbook_name = $1;", name)
+            .execute(&mut **transaction).await.map_err(sql_err)?;
+    }
+
+    Ok(())
+}
+
+/// What it takes to bring the devices, links and groups of a snapshot back under their ids
+#[derive(Debug, Default, PartialEq)]
+struct RestorePlan {
+    /// Items the snapshot doesn't have
+    delete: Vec<ItemIdEntry>,
+    /// Items still under their snapshotted id, but known by another natural key, such as a renamed device
+    rename: Vec<ItemIdEntry>,
+    /// Items deleted since, to insert again under their snapshotted id
+    insert: Vec<ItemIdEntry>,
+}
+
+fn restore_plan(current: &[ItemIdEntry], snapshot: &[ItemIdEntry]) -> RestorePlan {
+    let current_refs: HashMap<ItemId, &ItemRef> = current.iter().map(|e| (e.id, &e.item)).collect();
+    let snapshot_refs: HashMap<ItemId, &ItemRef> = snapshot.iter().map(|e| (e.id, &e.item)).collect();
+
+    RestorePlan {
+        delete: current.iter().filter(|e| !snapshot_refs.contains_key(&e.id)).cloned().collect(),
+        rename: snapshot.iter().filter(|e| current_refs.get(&e.id).is_some_and(|item| **item != e.item)).cloned().collect(),
+        insert: snapshot.iter().filter(|e| !current_refs.contains_key(&e.id)).cloned().collect(),
+    }
+}
+
+/// Brings the devices, links and groups of a snapshot back under their ids. Afterwards every item of the document
+/// resolves by natural key to its snapshotted id, so applying it updates them in place. Deleting and inserting them
+/// again would orphan everything that knows them by id: alerts, metrics, dashboards, rule targets and alert state.
+/// Only the rows themselves are restored here, their contents are left to the rest of `apply_document`
+async fn restore_item_ids<'t>(transaction: &mut Transaction<'t, Postgres>, document: &TopologyDocument) -> Result<(), E> {
+    let sql_err = |e: sqlx::Error| (format!("Could not restore item ids. SQL Error = '{e}'"), 500);
+    let missing = |item: &ItemRef| (format!("Snapshot has an id for {item:?}, but no such item"), 500);
+
+    let current = ItemIds::load(transaction).await.map_err(sql_err)?.entries();
+    let plan = restore_plan(&current, &document.item_ids);
+    let ids = ItemIds::from_entries(&document.item_ids);
+
+    // Deleted first, so the items brought back don't clash with those that took their place
+    for entry in &plan.delete {
+        match &entry.item {
+            ItemRef::Link(_) => sqlx::query!("DELETE FROM Analytics.links WHERE link_id = $1;", entry.id),
+            ItemRef::Group(_) => sqlx::query!("DELETE FROM Analytics.groups WHERE group_id = $1;", entry.id),
+            ItemRef::Device(_) => sqlx::query!("DELETE FROM Analytics.devices WHERE device_id = $1;", entry.id),
+        }.execute(&mut **transaction).await.map_err(sql_err)?;
+    }
+
+    // Devices before links, which reference them
+    let mut restored: Vec<(&ItemIdEntry, bool)> = plan.rename.iter().map(|e| (e, false))
+        .chain(plan.insert.iter().map(|e| (e, true)))
+        .collect();
+    restored.sort_by_key(|(e, _)| !matches!(e.item, ItemRef::Device(_)));
+
+    for (entry, insert) in restored {
+        match &entry.item {
+            ItemRef::Device(hostname) if insert => {
+                let device = document.devices.iter().find(|d| d.management_hostname == *hostname).ok_or(missing(&entry.item))?;
+                sqlx::query!("
+                    INSERT INTO Analytics.devices (device_id, device_name, latitude, longitude, management_hostname, requested_metadata, requested_metrics)
+                    VALUES ($1, $2, $3, $4, $5, '[]', '[]');",
+                    entry.id, device.name, device.latitude, device.longitude, hostname
+                ).execute(&mut **transaction).await.map_err(sql_err)?;
+            },
+            ItemRef::Device(hostname) => {
+                sqlx::query!("UPDATE Analytics.devices SET management_hostname = $1 WHERE device_id = $2;", hostname, entry.id)
+                    .execute(&mut **transaction).await.map_err(sql_err)?;
+            },
+            ItemRef::Group(name) if insert => {
+                let group = document.groups.iter().find(|g| g.name == *name).ok_or(missing(&entry.item))?;
+                sqlx::query!("INSERT INTO Analytics.groups (group_id, group_name, is_display_group) VALUES ($1, $2, $3);",
+                    entry.id, name, group.is_display_group
+                ).execute(&mut **transaction).await.map_err(sql_err)?;
+            },
+            ItemRef::Group(name) => {
+                sqlx::query!("UPDATE Analytics.groups SET group_name = $1 WHERE group_id = $2;", name, entry.id)
+                    .execute(&mut **transaction).await.map_err(sql_err)?;
+            },
+            ItemRef::Link(key) => {
+                let link = document.links.iter().find(|l| l.key() == *key).ok_or(missing(&entry.item))?;
+                let context = format!("Link '{} <-> {}'", link.side_a, link.side_b);
+                let side_a = ids.require(&ItemRef::Device(link.side_a.clone()), &context)?;
+                let side_b = ids.require(&ItemRef::Device(link.side_b.clone()), &context)?;
+                if insert {
+                    sqlx::query!("
+                        INSERT INTO Analytics.links (link_id, side_a, side_b, side_a_iface, side_b_iface, link_type, link_subtype)
+                        VALUES ($1, $2, $3, $4, $5, $6, $7);",
+                        entry.id, side_a, side_b, link.side_a_iface, link.side_b_iface, link.link_type.clone() as LinkType, link.link_subtype
+                    ).execute(&mut **transaction).await.map_err(sql_err)?;
+                } else {
+                    sqlx::query!("UPDATE Analytics.links SET side_a = $1, side_b = $2 WHERE link_id = $3;", side_a, side_b, entry.id)
+                        .execute(&mut **transaction).await.map_err(sql_err)?;
+                }
+            },
+        }
+    }
+
+    Ok(())
+}
+
+/// Ids of the rules, by name. Uniqueness isn't enforced by the database, the first one found wins
+async fn load_rule_ids(conn: &mut PgConnection) -> Result<HashMap<String, AlertRuleId>, sqlx::Error> {
+    let existing = sqlx::query!("SELECT rule_id, rule_name FROM Analytics.alert_rules ORDER BY rule_id;")
+        .fetch_all(&mut *conn).await?;
+
+    let mut ids = HashMap::new();
+    for rule in existing {
+        ids.entry(rule.rule_name).or_insert(rule.rule_id);
+    }
+    Ok(ids)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn document_diff() {
+        let current: TopologyDocument = serde_json::from_value(serde_json::json!({
+            "version": 1,
+            "playbooks": [{"name": "backup", "is-enabled": true}],
+            "links": [{"side-a": "sw1", "side-a-iface": "eth0", "side-b": "sw2", "side-b-iface": "eth1", "link-type": "copper"}],
+            "rules": [{"name": "down", "target": {"device": "sw1"}, "severity": "critical"}]
+        })).unwrap();
+
+        let incoming = parse(r#"
+version: 1
+playbooks:
+  - name: backup
+    is-enabled: false
+links:
+  - {side-a: sw2, side-a-iface: eth1, side-b: sw1, side-b-iface: eth0, link-type: copper}
+groups:
+  - name: core
+    is-display-group: true
+    members: [{device: sw1}, {link: [sw1, sw2]}]
+oncall-schedules:
+  - name: primary
+    rotation: {members: [alice, bob], start: 2026-10-19T09:00:00}
+"#).unwrap();
+
+        let result = diff(&current, &incoming, true).unwrap();
+        assert_eq!(result["playbooks"].update, vec!["backup"]);
+        // Same link, with the sides swapped
+        assert_eq!(result["links"].unchanged, 1);
+        assert_eq!(result["groups"].create, vec!["core"]);
+        assert_eq!(result["rules"].delete, vec!["down"]);
+        assert_eq!(result["oncall-schedules"].create, vec!["primary"]);
+
+        let no_prune = diff(&current, &incoming, false).unwrap();
+        assert!(no_prune["rules"].delete.is_empty());
+
+        assert!(parse("version: 2").is_err());
+        // Exports of rules whose target was deleted
+        let dangling = parse("{version: 1, rules: [{name: down, target: null, severity: critical}]}").unwrap();
+        assert_eq!(dangling.rules[0].target, None);
+        assert_eq!(parse(&to_yaml(&incoming).unwrap()).unwrap(), incoming);
+
+        let changed = changes(&current, &incoming);
+        assert_eq!(changed.keys().copied().collect::<Vec<_>>(), vec!["groups", "oncall-schedules", "playbooks", "rules"]);
+        assert_eq!(changed["playbooks"][0]["before"]["is-enabled"], serde_json::json!(true));
+        assert_eq!(changed["rules"][0]["after"], serde_json::Value::Null);
+    }
+
+    #[test]
+    fn route_references() {
+        let ids = ItemIds { devices: HashMap::from([("sw1".to_string(), 7)]), ..Default::default() };
+        let rule_names = HashMap::from([(3, "down".to_string())]);
+        let reference = |id, _: &str| ids.reference(id);
+
+        let stored = serde_json::json!({"match": {"rules": [3, 4], "targets": [7]}, "destinations": []});
+        let mut definition = stored.as_object().unwrap().clone();
+        export_matcher(&mut definition, "Route 'r'", &rule_names, reference);
+        // The rule that no longer exists is dropped
+        assert_eq!(definition["match"], serde_json::json!({"rules": ["down"], "targets": [{"device": "sw1"}]}));
+
+        let rule_ids = HashMap::from([("down".to_string(), 3)]);
+        import_matcher(&mut definition, "Route 'r'", &rule_ids, &ids).unwrap();
+        assert_eq!(definition["match"], serde_json::json!({"rules": [3], "targets": [7]}));
+
+        let mut unknown = serde_json::json!({"match": {"rules": ["up"]}}).as_object().unwrap().clone();
+        assert_eq!(import_matcher(&mut unknown, "Route 'r'", &rule_ids, &ids).unwrap_err().1, 400);
+
+        let mut polling = serde_json::json!({"type": "metric", "device-ids": 7, "fields": ["cpu"]});
+        export_polling(&mut polling, "Dashboard 'd'", reference);
+        assert_eq!(polling["device-ids"], serde_json::json!({"device": "sw1"}));
+        import_polling(&mut polling, "Dashboard 'd'", &ids).unwrap();
+        assert_eq!(polling["device-ids"], serde_json::json!(7));
+
+        let mut dangling = serde_json::json!({"device-ids": [7, 8]});
+        export_polling(&mut dangling, "Dashboard 'd'", reference);
+        assert_eq!(dangling["device-ids"], serde_json::json!([{"device": "sw1"}]));
+        let mut unknown = serde_json::json!({"device-ids": {"group": "core"}});
+        assert_eq!(import_polling(&mut unknown, "Dashboard 'd'", &ids).unwrap_err().1, 400);
+    }
+
+    #[test]
+    fn rollback_across_rename() {
+        let entry = |item, id| ItemIdEntry { item, id };
+        let device = |h: &str| ItemRef::Device(h.to_string());
+        let snapshot = vec![entry(device("sw1"), 5), entry(device("sw2"), 6), entry(ItemRef::Group("core".to_string()), 7)];
+        // sw1 was renamed, core deleted and sw3 added since
+        let current = vec![entry(device("sw1-new"), 5), entry(device("sw2"), 6), entry(device("sw3"), 9)];
+
+        let plan = restore_plan(&current, &snapshot);
+        assert_eq!(plan.rename, vec![entry(device("sw1"), 5)]);
+        assert_eq!(plan.insert, vec![entry(ItemRef::Group("core".to_string()), 7)]);
+        assert_eq!(plan.delete, vec![entry(device("sw3"), 9)]);
+        assert_eq!(restore_plan(&snapshot, &snapshot), RestorePlan::default());
+    }
+
+    /// Runs against the database in DATABASE_URL, and is skipped without one. Nothing is committed
+    #[tokio::test]
+    async fn rollback_keeps_ids() {
+        let Ok(url) = std::env::var("DATABASE_URL") else { return };
+        let pool = sqlx::PgPool::connect(&url).await.unwrap();
+        let mut transaction = pool.begin().await.unwrap();
+
+        let id: ItemId = sqlx::query_scalar("
+            INSERT INTO Analytics.devices (device_name, latitude, longitude, management_hostname, requested_metadata, requested_metrics)
+            VALUES ('sw1', 0, 0, 'rollback-test-sw1', '[]', '[]') RETURNING device_id;")
+            .fetch_one(&mut *transaction).await.unwrap();
+        let revision = snapshot(&mut transaction).await.unwrap();
+
+        sqlx::query("UPDATE Analytics.devices SET management_hostname = 'rollback-test-sw1-new' WHERE device_id = $1;")
+            .bind(id).execute(&mut *transaction).await.unwrap();
+        sqlx::query("INSERT INTO Analytics.alerts (alert_time, requires_ack, target_id, value) VALUES (NOW(), FALSE, $1, '1');")
+            .bind(id).execute(&mut *transaction).await.unwrap();
+
+        // Same as a rollback to the revision
+        let before = snapshot(&mut transaction).await.unwrap();
+        let diff = diff(&before, &revision, true).unwrap();
+        apply_document(&mut transaction, &revision, &diff).await.unwrap();
+
+        let hostname: String = sqlx::query_scalar("SELECT management_hostname FROM Analytics.devices WHERE device_id = $1;")
+            .bind(id).fetch_one(&mut *transaction).await.unwrap();
+        let alerts: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM Analytics.alerts WHERE target_id = $1;")
+            .bind(id).fetch_one(&mut *transaction).await.unwrap();
+        assert_eq!(hostname, "rollback-test-sw1");
+        assert_eq!(alerts, 1);
+    }
+}
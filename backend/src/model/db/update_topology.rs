@@ -1,11 +1,11 @@
 
 use sqlx::{Postgres, pool::PoolConnection};
 
-use crate::{AegisError, model::{cache::Cache, db::fetch_topology::{query_devices, query_groups, query_links, query_playbooks}, facts::fact_gathering_backend::FactMessage}};
+use crate::{AegisError, telemetry::Telemetry, model::{cache::Cache, db::fetch_topology::{query_devices, query_groups, query_links, query_playbooks}, facts::fact_gathering_backend::FactMessage}};
 
 pub async fn update_topology_cache(conn: &mut PoolConnection<Postgres> , forced : bool) -> Result<(), AegisError>{
     if let Some(mut last_update_guard) = Cache::instance().try_claim_update(forced).await {
-        println!("[INFO ][CACHE] Updating topology cache!");
+        log::info!(subsystem = "cache"; "Updating topology cache!");
 
         let devices = query_devices(conn).await?;
         let links   = query_links(conn).await?;
@@ -27,7 +27,7 @@ pub async fn update_device_metadata(pool: &sqlx::Pool<Postgres>, msg: &FactMessa
         let id = match Cache::instance().get_device_id(hostname).await {
             Some(id) => id,
             None => {
-                log::warn!("[WARN ][FACTS] Tried to update device metadata for a device that doesn't exist in cache. This code should be unreachable!");
+                log::warn!(subsystem = "facts"; "Tried to update device metadata for a device that doesn't exist in cache. This code should be unreachable!");
                 continue;
             }
         };
@@ -42,7 +42,8 @@ pub async fn update_device_metadata(pool: &sqlx::Pool<Postgres>, msg: &FactMessa
                 ).execute(pool).await;
 
         if let Err(e) = result {
-            log::error!("[ERROR][FACTS][DB] Failed to update metadata and available values for device = {}. SQL Error = {e}", &hostname);
+            log::error!(subsystem = "facts.db", device = hostname.as_str(); "Failed to update metadata and available values for device = {}. SQL Error = {e}", &hostname);
+            Telemetry::postgres_error();
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,245 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, OnceLock, RwLock};
+
+use sqlx::Postgres;
+
+use crate::config::Config;
+use crate::model::cache::Cache;
+use crate::model::data::device::Device;
+use crate::model::data::link::Link;
+use crate::model::data::link_type::LinkType;
+use crate::model::db::operations::audit_operations::AuditSource;
+use crate::model::db::operations::commit_changes::{commit_in_transaction, refresh_after_commit};
+use crate::model::db::operations::discovery_operations;
+use crate::model::discovery::{LinkProposalId, LinkProposalStatus, Neighbor};
+use crate::types::{DeviceHostname, DeviceId};
+
+type E = (String, i16);
+
+/// One side of a discovered link, (device, interface)
+type LinkSide = (DeviceId, String);
+
+/// A link as reported by a neighbor, with its sides ordered by device id, and the protocol that reported it
+type Candidate = (LinkSide, LinkSide, &'static str);
+
+/// Singleton that holds the last neighbors reported by each device
+pub struct DiscoveryBackend {
+    // std lock, as neighbors are recorded from within the blocking ansible task
+    neighbors: RwLock<HashMap<DeviceHostname, Vec<Neighbor>>>,
+}
+
+impl DiscoveryBackend {
+    fn new() -> Self {
+        Self { neighbors: RwLock::new(HashMap::new()) }
+    }
+
+    pub fn instance() -> Arc<DiscoveryBackend> {
+        static INSTANCE: OnceLock<Arc<DiscoveryBackend>> = OnceLock::new();
+        INSTANCE.get_or_init(|| Arc::new(DiscoveryBackend::new())).clone()
+    }
+
+    /// Replaces the neighbors known for a given host
+    pub fn record_neighbors(&self, hostname: &str, neighbors: Vec<Neighbor>) {
+        match self.neighbors.write() {
+            Ok(mut w) => { w.insert(hostname.to_string(), neighbors); },
+            Err(_) => log::error!(subsystem = "discovery"; "Neighbor store is poisoned, dropping neighbors of '{hostname}'"),
+        }
+    }
+
+    /// Matches the recorded neighbors against the known devices, and proposes any link that is
+    /// missing or whose interfaces differ from the ones in the topology.
+    /// Proposals are only stored, they must be approved through the API to become links
+    pub async fn run_discovery(&self, pool: &sqlx::Pool<Postgres>) {
+        if !Config::instance().settings().backend.controller.discovery.enabled {
+            return;
+        }
+
+        let observed = match self.neighbors.read() {
+            Ok(r) => r.clone(),
+            Err(_) => {
+                log::error!(subsystem = "discovery"; "Neighbor store is poisoned, skipping discovery");
+                return;
+            }
+        };
+        if observed.is_empty() {
+            return;
+        }
+
+        let cache = Cache::instance();
+        let devices = cache.get_devices().await;
+        let links = cache.get_links().await;
+
+        // Both ends usually report each other, and devices may be joined by several links (parallel or LAG members).
+        // Key by the ordered pair to only propose once, keeping every neighbor seen between them
+        let mut candidates: HashMap<(DeviceId, DeviceId), Vec<Candidate>> = HashMap::new();
+        for (hostname, neighbors) in &observed {
+            let local = match devices.iter().find(|d| &d.management_hostname == hostname) {
+                Some(d) => d.device_id,
+                None => continue,
+            };
+
+            for neighbor in neighbors {
+                let remote = match match_device(&devices, neighbor) {
+                    Some(id) if id != local => id,
+                    _ => {
+                        #[cfg(debug_assertions)] { log::info!(subsystem = "discovery"; "Neighbor of '{hostname}' on '{}' does not match any device", neighbor.local_iface); }
+                        continue;
+                    }
+                };
+
+                let local_side = (local, neighbor.local_iface.clone());
+                let remote_side = (remote, neighbor.remote_iface.clone());
+                let (a, b) = if local < remote { (local_side, remote_side) } else { (remote_side, local_side) };
+
+                candidates.entry((a.0, b.0)).or_default().push((a, b, neighbor.protocol));
+            }
+        }
+
+        for ((side_a, side_b), observed) in candidates {
+            let existing = links.iter().find(|l| (l.side_a == side_a && l.side_b == side_b) || (l.side_a == side_b && l.side_b == side_a));
+            let Some((a, b, protocol)) = pick_candidate(existing, observed) else { continue };
+
+            let link_id = existing.map(|l| l.link_id);
+            match discovery_operations::insert_proposal(pool, link_id, (a.0, &a.1), (b.0, &b.1), protocol).await {
+                Ok(true) => log::info!(subsystem = "discovery"; "Proposed link {}:{} <-> {}:{} (existing link = {:?})", a.0, a.1, b.0, b.1, link_id),
+                Ok(false) => (),
+                Err(e) => log::error!(subsystem = "discovery.db"; "Failed to store link proposal, e = '{e}'"),
+            }
+        }
+    }
+
+    /// Applies a pending proposal to the topology, via the regular commit path
+    pub async fn approve_proposal(pool: &sqlx::Pool<Postgres>, id: LinkProposalId, actor: &str) -> Result<(), E> {
+        let mut transaction = pool.begin().await
+            .map_err(|e| (format!("Could not begin approval transaction. Err = '{e}'"), 500))?;
+
+        // Claimed in the same transaction as the commit, so it's approved exactly once, or not at all
+        let claimed = discovery_operations::claim_proposal(&mut transaction, id).await
+            .map_err(|e| (format!("Could not claim link proposal, error = '{e}'"), 500))?;
+        let proposal = match claimed {
+            Some(p) => p,
+            None => {
+                let exists = discovery_operations::get_proposal(pool, id).await
+                    .map_err(|e| (format!("Could not fetch link proposal, error = '{e}'"), 500))?
+                    .is_some();
+                return match exists {
+                    true => Err((format!("Link proposal with id={id} is already resolved"), 409)),
+                    false => Err((format!("Link proposal with id={id} does not exist"), 404)),
+                };
+            }
+        };
+
+        // Changed links keep their type, new ones are unknown until edited
+        let existing = match proposal.link_id {
+            Some(link_id) => Cache::instance().get_link(link_id).await,
+            None => None,
+        };
+        let (link_type, link_subtype) = existing
+            .map(|l| (l.link_type, l.link_subtype))
+            .unwrap_or((LinkType::Unknown, None));
+
+        let link = Link {
+            link_id: proposal.link_id.unwrap_or(-1),
+            side_a: proposal.side_a,
+            side_b: proposal.side_b,
+            side_a_iface: proposal.side_a_iface,
+            side_b_iface: proposal.side_b_iface,
+            link_type,
+            link_subtype,
+        };
+
+        let data = serde_json::json!({"topology-changes": {"links": [link.to_map()]}});
+        commit_in_transaction(data, &mut transaction, actor, AuditSource::Discovery).await?;
+
+        transaction.commit().await.map_err(|e| (format!("Could not commit approval transaction, error = '{e}'"), 500))?;
+
+        refresh_after_commit(pool).await
+    }
+
+    pub async fn reject_proposal(pool: &sqlx::Pool<Postgres>, id: LinkProposalId) -> Result<(), E> {
+        let resolved = discovery_operations::resolve_proposal(pool, id, LinkProposalStatus::Rejected).await
+            .map_err(|e| (format!("Could not reject link proposal, error = '{e}'"), 500))?;
+
+        if !resolved {
+            return Err((format!("Link proposal with id={id} does not exist or is already resolved"), 404));
+        }
+        Ok(())
+    }
+}
+
+/// Lowercase name without domain, so `SW1.lab.local` matches `sw1`. IP addresses are kept whole
+fn short_name(name: &str) -> String {
+    let name = name.trim().to_lowercase();
+    if name.parse::<IpAddr>().is_ok() {
+        return name;
+    }
+    name.split('.').next().unwrap_or_default().to_string()
+}
+
+/// Finds the device a neighbor refers to, first by management IP, then by hostname or device name
+fn match_device(devices: &[Device], neighbor: &Neighbor) -> Option<DeviceId> {
+    if let Some(ip) = &neighbor.remote_mgmt_ip
+        && let Some(device) = devices.iter().find(|d| d.management_hostname.trim() == ip.trim()) {
+        return Some(device.device_id);
+    }
+
+    let name = short_name(neighbor.remote_name.as_ref()?);
+    if name.is_empty() {
+        return None;
+    }
+
+    devices.iter()
+        .find(|d| short_name(&d.management_hostname) == name || short_name(&d.device_name) == name)
+        .map(|d| d.device_id)
+}
+
+/// Picks the link to propose between two devices out of every neighbor seen between them. None if any of them
+/// matches the existing link, as it's then just one of several. Otherwise the one with the lowest interface
+/// names, so the same proposal is made on every cycle regardless of the order neighbors were reported in
+fn pick_candidate(existing: Option<&Link>, observed: Vec<Candidate>) -> Option<Candidate> {
+    if let Some(link) = existing && observed.iter().any(|(a, b, _)| same_interfaces(link, a, b)) {
+        return None;
+    }
+    observed.into_iter().min_by(|(a1, b1, _), (a2, b2, _)| (&a1.1, &b1.1).cmp(&(&a2.1, &b2.1)))
+}
+
+fn same_interfaces(link: &Link, a: &LinkSide, b: &LinkSide) -> bool {
+    if link.side_a == a.0 {
+        link.side_a_iface == a.1 && link.side_b_iface == b.1
+    } else {
+        link.side_a_iface == b.1 && link.side_b_iface == a.1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parallel_links() {
+        let candidate = |a: &str, b: &str| ((1, a.to_string()), (2, b.to_string()), "lldp");
+        let observed = vec![candidate("eth2", "eth2"), candidate("eth1", "eth1"), candidate("eth3", "eth3")];
+
+        // Lowest interface, whatever the order they were reported in
+        let mut reversed = observed.clone();
+        reversed.reverse();
+        assert_eq!(pick_candidate(None, observed.clone()), Some(candidate("eth1", "eth1")));
+        assert_eq!(pick_candidate(None, reversed), Some(candidate("eth1", "eth1")));
+
+        // Any of them may be the existing link, the others are its parallel members
+        let link = Link {
+            link_id: 10,
+            side_a: 2,
+            side_b: 1,
+            side_a_iface: "eth3".to_string(),
+            side_b_iface: "eth3".to_string(),
+            link_type: LinkType::Unknown,
+            link_subtype: None,
+        };
+        assert_eq!(pick_candidate(Some(&link), observed.clone()), None);
+
+        let moved = Link { side_a_iface: "eth9".to_string(), ..link };
+        assert_eq!(pick_candidate(Some(&moved), observed), Some(candidate("eth1", "eth1")));
+    }
+}
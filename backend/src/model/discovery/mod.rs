@@ -0,0 +1,165 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Type};
+
+use crate::types::{DeviceId, LinkId};
+
+pub mod discovery_backend;
+
+pub type LinkProposalId = i64;
+
+/// A neighbor as reported by a device, via LLDP or CDP
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Neighbor {
+    #[serde(rename = "local-iface")]
+    pub local_iface: String,
+
+    #[serde(rename = "remote-name")]
+    pub remote_name: Option<String>,
+
+    #[serde(rename = "remote-iface")]
+    pub remote_iface: String,
+
+    #[serde(rename = "remote-mgmt-ip")]
+    pub remote_mgmt_ip: Option<String>,
+
+    pub protocol: &'static str,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "linkproposalstatus", rename_all = "lowercase")]
+pub enum LinkProposalStatus {
+    Pending,
+    Approved,
+    Rejected,
+}
+
+/// A link found by discovery, waiting to be approved or rejected.
+/// If `link_id` is present, the proposal changes an existing link instead of creating a new one
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct LinkProposal {
+    #[serde(rename = "id")]
+    pub proposal_id: LinkProposalId,
+
+    #[serde(rename = "link-id")]
+    pub link_id: Option<LinkId>,
+
+    #[serde(rename = "side-a")]
+    pub side_a: DeviceId,
+
+    #[serde(rename = "side-b")]
+    pub side_b: DeviceId,
+
+    #[serde(rename = "side-a-iface")]
+    pub side_a_iface: String,
+
+    #[serde(rename = "side-b-iface")]
+    pub side_b_iface: String,
+
+    pub protocol: String,
+
+    pub status: LinkProposalStatus,
+
+    #[serde(rename = "discovered-at")]
+    pub discovered_at: DateTime<Utc>,
+
+    #[serde(rename = "resolved-at")]
+    pub resolved_at: Option<DateTime<Utc>>,
+}
+
+fn str_field<'a>(value: &'a serde_json::Value, keys: &[&str]) -> Option<&'a str> {
+    keys.iter()
+        .filter_map(|k| value.get(*k).and_then(|v| v.as_str()))
+        .find(|s| !s.is_empty())
+}
+
+/// Extracts the LLDP/CDP neighbors present in the raw ansible facts of a single host.
+/// Supported formats are:
+///  - `ansible_net_neighbors`, as set by the network `*_facts` modules: `{local_iface: [{host, port, ip}]}`
+///  - `lldp`, as set by the `lldp` module: `{local_iface: {chassis: {name, mgmt-ip}, port: {ifname, descr}}}`
+///
+/// Returns None if the facts contain no neighbor information at all, to tell apart a
+/// host that has no neighbors from a playbook that doesn't gather them
+pub fn parse_neighbors(facts: &serde_json::Value) -> Option<Vec<Neighbor>> {
+    let net_neighbors = facts.get("ansible_net_neighbors").and_then(|v| v.as_object());
+    let lldp = facts.get("lldp").and_then(|v| v.as_object());
+
+    if net_neighbors.is_none() && lldp.is_none() {
+        return None;
+    }
+
+    let mut neighbors = Vec::new();
+
+    for (local_iface, entries) in net_neighbors.into_iter().flatten() {
+        let entries = match entries {
+            serde_json::Value::Array(arr) => arr.iter().collect::<Vec<_>>(),
+            other => vec![other],
+        };
+
+        for entry in entries {
+            let remote_iface = match str_field(entry, &["port", "remote_port"]) {
+                Some(p) => p.to_string(),
+                None => continue,
+            };
+
+            neighbors.push(Neighbor {
+                local_iface: local_iface.clone(),
+                remote_name: str_field(entry, &["host", "sysname"]).map(String::from),
+                remote_iface,
+                remote_mgmt_ip: str_field(entry, &["ip", "mgmt_ip"]).map(String::from),
+                protocol: "lldp/cdp",
+            });
+        }
+    }
+
+    for (local_iface, entry) in lldp.into_iter().flatten() {
+        let chassis = entry.get("chassis").unwrap_or(&serde_json::Value::Null);
+        let port = entry.get("port").unwrap_or(&serde_json::Value::Null);
+
+        let remote_iface = match str_field(port, &["ifname", "descr", "id"]) {
+            Some(p) => p.to_string(),
+            None => continue,
+        };
+
+        neighbors.push(Neighbor {
+            local_iface: local_iface.clone(),
+            remote_name: str_field(chassis, &["name"]).map(String::from),
+            remote_iface,
+            remote_mgmt_ip: str_field(chassis, &["mgmt-ip", "mgmt_ip"]).map(String::from),
+            protocol: "lldp",
+        });
+    }
+
+    Some(neighbors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_net_neighbors_and_lldp() {
+        let facts = serde_json::json!({
+            "ansible_net_neighbors": {
+                "GigabitEthernet0/1": [{"host": "sw2.lab", "port": "Gi0/2", "ip": "10.0.0.2"}],
+                "GigabitEthernet0/2": [{"host": "no-port"}]
+            },
+            "lldp": {
+                "eth0": {"chassis": {"name": "sw3", "mgmt-ip": "10.0.0.3"}, "port": {"ifname": "ge-0/0/1"}}
+            }
+        });
+
+        let mut neighbors = parse_neighbors(&facts).unwrap();
+        neighbors.sort_by(|a, b| a.local_iface.cmp(&b.local_iface));
+
+        assert_eq!(neighbors.len(), 2);
+        assert_eq!(neighbors[0].local_iface, "GigabitEthernet0/1");
+        assert_eq!(neighbors[0].remote_name.as_deref(), Some("sw2.lab"));
+        assert_eq!(neighbors[0].remote_iface, "Gi0/2");
+        assert_eq!(neighbors[1].remote_mgmt_ip.as_deref(), Some("10.0.0.3"));
+        assert_eq!(neighbors[1].remote_iface, "ge-0/0/1");
+
+        assert!(parse_neighbors(&serde_json::json!({"ansible_hostname": "sw1"})).is_none());
+    }
+}
@@ -3,6 +3,7 @@ use std::{collections::HashMap, env, fs, io, path::{Component, Path, PathBuf}};
 use pyo3::{Bound, PyAny, PyErr, Python, types::{PyAnyMethods, PyDict, PyIterator, PyModule}};
 
 use crate::{config::Config, model::{cache::Cache, data::device_state::DeviceStatus, db::fetch_topology::Playbook, facts::{ansible::ansible_status::AnsibleStatus, generics::{ToMetrics, recursive_merge}}}};
+use crate::model::discovery::{discovery_backend::DiscoveryBackend, parse_neighbors};
 use crate::types::{MetricValue, Metrics, Status};
 
 fn normalize_no_symlink(p: &Path) -> PathBuf {
@@ -78,12 +79,7 @@ fn parse_numbers_and_clean(metrics: &mut Metrics) {
 /// Internally, it calls ansible_runner via PyO3, as that's the official
 /// Ansible library or programming API
 async fn run_playbook(targets: Vec<String>, playbook: Playbook) -> (Metrics, Status) {
-    let config = Config::instance();
-
-    // let playbook : String = config.get("backend/model/playbooks/fact_gathering", "/")
-    //     .expect("[FATAL]Config file does not contain playbook for fact gathering");
-    let private : String = config.get("backend/model/private_data_dir", "/")
-        .expect("[FATAL]Config file does not contain a private directory");
+    let private = Config::instance().settings().backend.model.private_data_dir.clone();
 
     
     let cwd = env::current_dir().expect("[FATAL]Failed to get current working directory");
@@ -92,11 +88,10 @@ async fn run_playbook(targets: Vec<String>, playbook: Playbook) -> (Metrics, Sta
     let playbook_path = private.join(&playbook_name);
     
     if let Err(e) = fs::metadata(playbook_path.clone()) && e.kind() == io::ErrorKind::NotFound {
-        log::warn!("[WARN ][FACTS][ANSIBLE] Playbook referenced in database does not map to actual file; playbook = '{}', id={}. Skipping...", playbook_name, playbook.playbook_id);
-        log::info!("[INFO]   ^^^^^^^^^^^^^^^^^^^^^^^ Playbook_path = {}", playbook_path.display());
-        log::info!("[INFO]                           CWD = {}", cwd.display());
+        log::warn!(subsystem = "facts.ansible", playbook_path:% = playbook_path.display(), cwd:% = cwd.display();
+            "Playbook referenced in database does not map to actual file; playbook = '{}', id={}. Skipping...", playbook_name, playbook.playbook_id);
         for path in fs::read_dir(private.clone()).unwrap() {
-            log::info!("[INFO]                           Files in directory= {:?}", path);
+            log::info!(subsystem = "facts.ansible"; "Files in directory= {:?}", path);
         }
         return (HashMap::new(), HashMap::new());
     }
@@ -161,11 +156,14 @@ async fn run_playbook(targets: Vec<String>, playbook: Playbook) -> (Metrics, Sta
                 Ok(r) => r,
                 Err(e) => {
                     let cwd = env::current_dir().expect("[FATAL]Failed to get current working directory");
-                    log::error!("[FATAL] Ansible runner can't run, e='{e}'");
-                    log::info!("[INFO ] Current working directory was={}", cwd.display());
-                    log::info!("[INFO ] Current config file was={}", Config::instance().get_curr_config_path());
-                    log::info!("[INFO ] Playbook name was={}", playbook.playbook_name);
-                    log::info!("[INFO ] Private directory was={}", private.display());
+                    log::error!(
+                        subsystem = "facts.ansible",
+                        cwd:% = cwd.display(),
+                        config_file = Config::instance().get_curr_config_path().as_str(),
+                        playbook = playbook.playbook_name.as_str(),
+                        private_dir:% = private.display();
+                        "Ansible runner can't run, e='{e}'"
+                    );
                     panic!("[FATAL] Ansible runner can't run, e='{e}'");
                 }
             };
@@ -216,6 +214,11 @@ async fn run_playbook(targets: Vec<String>, playbook: Playbook) -> (Metrics, Sta
                         Ok(v) => v, Err(_) => continue,
                     };
 
+                    // Neighbor facts lose their structure once flattened, extract them for discovery first
+                    if let Some(neighbors) = parse_neighbors(&af) {
+                        DiscoveryBackend::instance().record_neighbors(&host, neighbors);
+                    }
+
                     let af : HashMap<String, MetricValue> = af.to_metrics(None);
 
                     // get or insert to metrics
@@ -255,19 +258,19 @@ async fn run_playbook(targets: Vec<String>, playbook: Playbook) -> (Metrics, Sta
 }
 
 pub async fn gather_facts() -> (Metrics, Status) {
-    log::info!("[INFO ][FACTS][ANSIBLE] Starting playbook execution");
+    log::info!(subsystem = "facts.ansible"; "Starting playbook execution");
     let cache = Cache::instance();
 
     let mut results : (Metrics, Status) = (HashMap::new(), HashMap::new());
 
     for playbook in cache.get_playbooks().await.into_iter().filter(|p| p.is_enabled) {
-        log::info!("[INFO ][FACTS][ANSIBLE] Playbook `{}.yml` is executing...", playbook.playbook_name);
+        log::info!(subsystem = "facts.ansible"; "Playbook `{}.yml` is executing...", playbook.playbook_name);
 
         let targets = Cache::instance().ansible_inventory(&playbook).await;
         let result = run_playbook(targets, playbook).await;
         recursive_merge(&mut results, result);
     }
-    log::info!("[INFO ][FACTS][ANSIBLE] Playbook execution done.");
+    log::info!(subsystem = "facts.ansible"; "Playbook execution done.");
 
     results
 }
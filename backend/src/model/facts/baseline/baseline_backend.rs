@@ -8,13 +8,13 @@ use crate::types::{Metrics, Status};
 async fn update_cache(baseline_cache: &Arc<BaselineCache>, forced: bool) {
     use db::operations::influx_operations::get_baseline_metrics;
     if let Some(last_update_guard) = baseline_cache.try_claim_update(forced).await {
-        log::info!("[INFO ][FACTS][BASELINE][CACHE] Updating Baseline cache!");
+        log::info!(subsystem = "facts.baseline.cache"; "Updating Baseline cache!");
         let device_hostnmame_maps = Cache::instance().get_device_hostnames().await;
 
         let device_hostname_map = match device_hostnmame_maps {
             Some(d) => d,
             None => {
-                log::warn!("[WARN ][FACTS][BASELINE] Cache update failed to read device hostnames. Last values will be used, and this one will be ignored...");
+                log::warn!(subsystem = "facts.baseline"; "Cache update failed to read device hostnames. Last values will be used, and this one will be ignored...");
                 return
             }
         };
@@ -22,19 +22,18 @@ async fn update_cache(baseline_cache: &Arc<BaselineCache>, forced: bool) {
         let metric_set = get_baseline_metrics(&baseline_cache.influx_client, &device_hostname_map).await;
 
         if metric_set.is_empty() {
-            log::warn!("[WARN ][FACTS][BASELINE] Cache update failed.  Baseline metrics was empty. Last value will be used, and this one will be ignored...");
-            log::info!("If Aegis was run after some time off, this is expected. ^^^^^^^^^^^^^^^^^^^^^^^^^^ ");
+            log::warn!(subsystem = "facts.baseline"; "Cache update failed.  Baseline metrics was empty. Last value will be used, and this one will be ignored. If Aegis was run after some time off, this is expected");
             return;
         }
         
         baseline_cache.update_metrics(last_update_guard, metric_set).await;
 
-        log::info!("[INFO ][FACTS][BASELINE][CACHE] Finished updating baseline!");
+        log::info!(subsystem = "facts.baseline.cache"; "Finished updating baseline!");
     }
 }
 
 pub async fn gather_facts() -> (Metrics, Status) {
-    log::info!("[INFO ][FACTS][BASELINE] Starting baseline gathering");
+    log::info!(subsystem = "facts.baseline"; "Starting baseline gathering");
 
     let cache = BaselineCache::instance();
 
@@ -23,7 +23,7 @@ impl BaselineCache {
     pub fn init(influx_client: &influxdb2::Client) {
         let innit_bruv = INSTANCE.set(Arc::new(BaselineCache::new(influx_client.clone())));
         if innit_bruv.is_err() {
-            println!("[WARN][BASELINE] Baseline backend was init more than once!. Ignoring second init...");
+            log::warn!(subsystem = "baseline"; "Baseline backend was init more than once!. Ignoring second init...");
         }
     }
 
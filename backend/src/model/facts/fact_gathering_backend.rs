@@ -1,7 +1,7 @@
 use std::collections::HashMap;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, OnceLock};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use rocket::futures::future::join_all;
 use sqlx::Postgres;
 use tokio::sync::mpsc::Sender;
@@ -11,11 +11,16 @@ use crate::config::Config;
 use crate::model::cache::Cache;
 use crate::model::data::device_state::DeviceStatus;
 use crate::model::db;
+use crate::model::discovery::discovery_backend::DiscoveryBackend;
 use crate::model::facts::ansible::ansible_backend;
 use crate::model::facts::baseline::baseline_backend;
 use crate::model::facts::generics::recursive_merge_metrics;
 use crate::types::{DeviceHostname, ExposedFields, MetricSet, Metrics, Status};
 use crate::model::facts::icmp::icmp_backend;
+use crate::model::facts::prometheus::prometheus_backend;
+use crate::model::facts::link_metrics;
+use crate::supervisor::{ShutdownSignal, Stage, Supervisor};
+use crate::telemetry::Telemetry;
 
 
 /// Message variants sent to listeners
@@ -52,13 +57,14 @@ impl FactGatheringBackend {
 
     /// Initialize
     pub fn init(influx_client : &influxdb2::Client) {
-        println!("[INFO] Attempting to init fact gathering backend (requires InfluxClient)");
+        log::info!("Attempting to init fact gathering backend (requires InfluxClient)");
         let _ = Self::instance();
         icmp_backend::init();
         ansible_backend::init();
         baseline_backend::init(influx_client);
+        prometheus_backend::init();
 
-        println!("[INFO ][FACTS] Init Fact Gathering Backend");
+        log::info!(subsystem = "facts"; "Init Fact Gathering Backend");
     }
 
 
@@ -75,7 +81,7 @@ impl FactGatheringBackend {
 
     /// Add a listener channel sender; returns a listener id
     pub async fn add_listener(&self, sender: Sender<FactMessage>) -> usize {
-        log::info!("[INFO ][FACTS] Registered listener");
+        log::info!(subsystem = "facts"; "Registered listener");
         let id = self.next_id.fetch_add(1, Ordering::Relaxed);
         let mut guard = self.listeners.lock().await;
         guard.insert(id, sender);
@@ -84,14 +90,14 @@ impl FactGatheringBackend {
 
     /// Remove a listener by id; returns whether any entry was removed
     pub async fn remove_listener(&self, id: usize) -> bool {
-        log::info!("[INFO ][FACTS] Gracefully removed listener");
+        log::info!(subsystem = "facts"; "Gracefully removed listener");
         let mut guard = self.listeners.lock().await;
         guard.remove(&id).is_some()
     }
 
     /// Remove all listeners
     pub async fn clear_listeners(&self) {
-        log::info!("[INFO ][FACTS] Cleared all listeners");
+        log::info!(subsystem = "facts"; "Cleared all listeners");
         let mut guard = self.listeners.lock().await;
         guard.clear();
     }
@@ -99,7 +105,7 @@ impl FactGatheringBackend {
     /// Broadcast a message to all listeners; prune dead ones
     /// Best-effort: ignores per-send errors and removes disconnected senders
     pub async fn broadcast(&self, msg: &FactMessage) {
-        #[cfg(debug_assertions)] { log::info!("[DEBUG][FACTS] Broadcasting messages. Listener count = {}", self.listener_count().await); }
+        #[cfg(debug_assertions)] { log::info!(subsystem = "facts"; "Broadcasting messages. Listener count = {}", self.listener_count().await); }
         // Snapshot keys to avoid holding lock while awaiting send
         let keys_and_senders: Vec<(usize, Sender<FactMessage>)> = {
             let guard = self.listeners.lock().await;
@@ -140,27 +146,27 @@ impl FactGatheringBackend {
         if !failed_ids.is_empty() {
             let mut guard = self.listeners.lock().await;
             for id in failed_ids {
-                log::info!("[INFO ][FACTS] Forcefully removed listener for failed receive");
+                log::info!(subsystem = "facts"; "Forcefully removed listener for failed receive");
                 guard.remove(&id);
             }
         }
     }
 
     pub async fn update_database(pool: &sqlx::Pool<Postgres>, influx_client : &influxdb2::Client, msg: &FactMessage) {
-        log::info!("[INFO ][FACTS] Updating Influx with metrics.");
+        log::info!(subsystem = "facts"; "Updating Influx with metrics.");
         db::operations::influx_operations::update_device_analytics(influx_client, msg).await;
         db::update_topology::update_device_metadata(pool, msg).await;
     }
 
     pub async fn update_cache(msg: FactMessage) {
-        log::info!("[INFO ][FACTS] Updating local facts cache.");
+        log::info!(subsystem = "facts"; "Updating local facts cache.");
         let cache = Cache::instance();
         
         for (hostname, facts) in &msg {
             let id = match cache.get_device_id(hostname).await {
                 Some(id) => id,
                 None => {
-                    log::error!("[ERROR][FACTS][CACHE] While updating device cache/status and exposed fields: Device wasn't found in cache. This should be unreachable!");
+                    log::error!(subsystem = "facts.cache"; "While updating device cache/status and exposed fields: Device wasn't found in cache. This should be unreachable!");
                     continue
                 }
             };
@@ -191,45 +197,96 @@ impl FactGatheringBackend {
     //                                                                           $$    $$/ 
     //                                                                            $$$$$$/  
     pub async fn spawn_gather_task(pool: sqlx::Pool<Postgres>, influx_client : influxdb2::Client) {
-        rocket::tokio::spawn(async move { 
-            println!("[INFO ][FACTS] Waiting for web bindings to finish to begin fact gathering loop...");
-            tokio::time::sleep(Duration::from_secs(2)).await;
-            println!("[INFO ][FACTS] Beginning FactGathering Loop!");
+        Supervisor::spawn("fact_gathering", Stage::Sources, move |shutdown| Self::gather_loop(pool.clone(), influx_client.clone(), shutdown));
+    }
+
+    /// Gathers facts every polling interval. On shutdown, a cycle in progress is finished, so its writes aren't lost
+    async fn gather_loop(pool: sqlx::Pool<Postgres>, influx_client : influxdb2::Client, mut shutdown: ShutdownSignal) {
+        let mut config_changes = Config::subscribe();
+        log::info!(subsystem = "facts"; "Waiting for web bindings to finish to begin fact gathering loop...");
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        log::info!(subsystem = "facts"; "Beginning FactGathering Loop!");
         loop {
             // Spawn tasks non-blocking for each data source
-            log::info!("[INFO ][FACTS] Gathering facts...");
-            let icmp_handle    = rocket::tokio::task::spawn(async { icmp_backend::gather_facts().await });
-            let ansible_handle = rocket::tokio::task::spawn(async { ansible_backend::gather_facts().await });
-            let baseline_handle = rocket::tokio::task::spawn(async { baseline_backend::gather_facts().await });
+            log::info!(subsystem = "facts"; "Gathering facts...");
+            let cycle_start = Instant::now();
+            let icmp_handle    = rocket::tokio::task::spawn(Self::timed("icmp", async { icmp_backend::gather_facts().await }));
+            let ansible_handle = rocket::tokio::task::spawn(Self::timed("ansible", async { ansible_backend::gather_facts().await }));
+            let baseline_handle = rocket::tokio::task::spawn(Self::timed("baseline", async { baseline_backend::gather_facts().await }));
+            let prometheus_handle = rocket::tokio::task::spawn(Self::timed("prometheus", async { prometheus_backend::gather_facts().await }));
             // other tasks...
             
-            let handles = vec![icmp_handle, ansible_handle, baseline_handle];
+            let handles = vec![icmp_handle, ansible_handle, baseline_handle, prometheus_handle];
 
             // Gather results off the tasks results
             let results: Vec<Result<(Metrics, Status), tokio::task::JoinError>> = join_all(handles).await;
-            log::info!("[INFO ][FACTS] Gathered facts!");
+            log::info!(subsystem = "facts"; "Gathered facts!");
 
             let results = Self::join_results(results);
 
+            // Links derive their state from the interfaces of their devices. Must be ready before listeners are notified
+            link_metrics::update_link_states(&results).await;
+
             // Broadcast to listeners
             FactGatheringBackend::instance().broadcast(&results).await;
             Self::update_database(&pool, &influx_client, &results).await;
             Self::update_cache(results).await; // should be the last one, as it takes ownership
-
-            let timeout_s = match Config::instance().get("backend/controller/fact_gathering/polling_time_s", "/") {
-                Ok(s) => s,
-                Err(_) => {
-                    log::error!("Error: [ERROR][FACTS] Missing timeout definition in config file, default to 15 seconds");
-                    log::info!("        ^^^^^^^^^^^^^^ Make sure 'polling_time_s' is found in the config file at 'backend/controller/fact_gathering' and is of type int");
-                    log::info!("                       Make sure 'polling_time_s' is present in the correct target (debug/release)");
-                    15
+            DiscoveryBackend::instance().run_discovery(&pool).await;
+
+            let telemetry = Telemetry::instance();
+            telemetry.fact_cycles.inc();
+            telemetry.fact_cycle_duration_s.set("all", cycle_start.elapsed().as_secs_f64());
+
+            let timeout_s = Config::instance().settings().backend.controller.fact_gathering.polling_time_s;
+            log::info!(subsystem = "facts"; "Sleeping until timeout ({}s) zzZ...", timeout_s);
+            tokio::select! {
+                _ = Self::sleep_until_timeout(timeout_s, &mut config_changes) => (),
+                _ = shutdown.requested() => {
+                    log::info!(subsystem = "facts"; "Stopping fact gathering loop");
+                    return;
                 },
-            };
-            log::info!("[INFO ][FACTS] Sleeping until timeout ({}s) zzZ...", timeout_s);
-            tokio::time::sleep(Duration::from_secs(timeout_s)).await;
-        }});
+            }
+        }
+    }
+
+
+    /// Runs a source's gathering, recording its duration and how many devices it reached
+    async fn timed(source: &'static str, gather: impl Future<Output = (Metrics, Status)>) -> (Metrics, Status) {
+        let start = Instant::now();
+        let (metrics, status) = gather.await;
+
+        // Sources that don't report reachability leave devices they couldn't read out of the metrics
+        let failed = status.iter().filter(|(host, s)| s.is_failure() || !metrics.contains_key(*host)).count();
+        let telemetry = Telemetry::instance();
+        telemetry.fact_cycle_duration_s.set(source, start.elapsed().as_secs_f64());
+        telemetry.fact_devices_gathered.set(source, metrics.len() as f64);
+        telemetry.fact_devices_failed.set(source, failed as f64);
+
+        (metrics, status)
     }
 
+    /// Sleeps for `timeout_s`. If the polling time is changed meanwhile, the new one applies right away,
+    /// counted from when the sleep began
+    async fn sleep_until_timeout(mut timeout_s: u64, config_changes: &mut tokio::sync::watch::Receiver<Arc<Config>>) {
+        let start = tokio::time::Instant::now();
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep_until(start + Duration::from_secs(timeout_s)) => return,
+                changed = config_changes.changed() => {
+                    // The sender lives as long as the process, but never spin if it were to go away
+                    if changed.is_err() {
+                        tokio::time::sleep_until(start + Duration::from_secs(timeout_s)).await;
+                        return;
+                    }
+                    let new_timeout_s = config_changes.borrow_and_update().settings().backend.controller.fact_gathering.polling_time_s;
+                    if new_timeout_s != timeout_s {
+                        log::info!(subsystem = "facts"; "Polling time changed from {}s to {}s", timeout_s, new_timeout_s);
+                        timeout_s = new_timeout_s;
+                    }
+                },
+            }
+        }
+    }
 
     pub fn join_results(results: Vec<Result<(Metrics, Status), tokio::task::JoinError>>) -> FactMessage {
 
@@ -237,7 +294,10 @@ impl FactGatheringBackend {
         let mut combined_status = Status::new();
         for source_results in results {
             let (metrics, status) = match source_results {
-                Err(_) => continue,
+                Err(e) => {
+                    log::error!(subsystem = "facts"; "A fact source failed and its results were skipped, e='{e}'");
+                    continue
+                },
                 Ok(r) => r
             };
 
@@ -266,7 +326,7 @@ impl FactGatheringBackend {
             let status = match combined_status.remove(&hostname) {
                 Some(s) => s,
                 None => {
-                    log::error!("[ERROR][FACTS] While merging, device {} did not contain status. This code should be unreachable!", &hostname);
+                    log::error!(subsystem = "facts"; "While merging, device {} did not contain status. This code should be unreachable!", &hostname);
                     log::info! ("               ^ HELP: This might mean the device contains only syslog as data source");
                     DeviceStatus::empty()
                 }
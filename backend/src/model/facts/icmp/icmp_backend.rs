@@ -155,7 +155,7 @@ async fn ping_devices(
             // The blocking task panicked or join failed; record a generic failure
             Err(join_err) => {
                 // join_err does not include the host; choose how to log/handle this in real code
-                eprintln!("ping task join error: {}", join_err);
+                log::error!(subsystem = "facts.icmp"; "Ping task join error: {}", join_err);
             }
         }
     }
@@ -166,19 +166,19 @@ async fn ping_devices(
 pub async fn gather_facts(
     // TODO: Change Metric into MetricValue to make it generic
 ) -> (Metrics, Status) {
-    log::info!("[INFO ][FACTS][ICMP] Starting ping sweep...");
+    log::info!(subsystem = "facts.icmp"; "Starting ping sweep...");
     let targets = Cache::instance().icmp_inventory().await;
 
     let results = ping_devices(targets).await;
 
-    log::info!("[INFO ][FACTS][ICMP] Ping sweep done.");
+    log::info!(subsystem = "facts.icmp"; "Ping sweep done.");
 
     results
 }
 
 pub fn init() {
 
-    println!("[INFO ][FACTS][ICMP] Init ICMP backend");
+    log::info!(subsystem = "facts.icmp"; "Init ICMP backend");
 }
 
 #[cfg(test)]
@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use tokio::sync::Mutex;
+
+use crate::config::Config;
+use crate::model::cache::Cache;
+use crate::model::data::link_state::{InterfaceState, LinkMessage, LinkState};
+use crate::model::facts::fact_gathering_backend::FactMessage;
+use crate::types::{DeviceHostname, EpochSeconds, MetricSet, MetricValue};
+
+/// Previous reading of an interface counter, to derive rates from. (value, epoch seconds)
+type CounterRecord = (f64, EpochSeconds);
+
+/// (device hostname, interface, counter name)
+type CounterKey = (DeviceHostname, String, &'static str);
+type CounterRecords = HashMap<CounterKey, CounterRecord>;
+
+static COUNTERS: OnceLock<Mutex<CounterRecords>> = OnceLock::new();
+
+/// Metric names from which each interface value is read, tried in order. `{iface}` is replaced by the interface name.
+/// Can be overriden via `backend/controller/links/interface_metrics/<value>`
+fn default_templates(value: &str) -> Vec<String> {
+    let templates: &[&str] = match value {
+        "up"         => &["ansible_{iface}_active", "ansible_net_interfaces_{iface}_operstatus", "if_{iface}_oper_status"],
+        "in_octets"  => &["if_{iface}_in_octets", "ansible_net_interfaces_{iface}_in_octets"],
+        "out_octets" => &["if_{iface}_out_octets", "ansible_net_interfaces_{iface}_out_octets"],
+        "in_errors"  => &["if_{iface}_in_errors", "ansible_net_interfaces_{iface}_in_errors"],
+        "out_errors" => &["if_{iface}_out_errors", "ansible_net_interfaces_{iface}_out_errors"],
+        _ => &[],
+    };
+    templates.iter().map(|t| t.to_string()).collect()
+}
+
+fn templates(value: &str) -> Vec<String> {
+    Config::instance().settings().backend.controller.links.interface_metrics
+        .get(value)
+        .cloned()
+        .unwrap_or_else(|| default_templates(value))
+}
+
+/// Looks up the first metric matching the templates for the given interface.
+/// Ansible replaces any non alphanumeric character of interface names with `_` in fact names, so both forms are tried
+fn lookup<'a>(metrics: &'a MetricSet, templates: &[String], iface: &str) -> Option<&'a MetricValue> {
+    let sanitized: String = iface.chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }).collect();
+
+    templates.iter()
+        .flat_map(|t| [t.replace("{iface}", iface), t.replace("{iface}", &sanitized)])
+        .find_map(|name| metrics.get(&name))
+}
+
+fn as_up(value: &MetricValue) -> Option<bool> {
+    match value {
+        MetricValue::Boolean(b) => Some(*b),
+        MetricValue::String(s) => match s.trim().to_lowercase().as_str() {
+            "up" | "true" | "connected" => Some(true),
+            "" | "unknown" => None,
+            _ => Some(false),
+        },
+        // IF-MIB ifOperStatus: 1 is up, anything else isn't. Same for Prometheus' node_network_up
+        MetricValue::Integer(i) => Some(*i == 1),
+        MetricValue::Number(n) => Some(n.into_inner() == 1.0),
+        _ => None,
+    }
+}
+
+fn as_f64(value: &MetricValue) -> Option<f64> {
+    match value {
+        MetricValue::Integer(i) => Some(*i as f64),
+        MetricValue::Number(n) => Some(n.into_inner()),
+        _ => None,
+    }
+}
+
+/// Rate in bits per second of an octet counter, compared to its previous reading.
+/// Returns None on the first reading, or if the counter went backwards (wrap or device reboot)
+fn rate_bps(records: &mut CounterRecords, key: CounterKey, value: f64, now: EpochSeconds) -> Option<f64> {
+    let previous = records.insert(key, (value, now));
+
+    let (prev_value, prev_time) = previous?;
+    let elapsed = now.saturating_sub(prev_time);
+    if elapsed == 0 || value < prev_value {
+        return None;
+    }
+
+    Some((value - prev_value) * 8.0 / elapsed as f64)
+}
+
+fn interface_state(
+    records: &mut CounterRecords,
+    facts: &FactMessage,
+    hostname: &str,
+    iface: &str,
+    now: EpochSeconds
+) -> InterfaceState {
+    let metrics = match facts.get(hostname) {
+        Some(f) => &f.metrics,
+        None => return InterfaceState::default(),
+    };
+
+    let mut state = InterfaceState {
+        up: lookup(metrics, &templates("up"), iface).and_then(as_up),
+        in_errors: lookup(metrics, &templates("in_errors"), iface).and_then(as_f64).map(|v| v as i64),
+        out_errors: lookup(metrics, &templates("out_errors"), iface).and_then(as_f64).map(|v| v as i64),
+        ..Default::default()
+    };
+
+    if let Some(v) = lookup(metrics, &templates("in_octets"), iface).and_then(as_f64) {
+        state.in_bps = rate_bps(records, (hostname.to_string(), iface.to_string(), "in_octets"), v, now);
+    }
+    if let Some(v) = lookup(metrics, &templates("out_octets"), iface).and_then(as_f64) {
+        state.out_bps = rate_bps(records, (hostname.to_string(), iface.to_string(), "out_octets"), v, now);
+    }
+
+    state
+}
+
+/// Derives the state of every link from the interface metrics of both of its ends,
+/// and stores it in the Cache, so it's available for rule evaluation and realtime listeners
+pub async fn update_link_states(facts: &FactMessage) {
+    let cache = Cache::instance();
+    let links = cache.get_links().await;
+    let now = Cache::current_epoch_secs();
+
+    let mut records = COUNTERS.get_or_init(|| Mutex::new(HashMap::new())).lock().await;
+    let mut states = LinkMessage::with_capacity(links.len());
+
+    for link in links {
+        let (host_a, host_b) = match (cache.get_device_hostname(link.side_a).await, cache.get_device_hostname(link.side_b).await) {
+            (Some(a), Some(b)) => (a, b),
+            _ => continue,
+        };
+
+        let side_a = interface_state(&mut records, facts, &host_a, &link.side_a_iface, now);
+        let side_b = interface_state(&mut records, facts, &host_b, &link.side_b_iface, now);
+
+        states.insert(link.link_id, LinkState::new(side_a, side_b));
+    }
+
+    #[cfg(debug_assertions)] { log::info!(subsystem = "facts.links"; "Derived state for {} links", states.len()); }
+    cache.update_link_states(states).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn link_state_from_interface_values() {
+        assert_eq!(as_up(&MetricValue::String("down".to_string())), Some(false));
+        assert_eq!(as_up(&MetricValue::Integer(1)), Some(true));
+
+        let mut records = HashMap::new();
+        let key = ("sw1".to_string(), "eth0".to_string(), "in_octets");
+        assert_eq!(rate_bps(&mut records, key.clone(), 1000.0, 100), None);
+        assert_eq!(rate_bps(&mut records, key.clone(), 2000.0, 110), Some(800.0));
+        // counter reset
+        assert_eq!(rate_bps(&mut records, key, 10.0, 120), None);
+
+        let mut metrics = MetricSet::new();
+        metrics.insert("ansible_eth0_1_active".to_string(), MetricValue::Boolean(true));
+        assert_eq!(lookup(&metrics, &default_templates("up"), "eth0.1"), Some(&MetricValue::Boolean(true)));
+
+        let state = LinkState::new(
+            InterfaceState { up: Some(true), out_bps: Some(10.0), ..Default::default() },
+            InterfaceState { up: Some(false), ..Default::default() },
+        );
+        let metrics = state.to_metrics();
+        assert_eq!(metrics.get("link_status"), Some(&MetricValue::String("down".to_string())));
+        assert_eq!(metrics.get("a_to_b_bps"), Some(&MetricValue::Number(10.0.into())));
+        assert!(!metrics.contains_key("b_to_a_bps"));
+    }
+}
@@ -2,5 +2,7 @@ pub mod fact_gathering_backend;
 
 pub mod ansible;
 pub mod icmp;
+pub mod prometheus;
 pub mod generics;
-pub mod baseline;
\ No newline at end of file
+pub mod baseline;
+pub mod link_metrics;
\ No newline at end of file
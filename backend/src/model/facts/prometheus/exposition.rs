@@ -0,0 +1,100 @@
+use std::collections::HashMap;
+
+/// A single sample of the Prometheus text exposition format, such as `node_network_up{device="eth0"} 1`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sample {
+    pub name: String,
+    pub labels: HashMap<String, String>,
+    pub value: f64,
+}
+
+/// Parses the Prometheus text exposition format. Comments, `# HELP` and `# TYPE` lines are skipped,
+/// as are malformed lines, so that a single bad series doesn't discard the whole scrape.
+/// Timestamps are ignored, the time of the scrape is used instead
+pub fn parse(text: &str) -> Vec<Sample> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(parse_line)
+        .collect()
+}
+
+fn parse_line(line: &str) -> Option<Sample> {
+    let name_end = line.find(|c: char| c == '{' || c.is_whitespace())?;
+    let name = line[..name_end].to_string();
+    let mut rest = &line[name_end..];
+
+    let mut labels = HashMap::new();
+    if let Some(after_brace) = rest.strip_prefix('{') {
+        let (parsed, remaining) = parse_labels(after_brace)?;
+        labels = parsed;
+        rest = remaining;
+    }
+
+    let value = rest.split_whitespace().next()?;
+    let value = match value {
+        "+Inf" => f64::INFINITY,
+        "-Inf" => f64::NEG_INFINITY,
+        v => v.parse().ok()?,
+    };
+
+    Some(Sample { name, labels, value })
+}
+
+/// Parses `key="value",...}`, returning the labels and whatever follows the closing brace
+fn parse_labels(mut s: &str) -> Option<(HashMap<String, String>, &str)> {
+    let mut labels = HashMap::new();
+    loop {
+        s = s.trim_start_matches(|c: char| c == ',' || c.is_whitespace());
+        if let Some(rest) = s.strip_prefix('}') {
+            return Some((labels, rest));
+        }
+
+        let (key, rest) = s.split_once('=')?;
+        let mut chars = rest.trim_start().strip_prefix('"')?.char_indices();
+        let mut value = String::new();
+        let end = loop {
+            match chars.next()? {
+                (i, '"') => break i,
+                (_, '\\') => match chars.next()?.1 {
+                    'n' => value.push('\n'),
+                    c => value.push(c),
+                },
+                (_, c) => value.push(c),
+            }
+        };
+
+        labels.insert(key.trim().to_string(), value);
+        s = &rest.trim_start()[1 + end + 1..];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_exposition() {
+        let text = r#"
+# HELP node_load1 1m load average.
+# TYPE node_load1 gauge
+node_load1 0.21
+node_network_receive_bytes_total{device="eth0",duplex="full"} 1.234e+06 1712000000000
+node_network_receive_bytes_total{ device="lo" } 42
+node_textfile{path="C:\\dir",msg="say \"hi\"\n"} 1
+node_boot_time_seconds +Inf
+malformed_line{device="eth0" 1
+"#;
+        let samples = parse(text);
+        assert_eq!(samples.len(), 5);
+
+        assert_eq!(samples[0], Sample { name: "node_load1".to_string(), labels: HashMap::new(), value: 0.21 });
+        assert_eq!(samples[1].labels["device"], "eth0");
+        assert_eq!(samples[1].labels["duplex"], "full");
+        assert_eq!(samples[1].value, 1_234_000.0);
+        assert_eq!(samples[2].labels["device"], "lo");
+        assert_eq!(samples[3].labels["path"], "C:\\dir");
+        assert_eq!(samples[3].labels["msg"], "say \"hi\"\n");
+        assert!(samples[4].value.is_infinite());
+    }
+}
@@ -0,0 +1,2 @@
+pub mod prometheus_backend;
+pub mod exposition;
@@ -0,0 +1,158 @@
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use ordered_float::OrderedFloat;
+use rocket::futures::future::join_all;
+
+use crate::config::Config;
+use crate::config::settings::PrometheusMapping;
+use crate::model::cache::Cache;
+use crate::model::data::device_state::DeviceStatus;
+use crate::model::facts::prometheus::exposition::{self, Sample};
+use crate::types::{MetricSet, MetricValue, Metrics, Status};
+
+static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+
+fn client() -> &'static reqwest::Client {
+    CLIENT.get_or_init(reqwest::Client::new)
+}
+
+/// Endpoints to scrape for a device, either listed for it in the configuration or the default one
+fn endpoints(hostname: &str) -> Vec<String> {
+    let config = Config::instance();
+    let prometheus = &config.settings().backend.controller.prometheus;
+    match prometheus.targets.get(hostname) {
+        Some(targets) => targets.clone(),
+        None => vec![prometheus.default_endpoint.replace("{hostname}", hostname)],
+    }
+}
+
+async fn scrape(url: &str, timeout: Duration) -> Result<String, String> {
+    let response = client().get(url).timeout(timeout).send().await
+        .map_err(|e| format!("request failed, e='{e}'"))?;
+    if !response.status().is_success() {
+        return Err(format!("returned status {}", response.status()));
+    }
+    response.text().await.map_err(|e| format!("failed to read body, e='{e}'"))
+}
+
+/// Fills the `{label}` placeholders of a metric name. Returns None if the sample lacks any of the labels.
+/// Label values are sanitized the same way Ansible names facts, so that `eth0.100` becomes `eth0_100`
+fn render_name(template: &str, labels: &HashMap<String, String>) -> Option<String> {
+    let mut name = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let end = start + rest[start..].find('}')?;
+        let value = labels.get(&rest[start + 1..end])?;
+
+        name.push_str(&rest[..start]);
+        name.extend(value.chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }));
+        rest = &rest[end + 1..];
+    }
+    name.push_str(rest);
+    Some(name)
+}
+
+/// Keeps the samples selected by the mappings, under their mapped metric name.
+/// Non-finite values are skipped, as they can't be stored
+pub fn map_samples(samples: &[Sample], mappings: &[PrometheusMapping]) -> MetricSet {
+    let mut metrics = MetricSet::new();
+    for sample in samples.iter().filter(|s| s.value.is_finite()) {
+        let matching = mappings.iter()
+            .filter(|m| m.series == sample.name)
+            .filter(|m| m.match_labels.iter().all(|(k, v)| sample.labels.get(k) == Some(v)));
+
+        for mapping in matching {
+            let template = if mapping.metric.is_empty() { &mapping.series } else { &mapping.metric };
+            if let Some(name) = render_name(template, &sample.labels) {
+                metrics.insert(name, MetricValue::Number(OrderedFloat(sample.value)));
+            }
+        }
+    }
+    metrics
+}
+
+/// Scrapes every endpoint of a device, merging their metrics. Fails only if no endpoint could be scraped
+async fn scrape_device(hostname: String, mappings: &[PrometheusMapping], timeout: Duration) -> (String, Option<MetricSet>) {
+    let mut metrics = MetricSet::new();
+    let mut any_scraped = false;
+
+    for url in endpoints(&hostname) {
+        match scrape(&url, timeout).await {
+            Ok(text) => {
+                any_scraped = true;
+                metrics.extend(map_samples(&exposition::parse(&text), mappings));
+            },
+            Err(e) => log::warn!(subsystem = "facts.prometheus", device = hostname.as_str(); "Failed to scrape {} for device {}, {}", &url, &hostname, e),
+        }
+    }
+
+    (hostname, any_scraped.then_some(metrics))
+}
+
+pub async fn gather_facts() -> (Metrics, Status) {
+    let targets = Cache::instance().prometheus_inventory().await;
+    if targets.is_empty() {
+        return (Metrics::new(), Status::new());
+    }
+    log::info!(subsystem = "facts.prometheus"; "Scraping {} devices...", targets.len());
+
+    let prometheus = Config::instance().settings().backend.controller.prometheus.clone();
+    let timeout = Duration::from_secs(prometheus.timeout_s);
+    let results = join_all(targets.into_iter().map(|hostname| scrape_device(hostname, &prometheus.mappings, timeout))).await;
+
+    let mut metrics = Metrics::new();
+    let mut status = Status::new();
+    for (hostname, scraped) in results {
+        // Reachability is reported by icmp and ansible. Devices that couldn't be scraped are left out of the metrics
+        status.insert(hostname.clone(), DeviceStatus::empty());
+        if let Some(scraped) = scraped {
+            metrics.insert(hostname, scraped);
+        }
+    }
+
+    log::info!(subsystem = "facts.prometheus"; "Scraping done.");
+    (metrics, status)
+}
+
+pub fn init() {
+    let _ = client();
+    log::info!(subsystem = "facts.prometheus"; "Init Prometheus backend");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn map_node_exporter_samples() {
+        let text = r#"
+node_load1 0.5
+node_network_receive_bytes_total{device="eth0"} 1000
+node_network_receive_bytes_total{device="eth0.100"} 20
+node_network_up{device="eth0"} 1
+node_cpu_seconds_total{cpu="0",mode="idle"} 100
+node_cpu_seconds_total{cpu="0",mode="user"} 7
+node_memory_MemAvailable_bytes NaN
+"#;
+        let mut mappings = crate::config::settings::PrometheusSettings::default().mappings;
+        mappings.push(PrometheusMapping {
+            series: "node_cpu_seconds_total".to_string(),
+            metric: "cpu{cpu}_idle_seconds".to_string(),
+            match_labels: HashMap::from([("mode".to_string(), "idle".to_string())]),
+        });
+
+        let metrics = map_samples(&exposition::parse(text), &mappings);
+        let number = |n: f64| Some(MetricValue::Number(OrderedFloat(n)));
+        assert_eq!(metrics.get("load1").cloned(), number(0.5));
+        assert_eq!(metrics.get("if_eth0_in_octets").cloned(), number(1000.0));
+        assert_eq!(metrics.get("if_eth0_100_in_octets").cloned(), number(20.0));
+        assert_eq!(metrics.get("if_eth0_oper_status").cloned(), number(1.0));
+        assert_eq!(metrics.get("cpu0_idle_seconds").cloned(), number(100.0));
+        assert!(!metrics.contains_key("memory_available_bytes"));
+        assert_eq!(metrics.len(), 5);
+
+        assert_eq!(render_name("if_{device}_{missing}", &HashMap::from([("device".to_string(), "eth0".to_string())])), None);
+    }
+}
@@ -1,5 +1,6 @@
 pub mod db;
 pub mod data;
 pub mod facts;
+pub mod discovery;
 
 pub mod cache;
\ No newline at end of file
@@ -0,0 +1,245 @@
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+
+use chrono::Utc;
+use rocket::futures::future::join_all;
+use serde::Serialize;
+use tokio::sync::watch;
+use tokio::task::{JoinError, JoinHandle};
+use tokio::time::Instant;
+
+use crate::config::Config;
+use crate::telemetry::Telemetry;
+use crate::types::EpochSeconds;
+
+// Background loops run under the supervisor, so that one that panics or returns is restarted instead of silently disappearing.
+// Restarts are delayed with an exponential backoff, which is reset once the task stays up for as long as the longest backoff.
+// On shutdown, tasks are asked to stop one stage at a time, so that what a stage queued is drained by the next one.
+
+/// Shutdown order of the tasks. Earlier stages feed the later ones
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Stage {
+    /// Fact gathering, syslog receiver and bot updates
+    Sources,
+    /// Alert rule evaluation
+    Evaluation,
+    /// Storing and broadcasting raised alerts
+    Events,
+    /// Sending alerts to external services
+    Notifications,
+}
+
+impl Stage {
+    const ALL: [Stage; 4] = [Stage::Sources, Stage::Evaluation, Stage::Events, Stage::Notifications];
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TaskState {
+    Running,
+    /// Crashed, waiting for the backoff to restart
+    Restarting,
+    /// Stopped on shutdown
+    Stopped,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TaskStatus {
+    pub state: TaskState,
+    pub stage: Stage,
+    pub restarts: u64,
+    #[serde(rename = "last-error")]
+    pub last_error: Option<String>,
+    /// Time of the last state change
+    pub since: EpochSeconds,
+}
+
+/// Handed to every task, to know when it should stop
+#[derive(Debug, Clone)]
+pub struct ShutdownSignal {
+    stage: Stage,
+    stopping: watch::Receiver<Option<Stage>>,
+}
+
+impl ShutdownSignal {
+    pub fn is_requested(&self) -> bool {
+        self.stopping.borrow().is_some_and(|stopping| stopping >= self.stage)
+    }
+
+    /// Resolves once the task's stage is asked to stop. Cancel safe, so it can be used in `select!` loops
+    pub async fn requested(&mut self) {
+        let stage = self.stage;
+        if self.stopping.wait_for(|stopping| stopping.is_some_and(|s| s >= stage)).await.is_err() {
+            // The sender lives as long as the process. Never report a shutdown that wasn't requested
+            std::future::pending::<()>().await;
+        }
+    }
+}
+
+pub struct Supervisor {
+    tasks: Mutex<BTreeMap<&'static str, TaskStatus>>,
+    monitors: Mutex<Vec<(Stage, JoinHandle<()>)>>,
+    stopping: watch::Sender<Option<Stage>>,
+}
+
+static INSTANCE: OnceLock<Arc<Supervisor>> = OnceLock::new();
+
+impl Supervisor {
+    /// Get the singleton instance
+    pub fn instance() -> Arc<Supervisor> {
+        INSTANCE.get_or_init(|| Arc::new(Supervisor {
+            tasks: Mutex::new(BTreeMap::new()),
+            monitors: Mutex::new(Vec::new()),
+            stopping: watch::Sender::new(None),
+        })).clone()
+    }
+
+    fn signal(&self, stage: Stage) -> ShutdownSignal {
+        ShutdownSignal { stage, stopping: self.stopping.subscribe() }
+    }
+
+    /// Status of every supervised task, by name
+    pub fn statuses(&self) -> BTreeMap<&'static str, TaskStatus> {
+        self.tasks.lock().map(|t| t.clone()).unwrap_or_default()
+    }
+
+    fn set_state(&self, name: &'static str, stage: Stage, state: TaskState, error: Option<String>) {
+        let Ok(mut tasks) = self.tasks.lock() else { return };
+        let since = Utc::now().timestamp() as EpochSeconds;
+        let status = tasks.entry(name).or_insert(TaskStatus { state, stage, restarts: 0, last_error: None, since });
+        if state == TaskState::Restarting {
+            status.restarts += 1;
+        }
+        if error.is_some() {
+            status.last_error = error;
+        }
+        status.state = state;
+        status.since = since;
+    }
+
+    /// Runs `worker` as a background task named `name`, restarting it whenever it panics or returns,
+    /// until its stage is asked to stop. Anything the worker must keep across restarts, such as a channel receiver,
+    /// must be owned outside of it
+    pub fn spawn<F, Fut>(name: &'static str, stage: Stage, worker: F)
+    where
+        F: Fn(ShutdownSignal) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let supervisor = Self::instance();
+        supervisor.set_state(name, stage, TaskState::Running, None);
+
+        let monitor = tokio::spawn(async move {
+            let supervisor = Self::instance();
+            let mut backoff = None;
+            loop {
+                let mut signal = supervisor.signal(stage);
+                let started = Instant::now();
+                let result = tokio::spawn(worker(signal.clone())).await;
+
+                if signal.is_requested() {
+                    log::info!(subsystem = "supervisor"; "Task '{}' stopped", name);
+                    supervisor.set_state(name, stage, TaskState::Stopped, None);
+                    return;
+                }
+
+                let error = match result {
+                    Ok(()) => "returned unexpectedly".to_string(),
+                    Err(e) => panic_message(e),
+                };
+                let settings = Config::instance().settings().backend.controller.supervisor.clone();
+                let delay = next_backoff(backoff, started.elapsed(), Duration::from_secs(settings.backoff_initial_s), Duration::from_secs(settings.backoff_max_s));
+                backoff = Some(delay);
+
+                log::error!(subsystem = "supervisor", task = name; "Task '{}' {}. Restarting in {}s...", name, error, delay.as_secs());
+                supervisor.set_state(name, stage, TaskState::Restarting, Some(error));
+                Telemetry::instance().task_restarts.inc(name);
+
+                tokio::select! {
+                    _ = tokio::time::sleep(delay) => supervisor.set_state(name, stage, TaskState::Running, None),
+                    _ = signal.requested() => {
+                        supervisor.set_state(name, stage, TaskState::Stopped, None);
+                        return;
+                    },
+                }
+            }
+        });
+
+        if let Ok(mut monitors) = supervisor.monitors.lock() {
+            monitors.push((stage, monitor));
+        }
+    }
+
+    /// Stops every task, one stage at a time, waiting for each to drain. Tasks still running after
+    /// `shutdown_grace_s` are left behind, to be dropped with the process
+    pub async fn shutdown(&self) {
+        let grace_s = Config::instance().settings().backend.controller.supervisor.shutdown_grace_s;
+        let deadline = Instant::now() + Duration::from_secs(grace_s);
+        log::info!(subsystem = "supervisor"; "Shutting down background tasks, grace period of {}s", grace_s);
+
+        for stage in Stage::ALL {
+            self.stopping.send_replace(Some(stage));
+            let monitors: Vec<JoinHandle<()>> = match self.monitors.lock() {
+                Ok(mut monitors) => {
+                    let (stopping, running) = std::mem::take(&mut *monitors).into_iter().partition(|(s, _)| *s == stage);
+                    *monitors = running;
+                    stopping.into_iter().map(|(_, monitor)| monitor).collect()
+                },
+                Err(_) => continue,
+            };
+
+            if tokio::time::timeout_at(deadline, join_all(monitors)).await.is_err() {
+                log::warn!(subsystem = "supervisor"; "{:?} tasks did not stop within the grace period", stage);
+            }
+        }
+        log::info!(subsystem = "supervisor"; "Background tasks stopped");
+    }
+}
+
+/// Delay before the next restart. Doubles on every crash, unless the task had been up for longer than `max`
+fn next_backoff(previous: Option<Duration>, uptime: Duration, initial: Duration, max: Duration) -> Duration {
+    match previous {
+        Some(previous) if uptime < max => (previous * 2).clamp(initial, max),
+        _ => initial.min(max),
+    }
+}
+
+fn panic_message(e: JoinError) -> String {
+    if !e.is_panic() {
+        return "was cancelled".to_string();
+    }
+    let payload = e.into_panic();
+    let message = payload.downcast_ref::<&str>().map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or("unknown panic".to_string());
+    format!("panicked: '{message}'")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn backoff_and_signals() {
+        let (s, m) = (Duration::from_secs(1), Duration::from_secs(60));
+        assert_eq!(next_backoff(None, Duration::ZERO, s, m), s);
+        assert_eq!(next_backoff(Some(s), Duration::ZERO, s, m), Duration::from_secs(2));
+        assert_eq!(next_backoff(Some(Duration::from_secs(40)), Duration::ZERO, s, m), m);
+        assert_eq!(next_backoff(Some(m), Duration::from_secs(120), s, m), s);
+
+        let panicked = tokio::spawn(async { panic!("ansible_runner failed") }).await.expect_err("Task should panic");
+        assert_eq!(panic_message(panicked), "panicked: 'ansible_runner failed'");
+
+        let (tx, rx) = watch::channel(None);
+        let mut events = ShutdownSignal { stage: Stage::Events, stopping: rx.clone() };
+        let sources = ShutdownSignal { stage: Stage::Sources, stopping: rx };
+        tx.send_replace(Some(Stage::Evaluation));
+        assert!(sources.is_requested());
+        assert!(!events.is_requested());
+
+        tx.send_replace(Some(Stage::Events));
+        tokio::time::timeout(Duration::from_secs(1), events.requested()).await.expect("Signal should resolve");
+    }
+}
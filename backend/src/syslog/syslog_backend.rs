@@ -9,6 +9,8 @@ use tokio::sync::Mutex;
 use crate::config::Config;
 use crate::model::db;
 use crate::syslog::SyslogMessage;
+use crate::supervisor::{ShutdownSignal, Stage, Supervisor};
+use crate::telemetry::Telemetry;
 
 
 pub struct SyslogBackend {
@@ -31,11 +33,11 @@ impl SyslogBackend {
 
     /// Initialize
     pub fn init() {
-        println!("[INFO] Attempting to init Syslog Backend");
+        log::info!("Attempting to init Syslog Backend");
 
         let _ = Self::instance();
 
-        println!("[INFO ][SYSLOG] Init Syslog Backend");
+        log::info!(subsystem = "syslog"; "Init Syslog Backend");
     }
 
         //  __        __              __                                                     ______   _______   ______ 
@@ -50,7 +52,7 @@ impl SyslogBackend {
 
     /// Add a listener channel sender; returns a listener id
     pub async fn add_listener(&self, sender: Sender<SyslogMessage>) -> usize {
-        log::info!("[INFO ][SYSLOG] Registered listener");
+        log::info!(subsystem = "syslog"; "Registered listener");
         let id = self.next_id.fetch_add(1, Ordering::Relaxed);
         let mut guard = self.listeners.lock().await;
         guard.insert(id, sender);
@@ -59,14 +61,14 @@ impl SyslogBackend {
 
     /// Remove a listener by id; returns whether any entry was removed
     pub async fn remove_listener(&self, id: usize) -> bool {
-        log::info!("[INFO ][SYSLOG] Gracefully removed listener");
+        log::info!(subsystem = "syslog"; "Gracefully removed listener");
         let mut guard = self.listeners.lock().await;
         guard.remove(&id).is_some()
     }
 
     /// Remove all listeners
     pub async fn clear_listeners(&self) {
-        log::info!("[INFO ][SYSLOG] Cleared all listeners");
+        log::info!(subsystem = "syslog"; "Cleared all listeners");
         let mut guard = self.listeners.lock().await;
         guard.clear();
     }
@@ -103,6 +105,7 @@ impl SyslogBackend {
                                 }
                                 Err(_) => {
                                     // treat inability to reserve as potential disconnect; don't panic
+                                    Telemetry::instance().syslog_dropped.inc();
                                 }
                             }
                         }
@@ -115,7 +118,7 @@ impl SyslogBackend {
         if !failed_ids.is_empty() {
             let mut guard = instance.listeners.lock().await;
             for id in failed_ids {
-                log::info!("[INFO ][SYSLOG] Forcefully removed listener for failed receive");
+                log::info!(subsystem = "syslog"; "Forcefully removed listener for failed receive");
                 guard.remove(&id);
             }
         }
@@ -125,7 +128,9 @@ impl SyslogBackend {
         match db::operations::syslog_operations::update_database(postgres_pool, msg).await {
             Ok(_) => (),
             Err(_) => {
-                log::error!("[ERROR][SYSLOG] Failed to insert message into database. Syslog Message will be dropped");
+                log::error!(subsystem = "syslog"; "Failed to insert message into database. Syslog Message will be dropped");
+                Telemetry::instance().syslog_db_failed.inc();
+                Telemetry::postgres_error();
                 
             }
         }
@@ -150,57 +155,94 @@ impl SyslogBackend {
     //                                                                           $$    $$/ 
     //                                                                            $$$$$$/  
     pub async fn spawn_gather_task(postgres_pool : Pool<Postgres>) {
-        let config = Config::instance();
-        let port = match config.get("backend/controller/api/syslog/RFC5424-port", "/") {
-            Ok(v) => v,
-            Err(_) => {
-                println!("[ERROR][SYSLOG] During init: Syslog port not found, defaulting to 1514...");
-                1514
-            }
-        };
-
-        let addr = match config.get("backend/controller/api/syslog/bind_address", "/") {
-            Ok(v) => v,
-            Err(_) => {
-                println!("[ERROR][SYSLOG] During init: Syslog bind address not found, defaulting to 0.0.0.0...");
-                "0.0.0.0".to_owned()
-            }
-        };
-        let bind_addr = format!("{addr}:{port}");
+        let bind_addr = Self::bind_address(&Config::instance());
 
-        let mut buf = [0u8; 2048];
         let socket = match UdpSocket::bind(&bind_addr).await {
             Ok(socket) => {
-                println!("[INFO ][SYSLOG] Spawning syslog listener task bound to={}", &bind_addr);
+                log::info!(subsystem = "syslog"; "Spawning syslog listener task bound to={}", &bind_addr);
                 socket
             },
             Err(e) => {
-                let e = format!("[ERROR][SYSLOG] Failed to bind to {} for syslog messages, e='{}'", &bind_addr, e);
-                println!("{}", e);
+                let e = format!("Failed to bind to {} for syslog messages, e='{}'", &bind_addr, e);
+                log::error!(subsystem = "syslog"; "{}", e);
                 panic!("{}", e);
             }
         };
 
-        println!("[INFO ][SYSLOG] Spawning syslog receiver");
-        tokio::task::spawn(async move {
-            loop{
-                let (len, _) = match socket.recv_from(&mut buf).await {
-                    Ok((len, addr)) => (len, addr),
-                    Err(e) => {
-                        log::error!("[ERROR][SYSLOG] Failed to receive syslog message on {}, e={}", &bind_addr, e);
+        // The socket outlives the receiver, so that it's kept bound if the receiver is restarted
+        let listener = Arc::new(Mutex::new((socket, bind_addr)));
+        log::info!(subsystem = "syslog"; "Spawning syslog receiver");
+        Supervisor::spawn("syslog_receiver", Stage::Sources, move |shutdown| Self::receive_loop(listener.clone(), postgres_pool.clone(), shutdown));
+    }
+
+    async fn receive_loop(listener: Arc<Mutex<(UdpSocket, String)>>, postgres_pool : Pool<Postgres>, mut shutdown: ShutdownSignal) {
+        let mut guard = listener.lock().await;
+        let (socket, bind_addr) = &mut *guard;
+        let mut buf = [0u8; 2048];
+        let mut config_changes = Config::subscribe();
+        let mut watching_config = true;
+        loop{
+            let received = tokio::select! {
+                received = socket.recv_from(&mut buf) => received,
+                _ = shutdown.requested() => {
+                    log::info!(subsystem = "syslog"; "Stopping syslog receiver");
+                    return;
+                },
+                changed = config_changes.changed(), if watching_config => {
+                    // The sender lives as long as the process, but never spin if it were to go away
+                    if changed.is_err() {
+                        watching_config = false;
                         continue
                     }
-                };
-                let message: std::borrow::Cow<'_, str> = String::from_utf8_lossy(&buf[..len]);
-                log::info!("[INFO ][SYSLOG] Received message {}", &message);
-                let message = syslog_loose::parse_message(&message, syslog_loose::Variant::Either);
-                let message: SyslogMessage = message.into();
-
-                Self::update_database(&postgres_pool, &message).await;
-                Self::broadcast(&message).await;
+                    let new_addr = Self::bind_address(&config_changes.borrow_and_update());
+                    if new_addr != *bind_addr {
+                        Self::rebind(socket, bind_addr, new_addr).await;
+                    }
+                    continue
+                },
+            };
+
+            let (len, _) = match received {
+                Ok((len, addr)) => (len, addr),
+                Err(e) => {
+                    log::error!(subsystem = "syslog"; "Failed to receive syslog message on {}, e={}", &bind_addr, e);
+                    continue
+                }
+            };
+            let message: std::borrow::Cow<'_, str> = String::from_utf8_lossy(&buf[..len]);
+            log::info!(subsystem = "syslog"; "Received message {}", &message);
+            let message = syslog_loose::parse_message(&message, syslog_loose::Variant::Either);
+
+            // Anything is accepted as a message, but only those with a priority had a syslog header
+            let telemetry = Telemetry::instance();
+            telemetry.syslog_received.inc();
+            if message.severity.is_some() {
+                telemetry.syslog_parsed.inc();
             }
-        });
+            let message: SyslogMessage = message.into();
+
+            Self::update_database(&postgres_pool, &message).await;
+            Self::broadcast(&message).await;
+        }
+    }
 
+    fn bind_address(config: &Config) -> String {
+        let syslog = &config.settings().backend.controller.syslog;
+        format!("{}:{}", syslog.bind_address, syslog.rfc5424_port)
+    }
+
+    /// Moves the listener to `new_addr`. If it can't be bound, keeps listening on the current address
+    async fn rebind(socket: &mut UdpSocket, bind_addr: &mut String, new_addr: String) {
+        match UdpSocket::bind(&new_addr).await {
+            Ok(new_socket) => {
+                log::info!(subsystem = "syslog"; "Configuration changed, syslog listener moved from {} to {}", bind_addr, &new_addr);
+                *socket = new_socket;
+                *bind_addr = new_addr;
+            },
+            Err(e) => {
+                log::error!(subsystem = "syslog"; "Failed to bind to {} after a configuration change, still listening on {}. e='{}'", &new_addr, bind_addr, e);
+            }
+        }
     }
 
 }
\ No newline at end of file
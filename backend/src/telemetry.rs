@@ -0,0 +1,208 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+
+use crate::alerts::alert_backend::AlertBackend;
+use crate::model::facts::fact_gathering_backend::FactGatheringBackend;
+use crate::syslog::syslog_backend::SyslogBackend;
+
+// Self-observability of the backend, exposed at `/metrics` in the Prometheus text format.
+// Recording is lock-free for counters and gauges, so it can be done from any hot path.
+
+#[derive(Debug, Default)]
+pub struct Counter(AtomicU64);
+
+impl Counter {
+    pub fn inc(&self) {
+        self.add(1);
+    }
+
+    pub fn add(&self, n: u64) {
+        self.0.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Gauge(AtomicI64);
+
+impl Gauge {
+    pub fn inc(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn dec(&self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> i64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Gauge with one value per label value, such as per fact source
+#[derive(Debug, Default)]
+pub struct LabeledGauge(Mutex<BTreeMap<String, f64>>);
+
+impl LabeledGauge {
+    pub fn set(&self, label: &str, value: f64) {
+        if let Ok(mut values) = self.0.lock() {
+            values.insert(label.to_string(), value);
+        }
+    }
+
+    pub fn values(&self) -> Vec<(String, f64)> {
+        self.0.lock().map(|v| v.iter().map(|(k, v)| (k.clone(), *v)).collect()).unwrap_or_default()
+    }
+}
+
+/// Counter with one value per label value, such as per database
+#[derive(Debug, Default)]
+pub struct LabeledCounter(Mutex<BTreeMap<String, u64>>);
+
+impl LabeledCounter {
+    pub fn inc(&self, label: &str) {
+        if let Ok(mut values) = self.0.lock() {
+            *values.entry(label.to_string()).or_default() += 1;
+        }
+    }
+
+    pub fn values(&self) -> Vec<(String, f64)> {
+        self.0.lock().map(|v| v.iter().map(|(k, v)| (k.clone(), *v as f64)).collect()).unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Telemetry {
+    pub fact_cycles: Counter,
+    /// Duration of the last gathering cycle of each source, and of the whole cycle as `source="all"`
+    pub fact_cycle_duration_s: LabeledGauge,
+    /// Devices that returned data on the last cycle, per source
+    pub fact_devices_gathered: LabeledGauge,
+    /// Devices that could not be reached on the last cycle, per source
+    pub fact_devices_failed: LabeledGauge,
+
+    pub syslog_received: Counter,
+    /// Messages with a valid syslog header. The rest are still stored, as plain text
+    pub syslog_parsed: Counter,
+    /// Messages a realtime listener couldn't take, as its queue was full
+    pub syslog_dropped: Counter,
+    pub syslog_db_failed: Counter,
+
+    pub rules_evaluated: Counter,
+    pub alerts_raised: Counter,
+    /// Alert events put back in the queue after failing to be written
+    pub alert_requeues: Counter,
+    /// Routed alert deliveries that failed, per channel
+    pub notifications_failed: LabeledCounter,
+    /// Alert events a listener couldn't take, as its queue was full. Includes the webhook and email notifications
+    pub alert_events_dropped: Counter,
+
+    pub ws_sessions: Gauge,
+
+    /// Failed queries or writes, per database
+    pub db_errors: LabeledCounter,
+
+    /// Restarts of crashed background tasks, per task
+    pub task_restarts: LabeledCounter,
+}
+
+static INSTANCE: OnceLock<Arc<Telemetry>> = OnceLock::new();
+
+impl Telemetry {
+    /// Get the singleton instance
+    pub fn instance() -> Arc<Telemetry> {
+        INSTANCE.get_or_init(|| Arc::new(Telemetry::default())).clone()
+    }
+
+    pub fn influx_error() {
+        Self::instance().db_errors.inc("influx");
+    }
+
+    pub fn postgres_error() {
+        Self::instance().db_errors.inc("postgres");
+    }
+
+    /// Renders every metric in the Prometheus text format
+    pub async fn render() -> String {
+        let t = Self::instance();
+        let listeners = [
+            ("facts", FactGatheringBackend::instance().listener_count().await as f64),
+            ("syslog", SyslogBackend::instance().listener_count().await as f64),
+            ("alerts", match AlertBackend::try_instance() { Some(alerts) => alerts.listener_count().await as f64, None => 0.0 }),
+        ].map(|(k, v)| (k.to_string(), v));
+
+        let mut out = Exposition::default();
+        out.single("aegis_fact_gathering_cycles_total", "Fact gathering cycles completed", "counter", t.fact_cycles.get() as f64);
+        out.labeled("aegis_fact_gathering_duration_seconds", "Duration of the last fact gathering cycle", "gauge", "source", &t.fact_cycle_duration_s.values());
+        out.labeled("aegis_fact_devices_gathered", "Devices that returned data on the last cycle", "gauge", "source", &t.fact_devices_gathered.values());
+        out.labeled("aegis_fact_devices_failed", "Devices that could not be reached on the last cycle", "gauge", "source", &t.fact_devices_failed.values());
+
+        out.single("aegis_syslog_messages_received_total", "Syslog datagrams received", "counter", t.syslog_received.get() as f64);
+        out.single("aegis_syslog_messages_parsed_total", "Syslog messages with a valid syslog header", "counter", t.syslog_parsed.get() as f64);
+        out.single("aegis_syslog_messages_dropped_total", "Syslog messages not delivered to a realtime listener, as its queue was full", "counter", t.syslog_dropped.get() as f64);
+        out.single("aegis_syslog_messages_db_failed_total", "Syslog messages that could not be stored", "counter", t.syslog_db_failed.get() as f64);
+
+        out.single("aegis_alert_rules_evaluated_total", "Alert rule evaluations", "counter", t.rules_evaluated.get() as f64);
+        out.single("aegis_alerts_raised_total", "Alerts raised", "counter", t.alerts_raised.get() as f64);
+        out.single("aegis_alert_requeues_total", "Alert events requeued after failing to be stored", "counter", t.alert_requeues.get() as f64);
+        out.labeled("aegis_notifications_failed_total", "Routed alert deliveries that failed", "counter", "channel", &t.notifications_failed.values());
+        out.single("aegis_alert_events_dropped_total", "Alert events not delivered to a listener, as its queue was full", "counter", t.alert_events_dropped.get() as f64);
+
+        out.labeled("aegis_listeners", "Realtime listeners registered on each backend", "gauge", "backend", &listeners);
+        out.single("aegis_websocket_sessions", "Open WebSocket sessions", "gauge", t.ws_sessions.get() as f64);
+        out.labeled("aegis_db_errors_total", "Failed database queries or writes", "counter", "db", &t.db_errors.values());
+        out.labeled("aegis_task_restarts_total", "Restarts of crashed background tasks", "counter", "task", &t.task_restarts.values());
+
+        out.0
+    }
+}
+
+#[derive(Default)]
+struct Exposition(String);
+
+impl Exposition {
+    fn header(&mut self, name: &str, help: &str, kind: &str) {
+        let _ = writeln!(self.0, "# HELP {name} {help}");
+        let _ = writeln!(self.0, "# TYPE {name} {kind}");
+    }
+
+    fn single(&mut self, name: &str, help: &str, kind: &str, value: f64) {
+        self.header(name, help, kind);
+        let _ = writeln!(self.0, "{name} {value}");
+    }
+
+    fn labeled(&mut self, name: &str, help: &str, kind: &str, label: &str, values: &[(String, f64)]) {
+        self.header(name, help, kind);
+        for (label_value, value) in values {
+            let label_value = label_value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n");
+            let _ = writeln!(self.0, "{name}{{{label}=\"{label_value}\"}} {value}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn render_exposition() {
+        let t = Telemetry::instance();
+        t.syslog_received.add(3);
+        t.fact_cycle_duration_s.set("icmp", 0.5);
+        t.ws_sessions.inc();
+        Telemetry::influx_error();
+
+        let text = Telemetry::render().await;
+        assert!(text.contains("# TYPE aegis_syslog_messages_received_total counter\naegis_syslog_messages_received_total 3\n"));
+        assert!(text.contains("aegis_fact_gathering_duration_seconds{source=\"icmp\"} 0.5\n"));
+        // Other tests may record into the same instance, so only the series are checked for these
+        assert!(text.contains("aegis_websocket_sessions "));
+        assert!(text.contains("aegis_db_errors_total{db=\"influx\"} "));
+        assert!(text.contains("aegis_listeners{backend=\"facts\"} "));
+    }
+}
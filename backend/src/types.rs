@@ -12,6 +12,8 @@ pub type AlertEventId = i64;
 pub type AlertRuleId = i64;
 pub type AlertAckActor = i64;
 pub type AlertTargetId = i64;
+pub type AlertRouteId = i64;
+pub type OnCallScheduleId = i64;
 
 pub type ItemId = i64;
 pub type EvaluableItemId = i64;
@@ -169,8 +171,68 @@ impl From<MetricValue> for FieldValue {
             MetricValue::Number(m) => FieldValue::F64(*m),
             MetricValue::Integer(m) => FieldValue::I64(m),
             MetricValue::Boolean(m) => FieldValue::Bool(m),
-            MetricValue::Array(_) => FieldValue::String("Unsupported type".to_string()),
-            MetricValue::Null() => FieldValue::String("Unsupported type".to_string()),
+            // Arrays fall back to their JSON representation. Use `to_influx_fields` to honor the per metric encoding
+            MetricValue::Array(_) => FieldValue::String(serde_json::Value::from(val).to_string()),
+            // Influx has no null field. Callers are expected to skip these, see `to_influx_fields`
+            MetricValue::Null() => FieldValue::String(String::new()),
         }
     }
 }
+
+/// Defines how an array metric is flattened before being written into Influx.
+/// Selected per metric at `backend/controller/influx/array_encoding/<metric>`, defaults to `Json`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all="lowercase")]
+pub enum ArrayEncoding {
+    /// The whole array is stored as a single JSON encoded string field, under the metric name
+    #[default]
+    Json,
+
+    /// Each element is stored as its own field named `<metric>_<index>`.
+    /// Nested arrays keep flattening, such that `[[1, 2]]` becomes `<metric>_0_0` and `<metric>_0_1`
+    Indexed,
+}
+
+impl MetricValue {
+    /// Flattens the value into the Influx fields it should be written as.
+    /// Nulls are omitted, both at the top level and inside indexed arrays
+    pub fn to_influx_fields(&self, name: &str, encoding: ArrayEncoding) -> Vec<(String, FieldValue)> {
+        match (self, encoding) {
+            (MetricValue::Null(), _) => Vec::new(),
+            (MetricValue::Array(values), ArrayEncoding::Indexed) => {
+                values.iter()
+                    .enumerate()
+                    .flat_map(|(idx, v)| v.to_influx_fields(&format!("{name}_{idx}"), encoding))
+                    .collect()
+            },
+            (value, _) => vec![(name.to_string(), value.clone().into())],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn influx_fields_flattening() {
+        let value = MetricValue::Array(vec![
+            MetricValue::String("eth0".to_string()),
+            MetricValue::Null(),
+            MetricValue::Array(vec![MetricValue::Integer(1), MetricValue::Boolean(true)]),
+        ]);
+
+        let json = value.to_influx_fields("ifaces", ArrayEncoding::Json);
+        assert_eq!(json.len(), 1);
+        assert_eq!(json[0].0, "ifaces");
+
+        let indexed: HashMap<String, FieldValue> = value.to_influx_fields("ifaces", ArrayEncoding::Indexed).into_iter().collect();
+        assert_eq!(indexed.len(), 3);
+        assert!(indexed.contains_key("ifaces_0"));
+        assert!(!indexed.contains_key("ifaces_1")); // nulls are omitted
+        assert!(indexed.contains_key("ifaces_2_0"));
+        assert!(indexed.contains_key("ifaces_2_1"));
+
+        assert!(MetricValue::Null().to_influx_fields("nothing", ArrayEncoding::Json).is_empty());
+    }
+}